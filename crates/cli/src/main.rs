@@ -1,9 +1,29 @@
-use clap::{Parser, Subcommand};
-use codio_content_id::ContentId;
-use codio_dht::{DhtConfig, DhtEvent, DhtNode};
+use anyhow::Context;
+use clap::{Parser, Subcommand, ValueEnum};
+use codio_blockstore::{
+    collect_garbage_with_files, put_chunked, write_chunked, Blockstore, ChunkedFile, FileIndex,
+    FsBlockstore, PinMode, PinSet,
+};
+use codio_chunk::{ChokeGate, ChunkConfig, ChunkDistributor, PeerInfo};
+use codio_common::RateLimits;
+use codio_content::{
+    is_dag_node, split_unixfs_path, Block, Capability, Car, CarVersion, Chunker, ContentKey,
+    DagDiff, PbNode, UnixFsBuilder, UnixFsDag, UnixFsData, UnixFsDirectory, UnixFsType,
+    ENCRYPTED_SEGMENT_SIZE,
+};
+use codio_content_id::{Codec, ContentId, ContentIdHasher, HashAlgorithm, Multibase, Version};
+use codio_network::{NetworkConfig, NetworkHandle, NetworkManager};
 use colored::Colorize;
+use libp2p::{Multiaddr, PeerId};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 
 #[derive(Parser)]
 #[command(name = "codio-cdn")]
@@ -15,6 +35,28 @@ struct Cli {
     /// Enable verbose logging
     #[arg(short, long)]
     verbose: bool,
+
+    /// Keep blocks in this directory: `publish`, `get` and `car import` add
+    /// to it, and `get` and `car export` read from it before the network.
+    /// Published and imported roots are pinned so `gc` keeps them
+    #[arg(long, global = true, env = "CODIO_REPO")]
+    repo: Option<PathBuf>,
+
+    /// Limit uploads to all peers together, in bytes per second (0 for none)
+    #[arg(long, global = true, default_value_t = 0, env = "CODIO_UPLOAD_LIMIT")]
+    upload_limit: u64,
+
+    /// Limit downloads from all peers together, in bytes per second (0 for none)
+    #[arg(long, global = true, default_value_t = 0, env = "CODIO_DOWNLOAD_LIMIT")]
+    download_limit: u64,
+
+    /// Limit uploads to each peer, in bytes per second (0 for none)
+    #[arg(long, global = true, default_value_t = 0)]
+    peer_upload_limit: u64,
+
+    /// Limit downloads from each peer, in bytes per second (0 for none)
+    #[arg(long, global = true, default_value_t = 0)]
+    peer_download_limit: u64,
 }
 
 #[derive(Subcommand)]
@@ -27,137 +69,1772 @@ enum Commands {
         /// Announce to DHT
         #[arg(long, default_value_t = true)]
         announce: bool,
+
+        /// Keep running and serve the content to other peers
+        #[arg(long)]
+        serve: bool,
+
+        /// Port to listen on (0 for random)
+        #[arg(long, default_value_t = 0)]
+        port: u16,
+
+        /// Bootstrap peer multiaddr (repeatable), e.g. /ip4/1.2.3.4/tcp/4001/p2p/12D3...
+        #[arg(long = "peer")]
+        peers: Vec<Multiaddr>,
+
+        /// Disable mDNS local peer discovery
+        #[arg(long)]
+        no_mdns: bool,
+
+        /// Encrypt the file with a random key and print a capability
+        /// (<cid>:<key>) that `get` can read it with
+        #[arg(long)]
+        encrypt: bool,
+
+        /// Encrypt the file with a key derived from its content, so identical
+        /// files share a CID (implies --encrypt)
+        #[arg(long)]
+        convergent: bool,
     },
 
     /// Retrieve content by CID
     Get {
-        /// Content ID (CID) to retrieve
+        /// Content ID (CID) to retrieve, optionally followed by a path inside
+        /// a published directory (<cid>/assets/app.js), or a capability
+        /// (<cid>:<key>) to retrieve and decrypt an encrypted file
         cid: String,
 
-        /// Output path
+        /// Output path (defaults to stdout; directories are listed there)
         #[arg(short, long)]
         output: Option<PathBuf>,
+
+        /// Peer multiaddr to bootstrap from and fetch from (repeatable)
+        #[arg(long = "peer")]
+        peers: Vec<Multiaddr>,
+
+        /// Give up after this many seconds
+        #[arg(long, default_value_t = 60)]
+        timeout: u64,
+
+        /// Disable mDNS local peer discovery
+        #[arg(long)]
+        no_mdns: bool,
     },
 
     /// Show CID for content without publishing
     Hash {
-        /// File to hash
+        /// File or directory to hash
         path: PathBuf,
+
+        /// CID version; 1 addresses the file as raw bytes (directories are always 1)
+        #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=1))]
+        cid_version: u8,
+
+        /// Multibase to print the CID in (CIDv1 defaults to base32)
+        #[arg(long, value_enum)]
+        base: Option<Base>,
+
+        /// Hash function; anything but sha2-256 implies --cid-version 1
+        #[arg(long, value_enum, default_value_t = Hash::Sha2_256)]
+        hash: Hash,
+    },
+
+    /// Move content in and out of CAR archive files
+    Car {
+        #[command(subcommand)]
+        command: CarCommands,
+    },
+
+    /// Keep content in the repo through garbage collection
+    Pin {
+        #[command(subcommand)]
+        command: PinCommands,
+    },
+
+    /// Compare two versions of content chunk by chunk: the chunks and DAG
+    /// nodes the new version adds are all peers holding the old one need
+    Diff {
+        /// Old version: CID to fetch from peers, or a local file or directory
+        old: String,
+
+        /// New version: CID to fetch from peers, or a local file or directory
+        new: String,
+
+        /// Peer multiaddr to bootstrap from and fetch from (repeatable)
+        #[arg(long = "peer")]
+        peers: Vec<Multiaddr>,
+
+        /// Give up fetching after this many seconds
+        #[arg(long, default_value_t = 60)]
+        timeout: u64,
+
+        /// Disable mDNS local peer discovery
+        #[arg(long)]
+        no_mdns: bool,
+    },
+
+    /// Remove blocks no pin reaches from the repo
+    Gc {
+        /// Report what would be removed without removing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CarCommands {
+    /// Write content and everything below it to a CAR file
+    Export {
+        /// CID to fetch from peers, or a local file or directory to pack
+        target: String,
+
+        /// CAR file to write
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// CAR version; 2 adds an index of block offsets
+        #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(1..=2))]
+        car_version: u8,
+
+        /// Peer multiaddr to bootstrap from and fetch from (repeatable)
+        #[arg(long = "peer")]
+        peers: Vec<Multiaddr>,
+
+        /// Give up fetching after this many seconds
+        #[arg(long, default_value_t = 60)]
+        timeout: u64,
+
+        /// Disable mDNS local peer discovery
+        #[arg(long)]
+        no_mdns: bool,
+    },
+
+    /// Verify every block of a CAR file, then unpack or serve its content
+    Import {
+        /// CAR file to read
+        file: PathBuf,
+
+        /// Write the root's file or directory tree here
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Keep running and serve the blocks to other peers
+        #[arg(long)]
+        serve: bool,
+
+        /// Port to listen on (0 for random)
+        #[arg(long, default_value_t = 0)]
+        port: u16,
+
+        /// Bootstrap peer multiaddr (repeatable)
+        #[arg(long = "peer")]
+        peers: Vec<Multiaddr>,
+
+        /// Disable mDNS local peer discovery
+        #[arg(long)]
+        no_mdns: bool,
     },
 }
 
+#[derive(Subcommand)]
+enum PinCommands {
+    /// Pin a CID, fetching whatever the repo is missing from peers
+    Add {
+        /// CID to pin
+        cid: String,
+
+        /// Pin only this block, not the blocks it links to
+        #[arg(long)]
+        direct: bool,
+
+        /// Label to list the pin under
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Peer multiaddr to bootstrap from and fetch from (repeatable)
+        #[arg(long = "peer")]
+        peers: Vec<Multiaddr>,
+
+        /// Give up fetching after this many seconds
+        #[arg(long, default_value_t = 60)]
+        timeout: u64,
+
+        /// Disable mDNS local peer discovery
+        #[arg(long)]
+        no_mdns: bool,
+    },
+
+    /// Unpin a CID; its blocks are removed by the next `gc`
+    Rm {
+        /// CID to unpin
+        cid: String,
+    },
+
+    /// List pins as CID, mode and name
+    Ls,
+}
+
+/// Multibase choices for CIDv1 output
+#[derive(Clone, Copy, ValueEnum)]
+enum Base {
+    Base32,
+    Base58btc,
+    Base36,
+}
+
+/// Hash function choices for `hash`
+#[derive(Clone, Copy, ValueEnum)]
+enum Hash {
+    #[value(name = "sha2-256")]
+    Sha2_256,
+    #[value(name = "sha2-512")]
+    Sha2_512,
+    #[value(name = "sha3-256")]
+    Sha3_256,
+    #[value(name = "sha3-512")]
+    Sha3_512,
+    Blake3,
+}
+
+impl From<Hash> for HashAlgorithm {
+    fn from(hash: Hash) -> Self {
+        match hash {
+            Hash::Sha2_256 => HashAlgorithm::Sha2_256,
+            Hash::Sha2_512 => HashAlgorithm::Sha2_512,
+            Hash::Sha3_256 => HashAlgorithm::Sha3_256,
+            Hash::Sha3_512 => HashAlgorithm::Sha3_512,
+            Hash::Blake3 => HashAlgorithm::Blake3,
+        }
+    }
+}
+
+impl From<Base> for Multibase {
+    fn from(base: Base) -> Self {
+        match base {
+            Base::Base32 => Multibase::Base32Lower,
+            Base::Base58btc => Multibase::Base58Btc,
+            Base::Base36 => Multibase::Base36Lower,
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
     // Setup logging
     let log_level = if cli.verbose { "debug" } else { "info" };
-    tracing_subscriber::fmt().with_env_filter(log_level).init();
+    // Logs go to stderr so `get` can stream content to stdout
+    tracing_subscriber::fmt()
+        .with_env_filter(log_level)
+        .with_writer(std::io::stderr)
+        .init();
+
+    let mut repo = cli.repo.as_deref().map(Repo::open).transpose()?;
+    let blocks = repo.as_ref().map(|repo| repo.store.clone());
+    let limits = RateLimits::default()
+        .with_upload(cli.upload_limit)
+        .with_download(cli.download_limit)
+        .with_peer_upload(cli.peer_upload_limit)
+        .with_peer_download(cli.peer_download_limit);
 
     match cli.command {
-        Commands::Publish { path, announce } => {
-            publish_content(path, announce).await?;
+        Commands::Publish {
+            path,
+            announce,
+            serve,
+            port,
+            peers,
+            no_mdns,
+            encrypt,
+            convergent,
+        } => {
+            let config = network_config(port, &peers, no_mdns, limits);
+            let key = match (encrypt, convergent) {
+                (_, true) => Some(KeyKind::Convergent),
+                (true, false) => Some(KeyKind::Random),
+                (false, false) => None,
+            };
+            publish_content(path, announce, serve, config, peers, key, repo.as_mut()).await?;
+        }
+        Commands::Get {
+            cid,
+            output,
+            peers,
+            timeout,
+            no_mdns,
+        } => {
+            let config = network_config(0, &peers, no_mdns, limits);
+            let timeout = Duration::from_secs(timeout);
+            let fetcher = Fetcher::new(config, peers, timeout, blocks);
+            get_content(&cid, output, fetcher, timeout).await?;
+        }
+        Commands::Hash {
+            path,
+            cid_version,
+            base,
+            hash,
+        } => {
+            hash_content(path, cid_version, base, hash.into())?;
+        }
+        Commands::Car {
+            command:
+                CarCommands::Export {
+                    target,
+                    output,
+                    car_version,
+                    peers,
+                    timeout,
+                    no_mdns,
+                },
+        } => {
+            let version = match car_version {
+                1 => CarVersion::V1,
+                _ => CarVersion::V2,
+            };
+            let config = network_config(0, &peers, no_mdns, limits);
+            let timeout = Duration::from_secs(timeout);
+            let fetcher = Fetcher::new(config, peers, timeout, blocks);
+            export_car(&target, &output, version, fetcher, timeout).await?;
+        }
+        Commands::Car {
+            command:
+                CarCommands::Import {
+                    file,
+                    output,
+                    serve,
+                    port,
+                    peers,
+                    no_mdns,
+                },
+        } => {
+            let config = network_config(port, &peers, no_mdns, limits);
+            import_car(&file, output, serve, config, peers, repo.as_mut()).await?;
+        }
+        Commands::Pin {
+            command:
+                PinCommands::Add {
+                    cid,
+                    direct,
+                    name,
+                    peers,
+                    timeout,
+                    no_mdns,
+                },
+        } => {
+            let repo = Repo::required(repo.as_mut(), "pin add")?;
+            let mode = match direct {
+                true => PinMode::Direct,
+                false => PinMode::Recursive,
+            };
+            let config = network_config(0, &peers, no_mdns, limits);
+            let timeout = Duration::from_secs(timeout);
+            let fetcher = Fetcher::new(config, peers, timeout, blocks);
+            pin_add(&cid, mode, name, repo, fetcher, timeout).await?;
+        }
+        Commands::Pin {
+            command: PinCommands::Rm { cid },
+        } => {
+            let repo = Repo::required(repo.as_mut(), "pin rm")?;
+            let cid = parse_cid(&cid)?;
+            if repo.pins.remove(&cid)?.is_none() {
+                anyhow::bail!("{} is not pinned", cid);
+            }
+            println!("Unpinned {}", cid.to_string().bright_blue());
+        }
+        Commands::Pin {
+            command: PinCommands::Ls,
+        } => {
+            let repo = Repo::required(repo.as_mut(), "pin ls")?;
+            for pin in repo.pins.iter() {
+                println!(
+                    "{}\t{}\t{}",
+                    pin.cid,
+                    pin.mode.as_str(),
+                    pin.name.as_deref().unwrap_or("")
+                );
+            }
         }
-        Commands::Get { cid, output } => {
-            get_content(&cid, output).await?;
+        Commands::Diff {
+            old,
+            new,
+            peers,
+            timeout,
+            no_mdns,
+        } => {
+            let config = network_config(0, &peers, no_mdns, limits);
+            let timeout = Duration::from_secs(timeout);
+            let fetcher = Fetcher::new(config, peers, timeout, blocks);
+            diff(&old, &new, fetcher, timeout).await?;
         }
-        Commands::Hash { path } => {
-            hash_content(path)?;
+        Commands::Gc { dry_run } => {
+            let repo = Repo::required(repo.as_mut(), "gc")?;
+            gc(repo, dry_run).await?;
         }
     }
 
     Ok(())
 }
 
-async fn publish_content(path: PathBuf, announce: bool) -> anyhow::Result<()> {
+/// How `publish` picks the key of an encrypted file
+#[derive(Clone, Copy)]
+enum KeyKind {
+    Random,
+    Convergent,
+}
+
+/// Blocks and pins kept by `--repo`
+struct Repo {
+    store: RepoStore,
+    pins: PinSet,
+}
+
+/// The repo's blocks and the index of files stored chunk by chunk, shared
+/// with the [`Fetcher`]
+#[derive(Clone)]
+struct RepoStore {
+    blocks: Arc<dyn Blockstore>,
+    files: Arc<Mutex<FileIndex>>,
+}
+
+impl Repo {
+    /// Pin file, next to the block shards
+    const PINS_FILE: &'static str = "pins.json";
+
+    /// Index of chunked files, next to the pin file
+    const FILES_FILE: &'static str = "files.json";
+
+    fn open(path: &Path) -> anyhow::Result<Self> {
+        Ok(Repo {
+            store: RepoStore {
+                blocks: Arc::new(FsBlockstore::open(path)?),
+                files: Arc::new(Mutex::new(FileIndex::open(path.join(Self::FILES_FILE))?)),
+            },
+            pins: PinSet::open(path.join(Self::PINS_FILE))?,
+        })
+    }
+
+    /// The repo, for commands that can't run without one
+    fn required<'a>(repo: Option<&'a mut Repo>, command: &str) -> anyhow::Result<&'a mut Repo> {
+        repo.ok_or_else(|| anyhow::anyhow!("`{}` needs --repo (or CODIO_REPO)", command))
+    }
+
+    /// Add blocks and pin `root` so `gc` keeps them
+    async fn add(
+        &mut self,
+        root: &ContentId,
+        blocks: impl IntoIterator<Item = Block>,
+    ) -> anyhow::Result<()> {
+        for block in blocks {
+            self.store.blocks.put(&block.cid, block.data).await?;
+        }
+        self.pins.add(root.clone(), PinMode::Recursive, None)
+    }
+}
+
+impl RepoStore {
+    /// The chunked file `cid` is stored as, if it is
+    fn chunked(&self, cid: &ContentId) -> Option<ChunkedFile> {
+        self.files.lock().unwrap().get(cid).cloned()
+    }
+
+    /// Get `cid`, reassembling it from its chunks if it's stored chunked
+    async fn get(&self, cid: &ContentId) -> anyhow::Result<Option<Vec<u8>>> {
+        match self.chunked(cid) {
+            Some(file) => {
+                let mut data = Vec::with_capacity(file.size as usize);
+                write_chunked(self.blocks.as_ref(), &file, &mut data).await?;
+                Ok(Some(data))
+            }
+            None => self.blocks.get(cid).await,
+        }
+    }
+
+    /// Write `cid` to the file at `path` if it's stored, returning its size
+    async fn get_to_file(&self, cid: &ContentId, path: &Path) -> anyhow::Result<Option<u64>> {
+        let Some(file) = self.chunked(cid) else {
+            let Some(data) = self.blocks.get(cid).await? else {
+                return Ok(None);
+            };
+            fs::write(path, &data)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            return Ok(Some(data.len() as u64));
+        };
+
+        let mut out = tokio::fs::File::create(path)
+            .await
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(Some(
+            write_chunked(self.blocks.as_ref(), &file, &mut out).await?,
+        ))
+    }
+
+    /// Store the content `reader` yields, which must be `cid`, one block per
+    /// chunk as the distributor splits it
+    async fn put<R: tokio::io::AsyncRead + Unpin>(
+        &self,
+        cid: &ContentId,
+        reader: R,
+    ) -> anyhow::Result<()> {
+        let chunker =
+            Chunker::new(ChunkConfig::default().chunk_size).with_hash_algorithm(cid.algorithm());
+        let file = put_chunked(self.blocks.as_ref(), &chunker, reader).await?;
+        if file.cid.routing_key() != cid.routing_key() {
+            anyhow::bail!("Content changed while storing {}", cid);
+        }
+        self.files.lock().unwrap().add(file)
+    }
+}
+
+/// Parse a CID, accepting an `/ipfs/` prefix
+fn parse_cid(target: &str) -> anyhow::Result<ContentId> {
+    Ok(ContentId::from_str(
+        target.strip_prefix("/ipfs/").unwrap_or(target),
+    )?)
+}
+
+fn network_config(
+    port: u16,
+    peers: &[Multiaddr],
+    no_mdns: bool,
+    limits: RateLimits,
+) -> NetworkConfig {
+    let mut config = NetworkConfig::with_port(port).with_rate_limits(limits);
+    if no_mdns {
+        config = config.without_mdns();
+    }
+    for addr in peers {
+        config.add_bootstrap_peer(addr.clone());
+    }
+    config
+}
+
+/// Start a network node and dial the given peers
+async fn start_node(
+    config: NetworkConfig,
+    peers: Vec<Multiaddr>,
+) -> anyhow::Result<NetworkManager> {
+    let mut manager = NetworkManager::new(config).await?;
+    manager.start().await?;
+
+    for addr in peers {
+        manager
+            .connect_peer(addr.clone())
+            .await
+            .with_context(|| format!("Failed to connect to {}", addr))?;
+    }
+
+    Ok(manager)
+}
+
+async fn publish_content(
+    path: PathBuf,
+    announce: bool,
+    serve: bool,
+    config: NetworkConfig,
+    peers: Vec<Multiaddr>,
+    key: Option<KeyKind>,
+    repo: Option<&mut Repo>,
+) -> anyhow::Result<()> {
     println!("{}", "Publishing content...".cyan());
 
-    // Read content
-    let content = fs::read(&path)?;
-    println!("  {} Read {} bytes", "✓".green(), content.len());
+    let mut capability = None;
+    // Keeps an encrypted file's ciphertext on disk until publishing ends
+    let mut _ciphertext = None;
+    let published = if let Some(kind) = key {
+        if path.is_dir() {
+            anyhow::bail!(
+                "Only files can be encrypted, {} is a directory",
+                path.display()
+            );
+        }
+        let open = || async {
+            tokio::fs::File::open(&path)
+                .await
+                .with_context(|| format!("Failed to open {}", path.display()))
+        };
+        let size = open().await?.metadata().await?.len();
+        println!("  {} Read {} bytes", "✓".green(), size);
+
+        // Peers only ever see the ciphertext
+        let key = match kind {
+            KeyKind::Random => ContentKey::random(),
+            KeyKind::Convergent => ContentKey::convergent_from_async_reader(open().await?).await?,
+        };
+
+        // Encrypt into a temporary file a segment at a time, hashing as it
+        // goes, and publish that like any other file
+        let temp = tempfile::NamedTempFile::new().context("Failed to create a temporary file")?;
+        let mut out = tokio::fs::File::create(temp.path()).await?;
+        let mut reader = key.encrypt_reader(open().await?);
+        let mut hasher = ContentIdHasher::new();
+        let mut buf = vec![0u8; ENCRYPTED_SEGMENT_SIZE];
+        let mut size = 0;
+        loop {
+            let n = reader.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            out.write_all(&buf[..n]).await?;
+            size += n as u64;
+        }
+        out.flush().await?;
+        let cid = hasher.finalize();
+        println!("  {} Encrypted {} bytes", "✓".green(), size);
+        capability = Some(Capability::new(cid.clone(), key));
+
+        let path = temp.path().to_path_buf();
+        _ciphertext = Some(temp);
+        Published::file(cid, path, size)
+    } else if path.is_dir() {
+        let published = hash_directory(&path, HashAlgorithm::Sha2_256)?;
+        println!(
+            "  {} Read {} files ({} bytes)",
+            "✓".green(),
+            published.num_files,
+            published.size
+        );
+        published
+    } else {
+        // Hash the file as a stream so large files never sit in memory
+        let file = tokio::fs::File::open(&path)
+            .await
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        let size = file.metadata().await?.len();
+        let cid = ContentId::from_async_reader(file).await?;
+        println!("  {} Read {} bytes", "✓".green(), size);
 
-    // Generate CID
-    let cid = ContentId::new(&content);
+        Published::file(cid, path, size)
+    };
+    let cid = published.cid.clone();
     println!(
         "  {} Generated CID: {}",
         "✓".green(),
         cid.to_string().bright_blue()
     );
 
+    if let Some(repo) = repo {
+        for file in &published.files {
+            repo.store.put(&file.cid, file.open().await?).await?;
+        }
+        repo.add(&cid, published.blocks.iter().cloned()).await?;
+        println!("  {} Added to repo and pinned", "✓".green());
+    }
+
     // Announce to DHT if requested
-    if announce {
+    let mut node = None;
+    if announce || serve {
         println!("{}", "Announcing to DHT...".cyan());
 
-        let config = DhtConfig::default();
-        let (mut node, _event_rx) = DhtNode::new(config).await?;
-
-        // Listen on random port
-        let listen_addr = "/ip4/0.0.0.0/tcp/0".parse()?;
-        node.listen(listen_addr).await?;
+        let mut manager = start_node(config, peers).await?;
 
-        // Announce content
-        node.provide(cid.clone()).await?;
+        // Announce content: files and file leaves served straight from
+        // disk, DAG nodes from memory
+        for file in published.files {
+            manager.provide_file_range(file.cid, file.path, file.range)?;
+        }
+        for block in published.blocks {
+            manager.provide_content(block.cid, block.data)?;
+        }
         println!("  {} Content announced", "✓".green());
 
-        // Wait briefly for DHT propagation
-        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+        // Drive the swarm briefly for DHT propagation
+        let _ = tokio::time::timeout(Duration::from_secs(2), manager.run()).await;
+        node = Some(manager);
     }
 
     println!("\n{}", "Success!".bright_green().bold());
     println!("CID: {}", cid.to_string().bright_blue());
+    if let Some(capability) = capability {
+        println!("Capability: {}", capability.to_string().bright_blue());
+        println!(
+            "{}",
+            "Anyone with the capability can read the file; share it privately".yellow()
+        );
+    }
+
+    if let (true, Some(manager)) = (serve, node) {
+        serve_until_stopped(manager).await?;
+    }
+
+    Ok(())
+}
+
+/// Print the node's addresses and serve content until Ctrl+C
+///
+/// Chunk uploads go through choke rounds, so only a few peers per content
+/// are served at a time.
+async fn serve_until_stopped(mut manager: NetworkManager) -> anyhow::Result<()> {
+    let gate = ChokeGate::new(ChunkConfig::default().upload_slots);
+    gate.start_rounds();
+    manager.set_upload_gate(Arc::new(gate));
+
+    println!("\n{}", "Serving content (Ctrl+C to stop) on:".cyan());
+    let peer_id = manager.peer_id();
+    for addr in manager.listen_addrs() {
+        println!("  {}/p2p/{}", addr, peer_id);
+    }
+    std::io::stdout().flush()?;
+
+    tokio::select! {
+        result = manager.run() => result?,
+        _ = tokio::signal::ctrl_c() => {
+            println!("\n{}", "Stopped serving".yellow());
+        }
+    }
 
     Ok(())
 }
 
-async fn get_content(cid_str: &str, _output: Option<PathBuf>) -> anyhow::Result<()> {
-    println!("{} {}", "Retrieving:".cyan(), cid_str.bright_blue());
+async fn get_content(
+    target: &str,
+    output: Option<PathBuf>,
+    mut fetcher: Fetcher,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    // Progress goes to stderr so stdout carries nothing but the content
+    eprintln!("{} {}", "Retrieving:".cyan(), target.bright_blue());
 
-    // Parse CID
+    // Parse CID and the path below it
+    let target = target.strip_prefix("/ipfs/").unwrap_or(target);
+    let (cid_str, path) = target.split_once('/').unwrap_or((target, ""));
+    if Capability::is_capability(cid_str) {
+        if !path.is_empty() {
+            anyhow::bail!("Capabilities name files; paths below them aren't supported");
+        }
+        let capability = Capability::from_str(cid_str)?;
+        return get_encrypted(capability, output, fetcher, timeout).await;
+    }
     let cid = ContentId::from_str(cid_str)?;
-    println!("  {} CID parsed", "✓".green());
+    let names = split_unixfs_path(path)?;
+    eprintln!("  {} CID parsed", "✓".green());
 
-    // Create DHT node
-    println!("{}", "Searching DHT...".cyan());
-    let config = DhtConfig::default();
-    let (mut node, mut event_rx) = DhtNode::new(config).await?;
+    tokio::time::timeout(timeout, async {
+        let cid = fetcher.resolve(cid, &names).await?;
 
-    // Listen
-    let listen_addr = "/ip4/0.0.0.0/tcp/0".parse()?;
-    node.listen(listen_addr).await?;
+        // Files written to disk download straight into place, so an
+        // interrupted get picks up where it stopped
+        if let (Some(path), false) = (&output, is_dag_node(&cid)) {
+            let size = fetcher.fetch_to_file(&cid, path).await?;
+            eprintln!(
+                "  {} Wrote {} bytes to {}",
+                "✓".green(),
+                size,
+                path.display()
+            );
+            return anyhow::Ok(());
+        }
 
-    // Find providers
-    node.find_providers(cid.clone()).await?;
+        let content = fetcher.fetch(&cid).await?;
 
-    // Wait for providers
-    tokio::select! {
-        Some(event) = event_rx.recv() => {
-            match event {
-                DhtEvent::ProvidersFound { cid: _, providers } => {
-                    println!("  {} Found {} providers", "✓".green(), providers.len());
-
-                    // TODO: Actually download from providers
-                    println!("\n{}", "Note: Content download not yet implemented".yellow());
-                    println!("Providers found: {:?}", providers);
+        match directory(&cid, &content) {
+            Some(dir) => match output {
+                Some(path) => {
+                    let files = fetcher.fetch_directory(dir, &path).await?;
+                    eprintln!(
+                        "  {} Wrote {} files to {}",
+                        "✓".green(),
+                        files,
+                        path.display()
+                    );
+                }
+                None => {
+                    let mut stdout = std::io::stdout().lock();
+                    for entry in dir.entries() {
+                        writeln!(
+                            stdout,
+                            "{}\t{}\t{}",
+                            entry.hash,
+                            entry.tsize.unwrap_or(0),
+                            entry.name.as_deref().unwrap_or("")
+                        )?;
+                    }
+                    stdout.flush()?;
                 }
-                _ => {}
+            },
+            None => match output {
+                Some(path) => {
+                    let file = fs::File::create(&path)
+                        .with_context(|| format!("Failed to write {}", path.display()))?;
+                    let mut out = std::io::BufWriter::new(file);
+                    let size = fetcher.write_file(&cid, &content, &mut out).await?;
+                    out.flush()?;
+                    eprintln!(
+                        "  {} Wrote {} bytes to {}",
+                        "✓".green(),
+                        size,
+                        path.display()
+                    );
+                }
+                None => {
+                    let mut stdout = std::io::stdout().lock();
+                    fetcher.write_file(&cid, &content, &mut stdout).await?;
+                    stdout.flush()?;
+                }
+            },
+        }
+
+        anyhow::Ok(())
+    })
+    .await
+    .map_err(|_| anyhow::anyhow!("Timed out after {:?} retrieving {}", timeout, target))?
+}
+
+/// Fetch the ciphertext a capability names and write it out decrypted, a
+/// segment at a time
+///
+/// The ciphertext downloads to a file next to `output` (a temporary file
+/// for stdout), so an interrupted get resumes, and is decrypted from there.
+/// Each segment is written once it is authenticated; if a later one fails,
+/// `output` is removed.
+async fn get_encrypted(
+    capability: Capability,
+    output: Option<PathBuf>,
+    mut fetcher: Fetcher,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    eprintln!("  {} Capability parsed", "✓".green());
+
+    let mut _temp = None;
+    let ciphertext = match &output {
+        Some(path) => {
+            let mut name = path.as_os_str().to_owned();
+            name.push(".ciphertext");
+            PathBuf::from(name)
+        }
+        None => {
+            let temp =
+                tempfile::NamedTempFile::new().context("Failed to create a temporary file")?;
+            let path = temp.path().to_path_buf();
+            _temp = Some(temp);
+            path
+        }
+    };
+    tokio::time::timeout(timeout, fetcher.fetch_to_file(&capability.cid, &ciphertext))
+        .await
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "Timed out after {:?} retrieving {}",
+                timeout,
+                capability.cid
+            )
+        })??;
+
+    let file = tokio::fs::File::open(&ciphertext)
+        .await
+        .with_context(|| format!("Failed to read {}", ciphertext.display()))?;
+    let mut reader = capability.key.decrypt_reader(file);
+    let decrypted = match &output {
+        Some(path) => {
+            let mut out = tokio::fs::File::create(path)
+                .await
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            let decrypted = tokio::io::copy(&mut reader, &mut out).await;
+            if decrypted.is_err() {
+                let _ = fs::remove_file(path);
             }
+            decrypted
         }
-        _ = tokio::time::sleep(std::time::Duration::from_secs(10)) => {
-            println!("  {} Timeout: No providers found", "✗".red());
+        None => tokio::io::copy(&mut reader, &mut tokio::io::stdout()).await,
+    };
+    if output.is_some() {
+        let _ = fs::remove_file(&ciphertext);
+    }
+    let size = decrypted.with_context(|| format!("Failed to decrypt {}", capability.cid))?;
+    eprintln!("  {} Decrypted {} bytes", "✓".green(), size);
+
+    if let Some(path) = output {
+        eprintln!(
+            "  {} Wrote {} bytes to {}",
+            "✓".green(),
+            size,
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Decode `content` as a directory if `cid` can name one
+fn directory(cid: &ContentId, content: &[u8]) -> Option<UnixFsDirectory> {
+    if !is_dag_node(cid) {
+        return None;
+    }
+    UnixFsDirectory::decode(content).ok()
+}
+
+/// Decode `content` as a UnixFS file node if `cid` can name one: the bytes
+/// it holds itself, then the links to the rest of the file in order
+fn file_node(cid: &ContentId, content: &[u8]) -> Option<(Vec<u8>, Vec<ContentId>)> {
+    if !is_dag_node(cid) {
+        return None;
+    }
+    let node = PbNode::decode(content).ok()?;
+    let unixfs = UnixFsData::decode(node.data.as_deref()?).ok()?;
+    if unixfs.kind == UnixFsType::Directory {
+        return None;
+    }
+    let links = node.links.into_iter().map(|link| link.hash).collect();
+    Some((unixfs.data.unwrap_or_default(), links))
+}
+
+/// Write the file whose root block is `content` to `out`, getting the rest
+/// of a UnixFS file's blocks from `block`. Returns the file size.
+fn write_file(
+    cid: &ContentId,
+    content: &[u8],
+    mut block: impl FnMut(&ContentId) -> anyhow::Result<Vec<u8>>,
+    out: &mut impl Write,
+) -> anyhow::Result<u64> {
+    let mut pending = Vec::new();
+    let mut size = write_file_block(cid, content, &mut pending, out)?;
+    while let Some(cid) = pending.pop() {
+        size += write_file_block(&cid, &block(&cid)?, &mut pending, out)?;
+    }
+    Ok(size)
+}
+
+/// Write the bytes one block of a file holds, and queue its links on the
+/// `pending` stack (popped last first). Returns the bytes written.
+fn write_file_block(
+    cid: &ContentId,
+    content: &[u8],
+    pending: &mut Vec<ContentId>,
+    out: &mut impl Write,
+) -> anyhow::Result<u64> {
+    match file_node(cid, content) {
+        Some((data, links)) => {
+            out.write_all(&data)?;
+            pending.extend(links.into_iter().rev());
+            Ok(data.len() as u64)
+        }
+        None => {
+            out.write_all(content)?;
+            Ok(content.len() as u64)
         }
     }
+}
+
+/// Downloads content by CID, verifying each chunk and the reassembled
+/// content. Content already in the repo is read from it, and downloaded
+/// content added to it chunk by chunk; a node is only started once
+/// something must come from peers.
+struct Fetcher {
+    /// Config for the node, until it's started
+    config: Option<NetworkConfig>,
+    /// Peers given on the command line, dialed and tried before DHT providers
+    peers: Vec<Multiaddr>,
+    repo: Option<RepoStore>,
+    node: Option<FetchNode>,
+    timeout: Duration,
+}
+
+/// A running node and the distributor downloading through it
+struct FetchNode {
+    /// Peers given on the command line
+    peers: Vec<PeerId>,
+    handle: NetworkHandle,
+    distributor: ChunkDistributor,
+    /// Task driving the node
+    task: tokio::task::JoinHandle<anyhow::Result<()>>,
+}
+
+impl Drop for FetchNode {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl FetchNode {
+    /// Providers of `cid`: the peers given on the command line first, then
+    /// whoever the DHT knows
+    async fn providers(&self, cid: &ContentId, timeout: Duration) -> anyhow::Result<Vec<PeerInfo>> {
+        let mut candidates = self.peers.clone();
+
+        eprintln!("{} {}", "Searching DHT for".cyan(), cid);
+        match self.handle.find_providers(cid, timeout).await {
+            Ok(providers) => {
+                eprintln!("  {} Found {} providers", "✓".green(), providers.len());
+                for provider in providers {
+                    if !candidates.contains(&provider) {
+                        candidates.push(provider);
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("  {} Provider lookup failed: {}", "✗".red(), e);
+            }
+        }
+
+        if candidates.is_empty() {
+            anyhow::bail!("No providers found for {}", cid);
+        }
+
+        eprintln!(
+            "{} {} providers",
+            "Downloading from".cyan(),
+            candidates.len()
+        );
+        Ok(candidates
+            .iter()
+            .map(|peer_id| PeerInfo::new(codio_common::PeerId(peer_id.to_string())))
+            .collect())
+    }
+}
+
+impl Fetcher {
+    fn new(
+        config: NetworkConfig,
+        peers: Vec<Multiaddr>,
+        timeout: Duration,
+        repo: Option<RepoStore>,
+    ) -> Self {
+        Fetcher {
+            config: Some(config),
+            peers,
+            repo,
+            node: None,
+            timeout,
+        }
+    }
+
+    /// The node, started and dialed to the given peers on first use
+    async fn node(&mut self) -> anyhow::Result<&mut FetchNode> {
+        if let Some(config) = self.config.take() {
+            let limits = config.rate_limits;
+            let ids = self
+                .peers
+                .iter()
+                .filter_map(|addr| {
+                    addr.iter().find_map(|p| match p {
+                        libp2p::multiaddr::Protocol::P2p(peer_id) => Some(peer_id),
+                        _ => None,
+                    })
+                })
+                .collect();
+
+            // Keep the node running while the distributor talks to it
+            let mut manager = start_node(config, self.peers.clone()).await?;
+            let handle = manager.handle();
+            let task = tokio::spawn(async move { manager.run().await });
+
+            let distributor =
+                ChunkDistributor::new(ChunkConfig::default().with_rate_limits(limits))
+                    .await?
+                    .with_network(handle.clone());
+
+            self.node = Some(FetchNode {
+                peers: ids,
+                handle,
+                distributor,
+                task,
+            });
+        }
+
+        self.node
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("Network node failed to start"))
+    }
+
+    /// Get `cid` from the repo, or download it from providers into the repo
+    async fn fetch(&mut self, cid: &ContentId) -> anyhow::Result<Vec<u8>> {
+        if let Some(repo) = &self.repo {
+            if let Some(data) = repo.get(cid).await? {
+                eprintln!("  {} Found {} in repo", "✓".green(), cid);
+                return Ok(data);
+            }
+        }
+
+        let data = self.download(cid).await?;
+        if let Some(repo) = &self.repo {
+            // Nodes stay whole so gc can follow their links
+            match is_dag_node(cid) {
+                true => repo.blocks.put(cid, data.clone()).await?,
+                false => repo.put(cid, &data[..]).await?,
+            }
+        }
+        Ok(data)
+    }
+
+    /// Get `cid` into the file at `path`, resuming an earlier attempt that
+    /// left a journal next to it
+    async fn fetch_to_file(&mut self, cid: &ContentId, path: &Path) -> anyhow::Result<u64> {
+        if let Some(repo) = &self.repo {
+            if let Some(size) = repo.get_to_file(cid, path).await? {
+                eprintln!("  {} Found {} in repo", "✓".green(), cid);
+                return Ok(size);
+            }
+        }
+
+        let timeout = self.timeout;
+        let node = self.node().await?;
+        let providers = node.providers(cid, timeout).await?;
+        // On Ctrl+C the journal is saved so the next run picks up from here
+        let size = tokio::select! {
+            result = node.distributor.download_to_file(cid.clone(), providers, path) => result?,
+            _ = tokio::signal::ctrl_c() => {
+                node.distributor.cancel_download(cid).await;
+                anyhow::bail!("Interrupted, run again to resume");
+            }
+        };
+        eprintln!("  {} Verified {} bytes", "✓".green(), size);
+
+        if let Some(repo) = &self.repo {
+            let file = tokio::fs::File::open(path)
+                .await
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            repo.put(cid, file).await?;
+        }
+        Ok(size)
+    }
+
+    /// Find providers for `cid` and download it from all of them
+    async fn download(&mut self, cid: &ContentId) -> anyhow::Result<Vec<u8>> {
+        let timeout = self.timeout;
+        let node = self.node().await?;
+        let providers = node.providers(cid, timeout).await?;
+
+        let data = node
+            .distributor
+            .download_content(cid.clone(), providers)
+            .await?;
+        eprintln!("  {} Verified {} bytes", "✓".green(), data.len());
+        Ok(data)
+    }
+
+    /// Write the file whose root block is `content` to `out`, fetching the
+    /// rest of a UnixFS file's blocks. Returns the file size.
+    async fn write_file(
+        &mut self,
+        cid: &ContentId,
+        content: &[u8],
+        out: &mut impl Write,
+    ) -> anyhow::Result<u64> {
+        let mut pending = Vec::new();
+        let mut size = write_file_block(cid, content, &mut pending, out)?;
+        while let Some(cid) = pending.pop() {
+            let content = self.fetch(&cid).await?;
+            size += write_file_block(&cid, &content, &mut pending, out)?;
+        }
+        Ok(size)
+    }
+
+    /// Follow `names` from `cid` through published directories
+    async fn resolve(&mut self, mut cid: ContentId, names: &[&str]) -> anyhow::Result<ContentId> {
+        let root = cid.clone();
+
+        for (i, name) in names.iter().enumerate() {
+            let parent = match i {
+                0 => root.to_string(),
+                _ => format!("{}/{}", root, names[..i].join("/")),
+            };
+            let dir = match is_dag_node(&cid) {
+                true => UnixFsDirectory::decode(&self.fetch(&cid).await?).ok(),
+                false => None,
+            }
+            .ok_or_else(|| anyhow::anyhow!("{} is not a directory", parent))?;
+
+            cid = dir
+                .get(name)
+                .ok_or_else(|| anyhow::anyhow!("No such file: {}/{}", parent, name))?
+                .hash
+                .clone();
+        }
+
+        Ok(cid)
+    }
+
+    /// Download `cid` and every block below it into `car`
+    async fn fetch_dag(&mut self, cid: ContentId, car: &mut Car) -> anyhow::Result<()> {
+        let mut pending = vec![cid];
+
+        while let Some(cid) = pending.pop() {
+            if car.get(&cid).is_some() {
+                continue;
+            }
+            let data = self.fetch(&cid).await?;
+            if let Some(dir) = directory(&cid, &data) {
+                pending.extend(dir.entries().iter().map(|entry| entry.hash.clone()));
+            } else if let Some((_, links)) = file_node(&cid, &data) {
+                pending.extend(links);
+            }
+            car.push(Block { cid, data });
+        }
+
+        Ok(())
+    }
+
+    /// Every block below `root` as `(cid, size)`: DAG nodes whole, and other
+    /// content in chunks as [`file_chunks`] splits it
+    async fn diff_blocks(&mut self, root: ContentId) -> anyhow::Result<Vec<(ContentId, u64)>> {
+        let mut blocks = Vec::new();
+        let mut pending = vec![root.clone()];
+
+        while let Some(cid) = pending.pop() {
+            if is_dag_node(&cid) {
+                let data = self.fetch(&cid).await?;
+                if let Some(dir) = directory(&cid, &data) {
+                    pending.extend(dir.entries().iter().map(|entry| entry.hash.clone()));
+                } else if let Some((_, links)) = file_node(&cid, &data) {
+                    pending.extend(links);
+                }
+                blocks.push((cid, data.len() as u64));
+                continue;
+            }
+
+            // A whole file goes through disk; leaves are one chunk at most
+            let (_, chunks) = if cid == root {
+                let temp =
+                    tempfile::NamedTempFile::new().context("Failed to create a temporary file")?;
+                self.fetch_to_file(&cid, temp.path()).await?;
+                file_chunks(fs::File::open(temp.path())?)?
+            } else {
+                file_chunks(&self.fetch(&cid).await?[..])?
+            };
+            match chunks.len() {
+                // Content that fits in a chunk keeps the CID it's linked by
+                0 | 1 => blocks.push((cid, chunks.iter().map(|(_, size)| size).sum())),
+                _ => blocks.extend(chunks),
+            }
+        }
+
+        Ok(blocks)
+    }
+
+    /// Download every file below `dir` into `path`, returning the file count
+    async fn fetch_directory(
+        &mut self,
+        dir: UnixFsDirectory,
+        path: &Path,
+    ) -> anyhow::Result<usize> {
+        let mut pending = vec![(dir, path.to_path_buf())];
+        let mut files = 0;
+
+        while let Some((dir, path)) = pending.pop() {
+            fs::create_dir_all(&path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+
+            for entry in dir.entries() {
+                let name = entry.name.as_deref().unwrap_or("");
+                if split_unixfs_path(name)? != [name] {
+                    anyhow::bail!("Refusing to write directory entry {:?}", name);
+                }
+
+                let content = self.fetch(&entry.hash).await?;
+                match directory(&entry.hash, &content) {
+                    Some(subdir) => pending.push((subdir, path.join(name))),
+                    None => {
+                        let target = path.join(name);
+                        let file = fs::File::create(&target)
+                            .with_context(|| format!("Failed to write {}", target.display()))?;
+                        let mut out = std::io::BufWriter::new(file);
+                        self.write_file(&entry.hash, &content, &mut out).await?;
+                        out.flush()?;
+                        files += 1;
+                    }
+                }
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+async fn export_car(
+    target: &str,
+    output: &Path,
+    version: CarVersion,
+    mut fetcher: Fetcher,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let car = collect_blocks(target, &mut fetcher, timeout).await?;
+
+    let file = fs::File::create(output)
+        .with_context(|| format!("Failed to create {}", output.display()))?;
+    car.write_to(std::io::BufWriter::new(file), version)?;
+
+    let size = car
+        .blocks()
+        .iter()
+        .map(|b| b.data.len() as u64)
+        .sum::<u64>();
+    println!(
+        "  {} Wrote {} blocks ({} bytes) to {}",
+        "✓".green(),
+        car.blocks().len(),
+        size,
+        output.display()
+    );
+    println!("\nRoot: {}", car.roots()[0].to_string().bright_blue());
+
+    Ok(())
+}
+
+/// Every block below `target`: a local file or directory packed as
+/// `publish` would address it, or a CID fetched from the repo or peers
+async fn collect_blocks(
+    target: &str,
+    fetcher: &mut Fetcher,
+    timeout: Duration,
+) -> anyhow::Result<Car> {
+    let path = Path::new(target);
+    if path.exists() {
+        println!("{} {}", "Packing:".cyan(), path.display());
+        return pack_path(path);
+    }
+
+    println!("{} {}", "Fetching:".cyan(), target.bright_blue());
+    let cid = parse_cid(target)?;
+
+    let mut car = Car::new(vec![cid.clone()]);
+    tokio::time::timeout(timeout, fetcher.fetch_dag(cid, &mut car))
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out after {:?} fetching {}", timeout, target))??;
+    Ok(car)
+}
+
+/// Print the chunks and nodes `new` adds to and removes from `old`
+async fn diff(old: &str, new: &str, mut fetcher: Fetcher, timeout: Duration) -> anyhow::Result<()> {
+    let (old_cid, old) = diff_blocks(old, &mut fetcher, timeout).await?;
+    let (new_cid, new) = diff_blocks(new, &mut fetcher, timeout).await?;
+    let diff = DagDiff::between(old.iter().cloned(), new.iter().cloned());
+
+    println!("\nOld: {}", old_cid.to_string().bright_blue());
+    println!("New: {}", new_cid.to_string().bright_blue());
+    println!(
+        "  {} Added:   {} blocks ({} bytes)",
+        "+".green(),
+        diff.added.len(),
+        diff.added_bytes
+    );
+    println!(
+        "  {} Removed: {} blocks ({} bytes)",
+        "-".red(),
+        diff.removed.len(),
+        diff.removed_bytes
+    );
+    println!(
+        "  {} Shared:  {} blocks ({} bytes)",
+        "=".dimmed(),
+        diff.shared.len(),
+        diff.shared_bytes
+    );
+
+    if diff.is_empty() {
+        println!("\n{}", "No changes".bright_green());
+        return Ok(());
+    }
+
+    // One line per block, for deploy scripts to pick the blocks to seed
+    println!();
+    let new: HashMap<_, _> = new.into_iter().collect();
+    let old: HashMap<_, _> = old.into_iter().collect();
+    for cid in &diff.added {
+        println!("+ {} {}", cid, new.get(cid).copied().unwrap_or(0));
+    }
+    for cid in &diff.removed {
+        println!("- {} {}", cid, old.get(cid).copied().unwrap_or(0));
+    }
+
+    Ok(())
+}
+
+/// The root of `target` and the blocks below it as `(cid, size)`: DAG nodes
+/// whole and file content in chunks. A local file or directory is streamed
+/// from disk and addressed as `publish` would; a CID is fetched from the
+/// repo or peers.
+async fn diff_blocks(
+    target: &str,
+    fetcher: &mut Fetcher,
+    timeout: Duration,
+) -> anyhow::Result<(ContentId, Vec<(ContentId, u64)>)> {
+    let path = Path::new(target);
+    if path.is_dir() {
+        println!("{} {}", "Hashing:".cyan(), path.display());
+        let published = hash_directory(path, HashAlgorithm::Sha2_256)?;
+        let nodes = published
+            .blocks
+            .iter()
+            .map(|block| (block.cid.clone(), block.data.len() as u64));
+        let leaves = published
+            .files
+            .iter()
+            .map(|file| (file.cid.clone(), file.range.end - file.range.start));
+        return Ok((published.cid, nodes.chain(leaves).collect()));
+    }
+    if path.exists() {
+        println!("{} {}", "Hashing:".cyan(), path.display());
+        let file =
+            fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        return file_chunks(file).with_context(|| format!("Failed to read {}", path.display()));
+    }
+
+    println!("{} {}", "Fetching:".cyan(), target.bright_blue());
+    let cid = parse_cid(target)?;
+    let blocks = tokio::time::timeout(timeout, fetcher.diff_blocks(cid.clone()))
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out after {:?} fetching {}", timeout, target))??;
+    Ok((cid, blocks))
+}
+
+/// Split the content `reader` yields into the chunks the repo stores it in,
+/// one at a time, returning its CID and the chunks' CIDs and sizes
+fn file_chunks(reader: impl Read) -> anyhow::Result<(ContentId, Vec<(ContentId, u64)>)> {
+    let chunker = Chunker::new(ChunkConfig::default().chunk_size);
+    let mut stream = chunker.chunk_reader(std::io::BufReader::new(reader));
+    let mut chunks = Vec::new();
+    for chunk in stream.by_ref() {
+        let chunk = chunk?;
+        chunks.push((chunk.cid, chunk.data.len() as u64));
+    }
+    Ok((stream.finish().cid, chunks))
+}
+
+/// Pack a local file or directory as `publish` would address it
+fn pack_path(path: &Path) -> anyhow::Result<Car> {
+    let published = if path.is_dir() {
+        hash_directory(path, HashAlgorithm::Sha2_256)?
+    } else {
+        let file =
+            fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let size = file.metadata()?.len();
+        let cid = ContentId::from_reader(file)?;
+        Published::file(cid, path.to_path_buf(), size)
+    };
+
+    let mut car = Car::new(vec![published.cid]);
+    for FileRange { cid, path, range } in published.files {
+        let mut file =
+            fs::File::open(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+        file.seek(SeekFrom::Start(range.start))?;
+        let mut data = vec![0u8; (range.end - range.start) as usize];
+        file.read_exact(&mut data)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        if !cid.verify(&data) {
+            anyhow::bail!("{} changed while exporting", path.display());
+        }
+        car.push(Block { cid, data });
+    }
+    for block in published.blocks {
+        car.push(block);
+    }
+
+    Ok(car)
+}
+
+async fn import_car(
+    file: &Path,
+    output: Option<PathBuf>,
+    serve: bool,
+    config: NetworkConfig,
+    peers: Vec<Multiaddr>,
+    repo: Option<&mut Repo>,
+) -> anyhow::Result<()> {
+    println!("{} {}", "Importing:".cyan(), file.display());
+
+    let reader =
+        fs::File::open(file).with_context(|| format!("Failed to open {}", file.display()))?;
+    let car = Car::read_from(std::io::BufReader::new(reader))
+        .with_context(|| format!("Failed to import {}", file.display()))?;
+    println!("  {} Verified {} blocks", "✓".green(), car.blocks().len());
+    for root in car.roots() {
+        println!("Root: {}", root.to_string().bright_blue());
+    }
+
+    if let Some(repo) = repo {
+        for block in car.blocks() {
+            repo.store
+                .blocks
+                .put(&block.cid, block.data.clone())
+                .await?;
+        }
+        for root in car.roots() {
+            repo.pins.add(root.clone(), PinMode::Recursive, None)?;
+        }
+        println!("  {} Added to repo and pinned", "✓".green());
+    }
+
+    if let Some(path) = output {
+        let root = car
+            .roots()
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("CAR file has no root to unpack"))?;
+        let files = unpack(&car, root, &path)?;
+        println!(
+            "  {} Wrote {} files to {}",
+            "✓".green(),
+            files,
+            path.display()
+        );
+    }
+
+    if serve {
+        let mut manager = start_node(config, peers).await?;
+        for block in car.into_blocks() {
+            manager.provide_content(block.cid, block.data)?;
+        }
+        println!("  {} Content announced", "✓".green());
+        serve_until_stopped(manager).await?;
+    }
+
+    Ok(())
+}
+
+async fn pin_add(
+    target: &str,
+    mode: PinMode,
+    name: Option<String>,
+    repo: &mut Repo,
+    mut fetcher: Fetcher,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    let cid = parse_cid(target)?;
+
+    // Everything the pin keeps must be in the repo first
+    tokio::time::timeout(timeout, async {
+        match mode {
+            PinMode::Direct => fetcher.fetch(&cid).await.map(drop),
+            PinMode::Recursive => {
+                let mut car = Car::new(vec![cid.clone()]);
+                fetcher.fetch_dag(cid.clone(), &mut car).await
+            }
+        }
+    })
+    .await
+    .map_err(|_| anyhow::anyhow!("Timed out after {:?} fetching {}", timeout, target))??;
+
+    repo.pins.add(cid.clone(), mode, name)?;
+    println!(
+        "Pinned {} ({})",
+        cid.to_string().bright_blue(),
+        mode.as_str()
+    );
+
+    Ok(())
+}
+
+async fn gc(repo: &Repo, dry_run: bool) -> anyhow::Result<()> {
+    let files = repo.store.files.lock().unwrap().clone();
+    let report =
+        collect_garbage_with_files(repo.store.blocks.as_ref(), &repo.pins, &files, dry_run).await?;
+
+    for cid in &report.removed {
+        println!(
+            "{} {}",
+            if dry_run { "would remove" } else { "removed" },
+            cid
+        );
+    }
+    println!(
+        "\n{} {} blocks ({} bytes), kept {} pinned blocks",
+        if dry_run { "Would remove" } else { "Removed" },
+        report.removed.len(),
+        report.reclaimed_bytes,
+        report.kept
+    );
 
     Ok(())
 }
 
-fn hash_content(path: PathBuf) -> anyhow::Result<()> {
+/// Write the file or directory tree at `cid` from `car` to `path`,
+/// returning the file count
+fn unpack(car: &Car, cid: &ContentId, path: &Path) -> anyhow::Result<usize> {
+    let block = |cid: &ContentId| {
+        car.get(cid)
+            .ok_or_else(|| anyhow::anyhow!("CAR file is missing block {}", cid))
+    };
+
+    let unpack_file = |cid: &ContentId, content: &[u8], path: &Path| {
+        let file = fs::File::create(path)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        let mut out = std::io::BufWriter::new(file);
+        write_file(cid, content, |cid| block(cid).map(<[u8]>::to_vec), &mut out)?;
+        out.flush()
+            .with_context(|| format!("Failed to write {}", path.display()))
+    };
+
+    let content = block(cid)?;
+    let Some(dir) = directory(cid, content) else {
+        unpack_file(cid, content, path)?;
+        return Ok(1);
+    };
+
+    let mut pending = vec![(dir, path.to_path_buf())];
+    let mut files = 0;
+    while let Some((dir, path)) = pending.pop() {
+        fs::create_dir_all(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+
+        for entry in dir.entries() {
+            let name = entry.name.as_deref().unwrap_or("");
+            if split_unixfs_path(name)? != [name] {
+                anyhow::bail!("Refusing to write directory entry {:?}", name);
+            }
+
+            let content = block(&entry.hash)?;
+            match directory(&entry.hash, content) {
+                Some(subdir) => pending.push((subdir, path.join(name))),
+                None => {
+                    unpack_file(&entry.hash, content, &path.join(name))?;
+                    files += 1;
+                }
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+fn hash_content(
+    path: PathBuf,
+    cid_version: u8,
+    base: Option<Base>,
+    algorithm: HashAlgorithm,
+) -> anyhow::Result<()> {
     println!("{} {}", "Hashing:".cyan(), path.display());
 
-    let content = fs::read(&path)?;
-    let cid = ContentId::new(&content);
+    let (cid, size) = if path.is_dir() {
+        let published = hash_directory(&path, algorithm)?;
+        (published.cid, published.size)
+    } else {
+        let file = fs::File::open(&path)?;
+        let size = file.metadata()?.len();
+        let cid = ContentId::from_reader_with(algorithm, file)?;
+        let cid = if cid_version == 1 && cid.version() == Version::V0 {
+            ContentId::from_digest_v1(Codec::Raw, cid.algorithm(), cid.hash())?
+        } else {
+            cid
+        };
+        (cid, size)
+    };
+    let cid = match base {
+        Some(base) => cid
+            .to_string_of_base(base.into())
+            .context("CIDv0 can only be written in base58btc; pass --cid-version 1")?,
+        None => cid.to_string(),
+    };
 
-    println!("\nCID: {}", cid.to_string().bright_blue());
-    println!("Size: {} bytes", content.len());
+    println!("\nCID: {}", cid.bright_blue());
+    println!("Size: {} bytes", size);
 
     Ok(())
 }
+
+/// Content ready to announce
+struct Published {
+    /// Root CID
+    cid: ContentId,
+    /// Total file bytes
+    size: u64,
+    /// Number of files
+    num_files: usize,
+    /// Files, or the leaves of a directory's files, served from disk
+    files: Vec<FileRange>,
+    /// DAG nodes, served from memory
+    blocks: Vec<Block>,
+}
+
+impl Published {
+    /// A single file addressed by its whole content
+    fn file(cid: ContentId, path: PathBuf, size: u64) -> Self {
+        Published {
+            cid: cid.clone(),
+            size,
+            num_files: 1,
+            files: vec![FileRange {
+                cid,
+                path,
+                range: 0..size,
+            }],
+            blocks: vec![],
+        }
+    }
+}
+
+/// Content that is some bytes of a file on disk
+struct FileRange {
+    cid: ContentId,
+    path: PathBuf,
+    range: Range<u64>,
+}
+
+impl FileRange {
+    /// Read the bytes
+    async fn open(&self) -> anyhow::Result<tokio::io::Take<tokio::fs::File>> {
+        let mut file = tokio::fs::File::open(&self.path)
+            .await
+            .with_context(|| format!("Failed to read {}", self.path.display()))?;
+        file.seek(SeekFrom::Start(self.range.start)).await?;
+        Ok(file.take(self.range.end - self.range.start))
+    }
+}
+
+/// Hash a directory tree the way `ipfs add -r --cid-version 1` does: each
+/// file as a balanced UnixFS DAG over raw leaves and each directory as a
+/// UnixFS node linking its entries by name. The leaves stay on disk.
+/// Symlinks are skipped.
+fn hash_directory(path: &Path, algorithm: HashAlgorithm) -> anyhow::Result<Published> {
+    let builder = UnixFsBuilder::new().with_cid_version(Version::V1);
+    let builder = builder
+        .clone()
+        .with_chunker(builder.chunker().clone().with_hash_algorithm(algorithm));
+
+    let mut published = Published {
+        cid: ContentId::new(&[]),
+        size: 0,
+        num_files: 0,
+        files: Vec::new(),
+        blocks: Vec::new(),
+    };
+    let dag = add_directory(&builder, path, &mut published)?;
+    published.cid = dag.root_cid().clone();
+    published.blocks = dag.into_blocks();
+
+    Ok(published)
+}
+
+fn add_directory(
+    builder: &UnixFsBuilder,
+    path: &Path,
+    published: &mut Published,
+) -> anyhow::Result<UnixFsDag> {
+    let mut entries = Vec::new();
+
+    for entry in fs::read_dir(path).with_context(|| format!("Failed to read {}", path.display()))? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| anyhow::anyhow!("File name is not UTF-8: {:?}", name))?;
+
+        let file_type = entry.file_type()?;
+        let dag = if file_type.is_dir() {
+            add_directory(builder, &path, published)?
+        } else if file_type.is_file() {
+            let file = fs::File::open(&path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            let (dag, leaves) = builder
+                .file_reader(std::io::BufReader::new(file))
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+
+            let mut offset = 0;
+            for (cid, size) in leaves {
+                published.files.push(FileRange {
+                    cid,
+                    path: path.clone(),
+                    range: offset..offset + size,
+                });
+                offset += size;
+            }
+            published.size += offset;
+            published.num_files += 1;
+            dag
+        } else {
+            continue;
+        };
+        entries.push((name, dag));
+    }
+
+    builder.directory(entries.iter().map(|(name, dag)| (name.as_str(), dag)))
+}
@@ -0,0 +1,468 @@
+use anyhow::{anyhow, Context, Result};
+use codio_content::{Chunker, MerkleDAG};
+use codio_content_id::{Codec, ContentId, ContentIdHasher};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use crate::Blockstore;
+
+/// Content stored one block per chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkedFile {
+    /// CID of the whole content
+    pub cid: ContentId,
+    /// Where reading starts: the root of the Merkle DAG over the chunks, or
+    /// the block itself for content of at most one chunk
+    pub root: ContentId,
+    /// Size in bytes
+    pub size: u64,
+}
+
+impl ChunkedFile {
+    /// Whether the content is a single block stored under its own hash, so
+    /// it needs no [`FileIndex`] entry.
+    pub fn is_single_block(&self) -> bool {
+        self.root.routing_key() == self.cid.routing_key()
+    }
+}
+
+/// A file as stored in the index file
+#[derive(Serialize, Deserialize)]
+struct FileRecord {
+    cid: String,
+    root: String,
+    size: u64,
+}
+
+/// Which DAG root holds each chunked file, optionally saved to a JSON file.
+///
+/// A file stored with [`put_chunked`] has no block under its own CID, so
+/// this is how reads and garbage collection get from the CID to its
+/// chunks. Files are keyed by hash like blocks and pins, and every change
+/// is written to the file before it returns.
+///
+/// # Example
+///
+/// ```rust
+/// use codio_blockstore::{put_chunked, write_chunked, FileIndex, MemoryBlockstore};
+/// use codio_content::Chunker;
+///
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let store = MemoryBlockstore::new();
+/// let mut files = FileIndex::new();
+///
+/// let data = vec![7u8; 10_000];
+/// let file = put_chunked(&store, &Chunker::new(1024), &data[..]).await?;
+/// files.add(file.clone())?;
+///
+/// let found = files.get(&file.cid).unwrap();
+/// let mut read = Vec::new();
+/// write_chunked(&store, found, &mut read).await?;
+/// assert_eq!(read, data);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FileIndex {
+    /// Files by the routing key of their CID
+    files: BTreeMap<Vec<u8>, ChunkedFile>,
+    /// File the index is saved to
+    path: Option<PathBuf>,
+}
+
+impl FileIndex {
+    /// Create an empty index held in memory
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the index saved at `path`, or start an empty one there
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let mut index = FileIndex::new();
+
+        match fs::read(&path) {
+            Ok(bytes) => {
+                let records: Vec<FileRecord> = serde_json::from_slice(&bytes)
+                    .with_context(|| format!("Invalid file index {}", path.display()))?;
+                for record in records {
+                    let parse = |s: &str| {
+                        ContentId::from_str(s).map_err(|e| anyhow!("Invalid file {}: {}", s, e))
+                    };
+                    index.insert(ChunkedFile {
+                        cid: parse(&record.cid)?,
+                        root: parse(&record.root)?,
+                        size: record.size,
+                    });
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+
+        index.path = Some(path);
+        Ok(index)
+    }
+
+    /// Get the file the index is saved to, if any
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Record where `file` is stored; single blocks are found by their own
+    /// hash and aren't recorded
+    pub fn add(&mut self, file: ChunkedFile) -> Result<()> {
+        if file.is_single_block() {
+            return Ok(());
+        }
+        self.insert(file);
+        self.save()
+    }
+
+    /// Forget the file with CID `cid`, returning its entry
+    pub fn remove(&mut self, cid: &ContentId) -> Result<Option<ChunkedFile>> {
+        let file = self.files.remove(&cid.routing_key());
+        if file.is_some() {
+            self.save()?;
+        }
+        Ok(file)
+    }
+
+    /// Get the entry for `cid`
+    pub fn get(&self, cid: &ContentId) -> Option<&ChunkedFile> {
+        self.files.get(&cid.routing_key())
+    }
+
+    /// Iterate over the entries
+    pub fn iter(&self) -> impl Iterator<Item = &ChunkedFile> {
+        self.files.values()
+    }
+
+    /// Get the number of entries
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Check if no file is recorded
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    fn insert(&mut self, file: ChunkedFile) {
+        self.files.insert(file.cid.routing_key(), file);
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let records: Vec<_> = self
+            .files
+            .values()
+            .map(|file| FileRecord {
+                cid: file.cid.to_string(),
+                root: file.root.to_string(),
+                size: file.size,
+            })
+            .collect();
+        crate::write_atomically(path, &serde_json::to_vec_pretty(&records)?)
+    }
+}
+
+/// Store content from `reader` one block per chunk, with the Merkle DAG
+/// nodes over them, as the chunk distributor splits it.
+///
+/// Reads the content once, holding one chunk and the open DAG nodes at a
+/// time. Content of at most one chunk is stored as a single block under
+/// its own hash. Record the result in a [`FileIndex`] to read it back by
+/// its CID.
+pub async fn put_chunked<R: AsyncRead + Unpin>(
+    store: &dyn Blockstore,
+    chunker: &Chunker,
+    reader: R,
+) -> Result<ChunkedFile> {
+    let mut stream = chunker.chunk_reader(reader).with_node_blocks();
+
+    // The first chunk waits until a second shows it isn't all there is
+    let mut first = None;
+    while let Some(chunk) = stream.next_chunk_async().await? {
+        if chunk.index == 0 {
+            first = Some(chunk);
+        } else {
+            if let Some(first) = first.take() {
+                store.put(&first.cid, first.data).await?;
+            }
+            store.put(&chunk.cid, chunk.data).await?;
+        }
+        for block in stream.take_node_blocks() {
+            store.put(&block.cid, block.data).await?;
+        }
+    }
+    let streamed = stream.finish();
+
+    let root = match first {
+        Some(chunk) if streamed.num_chunks == 1 => {
+            store.put(&streamed.cid, chunk.data).await?;
+            streamed.cid.clone()
+        }
+        _ if streamed.num_chunks == 0 => {
+            store.put(&streamed.cid, Vec::new()).await?;
+            streamed.cid.clone()
+        }
+        _ => {
+            for block in streamed.node_blocks {
+                store.put(&block.cid, block.data).await?;
+            }
+            streamed.root_cid
+        }
+    };
+
+    Ok(ChunkedFile {
+        cid: streamed.cid,
+        root,
+        size: streamed.size,
+    })
+}
+
+/// Write the content of `file` to `writer` from its stored chunks,
+/// returning its size.
+///
+/// # Errors
+///
+/// Fails if a chunk or DAG node is missing, or the chunks don't add up to
+/// `file.cid`; what was written by then should be discarded.
+pub async fn write_chunked<W: AsyncWrite + Unpin>(
+    store: &dyn Blockstore,
+    file: &ChunkedFile,
+    writer: &mut W,
+) -> Result<u64> {
+    let mut hasher = ContentIdHasher::with_algorithm(file.cid.algorithm());
+    let mut size = 0;
+    let mut pending = vec![file.root.clone()];
+
+    while let Some(cid) = pending.pop() {
+        let data = store
+            .get(&cid)
+            .await?
+            .ok_or_else(|| anyhow!("Block {} of {} is missing", cid, file.cid))?;
+
+        if cid.codec() == Codec::HashList && !file.is_single_block() {
+            let links = MerkleDAG::decode_node(&data)
+                .with_context(|| format!("Block {} of {} is not a DAG node", cid, file.cid))?;
+            pending.extend(links.into_iter().rev());
+            continue;
+        }
+
+        hasher.update(&data);
+        size += data.len() as u64;
+        writer.write_all(&data).await?;
+    }
+    writer.flush().await?;
+
+    if hasher.finalize().routing_key() != file.cid.routing_key() {
+        return Err(anyhow!("Stored chunks don't match {}", file.cid));
+    }
+    Ok(size)
+}
+
+/// The bytes of one chunk that a range of a file covers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkSlice {
+    /// Block holding the chunk
+    pub cid: ContentId,
+    /// Bytes of the chunk inside the range
+    pub range: Range<usize>,
+}
+
+/// Find the chunks of `file` that hold the bytes in `range`, in order, and
+/// which of their bytes those are.
+///
+/// Only DAG nodes are read; chunks before the range are measured with
+/// [`Blockstore::size`], so the caller reads just the chunks it gets back.
+/// The range is clamped to the file's size.
+///
+/// # Errors
+///
+/// Fails if a DAG node, or a chunk up to the end of the range, is missing.
+pub async fn chunk_slices(
+    store: &dyn Blockstore,
+    file: &ChunkedFile,
+    range: Range<u64>,
+) -> Result<Vec<ChunkSlice>> {
+    let end = range.end.min(file.size);
+    let mut slices = Vec::new();
+    // Where the next chunk starts in the file
+    let mut offset = 0;
+    let mut pending = vec![file.root.clone()];
+
+    while offset < end {
+        let Some(cid) = pending.pop() else {
+            break;
+        };
+        let missing = || anyhow!("Block {} of {} is missing", cid, file.cid);
+
+        if cid.codec() == Codec::HashList && !file.is_single_block() {
+            let data = store.get(&cid).await?.ok_or_else(missing)?;
+            let links = MerkleDAG::decode_node(&data)
+                .with_context(|| format!("Block {} of {} is not a DAG node", cid, file.cid))?;
+            pending.extend(links.into_iter().rev());
+            continue;
+        }
+
+        let size = store.size(&cid).await?.ok_or_else(missing)?;
+        if offset + size > range.start {
+            let start = range.start.saturating_sub(offset) as usize;
+            let stop = (end - offset).min(size) as usize;
+            slices.push(ChunkSlice {
+                cid,
+                range: start..stop,
+            });
+        }
+        offset += size;
+    }
+
+    Ok(slices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryBlockstore;
+    use codio_content::MerkleDAG;
+
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545f4914f6cdd1du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_chunks_match_distributor() {
+        let store = MemoryBlockstore::new();
+        let data = noise(300 * 1024);
+        let chunker = Chunker::new(1024);
+
+        let file = put_chunked(&store, &chunker, &data[..]).await.unwrap();
+        let chunks = chunker.chunk(&data);
+        assert_eq!(file.cid, ContentId::new(&data));
+        assert_eq!(&file.root, MerkleDAG::from_chunks(&chunks).root_cid());
+        assert_eq!(file.size, data.len() as u64);
+        assert!(!file.is_single_block());
+
+        // One block per chunk, none for the whole file
+        for chunk in &chunks {
+            assert_eq!(store.get(&chunk.cid).await.unwrap().unwrap(), chunk.data);
+        }
+        assert!(!store.has(&file.cid).await.unwrap());
+
+        let mut read = Vec::new();
+        assert_eq!(
+            write_chunked(&store, &file, &mut read).await.unwrap(),
+            data.len() as u64
+        );
+        assert_eq!(read, data);
+
+        store.delete(&chunks[7].cid).await.unwrap();
+        assert!(write_chunked(&store, &file, &mut Vec::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_chunk_slices() {
+        let store = MemoryBlockstore::new();
+        let data = noise(300 * 1024);
+        let chunker = Chunker::new(1024);
+        let file = put_chunked(&store, &chunker, &data[..]).await.unwrap();
+        let chunks = chunker.chunk(&data);
+
+        let read = |slices: Vec<ChunkSlice>| async {
+            let mut read = Vec::new();
+            for slice in slices {
+                let chunk = store.get(&slice.cid).await.unwrap().unwrap();
+                read.extend_from_slice(&chunk[slice.range]);
+            }
+            read
+        };
+
+        for range in [0..1, 1000..5000, 2048..3072, 300_000..400_000, 0..u64::MAX] {
+            let slices = chunk_slices(&store, &file, range.clone()).await.unwrap();
+            let end = range.end.min(data.len() as u64) as usize;
+            assert_eq!(read(slices).await, &data[range.start as usize..end]);
+        }
+
+        // Only the covering chunks come back
+        let slices = chunk_slices(&store, &file, 1000..3000).await.unwrap();
+        let cids: Vec<_> = slices.iter().map(|slice| &slice.cid).collect();
+        assert_eq!(cids, [&chunks[0].cid, &chunks[1].cid, &chunks[2].cid]);
+        assert_eq!(slices[0].range, 1000..1024);
+        assert_eq!(slices[2].range, 0..952);
+        assert!(chunk_slices(&store, &file, 500_000..600_000)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Single blocks are sliced too
+        let small = put_chunked(&store, &chunker, &b"small"[..]).await.unwrap();
+        let slices = chunk_slices(&store, &small, 1..3).await.unwrap();
+        assert_eq!(read(slices).await, b"ma");
+    }
+
+    #[tokio::test]
+    async fn test_small_content_is_one_block() {
+        let store = MemoryBlockstore::new();
+        let chunker = Chunker::new(1024);
+
+        for data in [vec![], b"small".to_vec(), vec![1u8; 1024]] {
+            let file = put_chunked(&store, &chunker, &data[..]).await.unwrap();
+            assert!(file.is_single_block());
+            assert_eq!(store.get(&file.cid).await.unwrap(), Some(data.clone()));
+
+            let mut read = Vec::new();
+            write_chunked(&store, &file, &mut read).await.unwrap();
+            assert_eq!(read, data);
+
+            let mut files = FileIndex::new();
+            files.add(file).unwrap();
+            assert!(files.is_empty());
+        }
+        assert_eq!(store.cids().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_index_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("files.json");
+        let store = MemoryBlockstore::new();
+        let data = noise(5000);
+        let file = put_chunked(&store, &Chunker::new(1024), &data[..])
+            .await
+            .unwrap();
+
+        let mut files = FileIndex::open(&path).unwrap();
+        files.add(file.clone()).unwrap();
+
+        let mut files = FileIndex::open(&path).unwrap();
+        assert_eq!(files.get(&file.cid.to_v1()), Some(&file));
+        files.remove(&file.cid).unwrap();
+        assert!(FileIndex::open(&path).unwrap().is_empty());
+
+        fs::write(
+            &path,
+            "[{\"cid\": \"nope\", \"root\": \"nope\", \"size\": 0}]",
+        )
+        .unwrap();
+        assert!(FileIndex::open(&path).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use codio_content_id::ContentId;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::Blockstore;
+
+/// Extension of block files
+const BLOCK_EXTENSION: &str = "data";
+
+/// Extension of partially written blocks
+const TEMP_EXTENSION: &str = "tmp";
+
+/// Distinguishes temporary files written concurrently by this process
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// When [`FsBlockstore`] flushes writes to disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// Sync each block and its directory before a write returns, so stored
+    /// blocks survive power loss
+    #[default]
+    Always,
+    /// Leave flushing to the OS: faster, but blocks written just before a
+    /// crash may be lost (never corrupted, as writes are still atomic)
+    Never,
+}
+
+/// A blockstore keeping one file per block on disk.
+///
+/// Blocks live at `<root>/<shard>/<key>.data`, where `key` is the hex routing
+/// key and `shard` its next-to-last two characters (as in go-ipfs' flatfs),
+/// so no directory grows past a few thousand entries. Each block is written
+/// to a temporary file in its shard and renamed into place, so readers never
+/// see a partial block. Temporary files left by a crash are ignored.
+///
+/// # Example
+///
+/// ```rust
+/// use codio_blockstore::{Blockstore, FsBlockstore, FsyncPolicy};
+/// use codio_content_id::ContentId;
+///
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let dir = tempfile::tempdir()?;
+/// let data = b"persisted".to_vec();
+/// let cid = ContentId::new(&data);
+///
+/// let store = FsBlockstore::open(dir.path())?.with_fsync(FsyncPolicy::Never);
+/// store.put(&cid, data.clone()).await?;
+///
+/// // Reopening finds the block again
+/// let store = FsBlockstore::open(dir.path())?;
+/// assert_eq!(store.get(&cid).await?, Some(data));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FsBlockstore {
+    root: PathBuf,
+    fsync: FsyncPolicy,
+}
+
+impl FsBlockstore {
+    /// Open the blockstore at `root`, creating the directory if needed
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        fs::create_dir_all(&root)
+            .with_context(|| format!("Failed to create blockstore {}", root.display()))?;
+
+        Ok(Self {
+            root,
+            fsync: FsyncPolicy::default(),
+        })
+    }
+
+    /// Set when writes are flushed to disk
+    pub fn with_fsync(mut self, fsync: FsyncPolicy) -> Self {
+        self.fsync = fsync;
+        self
+    }
+
+    /// Get the blockstore directory
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Get the fsync policy
+    pub fn fsync(&self) -> FsyncPolicy {
+        self.fsync
+    }
+
+    /// Path of the file holding the block for `cid`, whether or not it's
+    /// stored
+    ///
+    /// Useful for serving blocks straight from disk.
+    pub fn block_path(&self, cid: &ContentId) -> PathBuf {
+        let key = hex::encode(cid.routing_key());
+        let shard = &key[key.len() - 3..key.len() - 1];
+        self.root
+            .join(shard)
+            .join(format!("{}.{}", key, BLOCK_EXTENSION))
+    }
+
+    fn write_block(&self, path: &Path, data: &[u8]) -> Result<()> {
+        if path.exists() {
+            // Content-addressed: the stored block already has these bytes
+            return Ok(());
+        }
+
+        let dir = path.parent().expect("block paths have a shard");
+        fs::create_dir_all(dir)?;
+
+        let temp = dir.join(format!(
+            ".{}.{}.{}",
+            std::process::id(),
+            TEMP_COUNTER.fetch_add(1, Ordering::Relaxed),
+            TEMP_EXTENSION
+        ));
+        let result = (|| {
+            let mut file = fs::File::create(&temp)?;
+            file.write_all(data)?;
+            if self.fsync == FsyncPolicy::Always {
+                file.sync_all()?;
+            }
+            fs::rename(&temp, path)
+        })();
+        if let Err(e) = result {
+            let _ = fs::remove_file(&temp);
+            return Err(e.into());
+        }
+
+        self.sync_dir(dir)
+    }
+
+    /// Make renames and removals in `dir` durable, per the fsync policy
+    fn sync_dir(&self, dir: &Path) -> Result<()> {
+        #[cfg(unix)]
+        if self.fsync == FsyncPolicy::Always {
+            fs::File::open(dir)?.sync_all()?;
+        }
+        #[cfg(not(unix))]
+        let _ = dir;
+        Ok(())
+    }
+
+    fn list(&self) -> Result<Vec<ContentId>> {
+        let mut cids = Vec::new();
+
+        for shard in fs::read_dir(&self.root)? {
+            let shard = shard?;
+            if !shard.file_type()?.is_dir() {
+                continue;
+            }
+
+            for entry in fs::read_dir(shard.path())? {
+                let name = entry?.file_name();
+                let Some(key) = name
+                    .to_str()
+                    .and_then(|name| name.strip_suffix(BLOCK_EXTENSION))
+                    .and_then(|name| name.strip_suffix('.'))
+                else {
+                    continue;
+                };
+
+                let key = hex::decode(key)
+                    .map_err(|_| anyhow!("Unexpected file in blockstore: {:?}", name))?;
+                cids.push(ContentId::from_routing_key(&key)?);
+            }
+        }
+
+        Ok(cids)
+    }
+}
+
+/// Run blocking filesystem work off the async runtime
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await?
+}
+
+/// `Ok(None)` for files that don't exist
+fn not_found_as_none<T>(result: std::io::Result<T>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[async_trait]
+impl Blockstore for FsBlockstore {
+    async fn get(&self, cid: &ContentId) -> Result<Option<Vec<u8>>> {
+        let path = self.block_path(cid);
+        blocking(move || not_found_as_none(fs::read(path))).await
+    }
+
+    async fn put(&self, cid: &ContentId, data: Vec<u8>) -> Result<()> {
+        let store = self.clone();
+        let path = self.block_path(cid);
+        blocking(move || store.write_block(&path, &data))
+            .await
+            .with_context(|| format!("Failed to store block {}", cid))
+    }
+
+    async fn delete(&self, cid: &ContentId) -> Result<bool> {
+        let store = self.clone();
+        let path = self.block_path(cid);
+        blocking(move || {
+            let removed = not_found_as_none(fs::remove_file(&path))?.is_some();
+            if removed {
+                store.sync_dir(path.parent().expect("block paths have a shard"))?;
+            }
+            Ok(removed)
+        })
+        .await
+    }
+
+    async fn size(&self, cid: &ContentId) -> Result<Option<u64>> {
+        let path = self.block_path(cid);
+        blocking(move || Ok(not_found_as_none(fs::metadata(path))?.map(|m| m.len()))).await
+    }
+
+    async fn cids(&self) -> Result<Vec<ContentId>> {
+        let store = self.clone();
+        blocking(move || store.list()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_path_sharding() {
+        let store = FsBlockstore {
+            root: PathBuf::from("/blocks"),
+            fsync: FsyncPolicy::Never,
+        };
+        let cid = ContentId::new(b"sharded");
+        let key = cid.hash_hex();
+
+        let expected = format!("/blocks/{}/{}.data", &key[61..63], key);
+        assert_eq!(store.block_path(&cid), PathBuf::from(expected));
+        assert_eq!(store.block_path(&cid.to_v1()), store.block_path(&cid));
+    }
+
+    #[tokio::test]
+    async fn test_put_get_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FsBlockstore::open(dir.path()).unwrap();
+        let cid = ContentId::new(b"block");
+
+        assert_eq!(store.get(&cid).await.unwrap(), None);
+        assert_eq!(store.size(&cid).await.unwrap(), None);
+
+        store.put(&cid, b"block".to_vec()).await.unwrap();
+        store.put(&cid, b"block".to_vec()).await.unwrap();
+        assert_eq!(store.get(&cid).await.unwrap(), Some(b"block".to_vec()));
+        assert_eq!(store.size(&cid).await.unwrap(), Some(5));
+        assert_eq!(store.cids().await.unwrap(), std::slice::from_ref(&cid));
+
+        assert!(store.delete(&cid).await.unwrap());
+        assert!(!store.delete(&cid).await.unwrap());
+        assert!(store.cids().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ignores_partial_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FsBlockstore::open(dir.path()).unwrap();
+        let cid = ContentId::new(b"block");
+        store.put(&cid, b"block".to_vec()).await.unwrap();
+
+        // A temporary file left by a crash mid-write
+        let shard = store.block_path(&cid).parent().unwrap().to_path_buf();
+        fs::write(shard.join(".1.0.tmp"), b"blo").unwrap();
+
+        assert_eq!(store.cids().await.unwrap(), [cid]);
+    }
+}
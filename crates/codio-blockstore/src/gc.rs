@@ -0,0 +1,349 @@
+use anyhow::Result;
+use codio_content::{MerkleDAG, PbNode};
+use codio_content_id::{Codec, ContentId, Version};
+use std::collections::HashSet;
+
+use crate::{Blockstore, FileIndex, PinMode, PinSet};
+
+/// What a garbage collection removed, or would remove in a dry run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// Stored blocks reachable from a pin
+    pub kept: usize,
+    /// Unreachable blocks
+    pub removed: Vec<ContentId>,
+    /// Total size of the unreachable blocks
+    pub reclaimed_bytes: u64,
+    /// Whether the blocks were left in place
+    pub dry_run: bool,
+}
+
+/// Remove every block no pin reaches: mark what the pins reach, then sweep
+/// the rest.
+///
+/// Recursive pins keep every block reachable through dag-pb links (UnixFS
+/// files and directories) and Merkle DAG nodes; direct pins keep just their
+/// block. A dry run reports what would be removed without deleting it.
+///
+/// Files stored with [`put_chunked`](crate::put_chunked) have no block of
+/// their own; use [`collect_garbage_with_files`] to keep their chunks.
+///
+/// # Errors
+///
+/// Fails without removing anything if a pin reaches a dag-pb CIDv1 block
+/// that doesn't decode, since the blocks below it can't be told apart from
+/// garbage.
+///
+/// Blocks stored while a collection runs may be swept; callers that store
+/// and pin content concurrently should hold the pin set locked for writing
+/// while they do, and for reading while collecting.
+///
+/// # Example
+///
+/// ```rust
+/// use codio_blockstore::{collect_garbage, Blockstore, MemoryBlockstore, PinMode, PinSet};
+/// use codio_content_id::ContentId;
+///
+/// # #[tokio::main]
+/// # async fn main() -> anyhow::Result<()> {
+/// let store = MemoryBlockstore::new();
+/// let kept = ContentId::new(b"kept");
+/// store.put(&kept, b"kept".to_vec()).await?;
+/// store.put(&ContentId::new(b"garbage"), b"garbage".to_vec()).await?;
+///
+/// let mut pins = PinSet::new();
+/// pins.add(kept.clone(), PinMode::Recursive, None)?;
+///
+/// let report = collect_garbage(&store, &pins, true).await?;
+/// assert_eq!(report.reclaimed_bytes, 7);
+/// assert!(store.has(&ContentId::new(b"garbage")).await?);
+///
+/// collect_garbage(&store, &pins, false).await?;
+/// assert!(!store.has(&ContentId::new(b"garbage")).await?);
+/// assert!(store.has(&kept).await?);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn collect_garbage(
+    store: &dyn Blockstore,
+    pins: &PinSet,
+    dry_run: bool,
+) -> Result<GcReport> {
+    collect_garbage_with_files(store, pins, &FileIndex::new(), dry_run).await
+}
+
+/// [`collect_garbage`], following each chunked file in `files` from its CID
+/// to its chunks.
+///
+/// A pinned file, or one a pinned directory links to, keeps its whole DAG
+/// even under a direct pin: its chunks are the file's only copy.
+pub async fn collect_garbage_with_files(
+    store: &dyn Blockstore,
+    pins: &PinSet,
+    files: &FileIndex,
+    dry_run: bool,
+) -> Result<GcReport> {
+    let roots = pins
+        .iter()
+        .map(|pin| (pin.cid.clone(), pin.mode == PinMode::Recursive));
+    let marked = walk(store, files, roots).await?.reached;
+
+    let mut report = GcReport {
+        dry_run,
+        ..Default::default()
+    };
+    for cid in store.cids().await? {
+        if marked.contains(&cid.routing_key()) {
+            report.kept += 1;
+            continue;
+        }
+
+        let Some(size) = store.size(&cid).await? else {
+            continue;
+        };
+        if !dry_run && !store.delete(&cid).await? {
+            continue;
+        }
+        report.reclaimed_bytes += size;
+        report.removed.push(cid);
+    }
+
+    Ok(report)
+}
+
+/// Blocks reachable from `cid` that the store doesn't have
+///
+/// Empty when the whole DAG is stored, so it can be pinned recursively
+/// knowing garbage collection will keep all of it.
+pub async fn missing_blocks(store: &dyn Blockstore, cid: &ContentId) -> Result<Vec<ContentId>> {
+    missing_blocks_with_files(store, &FileIndex::new(), cid).await
+}
+
+/// [`missing_blocks`], following each chunked file in `files` from its CID
+/// to its chunks.
+pub async fn missing_blocks_with_files(
+    store: &dyn Blockstore,
+    files: &FileIndex,
+    cid: &ContentId,
+) -> Result<Vec<ContentId>> {
+    Ok(walk(store, files, [(cid.clone(), true)]).await?.missing)
+}
+
+/// Result of following links from a set of roots
+struct Walk {
+    /// Routing keys of the stored blocks reached
+    reached: HashSet<Vec<u8>>,
+    /// Blocks linked to but not stored
+    missing: Vec<ContentId>,
+}
+
+/// Follow links from `roots`, given as `(cid, recursive)`, and from chunked
+/// files to their DAGs
+async fn walk(
+    store: &dyn Blockstore,
+    files: &FileIndex,
+    roots: impl IntoIterator<Item = (ContentId, bool)>,
+) -> Result<Walk> {
+    let mut walk = Walk {
+        reached: HashSet::new(),
+        missing: Vec::new(),
+    };
+    let mut seen = HashSet::new();
+    let mut pending: Vec<_> = roots.into_iter().collect();
+
+    while let Some((cid, recursive)) = pending.pop() {
+        // A block reached recursively after a direct pin still needs its links
+        if !seen.insert((cid.routing_key(), recursive)) {
+            continue;
+        }
+
+        if let Some(file) = files.get(&cid) {
+            pending.push((file.root.clone(), true));
+            continue;
+        }
+
+        if !recursive {
+            if store.has(&cid).await? {
+                walk.reached.insert(cid.routing_key());
+            } else {
+                walk.missing.push(cid);
+            }
+            continue;
+        }
+
+        let Some(data) = store.get(&cid).await? else {
+            walk.missing.push(cid);
+            continue;
+        };
+        walk.reached.insert(cid.routing_key());
+        pending.extend(links(&cid, &data)?.into_iter().map(|link| (link, true)));
+    }
+
+    Ok(walk)
+}
+
+/// CIDs a block links to: dag-pb links or Merkle DAG node links, nothing
+/// for other codecs
+///
+/// CIDv0 also addresses flat content, so a v0 block that doesn't decode is
+/// taken to have no links; a dag-pb CIDv1 that doesn't decode is an error.
+fn links(cid: &ContentId, data: &[u8]) -> Result<Vec<ContentId>> {
+    if cid.codec() == Codec::HashList {
+        return MerkleDAG::decode_node(data)
+            .map_err(|e| e.context(format!("Block {} is not a DAG node", cid)));
+    }
+    if cid.codec() != Codec::DagPb {
+        return Ok(Vec::new());
+    }
+
+    match PbNode::decode(data) {
+        Ok(node) => Ok(node.links.into_iter().map(|link| link.hash).collect()),
+        Err(_) if cid.version() == Version::V0 => Ok(Vec::new()),
+        Err(e) => Err(e.context(format!("Block {} is not a dag-pb node", cid))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{put_chunked, write_chunked, MemoryBlockstore};
+    use codio_content::{Chunker, UnixFsBuilder, UnixFsDag, Version};
+
+    async fn put_dag(store: &MemoryBlockstore, dag: &UnixFsDag) {
+        for block in dag.blocks() {
+            store.put(&block.cid, block.data.clone()).await.unwrap();
+        }
+    }
+
+    /// A directory of two multi-block files (v1, raw leaves)
+    fn site() -> (UnixFsDag, UnixFsDag, UnixFsDag) {
+        let builder = UnixFsBuilder::new().with_cid_version(Version::V1);
+        let a = builder.file(&vec![1u8; 600 * 1024]).unwrap();
+        let b = builder.file(&vec![2u8; 300 * 1024]).unwrap();
+        let dir = builder.directory([("a", &a), ("b", &b)]).unwrap();
+        (dir, a, b)
+    }
+
+    #[tokio::test]
+    async fn test_recursive_pin_keeps_dag() {
+        let store = MemoryBlockstore::new();
+        let (dir, a, _) = site();
+        put_dag(&store, &dir).await;
+        store
+            .put(&ContentId::new(b"junk"), b"junk".to_vec())
+            .await
+            .unwrap();
+
+        let mut pins = PinSet::new();
+        pins.add(dir.root_cid().clone(), PinMode::Recursive, None)
+            .unwrap();
+
+        let report = collect_garbage(&store, &pins, false).await.unwrap();
+        assert_eq!(report.kept, dir.blocks().len());
+        assert_eq!(report.removed, [ContentId::new(b"junk")]);
+        assert_eq!(report.reclaimed_bytes, 4);
+
+        // Unpinning the directory frees everything; pinning a file keeps it
+        pins.remove(dir.root_cid()).unwrap();
+        pins.add(a.root_cid().clone(), PinMode::Recursive, None)
+            .unwrap();
+        let report = collect_garbage(&store, &pins, false).await.unwrap();
+        assert_eq!(report.kept, a.blocks().len());
+        assert_eq!(store.cids().await.unwrap().len(), a.blocks().len());
+    }
+
+    #[tokio::test]
+    async fn test_direct_pin_and_dry_run() {
+        let store = MemoryBlockstore::new();
+        let (dir, _, _) = site();
+        put_dag(&store, &dir).await;
+
+        let mut pins = PinSet::new();
+        pins.add(dir.root_cid().clone(), PinMode::Direct, None)
+            .unwrap();
+
+        let total: u64 = dir.blocks().iter().map(|b| b.data.len() as u64).sum();
+        let root_size = dir.blocks().last().unwrap().data.len() as u64;
+
+        let report = collect_garbage(&store, &pins, true).await.unwrap();
+        assert!(report.dry_run);
+        assert_eq!(report.kept, 1);
+        assert_eq!(report.reclaimed_bytes, total - root_size);
+        assert_eq!(store.cids().await.unwrap().len(), dir.blocks().len());
+    }
+
+    #[tokio::test]
+    async fn test_undecodable_node_fails() {
+        let store = MemoryBlockstore::new();
+        let bad = ContentId::new_v1(Codec::DagPb, b"\xff\xff");
+        store.put(&bad, b"\xff\xff".to_vec()).await.unwrap();
+        store
+            .put(&ContentId::new(b"junk"), b"junk".to_vec())
+            .await
+            .unwrap();
+
+        let mut pins = PinSet::new();
+        pins.add(bad, PinMode::Recursive, None).unwrap();
+
+        assert!(collect_garbage(&store, &pins, false).await.is_err());
+        assert_eq!(store.cids().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_chunked_files_keep_their_chunks() {
+        let store = MemoryBlockstore::new();
+        let chunker = Chunker::new(1024);
+        let data = vec![5u8; 600 * 1024];
+        let file = put_chunked(&store, &chunker, &data[..]).await.unwrap();
+        let stored = store.cids().await.unwrap().len();
+        store
+            .put(&ContentId::new(b"junk"), b"junk".to_vec())
+            .await
+            .unwrap();
+
+        let mut files = FileIndex::new();
+        files.add(file.clone()).unwrap();
+        let mut pins = PinSet::new();
+        pins.add(file.cid.clone(), PinMode::Direct, None).unwrap();
+
+        let report = collect_garbage_with_files(&store, &pins, &files, false)
+            .await
+            .unwrap();
+        assert_eq!(report.kept, stored);
+        assert_eq!(report.removed, [ContentId::new(b"junk")]);
+
+        let mut read = Vec::new();
+        write_chunked(&store, &file, &mut read).await.unwrap();
+        assert_eq!(read, data);
+
+        assert!(missing_blocks_with_files(&store, &files, &file.cid)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Without the index, nothing reaches the chunks
+        let report = collect_garbage(&store, &pins, true).await.unwrap();
+        assert_eq!(report.removed.len(), stored);
+        assert_eq!(
+            missing_blocks(&store, &file.cid).await.unwrap(),
+            std::slice::from_ref(&file.cid)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_blocks() {
+        let store = MemoryBlockstore::new();
+        let (dir, _, b) = site();
+        put_dag(&store, &dir).await;
+        assert!(missing_blocks(&store, dir.root_cid())
+            .await
+            .unwrap()
+            .is_empty());
+
+        store.delete(b.root_cid()).await.unwrap();
+        assert_eq!(
+            missing_blocks(&store, dir.root_cid()).await.unwrap(),
+            [b.root_cid().clone()]
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! # Codio Blockstore
+//!
+//! Block storage shared by the gateway, the chunk distributor and the CLI.
+//!
+//! A [`Blockstore`] maps CIDs to block bytes. Two implementations are
+//! provided:
+//!
+//! - [`MemoryBlockstore`]: a hash map, for tests and short-lived processes
+//! - [`FsBlockstore`]: one file per block in sharded directories, with
+//!   atomic writes and a configurable [`FsyncPolicy`], so content survives
+//!   restarts and isn't limited by RAM
+//!
+//! A [`PinSet`] records which CIDs must be kept, and [`collect_garbage`]
+//! removes every block no pin reaches.
+//!
+//! Large files are stored one block per chunk with [`put_chunked`], under
+//! the same Merkle DAG the chunk distributor builds, and a [`FileIndex`]
+//! maps each file's CID to that DAG's root so it can be read back with
+//! [`write_chunked`] and kept by [`collect_garbage_with_files`].
+//! [`chunk_slices`] finds the chunks a byte range covers, for reading part
+//! of a file without the rest.
+//!
+//! Blocks are keyed by their CID's routing key (the hash), so every version
+//! and encoding of a CID names the same block, as in the DHT.
+//!
+//! ## Example Usage
+//!
+//! ```rust
+//! use codio_blockstore::{Blockstore, MemoryBlockstore};
+//! use codio_content_id::ContentId;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> anyhow::Result<()> {
+//! let store = MemoryBlockstore::new();
+//!
+//! let data = b"Hello, Codio!".to_vec();
+//! let cid = ContentId::new(&data);
+//! store.put(&cid, data.clone()).await?;
+//!
+//! assert!(store.has(&cid).await?);
+//! assert_eq!(store.get(&cid).await?, Some(data.clone()));
+//!
+//! // Any version of the CID finds the block
+//! assert_eq!(store.get(&cid.to_v1()).await?, Some(data));
+//! # Ok(())
+//! # }
+//! ```
+
+mod files;
+mod fs;
+mod gc;
+mod memory;
+mod pin;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use codio_content_id::ContentId;
+use std::io::Write;
+use std::path::Path;
+
+pub use files::{chunk_slices, put_chunked, write_chunked, ChunkSlice, ChunkedFile, FileIndex};
+pub use fs::{FsBlockstore, FsyncPolicy};
+pub use gc::{
+    collect_garbage, collect_garbage_with_files, missing_blocks, missing_blocks_with_files,
+    GcReport,
+};
+pub use memory::MemoryBlockstore;
+pub use pin::{Pin, PinMode, PinSet};
+
+/// Storage for content-addressed blocks.
+///
+/// Implementations are shared between tasks behind an `Arc<dyn Blockstore>`.
+/// Blocks are trusted to match their CIDs on [`put`](Blockstore::put);
+/// callers verify content received from peers before storing it.
+#[async_trait]
+pub trait Blockstore: Send + Sync {
+    /// Returns the block for `cid`, if stored.
+    async fn get(&self, cid: &ContentId) -> Result<Option<Vec<u8>>>;
+
+    /// Stores a block, replacing any block with the same hash.
+    async fn put(&self, cid: &ContentId, data: Vec<u8>) -> Result<()>;
+
+    /// Returns whether a block for `cid` is stored.
+    async fn has(&self, cid: &ContentId) -> Result<bool> {
+        Ok(self.size(cid).await?.is_some())
+    }
+
+    /// Removes the block for `cid`, returning whether it was stored.
+    async fn delete(&self, cid: &ContentId) -> Result<bool>;
+
+    /// Returns the size in bytes of the block for `cid`, if stored.
+    async fn size(&self, cid: &ContentId) -> Result<Option<u64>>;
+
+    /// Returns the CIDs of all stored blocks.
+    ///
+    /// Keys don't record version or codec, so sha2-256 blocks are listed as
+    /// CIDv0 and others as raw CIDv1 (see [`ContentId::from_routing_key`]).
+    async fn cids(&self) -> Result<Vec<ContentId>>;
+}
+
+/// Replace the file at `path` with `bytes` through a temporary file next to
+/// it, so readers see the old or new contents but never a torn write
+fn write_atomically(path: &Path, bytes: &[u8]) -> Result<()> {
+    let temp = path.with_extension("tmp");
+    let mut file = std::fs::File::create(&temp)
+        .with_context(|| format!("Failed to write {}", temp.display()))?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    std::fs::rename(&temp, path).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use codio_content_id::ContentId;
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+use crate::Blockstore;
+
+/// A blockstore held in memory; everything is lost when it's dropped.
+#[derive(Debug, Default)]
+pub struct MemoryBlockstore {
+    blocks: RwLock<HashMap<Vec<u8>, Vec<u8>>>,
+}
+
+impl MemoryBlockstore {
+    /// Create an empty blockstore
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Blockstore for MemoryBlockstore {
+    async fn get(&self, cid: &ContentId) -> Result<Option<Vec<u8>>> {
+        Ok(self.blocks.read().await.get(&cid.routing_key()).cloned())
+    }
+
+    async fn put(&self, cid: &ContentId, data: Vec<u8>) -> Result<()> {
+        self.blocks.write().await.insert(cid.routing_key(), data);
+        Ok(())
+    }
+
+    async fn delete(&self, cid: &ContentId) -> Result<bool> {
+        Ok(self
+            .blocks
+            .write()
+            .await
+            .remove(&cid.routing_key())
+            .is_some())
+    }
+
+    async fn size(&self, cid: &ContentId) -> Result<Option<u64>> {
+        Ok(self
+            .blocks
+            .read()
+            .await
+            .get(&cid.routing_key())
+            .map(|data| data.len() as u64))
+    }
+
+    async fn cids(&self) -> Result<Vec<ContentId>> {
+        self.blocks
+            .read()
+            .await
+            .keys()
+            .map(|key| Ok(ContentId::from_routing_key(key)?))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codio_content_id::{Codec, HashAlgorithm};
+
+    #[tokio::test]
+    async fn test_put_get_delete() {
+        let store = MemoryBlockstore::new();
+        let cid = ContentId::new(b"block");
+
+        assert_eq!(store.get(&cid).await.unwrap(), None);
+        store.put(&cid, b"block".to_vec()).await.unwrap();
+        assert_eq!(store.get(&cid).await.unwrap(), Some(b"block".to_vec()));
+        assert_eq!(store.size(&cid).await.unwrap(), Some(5));
+
+        assert!(store.delete(&cid).await.unwrap());
+        assert!(!store.delete(&cid).await.unwrap());
+        assert!(!store.has(&cid).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_cids() {
+        let store = MemoryBlockstore::new();
+        let sha = ContentId::new(b"a");
+        let blake = ContentId::new_v1_with(Codec::Raw, HashAlgorithm::Blake3, b"b");
+        store.put(&sha, b"a".to_vec()).await.unwrap();
+        store.put(&blake, b"b".to_vec()).await.unwrap();
+
+        let mut cids = store.cids().await.unwrap();
+        cids.sort_by_key(|cid| cid.to_string());
+        let mut expected = vec![sha, blake];
+        expected.sort_by_key(|cid| cid.to_string());
+        assert_eq!(cids, expected);
+    }
+}
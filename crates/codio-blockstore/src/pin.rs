@@ -0,0 +1,223 @@
+use anyhow::{anyhow, Context, Result};
+use codio_content_id::ContentId;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// How much of a DAG a pin keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PinMode {
+    /// Only the pinned block
+    Direct,
+    /// The pinned block and every block reachable through its links
+    Recursive,
+}
+
+impl PinMode {
+    /// Name used in the pin file and on the command line
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PinMode::Direct => "direct",
+            PinMode::Recursive => "recursive",
+        }
+    }
+}
+
+/// A CID that garbage collection must keep.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pin {
+    /// Pinned CID
+    pub cid: ContentId,
+    /// What the pin keeps
+    pub mode: PinMode,
+    /// Optional label, e.g. a release name
+    pub name: Option<String>,
+}
+
+/// A pin as stored in the pin file
+#[derive(Serialize, Deserialize)]
+struct PinRecord {
+    cid: String,
+    mode: PinMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+/// The set of pinned CIDs, optionally saved to a JSON file.
+///
+/// Pins are keyed by hash like blocks, so pinning any version of a CID pins
+/// the same content. Every change is written to the file (atomically, via a
+/// temporary file) before it returns.
+///
+/// # Example
+///
+/// ```rust
+/// use codio_blockstore::{PinMode, PinSet};
+/// use codio_content_id::ContentId;
+///
+/// let mut pins = PinSet::new();
+/// let cid = ContentId::new(b"release");
+///
+/// pins.add(cid.clone(), PinMode::Recursive, Some("v1.0".into())).unwrap();
+/// assert_eq!(pins.get(&cid.to_v1()).unwrap().name.as_deref(), Some("v1.0"));
+///
+/// assert!(pins.remove(&cid).unwrap().is_some());
+/// assert!(pins.is_empty());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PinSet {
+    /// Pins by routing key
+    pins: BTreeMap<Vec<u8>, Pin>,
+    /// File the set is saved to
+    path: Option<PathBuf>,
+}
+
+impl PinSet {
+    /// Create an empty pin set held in memory
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the pin set saved at `path`, or start an empty one there
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let mut set = PinSet {
+            pins: BTreeMap::new(),
+            path: None,
+        };
+
+        match fs::read(&path) {
+            Ok(bytes) => {
+                let records: Vec<PinRecord> = serde_json::from_slice(&bytes)
+                    .with_context(|| format!("Invalid pin file {}", path.display()))?;
+                for record in records {
+                    let cid = ContentId::from_str(&record.cid)
+                        .map_err(|e| anyhow!("Invalid pin {}: {}", record.cid, e))?;
+                    set.insert(cid, record.mode, record.name);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+
+        set.path = Some(path);
+        Ok(set)
+    }
+
+    /// Get the file the set is saved to, if any
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Pin `cid`, replacing any existing pin of the same content
+    pub fn add(&mut self, cid: ContentId, mode: PinMode, name: Option<String>) -> Result<()> {
+        self.insert(cid, mode, name);
+        self.save()
+    }
+
+    /// Unpin `cid`, returning the removed pin
+    pub fn remove(&mut self, cid: &ContentId) -> Result<Option<Pin>> {
+        let pin = self.pins.remove(&cid.routing_key());
+        if pin.is_some() {
+            self.save()?;
+        }
+        Ok(pin)
+    }
+
+    /// Get the pin for `cid`
+    pub fn get(&self, cid: &ContentId) -> Option<&Pin> {
+        self.pins.get(&cid.routing_key())
+    }
+
+    /// Iterate over the pins
+    pub fn iter(&self) -> impl Iterator<Item = &Pin> {
+        self.pins.values()
+    }
+
+    /// Get the number of pins
+    pub fn len(&self) -> usize {
+        self.pins.len()
+    }
+
+    /// Check if nothing is pinned
+    pub fn is_empty(&self) -> bool {
+        self.pins.is_empty()
+    }
+
+    fn insert(&mut self, cid: ContentId, mode: PinMode, name: Option<String>) {
+        self.pins.insert(cid.routing_key(), Pin { cid, mode, name });
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let records: Vec<_> = self
+            .pins
+            .values()
+            .map(|pin| PinRecord {
+                cid: pin.cid.to_string(),
+                mode: pin.mode,
+                name: pin.name.clone(),
+            })
+            .collect();
+        crate::write_atomically(path, &serde_json::to_vec_pretty(&records)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pins_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pins.json");
+        let a = ContentId::new(b"a");
+        let b = ContentId::new(b"b");
+
+        let mut pins = PinSet::open(&path).unwrap();
+        pins.add(a.clone(), PinMode::Recursive, Some("site".into()))
+            .unwrap();
+        pins.add(b.clone(), PinMode::Direct, None).unwrap();
+
+        let mut pins = PinSet::open(&path).unwrap();
+        assert_eq!(pins.len(), 2);
+        assert_eq!(
+            pins.get(&a),
+            Some(&Pin {
+                cid: a.clone(),
+                mode: PinMode::Recursive,
+                name: Some("site".into()),
+            })
+        );
+
+        pins.remove(&b).unwrap();
+        assert!(PinSet::open(&path).unwrap().get(&b).is_none());
+    }
+
+    #[test]
+    fn test_repin_replaces() {
+        let mut pins = PinSet::new();
+        let cid = ContentId::new(b"a");
+
+        pins.add(cid.clone(), PinMode::Direct, None).unwrap();
+        pins.add(cid.to_v1(), PinMode::Recursive, None).unwrap();
+
+        assert_eq!(pins.len(), 1);
+        assert_eq!(pins.get(&cid).unwrap().mode, PinMode::Recursive);
+    }
+
+    #[test]
+    fn test_open_rejects_invalid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pins.json");
+        fs::write(&path, "[{\"cid\": \"nope\", \"mode\": \"direct\"}]").unwrap();
+
+        assert!(PinSet::open(&path).is_err());
+    }
+}
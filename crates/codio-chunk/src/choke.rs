@@ -0,0 +1,580 @@
+//! Choking: deciding which peers we upload to.
+//!
+//! Each piece of content has its own [`Choker`], as each torrent does in
+//! BitTorrent, and [`ChokeGate`] keeps one per CID. Uploads are handed out in
+//! rounds. Every [`CHOKE_ROUND_INTERVAL`] the choker ranks the peers that
+//! asked us for chunks of its content within the last [`RATE_WINDOW`] and
+//! unchokes the best `upload_slots - 1` of them; everyone else is choked
+//! until a later round. While we're still downloading the content, peers are
+//! ranked by how fast they uploaded it to us over the window, so the slots go
+//! to peers that reciprocate. Once we hold all of it there's nothing left to
+//! reciprocate, so they're ranked by how fast they take it from us instead,
+//! favouring peers that will spread the content.
+//!
+//! The remaining slot is the optimistic unchoke. It goes to a choked peer in
+//! turn, the one unchoked longest ago (newcomers first), and moves on every
+//! [`OPTIMISTIC_UNCHOKE_INTERVAL`], giving new and previously slow peers a
+//! chance to show what they can upload.
+//!
+//! Slots left empty between rounds are filled as soon as a peer asks, rather
+//! than leaving upload capacity idle until the next round.
+
+use crate::OPTIMISTIC_UNCHOKE_INTERVAL;
+use codio_common::{ContentId, PeerId};
+use codio_network::UploadGate;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::time::{Instant, MissedTickBehavior};
+use tracing::debug;
+
+/// Time between choke rounds (10 seconds)
+pub const CHOKE_ROUND_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Window over which transfer rates are measured, and after which a peer
+/// that stopped asking for chunks is no longer considered (20 seconds)
+pub const RATE_WINDOW: Duration = Duration::from_secs(20);
+
+/// What we know about one peer
+#[derive(Debug, Default)]
+struct PeerState {
+    /// Bytes the peer sent us, by time
+    received: VecDeque<(Instant, u64)>,
+    /// Bytes we sent the peer, by time
+    sent: VecDeque<(Instant, u64)>,
+    /// When the peer last asked us for a chunk
+    last_request: Option<Instant>,
+    /// When the peer was last unchoked
+    last_unchoked: Option<Instant>,
+}
+
+impl PeerState {
+    /// Whether the peer asked for a chunk within the rate window
+    fn interested(&self, now: Instant) -> bool {
+        self.last_request
+            .is_some_and(|at| now.duration_since(at) < RATE_WINDOW)
+    }
+
+    /// Drop transfers that have left the rate window
+    fn prune(&mut self, now: Instant) {
+        for samples in [&mut self.received, &mut self.sent] {
+            while samples
+                .front()
+                .is_some_and(|(at, _)| now.duration_since(*at) >= RATE_WINDOW)
+            {
+                samples.pop_front();
+            }
+        }
+    }
+}
+
+/// Bytes transferred within the rate window
+fn window_bytes(samples: &VecDeque<(Instant, u64)>) -> u64 {
+    samples.iter().map(|(_, bytes)| bytes).sum()
+}
+
+/// Periodic choke/unchoke decisions for uploads
+///
+/// # Example
+///
+/// ```rust
+/// use codio_chunk::Choker;
+/// use codio_common::PeerId;
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let mut choker = Choker::new(4);
+/// let peer = PeerId("peer1".to_string());
+///
+/// // A peer asking while a slot is free is unchoked straight away
+/// assert!(choker.request(&peer));
+///
+/// // Later rounds rank it by what it uploads to us
+/// choker.record_received(&peer, 256 * 1024);
+/// choker.rechoke(false);
+/// assert!(choker.is_unchoked(&peer));
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Choker {
+    upload_slots: usize,
+    peers: HashMap<PeerId, PeerState>,
+    unchoked: HashSet<PeerId>,
+    optimistic: Option<PeerId>,
+    last_optimistic: Option<Instant>,
+}
+
+impl Choker {
+    /// Create a choker that unchokes at most `upload_slots` peers at a time,
+    /// one of them optimistically
+    pub fn new(upload_slots: usize) -> Self {
+        Self {
+            upload_slots,
+            peers: HashMap::new(),
+            unchoked: HashSet::new(),
+            optimistic: None,
+            last_optimistic: None,
+        }
+    }
+
+    /// Record `bytes` downloaded from `peer`
+    pub fn record_received(&mut self, peer: &PeerId, bytes: u64) {
+        let state = self.peers.entry(peer.clone()).or_default();
+        state.received.push_back((Instant::now(), bytes));
+    }
+
+    /// Record `bytes` uploaded to `peer`
+    pub fn record_sent(&mut self, peer: &PeerId, bytes: u64) {
+        let state = self.peers.entry(peer.clone()).or_default();
+        state.sent.push_back((Instant::now(), bytes));
+    }
+
+    /// Note that `peer` asked for a chunk, and decide whether to upload it
+    ///
+    /// Between rounds only peers already unchoked, or arriving while a slot
+    /// is free, are served.
+    pub fn request(&mut self, peer: &PeerId) -> bool {
+        let now = Instant::now();
+        self.peers.entry(peer.clone()).or_default().last_request = Some(now);
+
+        if !self.unchoked.contains(peer) && self.unchoked.len() < self.upload_slots {
+            debug!("Unchoking {} into a free upload slot", peer.0);
+            self.unchoke(peer, now);
+        }
+
+        self.unchoked.contains(peer)
+    }
+
+    /// Run a choke round now
+    ///
+    /// `seeding` says whether we hold all of the content, which switches how
+    /// peers are ranked.
+    pub fn rechoke(&mut self, seeding: bool) {
+        self.round(Instant::now(), seeding);
+    }
+
+    /// Whether no peer is interested and no transfer is in the rate window,
+    /// as of the last round
+    fn is_idle(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    /// Whether `peer` is currently unchoked
+    pub fn is_unchoked(&self, peer: &PeerId) -> bool {
+        self.unchoked.contains(peer)
+    }
+
+    /// Peers currently unchoked, in no particular order
+    pub fn unchoked(&self) -> Vec<PeerId> {
+        self.unchoked.iter().cloned().collect()
+    }
+
+    /// The peer holding the optimistic unchoke slot
+    pub fn optimistic(&self) -> Option<&PeerId> {
+        self.optimistic.as_ref()
+    }
+
+    fn unchoke(&mut self, peer: &PeerId, now: Instant) {
+        self.unchoked.insert(peer.clone());
+        self.peers.entry(peer.clone()).or_default().last_unchoked = Some(now);
+    }
+
+    fn round(&mut self, now: Instant, seeding: bool) {
+        for state in self.peers.values_mut() {
+            state.prune(now);
+        }
+        self.peers.retain(|_, state| {
+            state.interested(now) || !state.received.is_empty() || !state.sent.is_empty()
+        });
+
+        // Rank interested peers by their rate over the window, keeping
+        // peers that are already unchoked ahead on a tie so slots don't churn
+        let mut interested: Vec<(&PeerId, &PeerState)> = self
+            .peers
+            .iter()
+            .filter(|(_, state)| state.interested(now))
+            .collect();
+        interested.sort_by_key(|(peer, state)| {
+            let bytes = if seeding {
+                window_bytes(&state.sent)
+            } else {
+                window_bytes(&state.received)
+            };
+            (
+                Reverse(bytes),
+                !self.unchoked.contains(*peer),
+                peer.0.clone(),
+            )
+        });
+
+        let regular: Vec<PeerId> = interested
+            .iter()
+            .take(self.upload_slots.saturating_sub(1))
+            .map(|(peer, _)| (*peer).clone())
+            .collect();
+        let interested_count = interested.len();
+        let choked: Vec<(&PeerId, &PeerState)> = interested
+            .iter()
+            .filter(|(peer, _)| !regular.contains(*peer))
+            .copied()
+            .collect();
+
+        // Keep the optimistic unchoke until its time is up, unless its peer
+        // lost interest or earned a regular slot
+        let rotation_due = self
+            .last_optimistic
+            .is_none_or(|at| now.duration_since(at) >= OPTIMISTIC_UNCHOKE_INTERVAL);
+        let current = self
+            .optimistic
+            .as_ref()
+            .filter(|peer| choked.iter().any(|(candidate, _)| candidate == peer));
+        let optimistic = match current {
+            Some(peer) if !rotation_due => Some(peer.clone()),
+            _ if self.upload_slots == 0 => None,
+            _ => {
+                self.last_optimistic = Some(now);
+                choked
+                    .iter()
+                    .min_by_key(|(peer, state)| (state.last_unchoked, peer.0.clone()))
+                    .map(|(peer, _)| (*peer).clone())
+            }
+        };
+
+        self.unchoked.clear();
+        for peer in regular.iter().chain(&optimistic) {
+            self.unchoke(peer, now);
+        }
+        if optimistic != self.optimistic {
+            if let Some(peer) = &optimistic {
+                debug!("Optimistically unchoking {}", peer.0);
+            }
+        }
+        self.optimistic = optimistic;
+
+        debug!(
+            "Choke round: {} unchoked of {} interested ({})",
+            self.unchoked.len(),
+            interested_count,
+            if seeding { "seeding" } else { "downloading" }
+        );
+    }
+}
+
+/// A content's choker, and whether we held all of the content when last asked
+#[derive(Debug)]
+struct ContentChoker {
+    choker: Choker,
+    seeding: bool,
+}
+
+/// Chokers for every piece of content we upload, shared between the
+/// distributor and the network manager
+///
+/// Installed on a [`NetworkManager`](codio_network::NetworkManager) as its
+/// [`UploadGate`], it refuses chunk requests from choked peers. Rounds run
+/// for every content every [`CHOKE_ROUND_INTERVAL`] once
+/// [`start_rounds`](Self::start_rounds) is called.
+///
+/// # Example
+///
+/// ```rust
+/// use codio_chunk::ChokeGate;
+/// use codio_common::{ContentId, PeerId};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let gate = ChokeGate::new(4);
+/// gate.start_rounds();
+///
+/// let cid = ContentId::new(b"content");
+/// let peer = PeerId("peer1".to_string());
+/// assert!(gate.request(&cid, &peer, true));
+/// assert_eq!(gate.unchoked(&cid), vec![peer]);
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ChokeGate {
+    upload_slots: usize,
+    /// Chokers by routing key, so any CID version finds its content's
+    chokers: Arc<Mutex<HashMap<Vec<u8>, ContentChoker>>>,
+}
+
+impl ChokeGate {
+    /// Create a gate unchoking at most `upload_slots` peers per content, one
+    /// of them optimistically
+    pub fn new(upload_slots: usize) -> Self {
+        Self {
+            upload_slots,
+            chokers: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Run a choke round every [`CHOKE_ROUND_INTERVAL`] on a background task,
+    /// until every clone of the gate is dropped
+    pub fn start_rounds(&self) {
+        let chokers = Arc::downgrade(&self.chokers);
+        tokio::spawn(run_rounds(chokers));
+    }
+
+    /// Note that `peer` asked for a chunk of `cid`, and decide whether to
+    /// upload it. `seeding` says whether we hold all of `cid`.
+    pub fn request(&self, cid: &ContentId, peer: &PeerId, seeding: bool) -> bool {
+        self.with_choker(cid, |content| {
+            content.seeding = seeding;
+            content.choker.request(peer)
+        })
+    }
+
+    /// Record `bytes` of `cid` downloaded from `peer`
+    pub fn record_received(&self, cid: &ContentId, peer: &PeerId, bytes: u64) {
+        self.with_choker(cid, |content| content.choker.record_received(peer, bytes));
+    }
+
+    /// Record `bytes` of `cid` uploaded to `peer`
+    pub fn record_sent(&self, cid: &ContentId, peer: &PeerId, bytes: u64) {
+        self.with_choker(cid, |content| content.choker.record_sent(peer, bytes));
+    }
+
+    /// Run a choke round for every content now
+    pub fn rechoke(&self) {
+        rechoke_all(&mut self.lock());
+    }
+
+    /// Whether `peer` is currently unchoked for `cid`
+    pub fn is_unchoked(&self, cid: &ContentId, peer: &PeerId) -> bool {
+        self.lock()
+            .get(&cid.routing_key())
+            .is_some_and(|content| content.choker.is_unchoked(peer))
+    }
+
+    /// Peers currently unchoked for `cid`, in no particular order
+    pub fn unchoked(&self, cid: &ContentId) -> Vec<PeerId> {
+        self.lock()
+            .get(&cid.routing_key())
+            .map(|content| content.choker.unchoked())
+            .unwrap_or_default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Vec<u8>, ContentChoker>> {
+        self.chokers.lock().expect("choker poisoned")
+    }
+
+    /// Run `f` on the choker of `cid`, made if it's new; until a peer asks
+    /// for the content we assume we're still downloading it
+    fn with_choker<T>(&self, cid: &ContentId, f: impl FnOnce(&mut ContentChoker) -> T) -> T {
+        let mut chokers = self.lock();
+        let content = chokers
+            .entry(cid.routing_key())
+            .or_insert_with(|| ContentChoker {
+                choker: Choker::new(self.upload_slots),
+                seeding: false,
+            });
+        f(content)
+    }
+}
+
+impl UploadGate for ChokeGate {
+    fn allow(&self, peer: &libp2p::PeerId, cid: &ContentId, seeding: bool) -> bool {
+        self.request(cid, &PeerId(peer.to_string()), seeding)
+    }
+
+    fn served(&self, peer: &libp2p::PeerId, cid: &ContentId, bytes: u64) {
+        self.record_sent(cid, &PeerId(peer.to_string()), bytes);
+    }
+}
+
+/// Run a round for every content, dropping chokers nobody is using
+fn rechoke_all(chokers: &mut HashMap<Vec<u8>, ContentChoker>) {
+    for content in chokers.values_mut() {
+        content.choker.rechoke(content.seeding);
+    }
+    chokers.retain(|_, content| !content.choker.is_idle());
+}
+
+/// Run a round every [`CHOKE_ROUND_INTERVAL`] until the chokers are dropped
+async fn run_rounds(chokers: Weak<Mutex<HashMap<Vec<u8>, ContentChoker>>>) {
+    let mut interval = tokio::time::interval(CHOKE_ROUND_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // The first tick is immediate; peers fill free slots until the next one
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+        let Some(chokers) = chokers.upgrade() else {
+            return;
+        };
+        rechoke_all(&mut chokers.lock().expect("choker poisoned"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(name: &str) -> PeerId {
+        PeerId(name.to_string())
+    }
+
+    fn ask_all(choker: &mut Choker, peers: &[PeerId]) {
+        for peer in peers {
+            choker.request(peer);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slots_go_to_fastest_uploaders() {
+        let mut choker = Choker::new(3);
+        let peers: Vec<PeerId> = ["a", "b", "c", "d", "e"].map(peer).to_vec();
+        ask_all(&mut choker, &peers);
+        // The first peers fill the free slots
+        assert_eq!(choker.unchoked().len(), 3);
+
+        choker.record_received(&peers[3], 3000);
+        choker.record_received(&peers[4], 2000);
+        choker.record_received(&peers[0], 1000);
+
+        tokio::time::advance(CHOKE_ROUND_INTERVAL).await;
+        ask_all(&mut choker, &peers);
+        choker.rechoke(false);
+
+        assert!(choker.is_unchoked(&peers[3]));
+        assert!(choker.is_unchoked(&peers[4]));
+        // The last slot goes optimistically to one of the choked peers
+        assert_eq!(choker.optimistic(), Some(&peers[0]));
+        assert!(!choker.is_unchoked(&peers[1]));
+        assert!(!choker.is_unchoked(&peers[2]));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_optimistic_unchoke_rotates() {
+        let mut choker = Choker::new(1);
+        let peers: Vec<PeerId> = ["a", "b", "c"].map(peer).to_vec();
+        ask_all(&mut choker, &peers);
+        assert_eq!(choker.unchoked(), vec![peers[0].clone()]);
+
+        // The first round moves the slot to a peer that hasn't had a turn
+        choker.rechoke(false);
+        assert_eq!(choker.optimistic(), Some(&peers[1]));
+
+        let mut holders = Vec::new();
+        for _ in 0..9 {
+            tokio::time::advance(CHOKE_ROUND_INTERVAL).await;
+            ask_all(&mut choker, &peers);
+            choker.rechoke(false);
+            assert_eq!(choker.unchoked().len(), 1);
+            holders.push(choker.optimistic().unwrap().clone());
+        }
+
+        // Each peer holds the slot for a full interval, in turn
+        let expected: Vec<PeerId> = ["b", "b", "c", "c", "c", "a", "a", "a", "b"]
+            .map(peer)
+            .to_vec();
+        assert_eq!(holders, expected);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rates_use_sliding_window() {
+        let mut choker = Choker::new(2);
+        let (old, recent, idle) = (peer("old"), peer("recent"), peer("idle"));
+        choker.record_received(&old, 1_000_000);
+
+        tokio::time::advance(RATE_WINDOW).await;
+        choker.record_received(&recent, 1000);
+        ask_all(&mut choker, &[old.clone(), recent.clone(), idle.clone()]);
+        choker.rechoke(false);
+
+        // The old transfer has left the window, so `recent` ranks first, and
+        // the optimistic slot goes to `idle`, which hasn't had a turn yet
+        assert!(choker.is_unchoked(&recent));
+        assert_eq!(choker.optimistic(), Some(&idle));
+        assert!(!choker.is_unchoked(&old));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_seed_mode_ranks_by_upload() {
+        let mut choker = Choker::new(2);
+        let (giver, taker, other) = (peer("giver"), peer("taker"), peer("other"));
+        ask_all(&mut choker, &[giver.clone(), taker.clone(), other.clone()]);
+        choker.record_received(&giver, 10_000);
+        choker.record_sent(&taker, 5000);
+
+        choker.rechoke(false);
+        let leeching = choker.unchoked();
+        choker.rechoke(true);
+
+        assert!(leeching.contains(&giver));
+        assert!(choker.is_unchoked(&taker));
+        assert_ne!(choker.optimistic(), Some(&taker));
+        assert!(!choker.is_unchoked(&giver));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_uninterested_peers_are_choked() {
+        let mut choker = Choker::new(4);
+        let (gone, staying) = (peer("gone"), peer("staying"));
+        assert!(choker.request(&gone));
+        assert!(choker.request(&staying));
+
+        tokio::time::advance(RATE_WINDOW).await;
+        assert!(choker.request(&staying));
+        choker.rechoke(false);
+        assert!(choker.is_unchoked(&staying));
+        assert!(!choker.is_unchoked(&gone));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_no_slots_chokes_everyone() {
+        let mut choker = Choker::new(0);
+        assert!(!choker.request(&peer("a")));
+        assert!(choker.unchoked().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_gate_ranks_each_content_on_its_own() {
+        let gate = ChokeGate::new(2);
+        let (downloading, seeded) = (ContentId::new(b"downloading"), ContentId::new(b"seeded"));
+        let (giver, taker, other) = (peer("giver"), peer("taker"), peer("other"));
+
+        // `giver` uploads the content we're downloading to us; `taker` takes
+        // the content we're seeding
+        for peer in [&taker, &other, &giver] {
+            gate.request(&downloading, peer, false);
+        }
+        for peer in [&giver, &taker, &other] {
+            gate.request(&seeded, peer, true);
+        }
+        gate.record_received(&downloading, &giver, 10_000);
+        gate.record_sent(&seeded, &taker, 5000);
+        gate.rechoke();
+
+        // Each content ranks its own peers, by its own measure: had the
+        // seeded content been ranked by download rate, `giver` would hold
+        // its regular slot and `taker` would be choked
+        assert!(gate.is_unchoked(&downloading, &giver));
+        assert!(gate.is_unchoked(&seeded, &taker));
+        assert!(!gate.is_unchoked(&seeded, &giver));
+        assert_eq!(gate.unchoked(&seeded).len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_gate_rounds_run_on_a_timer() {
+        let gate = ChokeGate::new(1);
+        gate.start_rounds();
+        let cid = ContentId::new(b"content");
+        let (first, second) = (peer("first"), peer("second"));
+
+        assert!(gate.request(&cid, &first, true));
+        assert!(!gate.request(&cid, &second, true));
+
+        // No request needed: the round hands the slot to the peer that
+        // hasn't had a turn
+        tokio::time::sleep(CHOKE_ROUND_INTERVAL + Duration::from_millis(1)).await;
+        assert!(gate.is_unchoked(&cid, &second));
+        assert!(!gate.is_unchoked(&cid, &first));
+
+        // Once nobody is asking any more, the content's choker is dropped
+        tokio::time::sleep(RATE_WINDOW + CHOKE_ROUND_INTERVAL).await;
+        assert!(gate.lock().is_empty());
+    }
+}
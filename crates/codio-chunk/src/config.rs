@@ -1,3 +1,4 @@
+use codio_common::RateLimits;
 use std::time::Duration;
 
 /// Distribution strategy for chunk downloads
@@ -31,6 +32,17 @@ pub struct ChunkConfig {
 
     /// Chunk size in bytes (default: 256 KB)
     pub chunk_size: usize,
+
+    /// Chunks left outstanding at which a download enters endgame mode and
+    /// requests them from every peer that has them (0 disables endgame)
+    pub endgame_threshold: usize,
+
+    /// Peers we upload each content to at once, one of them through the
+    /// optimistic unchoke (0 disables uploads)
+    pub upload_slots: usize,
+
+    /// Bandwidth limits for chunk transfers (unlimited by default)
+    pub rate_limits: RateLimits,
 }
 
 impl Default for ChunkConfig {
@@ -41,6 +53,9 @@ impl Default for ChunkConfig {
             request_timeout: Duration::from_secs(30),
             strategy: DistributionStrategy::RarestFirst,
             chunk_size: 256 * 1024, // 256 KB
+            endgame_threshold: 4,
+            upload_slots: 4,
+            rate_limits: RateLimits::unlimited(),
         }
     }
 }
@@ -59,6 +74,9 @@ impl ChunkConfig {
             request_timeout,
             strategy,
             chunk_size: 256 * 1024,
+            endgame_threshold: 4,
+            upload_slots: 4,
+            rate_limits: RateLimits::unlimited(),
         }
     }
 
@@ -67,4 +85,22 @@ impl ChunkConfig {
         self.chunk_size = size;
         self
     }
+
+    /// Set the number of outstanding chunks that triggers endgame mode
+    pub fn with_endgame_threshold(mut self, chunks: usize) -> Self {
+        self.endgame_threshold = chunks;
+        self
+    }
+
+    /// Set the number of peers each content is uploaded to at once
+    pub fn with_upload_slots(mut self, slots: usize) -> Self {
+        self.upload_slots = slots;
+        self
+    }
+
+    /// Set bandwidth limits
+    pub fn with_rate_limits(mut self, limits: RateLimits) -> Self {
+        self.rate_limits = limits;
+        self
+    }
 }
@@ -8,6 +8,7 @@
 //! - **Rarest-First Strategy**: Prioritize downloading rare chunks to improve network distribution
 //! - **Tit-for-Tat**: Incentivize fair sharing by prioritizing peers who upload to us
 //! - **Integrity Verification**: Verify SHA-256 hash of every chunk to prevent corruption
+//! - **Network Transfer**: Fetch manifests and chunks from remote peers over libp2p
 //!
 //! ## Architecture
 //!
@@ -22,13 +23,69 @@
 //! 3. Download chunks in order of rarity (rarest first)
 //! 4. This ensures rare chunks spread quickly across the network
 //!
+//! ### Endgame
+//!
+//! Each chunk is normally requested from a single peer, at most
+//! `chunks_per_peer` at a time per peer. Once no more than
+//! `endgame_threshold` chunks are outstanding, they are also requested from
+//! every other peer that has them; the first copy to verify wins and the
+//! other requests are cancelled, so one slow peer can't hold up the end of a
+//! download. A chunk that fails is retried on another peer, and the download
+//! only fails once every peer that has the chunk has failed it. A peer that
+//! refuses a chunk because it is choking us or busy hasn't failed it: the
+//! chunk is asked for again after a backoff.
+//!
+//! ### Availability
+//!
+//! Over the network, the distributor swaps bitfields with every provider once
+//! it has the manifest, and shares each chunk as soon as it is verified,
+//! sending a HAVE to the peers it swapped with. Between batches of requests it
+//! picks up the HAVEs other peers sent, so peers that only hold part of the
+//! content are asked only for chunks they have. Providers that don't answer
+//! with a bitfield are assumed to have every chunk.
+//!
+//! ### Manifests
+//!
+//! Before downloading, the distributor asks the providers for the content's
+//! manifest: its total size, the chunk size the provider uses and the SHA-256
+//! of every chunk. Each chunk is checked against the manifest as it arrives and
+//! the reassembled content is checked against the CID, so a provider can't
+//! slip in data that doesn't belong to the content.
+//!
+//! ### Range Reads
+//!
+//! [`ChunkDistributor::download_range`] fetches only the chunks covering a
+//! byte range, for seeking in large files. A range can't be checked against
+//! the CID, which covers the whole content, so it relies on the manifest's
+//! chunk hashes alone.
+//!
+//! ### Resuming
+//!
+//! [`ChunkDistributor::download_to_file`] writes chunks straight into the
+//! output file and keeps a journal of the completed ones next to it, so an
+//! interrupted download picks up where it stopped. See [`resume`].
+//!
 //! ### Tit-for-Tat Mechanism
 //!
-//! 1. Track upload/download ratios for each peer
-//! 2. Prioritize uploading to peers who upload to us
-//! 3. Implement "optimistic unchoking" - randomly upload to a new peer every 30s
+//! 1. Upload each content to at most `upload_slots` peers at a time,
+//!    re-chosen every 10s
+//! 2. Give the slots to the peers that uploaded it to us fastest over the
+//!    last 20s, or that download it fastest once we hold all of it
+//! 3. Rotate one "optimistic unchoke" slot through the other peers every 30s
 //! 4. This incentivizes fair sharing and prevents free-riding
 //!
+//! [`ChunkDistributor::with_network`] installs the chokers on the network
+//! manager, which refuses chunk requests from choked peers. See [`choke`]
+//! for the details.
+//!
+//! ### Bandwidth Limits
+//!
+//! [`ChunkConfig::rate_limits`] caps the bytes per second downloaded and
+//! uploaded, across all peers and per peer, with token buckets (see
+//! [`RateLimiter`]). A chunk request waits for bandwidth before it is sent,
+//! and a served chunk before it is returned. The limits can be changed while
+//! running with [`ChunkDistributor::set_rate_limits`].
+//!
 //! ## Example Usage
 //!
 //! ```rust,no_run
@@ -42,6 +99,9 @@
 //!     request_timeout: std::time::Duration::from_secs(30),
 //!     strategy: DistributionStrategy::RarestFirst,
 //!     chunk_size: 256 * 1024,
+//!     endgame_threshold: 4,
+//!     upload_slots: 4,
+//!     rate_limits: Default::default(),
 //! };
 //!
 //! let mut distributor = ChunkDistributor::new(config).await?;
@@ -53,20 +113,57 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! To download from remote peers, attach a handle to a running
+//! [`NetworkManager`](codio_network::NetworkManager):
+//!
+//! ```rust,no_run
+//! use codio_chunk::{ChunkConfig, ChunkDistributor, PeerInfo};
+//! use codio_common::PeerId;
+//! use codio_content_id::ContentId;
+//! use codio_network::{NetworkConfig, NetworkManager};
+//!
+//! # async fn example(cid: ContentId) -> anyhow::Result<()> {
+//! let mut manager = NetworkManager::new(NetworkConfig::default()).await?;
+//! manager.start().await?;
+//! let providers = manager.find_providers(&cid, std::time::Duration::from_secs(10)).await?;
+//!
+//! let handle = manager.handle();
+//! tokio::spawn(async move { manager.run().await });
+//!
+//! let mut distributor = ChunkDistributor::new(ChunkConfig::default())
+//!     .await?
+//!     .with_network(handle);
+//! let providers = providers
+//!     .iter()
+//!     .map(|peer| PeerInfo::new(PeerId(peer.to_string())))
+//!     .collect();
+//! let content = distributor.download_content(cid, providers).await?;
+//! # Ok(())
+//! # }
+//! ```
 
+pub mod choke;
 pub mod config;
+pub mod resume;
 
+pub use choke::{ChokeGate, Choker};
 pub use config::{ChunkConfig, DistributionStrategy};
+pub use resume::DownloadFile;
 
 use anyhow::{anyhow, Result};
-use codio_common::{ContentId, PeerId};
+use codio_blockstore::{Blockstore, MemoryBlockstore};
+use codio_common::{ContentId, Direction, PeerId, RateLimiter, RateLimits};
+use codio_content::{verify_chunk_proof, Codec, DagBuilder, HashAlgorithm, MerkleDAG};
+use codio_network::{Bitfield, ContentManifest, ErrorCode, NetworkHandle, RemoteError};
 use futures::future::join_all;
 use rand::seq::SliceRandom;
 use sha2::{Digest, Sha256};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::{RwLock, Semaphore};
+use tokio::task::{AbortHandle, JoinSet};
 use tokio::time::timeout;
 use tracing::{debug, error, info, warn};
 
@@ -79,6 +176,14 @@ pub const MAX_CHUNKS_PER_PEER: usize = 4;
 /// Optimistic unchoking interval (30 seconds)
 pub const OPTIMISTIC_UNCHOKE_INTERVAL: Duration = Duration::from_secs(30);
 
+/// First wait before asking again for a chunk a peer refused (choked or
+/// busy), doubling with each refusal of the same chunk
+pub const REFUSED_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Longest wait before asking again for a refused chunk; well within a
+/// choke round, so an unchoke isn't missed for long
+pub const MAX_REFUSED_BACKOFF: Duration = Duration::from_secs(4);
+
 /// Information about a peer in the network
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PeerInfo {
@@ -164,6 +269,10 @@ pub struct Download {
     pub strategy: DistributionStrategy,
     pub start_time: Instant,
     pub downloaded_bytes: u64,
+    /// Expected SHA-256 of each chunk, empty if unknown
+    pub chunk_hashes: Vec<[u8; 32]>,
+    /// File verified chunks are written to instead of `chunks`
+    pub output: Option<DownloadFile>,
 }
 
 impl Download {
@@ -186,9 +295,44 @@ impl Download {
             strategy,
             start_time: Instant::now(),
             downloaded_bytes: 0,
+            chunk_hashes: Vec::new(),
+            output: None,
+        }
+    }
+
+    /// Create a download following a provider's manifest
+    pub fn from_manifest(
+        cid: ContentId,
+        manifest: &ContentManifest,
+        strategy: DistributionStrategy,
+    ) -> Self {
+        let mut download = Self::new(
+            cid,
+            manifest.total_size,
+            manifest.chunk_size as usize,
+            strategy,
+        );
+        download.total_chunks = manifest.chunk_count();
+        download.chunk_hashes = manifest.chunk_hashes.clone();
+        download
+    }
+
+    /// Check a chunk against the expected hash, if one is known
+    pub fn matches_manifest(&self, chunk: &Chunk) -> bool {
+        match self.chunk_hashes.get(chunk.index as usize) {
+            Some(expected) => *expected == chunk.hash,
+            None => self.chunk_hashes.is_empty(),
         }
     }
 
+    /// Size of chunk `index` in bytes; only the last chunk can be short
+    pub fn chunk_len(&self, index: u32) -> u64 {
+        let start = index as u64 * self.chunk_size as u64;
+        self.total_bytes
+            .saturating_sub(start)
+            .min(self.chunk_size as u64)
+    }
+
     /// Check if download is complete
     pub fn is_complete(&self) -> bool {
         self.downloaded_chunks.len() == self.total_chunks as usize
@@ -220,6 +364,52 @@ impl Download {
             .collect()
     }
 
+    /// Chunks covering `len` bytes from `offset`, cut short at the end
+    ///
+    /// Fails if `offset` is past the end.
+    pub fn chunk_range(&self, offset: u64, len: u64) -> Result<std::ops::Range<u32>> {
+        if offset > self.total_bytes {
+            return Err(anyhow!(
+                "Offset {} is past the end of {} bytes",
+                offset,
+                self.total_bytes
+            ));
+        }
+
+        let end = offset.saturating_add(len).min(self.total_bytes);
+        if offset == end {
+            return Ok(0..0);
+        }
+
+        let chunk_size = self.chunk_size as u64;
+        Ok((offset / chunk_size) as u32..end.div_ceil(chunk_size) as u32)
+    }
+
+    /// Read `len` bytes from `offset` out of the downloaded chunks
+    ///
+    /// Only the chunks covering the range need to be downloaded.
+    pub fn read_range(&self, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let range = self.chunk_range(offset, len)?;
+        let len = len.min(self.total_bytes - offset) as usize;
+
+        let mut result = Vec::with_capacity(len);
+        for i in range.clone() {
+            let chunk = self
+                .chunks
+                .get(&i)
+                .ok_or_else(|| anyhow!("Missing chunk {}", i))?;
+
+            if !chunk.verify() || !self.matches_manifest(chunk) {
+                return Err(anyhow!("Chunk {} failed integrity check", i));
+            }
+
+            result.extend_from_slice(&chunk.data);
+        }
+
+        let skip = (offset - range.start as u64 * self.chunk_size as u64) as usize;
+        Ok(result[skip.min(result.len())..][..len].to_vec())
+    }
+
     /// Reconstruct full content from chunks
     pub fn reconstruct(&self) -> Result<Vec<u8>> {
         if !self.is_complete() {
@@ -249,6 +439,41 @@ impl Download {
     }
 }
 
+/// The Merkle DAG a manifest's chunk hashes build with the default builder
+fn manifest_dag(manifest: &ContentManifest) -> MerkleDAG {
+    let leaves = manifest
+        .chunk_hashes
+        .iter()
+        .map(|hash| ContentId::from_hash(*hash))
+        .collect();
+    DagBuilder::new().build_from_cids(leaves)
+}
+
+/// Check downloaded chunks against `dag`'s root with inclusion proofs, and
+/// their lengths against the download's layout
+fn verify_against_root(download: &Download, dag: &MerkleDAG, indices: &[u32]) -> Result<()> {
+    for &i in indices {
+        let chunk = download
+            .chunks
+            .get(&i)
+            .ok_or_else(|| anyhow!("Missing chunk {}", i))?;
+
+        let proof = dag.prove(i)?;
+        if !verify_chunk_proof(dag.root_cid(), i, &chunk.data, &proof) {
+            return Err(anyhow!("Chunk {} is not under {}", i, dag.root_cid()));
+        }
+        if chunk.data.len() as u64 != download.chunk_len(i) {
+            return Err(anyhow!(
+                "Chunk {} is {} bytes, the manifest says {}",
+                i,
+                chunk.data.len(),
+                download.chunk_len(i)
+            ));
+        }
+    }
+    Ok(())
+}
+
 /// Upload tracking for tit-for-tat
 #[derive(Debug, Clone)]
 pub struct Upload {
@@ -327,14 +552,20 @@ pub struct ChunkDistributor {
     active_uploads: Arc<RwLock<HashMap<ContentId, Vec<Upload>>>>,
     pub peer_stats: Arc<RwLock<HashMap<PeerId, PeerStats>>>,
     download_semaphore: Arc<Semaphore>,
-    local_content: Arc<RwLock<HashMap<ContentId, Vec<u8>>>>,
-    last_optimistic_unchoke: Arc<RwLock<Instant>>,
+    local_content: Arc<dyn Blockstore>,
+    choker: ChokeGate,
+    rate_limiter: Arc<RateLimiter>,
+    network: Option<NetworkHandle>,
 }
 
 impl ChunkDistributor {
     /// Create a new chunk distributor
     pub async fn new(config: ChunkConfig) -> Result<Self> {
         let max_concurrent = config.max_concurrent_downloads;
+        let upload_slots = config.upload_slots;
+        let rate_limits = config.rate_limits;
+        let choker = ChokeGate::new(upload_slots);
+        choker.start_rounds();
 
         Ok(Self {
             config,
@@ -342,25 +573,67 @@ impl ChunkDistributor {
             active_uploads: Arc::new(RwLock::new(HashMap::new())),
             peer_stats: Arc::new(RwLock::new(HashMap::new())),
             download_semaphore: Arc::new(Semaphore::new(max_concurrent)),
-            local_content: Arc::new(RwLock::new(HashMap::new())),
-            last_optimistic_unchoke: Arc::new(RwLock::new(Instant::now())),
+            local_content: Arc::new(MemoryBlockstore::new()),
+            choker,
+            rate_limiter: Arc::new(RateLimiter::new(rate_limits)),
+            network: None,
         })
     }
 
+    /// Fetch chunks from remote peers through a running network manager
+    ///
+    /// Without a network handle, downloads are served from local content only.
+    /// The manager asks our chokers before serving chunks to other peers, so
+    /// uploads follow the same choke rounds as [`serve_chunk`](Self::serve_chunk).
+    pub fn with_network(mut self, network: NetworkHandle) -> Self {
+        if let Err(e) = network.set_upload_gate(Arc::new(self.choker.clone())) {
+            warn!("Failed to install the choker on the network: {}", e);
+        }
+        self.network = Some(network);
+        self
+    }
+
+    /// Share `limiter` with other components, so they draw on the same
+    /// bandwidth budget
+    ///
+    /// Replaces the limiter built from [`ChunkConfig::rate_limits`].
+    pub fn with_rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = limiter;
+        self
+    }
+
+    /// Change the bandwidth limits of running and future transfers
+    pub fn set_rate_limits(&self, limits: RateLimits) {
+        self.rate_limiter.set_limits(limits);
+    }
+
+    /// The bandwidth limits in force
+    pub fn rate_limits(&self) -> RateLimits {
+        self.rate_limiter.limits()
+    }
+
+    /// Keep local content in `store` instead of memory
+    ///
+    /// The store can be shared with other components, such as a gateway.
+    pub fn with_blockstore(mut self, store: Arc<dyn Blockstore>) -> Self {
+        self.local_content = store;
+        self
+    }
+
     /// Store content locally for serving to other peers
-    pub async fn store_content(&self, cid: ContentId, content: Vec<u8>) {
-        let mut local = self.local_content.write().await;
-        local.insert(cid, content);
+    pub async fn store_content(&self, cid: ContentId, content: Vec<u8>) -> Result<()> {
+        self.local_content.put(&cid, content).await
     }
 
     /// Download content from multiple providers
     ///
     /// This is the main entry point for downloading content. It:
-    /// 1. Creates a download tracker
-    /// 2. Determines chunk order based on strategy
-    /// 3. Downloads chunks in parallel from multiple peers
-    /// 4. Verifies chunk integrity
-    /// 5. Reconstructs the complete content
+    /// 1. Fetches the content manifest from the providers
+    /// 2. Creates a download tracker
+    /// 3. Determines chunk order based on strategy
+    /// 4. Downloads chunks in parallel from multiple peers
+    /// 5. Verifies every chunk against the manifest
+    /// 6. Reconstructs the complete content and verifies it against the CID
     pub async fn download_content(
         &mut self,
         cid: ContentId,
@@ -376,16 +649,11 @@ impl ChunkDistributor {
             providers.len()
         );
 
-        // Get content size from first provider (simplified - in production, query metadata)
-        let total_bytes = self.query_content_size(&cid, &providers[0]).await?;
+        // Get the chunk layout, dropping providers that can't serve it
+        let (manifest, providers) = self.query_manifest(&cid, providers).await?;
 
         // Create download tracker
-        let download = Download::new(
-            cid.clone(),
-            total_bytes,
-            self.config.chunk_size,
-            self.config.strategy,
-        );
+        let download = Download::from_manifest(cid.clone(), &manifest, self.config.strategy);
 
         let total_chunks = download.total_chunks;
 
@@ -399,17 +667,19 @@ impl ChunkDistributor {
             .determine_chunk_order(&cid, &providers, total_chunks)
             .await?;
 
-        // Download chunks in parallel
-        self.download_chunks_parallel(&cid, chunk_order, providers)
-            .await?;
-
-        // Reconstruct content
-        let content = {
-            let downloads = self.active_downloads.read().await;
-            let download = downloads
-                .get(&cid)
-                .ok_or_else(|| anyhow!("Download not found"))?;
-            download.reconstruct()?
+        // Download chunks in parallel, then reconstruct content
+        let result = match self
+            .download_chunks_parallel(&cid, chunk_order, providers)
+            .await
+        {
+            Ok(()) => {
+                let downloads = self.active_downloads.read().await;
+                downloads
+                    .get(&cid)
+                    .ok_or_else(|| anyhow!("Download not found"))
+                    .and_then(|download| download.reconstruct())
+            }
+            Err(e) => Err(e),
         };
 
         // Clean up
@@ -418,11 +688,176 @@ impl ChunkDistributor {
             downloads.remove(&cid);
         }
 
+        let content = result?;
+        if !cid.verify(&content) {
+            error!("Reassembled content does not match CID {}", cid);
+            return Err(anyhow!("Content verification failed for {}", cid));
+        }
+
         info!("Download complete for CID {}", cid);
 
         Ok(content)
     }
 
+    /// Download `len` bytes of content from `offset`, fetching only the
+    /// chunks that cover them
+    ///
+    /// Ranges running past the end are cut short. Only a Merkle DAG root
+    /// (default [`DagBuilder`] over the provider's SHA-256 chunks) can vouch
+    /// for part of the content: the manifest's hashes must rebuild to `cid`,
+    /// and each covering chunk is checked against it with an inclusion proof
+    /// before any byte is returned. Other CIDs are refused.
+    pub async fn download_range(
+        &mut self,
+        cid: ContentId,
+        providers: Vec<PeerInfo>,
+        offset: u64,
+        len: u64,
+    ) -> Result<Vec<u8>> {
+        if providers.is_empty() {
+            return Err(anyhow!("No providers available"));
+        }
+
+        // A whole-content hash can only check the whole content
+        if cid.codec() != Codec::HashList || cid.algorithm() != HashAlgorithm::Sha2_256 {
+            return Err(anyhow!(
+                "Can't verify a range of {}: not a SHA-256 Merkle DAG root",
+                cid
+            ));
+        }
+
+        let (manifest, providers) = self.query_manifest(&cid, providers).await?;
+        if !manifest.is_consistent() {
+            return Err(anyhow!("Manifest for {} is inconsistent", cid));
+        }
+
+        let dag = manifest_dag(&manifest);
+        if dag.root_cid() != &cid {
+            return Err(anyhow!("Manifest for {} does not match its root", cid));
+        }
+
+        let download = Download::from_manifest(cid.clone(), &manifest, self.config.strategy);
+        let range = download.chunk_range(offset, len)?;
+        debug!(
+            "Downloading bytes {}+{} of {}: chunks {:?}",
+            offset, len, cid, range
+        );
+
+        // The root doesn't cover the chunk size, so a range within the last
+        // chunk also fetches the first one, whose length must be that size
+        let mut wanted: Vec<u32> = range.collect();
+        let last = download.total_chunks.saturating_sub(1);
+        if wanted
+            .first()
+            .is_some_and(|&first| first == last && last > 0)
+        {
+            wanted.insert(0, 0);
+        }
+
+        {
+            let mut downloads = self.active_downloads.write().await;
+            downloads.insert(cid.clone(), download);
+        }
+
+        // Seeking wants the covering chunks in order
+        let result = match self
+            .download_chunks_parallel(&cid, wanted.clone(), providers)
+            .await
+        {
+            Ok(()) => {
+                let downloads = self.active_downloads.read().await;
+                downloads
+                    .get(&cid)
+                    .ok_or_else(|| anyhow!("Download not found"))
+                    .and_then(|download| {
+                        verify_against_root(download, &dag, &wanted)?;
+                        download.read_range(offset, len)
+                    })
+            }
+            Err(e) => Err(e),
+        };
+
+        {
+            let mut downloads = self.active_downloads.write().await;
+            downloads.remove(&cid);
+        }
+
+        result
+    }
+
+    /// Download content straight into the file at `path`, resuming an
+    /// earlier attempt
+    ///
+    /// Verified chunks are written in place as they arrive and recorded in a
+    /// journal next to the file (see [`DownloadFile`]), so a download that
+    /// fails or is interrupted continues from there on the next call. Chunks
+    /// are never all held in memory. Returns the content size.
+    pub async fn download_to_file(
+        &mut self,
+        cid: ContentId,
+        providers: Vec<PeerInfo>,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<u64> {
+        if providers.is_empty() {
+            return Err(anyhow!("No providers available"));
+        }
+
+        let (manifest, providers) = self.query_manifest(&cid, providers).await?;
+        if !manifest.is_consistent() {
+            return Err(anyhow!("Manifest for {} is inconsistent", cid));
+        }
+
+        let output = DownloadFile::open(path.as_ref(), &cid, &manifest)?;
+        let missing = output.missing();
+        info!(
+            "Downloading {} to {}: {} of {} chunks to go",
+            cid,
+            output.path().display(),
+            missing.len(),
+            manifest.chunk_count()
+        );
+
+        // Peers are served from the file, chunks from an earlier attempt
+        // included, rather than from copies in memory
+        if let Some(network) = &self.network {
+            network.share_file(&cid, manifest.clone(), output.path())?;
+            for index in output.completed().indices() {
+                network.announce_stored(&cid, index)?;
+            }
+        }
+
+        let mut download = Download::from_manifest(cid.clone(), &manifest, self.config.strategy);
+        download.downloaded_chunks = output.completed().indices().collect();
+        download.downloaded_bytes = output.completed_bytes();
+        download.output = Some(output);
+        {
+            let mut downloads = self.active_downloads.write().await;
+            downloads.insert(cid.clone(), download);
+        }
+
+        let result = match self
+            .determine_chunk_order(&cid, &providers, manifest.chunk_count())
+            .await
+        {
+            Ok(order) => {
+                let order = order.into_iter().filter(|i| missing.contains(i)).collect();
+                self.download_chunks_parallel(&cid, order, providers).await
+            }
+            Err(e) => Err(e),
+        };
+
+        let output = {
+            let mut downloads = self.active_downloads.write().await;
+            downloads.remove(&cid).and_then(|download| download.output)
+        };
+        result?;
+
+        let output = output.ok_or_else(|| anyhow!("Download not found"))?;
+        let size = output.finish()?;
+        info!("Download complete for CID {}", cid);
+        Ok(size)
+    }
+
     /// Determine chunk download order based on strategy
     pub async fn determine_chunk_order(
         &self,
@@ -504,61 +939,218 @@ impl ChunkDistributor {
     }
 
     /// Download chunks in parallel from multiple peers
+    ///
+    /// Chunks are handed out in `chunk_order` to the least busy peer that has
+    /// them, up to `chunks_per_peer` requests per peer. See the crate docs for
+    /// endgame mode and retries.
     async fn download_chunks_parallel(
         &self,
         cid: &ContentId,
         chunk_order: Vec<u32>,
-        providers: Vec<PeerInfo>,
+        mut providers: Vec<PeerInfo>,
     ) -> Result<()> {
-        let chunks_per_peer = self.config.chunks_per_peer;
-
-        // Distribute chunks across peers
-        let mut tasks = Vec::new();
-
-        for (i, chunk_idx) in chunk_order.iter().enumerate() {
-            // Select peer for this chunk (round-robin with availability check)
-            let peer = self.select_peer_for_chunk(*chunk_idx, &providers, i);
-
-            if let Some(peer_info) = peer {
-                let cid_clone = cid.clone();
-                let chunk_idx = *chunk_idx;
-                let peer_clone = peer_info.clone();
-                let distributor_clone = self.clone_arc_fields();
-
-                // Limit concurrent downloads
-                let permit = self.download_semaphore.clone().acquire_owned().await?;
-
-                let task = tokio::spawn(async move {
-                    let result = distributor_clone
-                        .download_chunk(&cid_clone, chunk_idx, &peer_clone)
-                        .await;
-                    drop(permit); // Release semaphore
-                    result
-                });
-
-                tasks.push(task);
-
-                // Limit tasks per peer
-                if tasks.len() >= providers.len() * chunks_per_peer {
-                    // Wait for some tasks to complete
-                    let results = join_all(tasks.drain(..)).await;
-                    for result in results {
-                        result??; // Propagate errors
+        let per_peer = self.config.chunks_per_peer.max(1);
+        let refresh_every = per_peer * providers.len().max(1);
+
+        let mut queue: VecDeque<u32> = chunk_order.into();
+        let mut in_flight = InFlight::default();
+        let mut completed = HashSet::new();
+        // Peers each chunk failed on, and the last error
+        let mut failed: HashMap<u32, HashSet<PeerId>> = HashMap::new();
+        let mut errors: HashMap<u32, anyhow::Error> = HashMap::new();
+        // Chunks refused by choked or busy peers, waiting to be asked again
+        let mut backoff: JoinSet<u32> = JoinSet::new();
+        let mut refusals: HashMap<u32, u32> = HashMap::new();
+        let mut round_robin = 0;
+        let mut finished = 0;
+
+        loop {
+            // Hand out queued chunks to peers with room for more requests
+            let mut waiting = VecDeque::new();
+            while let Some(chunk_idx) = queue.pop_front() {
+                if in_flight.all_busy(&providers, per_peer) {
+                    waiting.push_back(chunk_idx);
+                    continue;
+                }
+
+                let excluded = failed.get(&chunk_idx).cloned().unwrap_or_default();
+                let mut assignment = self.assign_peer(
+                    chunk_idx,
+                    &providers,
+                    &in_flight.load,
+                    per_peer,
+                    &excluded,
+                    round_robin,
+                );
+                if matches!(assignment, Assignment::NoPeer) {
+                    // A HAVE may have arrived since the last refresh
+                    self.refresh_availability(cid, &mut providers).await;
+                    assignment = self.assign_peer(
+                        chunk_idx,
+                        &providers,
+                        &in_flight.load,
+                        per_peer,
+                        &excluded,
+                        round_robin,
+                    );
+                }
+
+                match assignment {
+                    Assignment::Peer(peer) => {
+                        round_robin += 1;
+                        in_flight.spawn(self.clone_arc_fields(), cid.clone(), chunk_idx, peer);
+                    }
+                    Assignment::Busy => waiting.push_back(chunk_idx),
+                    // Wait for what's in flight: a HAVE may turn up a peer
+                    Assignment::NoPeer if !in_flight.tasks.is_empty() || !backoff.is_empty() => {
+                        waiting.push_back(chunk_idx)
+                    }
+                    Assignment::NoPeer => {
+                        return Err(match errors.remove(&chunk_idx) {
+                            Some(e) => e.context(format!("Every peer failed chunk {}", chunk_idx)),
+                            None => anyhow!("No peer has chunk {}", chunk_idx),
+                        });
                     }
-                    tasks.clear();
                 }
             }
-        }
+            queue = waiting;
+
+            // Endgame: ask every other peer for the last outstanding chunks
+            if queue.is_empty() && in_flight.requests.len() <= self.config.endgame_threshold {
+                let mut outstanding: Vec<u32> = in_flight.requests.keys().copied().collect();
+                outstanding.sort_unstable();
+                for chunk_idx in outstanding {
+                    let mut excluded = failed.get(&chunk_idx).cloned().unwrap_or_default();
+                    excluded.extend(in_flight.peers_asked(chunk_idx));
+                    if let Assignment::Peer(peer) = self.assign_peer(
+                        chunk_idx,
+                        &providers,
+                        &in_flight.load,
+                        per_peer,
+                        &excluded,
+                        round_robin,
+                    ) {
+                        debug!(
+                            "Endgame: also requesting chunk {} from {:?}",
+                            chunk_idx, peer.peer_id
+                        );
+                        in_flight.spawn(self.clone_arc_fields(), cid.clone(), chunk_idx, peer);
+                    }
+                }
+            }
+
+            let joined = tokio::select! {
+                joined = in_flight.tasks.join_next(), if !in_flight.tasks.is_empty() => joined,
+                Some(retry) = backoff.join_next(), if !backoff.is_empty() => {
+                    if let Ok(chunk_idx) = retry {
+                        if !completed.contains(&chunk_idx) {
+                            queue.push_back(chunk_idx);
+                        }
+                    }
+                    continue;
+                }
+                else => None,
+            };
+            let Some(joined) = joined else {
+                if queue.is_empty() {
+                    break;
+                }
+                continue;
+            };
+            let (chunk_idx, peer, result) = match joined {
+                Ok(outcome) => outcome,
+                Err(e) if e.is_cancelled() => continue,
+                Err(e) => return Err(e.into()),
+            };
+            // Results of cancelled requests that finished anyway are ignored
+            if !in_flight.finish(chunk_idx, &peer.peer_id) {
+                continue;
+            }
 
-        // Wait for remaining tasks
-        let results = join_all(tasks).await;
-        for result in results {
-            result??;
+            match result {
+                Ok(()) => {
+                    completed.insert(chunk_idx);
+                    in_flight.cancel(chunk_idx);
+                }
+                Err(e) if completed.contains(&chunk_idx) => {
+                    debug!("Late failure of chunk {} ignored: {:#}", chunk_idx, e);
+                }
+                Err(e) if is_refusal(&e) => {
+                    if !in_flight.requests.contains_key(&chunk_idx) {
+                        let count = refusals.entry(chunk_idx).or_insert(0);
+                        let wait = REFUSED_BACKOFF
+                            .saturating_mul(1 << (*count).min(16))
+                            .min(MAX_REFUSED_BACKOFF);
+                        *count += 1;
+                        debug!(
+                            "Chunk {} refused by peer {:?}, asking again in {:?}: {:#}",
+                            chunk_idx, peer.peer_id, wait, e
+                        );
+                        backoff.spawn(async move {
+                            tokio::time::sleep(wait).await;
+                            chunk_idx
+                        });
+                    }
+                }
+                Err(e) => {
+                    warn!(
+                        "Chunk {} from peer {:?} failed, retrying elsewhere: {:#}",
+                        chunk_idx, peer.peer_id, e
+                    );
+                    failed.entry(chunk_idx).or_default().insert(peer.peer_id);
+                    errors.insert(chunk_idx, e);
+                    if !in_flight.requests.contains_key(&chunk_idx) {
+                        queue.push_front(chunk_idx);
+                    }
+                }
+            }
+
+            finished += 1;
+            if finished % refresh_every == 0 {
+                self.refresh_availability(cid, &mut providers).await;
+            }
         }
 
         Ok(())
     }
 
+    /// Pick the least busy peer that has a chunk, skipping `excluded` peers
+    ///
+    /// Ties are broken round-robin.
+    fn assign_peer(
+        &self,
+        chunk_idx: u32,
+        providers: &[PeerInfo],
+        load: &HashMap<PeerId, usize>,
+        per_peer: usize,
+        excluded: &HashSet<PeerId>,
+        round_robin: usize,
+    ) -> Assignment {
+        let candidates: Vec<(&PeerInfo, usize)> = providers
+            .iter()
+            .filter(|p| p.available_chunks.is_empty() || p.available_chunks.contains(&chunk_idx))
+            .filter(|p| !excluded.contains(&p.peer_id))
+            .map(|p| (p, load.get(&p.peer_id).copied().unwrap_or(0)))
+            .collect();
+        if candidates.is_empty() {
+            return Assignment::NoPeer;
+        }
+
+        let Some(least) = candidates.iter().map(|(_, load)| *load).min() else {
+            return Assignment::NoPeer;
+        };
+        if least >= per_peer {
+            return Assignment::Busy;
+        }
+
+        let idle: Vec<&PeerInfo> = candidates
+            .into_iter()
+            .filter(|(_, load)| *load == least)
+            .map(|(peer, _)| peer)
+            .collect();
+        Assignment::Peer(idle[round_robin % idle.len()].clone())
+    }
+
     /// Select best peer for downloading a chunk
     ///
     /// Uses tit-for-tat: prefer peers who upload to us
@@ -591,6 +1183,16 @@ impl ChunkDistributor {
             chunk_idx, cid, peer.peer_id
         );
 
+        // Wait for bandwidth before asking, not after the chunk arrives, so
+        // the limit holds on the wire; the wait doesn't count as the request
+        let len = match self.active_downloads.read().await.get(cid) {
+            Some(download) => download.chunk_len(chunk_idx),
+            None => self.config.chunk_size as u64,
+        };
+        self.rate_limiter
+            .acquire(Direction::Download, &peer.peer_id, len)
+            .await;
+
         // Apply timeout
         let result = timeout(
             self.config.request_timeout,
@@ -607,12 +1209,42 @@ impl ChunkDistributor {
                 }
 
                 // Store chunk
+                let mut on_disk = false;
                 {
                     let mut downloads = self.active_downloads.write().await;
                     if let Some(download) = downloads.get_mut(cid) {
-                        download.downloaded_chunks.insert(chunk_idx);
-                        download.downloaded_bytes += chunk.data.len() as u64;
-                        download.chunks.insert(chunk_idx, chunk.clone());
+                        on_disk = download.output.is_some();
+                        if !download.matches_manifest(&chunk) {
+                            error!(
+                                "Chunk {} from peer {:?} does not match the manifest",
+                                chunk_idx, peer.peer_id
+                            );
+                            return Err(anyhow!("Chunk {} does not match the manifest", chunk_idx));
+                        }
+
+                        // In endgame two peers can deliver the same chunk
+                        if !download.downloaded_chunks.contains(&chunk_idx) {
+                            match &mut download.output {
+                                Some(output) => output.write_chunk(chunk_idx, &chunk.data)?,
+                                None => {
+                                    download.chunks.insert(chunk_idx, chunk.clone());
+                                }
+                            }
+                            download.downloaded_chunks.insert(chunk_idx);
+                            download.downloaded_bytes += chunk.data.len() as u64;
+                        }
+                    }
+                }
+
+                // Serve the chunk to the swarm right away
+                if let Some(network) = &self.network {
+                    let announced = if on_disk {
+                        network.announce_stored(cid, chunk_idx)
+                    } else {
+                        network.announce_have(cid, chunk_idx, chunk.data.clone())
+                    };
+                    if let Err(e) = announced {
+                        warn!("Failed to announce chunk {}: {}", chunk_idx, e);
                     }
                 }
 
@@ -624,6 +1256,8 @@ impl ChunkDistributor {
                         .or_insert_with(|| PeerStats::new(peer.peer_id.clone()));
                     peer_stats.record_download(chunk.data.len() as u64);
                 }
+                self.choker
+                    .record_received(cid, &peer.peer_id, chunk.data.len() as u64);
 
                 debug!("Chunk {} downloaded successfully", chunk_idx);
                 Ok(())
@@ -642,19 +1276,23 @@ impl ChunkDistributor {
         }
     }
 
-    /// Fetch chunk from peer (mock implementation)
+    /// Fetch chunk from peer
     ///
-    /// In production, this would use the network layer to request the chunk
-    /// from the peer via libp2p or WebRTC.
+    /// Goes over the network when a handle is attached, otherwise reads the
+    /// chunk from local content.
     async fn fetch_chunk_from_peer(
         &self,
         cid: &ContentId,
         chunk_idx: u32,
-        _peer: &PeerInfo,
+        peer: &PeerInfo,
     ) -> Result<Chunk> {
-        // Mock implementation: check local content
-        let local = self.local_content.read().await;
-        if let Some(content) = local.get(cid) {
+        if let Some(network) = &self.network {
+            let peer_id = to_network_peer_id(&peer.peer_id)?;
+            let data = network.fetch_chunk(peer_id, cid, chunk_idx).await?;
+            return Ok(Chunk::new(chunk_idx, data));
+        }
+
+        if let Some(content) = self.local_content.get(cid).await? {
             let chunk_size = self.config.chunk_size;
             let start = (chunk_idx as usize) * chunk_size;
             let end = std::cmp::min(start + chunk_size, content.len());
@@ -668,38 +1306,202 @@ impl ChunkDistributor {
         Err(anyhow!("Chunk not available"))
     }
 
-    /// Query content size from peer (mock implementation)
-    async fn query_content_size(&self, cid: &ContentId, _peer: &PeerInfo) -> Result<u64> {
-        // Mock: check local content
-        let local = self.local_content.read().await;
-        if let Some(content) = local.get(cid) {
-            return Ok(content.len() as u64);
+    /// Query the content manifest from the providers
+    ///
+    /// Returns the manifest along with the providers that can serve it. Without
+    /// a network handle the manifest is built from local content.
+    async fn query_manifest(
+        &self,
+        cid: &ContentId,
+        providers: Vec<PeerInfo>,
+    ) -> Result<(ContentManifest, Vec<PeerInfo>)> {
+        let Some(network) = &self.network else {
+            let content = self
+                .local_content
+                .get(cid)
+                .await?
+                .ok_or_else(|| anyhow!("Content {} not available locally", cid))?;
+            let manifest = ContentManifest::from_content(&content, self.config.chunk_size as u32);
+            return Ok((manifest, providers));
+        };
+
+        let queries = providers.iter().map(|provider| async move {
+            let peer_id = to_network_peer_id(&provider.peer_id)?;
+            timeout(
+                self.config.request_timeout,
+                network.fetch_manifest(peer_id, cid),
+            )
+            .await
+            .map_err(|_| anyhow!("Manifest request timed out"))?
+        });
+        let responses = join_all(queries).await;
+
+        // Chunk indices only line up between providers using the same layout,
+        // so follow the first manifest and drop providers that disagree
+        let mut manifest: Option<ContentManifest> = None;
+        let mut usable = Vec::new();
+        for (provider, response) in providers.into_iter().zip(responses) {
+            match response {
+                Ok(received) => match &manifest {
+                    None => {
+                        manifest = Some(received);
+                        usable.push(provider);
+                    }
+                    Some(expected) if *expected == received => usable.push(provider),
+                    Some(_) => {
+                        warn!(
+                            "Peer {:?} uses a different chunk layout for {}, skipping",
+                            provider.peer_id, cid
+                        );
+                    }
+                },
+                Err(e) => {
+                    warn!("Peer {:?} can't serve {}: {:#}", provider.peer_id, cid, e);
+                }
+            }
+        }
+
+        let manifest =
+            manifest.ok_or_else(|| anyhow!("No provider could serve the manifest for {}", cid))?;
+        debug!(
+            "Manifest for {}: {} bytes in {} chunks from {} providers",
+            cid,
+            manifest.total_size,
+            manifest.chunk_count(),
+            usable.len()
+        );
+
+        network.start_sharing(cid, manifest.clone())?;
+        let usable = self
+            .exchange_bitfields(network, cid, manifest.chunk_count(), usable)
+            .await;
+
+        Ok((manifest, usable))
+    }
+
+    /// Swap bitfields with the providers and fill in their available chunks
+    ///
+    /// Providers holding none of the chunks are dropped until a HAVE brings
+    /// them back. Providers that don't answer keep an empty set, which
+    /// [`select_peer_for_chunk`](Self::select_peer_for_chunk) treats as
+    /// having every chunk.
+    async fn exchange_bitfields(
+        &self,
+        network: &NetworkHandle,
+        cid: &ContentId,
+        total_chunks: u32,
+        providers: Vec<PeerInfo>,
+    ) -> Vec<PeerInfo> {
+        let exchanges = providers.iter().map(|provider| async move {
+            let peer_id = to_network_peer_id(&provider.peer_id)?;
+            timeout(
+                self.config.request_timeout,
+                network.exchange_bitfield(peer_id, cid, Bitfield::new(total_chunks)),
+            )
+            .await
+            .map_err(|_| anyhow!("Bitfield request timed out"))?
+        });
+        let responses = join_all(exchanges).await;
+
+        let mut usable = Vec::new();
+        for (mut provider, response) in providers.into_iter().zip(responses) {
+            match response {
+                Ok(bitfield) if bitfield.len() == total_chunks => {
+                    if total_chunks > 0 && bitfield.count() == 0 {
+                        debug!("Peer {:?} has no chunks of {} yet", provider.peer_id, cid);
+                        continue;
+                    }
+                    provider.available_chunks = bitfield.indices().collect();
+                    usable.push(provider);
+                }
+                Ok(bitfield) => {
+                    warn!(
+                        "Peer {:?} sent a bitfield of {} chunks for {} chunks, skipping",
+                        provider.peer_id,
+                        bitfield.len(),
+                        total_chunks
+                    );
+                }
+                Err(e) => {
+                    debug!(
+                        "No bitfield from {:?}, assuming it has every chunk: {:#}",
+                        provider.peer_id, e
+                    );
+                    usable.push(provider);
+                }
+            }
+        }
+
+        usable
+    }
+
+    /// Update the providers from the bitfields and HAVEs received so far
+    ///
+    /// Peers that swapped bitfields with us since the download started are
+    /// added once they hold a chunk. Without a network handle this does
+    /// nothing.
+    async fn refresh_availability(&self, cid: &ContentId, providers: &mut Vec<PeerInfo>) {
+        let Some(network) = &self.network else {
+            return;
+        };
+        let availability = match network.availability(cid).await {
+            Ok(availability) => availability,
+            Err(e) => {
+                warn!("Failed to refresh availability of {}: {}", cid, e);
+                return;
+            }
+        };
+
+        let mut known = HashSet::new();
+        for provider in providers.iter_mut() {
+            let Ok(peer_id) = to_network_peer_id(&provider.peer_id) else {
+                continue;
+            };
+            if let Some(bitfield) = availability.get(&peer_id) {
+                if bitfield.count() > 0 {
+                    provider.available_chunks = bitfield.indices().collect();
+                }
+            }
+            known.insert(peer_id);
         }
 
-        // Default size for testing
-        Ok(1024 * 1024) // 1 MB
+        for (peer_id, bitfield) in availability {
+            if !known.contains(&peer_id) && bitfield.count() > 0 {
+                debug!(
+                    "Peer {} now has {} chunks of {}",
+                    peer_id,
+                    bitfield.count(),
+                    cid
+                );
+                providers.push(PeerInfo::with_chunks(
+                    PeerId(peer_id.to_string()),
+                    bitfield.indices().collect(),
+                ));
+            }
+        }
     }
 
     /// Serve a chunk to a peer (for uploading)
     ///
-    /// Implements tit-for-tat: prioritize peers who upload to us
+    /// Implements tit-for-tat: only peers unchoked for `cid` are served
     pub async fn serve_chunk(
         &mut self,
         cid: ContentId,
         chunk_index: u32,
         peer: PeerId,
     ) -> Result<Chunk> {
-        // Check if we should serve to this peer (tit-for-tat)
-        if !self.should_upload_to_peer(&peer).await {
-            return Err(anyhow!("Peer not eligible for upload (tit-for-tat)"));
-        }
-
         // Get content
-        let local = self.local_content.read().await;
-        let content = local
+        let content = self
+            .local_content
             .get(&cid)
+            .await?
             .ok_or_else(|| anyhow!("Content not found"))?;
 
+        // Check if we should serve to this peer (tit-for-tat)
+        if !self.should_upload_to_peer(&cid, &peer).await {
+            return Err(anyhow!("Peer not eligible for upload (tit-for-tat)"));
+        }
+
         // Extract chunk
         let chunk_size = self.config.chunk_size;
         let start = (chunk_index as usize) * chunk_size;
@@ -711,6 +1513,9 @@ impl ChunkDistributor {
 
         let chunk_data = content[start..end].to_vec();
         let chunk = Chunk::new(chunk_index, chunk_data);
+        self.rate_limiter
+            .acquire(Direction::Upload, &peer, chunk.data.len() as u64)
+            .await;
 
         // Record upload
         {
@@ -720,6 +1525,8 @@ impl ChunkDistributor {
                 .or_insert_with(|| PeerStats::new(peer.clone()));
             peer_stats.record_upload(chunk.data.len() as u64);
         }
+        self.choker
+            .record_sent(&cid, &peer, chunk.data.len() as u64);
 
         {
             let mut uploads = self.active_uploads.write().await;
@@ -741,40 +1548,44 @@ impl ChunkDistributor {
         Ok(chunk)
     }
 
-    /// Determine if we should upload to a peer (tit-for-tat)
+    /// Determine if we should upload a chunk of `cid` to a peer (tit-for-tat)
     ///
-    /// Algorithm:
-    /// 1. Always upload to peers with good upload ratio
-    /// 2. Implement optimistic unchoking: randomly serve new peers every 30s
-    /// 3. This incentivizes fair sharing
-    pub async fn should_upload_to_peer(&self, peer: &PeerId) -> bool {
-        let stats = self.peer_stats.read().await;
-
-        // Check peer ratio first
-        if let Some(peer_stats) = stats.get(peer) {
-            // Upload to peers who upload to us or have reasonable ratio
-            // A ratio of infinity means they haven't downloaded anything yet
-            let ratio = peer_stats.ratio();
-            if ratio >= 0.3 || ratio.is_infinite() {
-                return true;
-            }
-        } else {
-            // New peer - give them a chance
-            return true;
-        }
+    /// Counts as a request from the peer, which keeps it in the running for
+    /// one of the content's upload slots. Rounds run every
+    /// [`CHOKE_ROUND_INTERVAL`](choke::CHOKE_ROUND_INTERVAL); between them
+    /// only peers already unchoked, or arriving while a slot is free, are
+    /// served. Peers are ranked as for a seed once all of `cid` is stored
+    /// locally.
+    pub async fn should_upload_to_peer(&self, cid: &ContentId, peer: &PeerId) -> bool {
+        let seeding = self.is_seeding(cid).await;
+        self.choker.request(cid, peer, seeding)
+    }
 
-        // Optimistic unchoking: periodically give low-ratio peers a chance
-        let last_unchoke = self.last_optimistic_unchoke.read().await;
-        if last_unchoke.elapsed() >= OPTIMISTIC_UNCHOKE_INTERVAL {
-            // Update timestamp
-            drop(last_unchoke);
-            let mut last_unchoke = self.last_optimistic_unchoke.write().await;
-            *last_unchoke = Instant::now();
-            return true;
-        }
+    /// Whether we hold all of `cid`: it's stored and not being downloaded
+    async fn is_seeding(&self, cid: &ContentId) -> bool {
+        !self.active_downloads.read().await.contains_key(cid)
+            && self.local_content.has(cid).await.unwrap_or(false)
+    }
+
+    /// Run a choke round for every content now, rather than at the next
+    /// interval
+    pub async fn rechoke(&self) {
+        self.choker.rechoke();
+    }
+
+    /// Peers currently unchoked for uploads of `cid`
+    pub async fn unchoked_peers(&self, cid: &ContentId) -> Vec<PeerId> {
+        self.choker.unchoked(cid)
+    }
 
-        // Default: don't upload to low-ratio peers outside optimistic unchoke
-        false
+    /// Drop an active download, saving the resume journal of one written
+    /// to disk
+    ///
+    /// For shutting down while [`download_to_file`](Self::download_to_file)
+    /// is still running. Returns whether a download was active.
+    pub async fn cancel_download(&self, cid: &ContentId) -> bool {
+        let mut downloads = self.active_downloads.write().await;
+        downloads.remove(cid).is_some()
     }
 
     /// Get download progress for a content ID
@@ -803,11 +1614,124 @@ impl ChunkDistributor {
             peer_stats: Arc::clone(&self.peer_stats),
             download_semaphore: Arc::clone(&self.download_semaphore),
             local_content: Arc::clone(&self.local_content),
-            last_optimistic_unchoke: Arc::clone(&self.last_optimistic_unchoke),
+            choker: self.choker.clone(),
+            rate_limiter: Arc::clone(&self.rate_limiter),
+            network: self.network.clone(),
         }
     }
 }
 
+/// Where a chunk request can go
+enum Assignment {
+    /// Request the chunk from this peer
+    Peer(PeerInfo),
+    /// Every peer with the chunk already has its fill of requests
+    Busy,
+    /// No peer left to ask
+    NoPeer,
+}
+
+/// Whether a peer turned a request away for now, choking us or busy, rather
+/// than failing it
+fn is_refusal(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<RemoteError>()
+        .is_some_and(|remote| remote.code == ErrorCode::Refused)
+}
+
+/// Chunk requests in flight during a download
+#[derive(Default)]
+struct InFlight {
+    tasks: JoinSet<(u32, PeerInfo, Result<()>)>,
+    /// Peers asked for each outstanding chunk, with a handle to cancel each
+    /// request
+    requests: HashMap<u32, Vec<(PeerId, AbortHandle)>>,
+    /// Requests outstanding per peer
+    load: HashMap<PeerId, usize>,
+}
+
+impl InFlight {
+    /// Request a chunk from a peer on its own task
+    fn spawn(
+        &mut self,
+        distributor: ChunkDistributor,
+        cid: ContentId,
+        chunk_idx: u32,
+        peer: PeerInfo,
+    ) {
+        let peer_id = peer.peer_id.clone();
+        let handle = self.tasks.spawn(async move {
+            // Limit concurrent downloads
+            let result = match distributor.download_semaphore.clone().acquire_owned().await {
+                Ok(_permit) => distributor.download_chunk(&cid, chunk_idx, &peer).await,
+                Err(e) => Err(e.into()),
+            };
+            (chunk_idx, peer, result)
+        });
+
+        *self.load.entry(peer_id.clone()).or_insert(0) += 1;
+        self.requests
+            .entry(chunk_idx)
+            .or_default()
+            .push((peer_id, handle));
+    }
+
+    /// Peers already asked for a chunk
+    fn peers_asked(&self, chunk_idx: u32) -> Vec<PeerId> {
+        self.requests
+            .get(&chunk_idx)
+            .map(|asked| asked.iter().map(|(peer_id, _)| peer_id.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Whether every provider has its fill of requests
+    fn all_busy(&self, providers: &[PeerInfo], per_peer: usize) -> bool {
+        !providers.is_empty()
+            && providers
+                .iter()
+                .all(|p| self.load.get(&p.peer_id).copied().unwrap_or(0) >= per_peer)
+    }
+
+    /// Record that a peer answered; false if the request was cancelled
+    fn finish(&mut self, chunk_idx: u32, peer_id: &PeerId) -> bool {
+        let Some(asked) = self.requests.get_mut(&chunk_idx) else {
+            return false;
+        };
+        let Some(position) = asked.iter().position(|(asked, _)| asked == peer_id) else {
+            return false;
+        };
+
+        asked.remove(position);
+        if asked.is_empty() {
+            self.requests.remove(&chunk_idx);
+        }
+        self.release(peer_id);
+        true
+    }
+
+    /// Cancel the remaining requests for a chunk
+    fn cancel(&mut self, chunk_idx: u32) {
+        for (peer_id, handle) in self.requests.remove(&chunk_idx).unwrap_or_default() {
+            handle.abort();
+            self.release(&peer_id);
+        }
+    }
+
+    fn release(&mut self, peer_id: &PeerId) {
+        if let Some(load) = self.load.get_mut(peer_id) {
+            *load = load.saturating_sub(1);
+        }
+    }
+}
+
+/// Convert a peer ID string into a libp2p peer ID
+fn to_network_peer_id(peer_id: &PeerId) -> Result<libp2p::PeerId> {
+    peer_id
+        .0
+        .parse()
+        .map_err(|e| anyhow!("Invalid peer ID {:?}: {}", peer_id.0, e))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -886,6 +1810,40 @@ mod tests {
         assert!(order[1] == 2 || order[1] == 3);
     }
 
+    #[tokio::test]
+    async fn test_assign_peer_prefers_least_busy() {
+        let distributor = ChunkDistributor::new(ChunkConfig::default()).await.unwrap();
+
+        let full = PeerInfo::new(PeerId("full".to_string()));
+        let partial = PeerInfo::with_chunks(PeerId("partial".to_string()), [0].into());
+        let providers = vec![full.clone(), partial.clone()];
+        let none = HashSet::new();
+
+        let mut load = HashMap::new();
+        load.insert(full.peer_id.clone(), 1);
+        let pick =
+            |chunk, load: &HashMap<PeerId, usize>, excluded: &HashSet<PeerId>| match distributor
+                .assign_peer(chunk, &providers, load, 2, excluded, 0)
+            {
+                Assignment::Peer(peer) => Some(peer.peer_id.0),
+                Assignment::Busy => Some("busy".to_string()),
+                Assignment::NoPeer => None,
+            };
+
+        assert_eq!(pick(0, &load, &none).as_deref(), Some("partial"));
+        assert_eq!(
+            pick(1, &load, &none).as_deref(),
+            Some("full"),
+            "Only one has it"
+        );
+
+        load.insert(full.peer_id.clone(), 2);
+        assert_eq!(pick(1, &load, &none).as_deref(), Some("busy"));
+
+        let excluded: HashSet<PeerId> = [full.peer_id.clone()].into();
+        assert_eq!(pick(1, &load, &excluded), None, "Nobody left to ask");
+    }
+
     #[tokio::test]
     async fn test_content_storage_and_retrieval() {
         let distributor = ChunkDistributor::new(ChunkConfig::default()).await.unwrap();
@@ -895,11 +1853,12 @@ mod tests {
 
         distributor
             .store_content(cid.clone(), content.clone())
-            .await;
+            .await
+            .unwrap();
 
         // Verify content is stored
-        let local = distributor.local_content.read().await;
-        assert_eq!(local.get(&cid), Some(&content));
+        let local = distributor.local_content.get(&cid).await.unwrap();
+        assert_eq!(local, Some(content));
     }
 
     #[tokio::test]
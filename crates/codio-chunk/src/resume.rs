@@ -0,0 +1,507 @@
+//! Downloads written straight to disk, resumable after a restart.
+//!
+//! Verified chunks are written in place into a sparse output file, and a small
+//! JSON journal next to it (`<output>.resume`) records the root CID, the chunk
+//! layout and which chunks are done. Opening the same output for the same
+//! content picks up from the journal; anything that doesn't match starts over.
+//!
+//! The journal is saved every [`JOURNAL_BATCH_CHUNKS`] chunks or
+//! [`JOURNAL_INTERVAL`], and when the file is dropped. A crash loses at most
+//! the last batch, which is fetched again on resume.
+
+use anyhow::{anyhow, Context, Result};
+use codio_common::ContentId;
+use codio_network::{Bitfield, ContentManifest};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Extension appended to the output path for the journal
+const JOURNAL_EXTENSION: &str = "resume";
+
+/// Chunks written between journal saves
+pub const JOURNAL_BATCH_CHUNKS: usize = 64;
+
+/// Longest time written chunks go unrecorded in the journal
+pub const JOURNAL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The resume journal as stored on disk
+#[derive(Debug, Serialize, Deserialize)]
+struct Journal {
+    /// Root CID of the content
+    cid: String,
+    /// Total content size in bytes
+    total_size: u64,
+    /// Size of every chunk except possibly the last
+    chunk_size: u32,
+    /// Completed chunks, hex of the bitmap (most significant bit first)
+    completed: String,
+}
+
+/// An output file being filled in chunk by chunk
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use codio_chunk::DownloadFile;
+/// use codio_common::ContentId;
+/// use codio_network::ContentManifest;
+///
+/// # fn example() -> anyhow::Result<()> {
+/// let content = b"Hello, Codio!".to_vec();
+/// let cid = ContentId::new(&content);
+/// let manifest = ContentManifest::from_content(&content, 4);
+///
+/// let mut file = DownloadFile::open("hello.txt", &cid, &manifest)?;
+/// for index in file.missing() {
+///     let range = manifest.chunk_range(index).unwrap();
+///     file.write_chunk(index, &content[range])?;
+/// }
+/// file.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct DownloadFile {
+    path: PathBuf,
+    journal_path: PathBuf,
+    file: File,
+    cid: ContentId,
+    manifest: ContentManifest,
+    completed: Bitfield,
+    /// Chunks written since the journal was last saved
+    unsaved: usize,
+    saved_at: Instant,
+}
+
+impl DownloadFile {
+    /// Open `path` to download `cid` into, resuming from its journal
+    ///
+    /// Chunks the journal lists are checked against the manifest and fetched
+    /// again if they no longer match. A journal for other content, or for a
+    /// different chunk layout, is discarded along with the file's contents.
+    pub fn open(
+        path: impl Into<PathBuf>,
+        cid: &ContentId,
+        manifest: &ContentManifest,
+    ) -> Result<Self> {
+        let path = path.into();
+        let journal_path = journal_path(&path);
+        let chunk_count = manifest.chunk_count();
+
+        let resumed = match read_journal(&journal_path)? {
+            Some(journal) => match resume_from(&journal, cid, manifest) {
+                Some(completed) => Some(completed),
+                None => {
+                    info!(
+                        "Journal {} is for other content, starting over",
+                        journal_path.display()
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(resumed.is_none())
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        // Unwritten chunks stay holes until they arrive
+        file.set_len(manifest.total_size)
+            .with_context(|| format!("Failed to size {}", path.display()))?;
+
+        let mut download = Self {
+            path,
+            journal_path,
+            file,
+            cid: cid.clone(),
+            manifest: manifest.clone(),
+            completed: Bitfield::new(chunk_count),
+            unsaved: 0,
+            saved_at: Instant::now(),
+        };
+
+        if let Some(completed) = resumed {
+            for index in completed.indices() {
+                let data = download.read_chunk(index)?;
+                if download.manifest.verify_chunk(index, &data) {
+                    download.completed.set(index);
+                } else {
+                    warn!(
+                        "Chunk {} of {} changed on disk, fetching it again",
+                        index,
+                        download.path.display()
+                    );
+                }
+            }
+            info!(
+                "Resuming {} with {} of {} chunks",
+                download.path.display(),
+                download.completed.count(),
+                chunk_count
+            );
+        }
+        download.save_journal()?;
+
+        Ok(download)
+    }
+
+    /// Path of the output file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Path of the resume journal
+    pub fn journal_path(&self) -> &Path {
+        &self.journal_path
+    }
+
+    /// Chunks already written
+    pub fn completed(&self) -> &Bitfield {
+        &self.completed
+    }
+
+    /// Chunks still to download, in order
+    pub fn missing(&self) -> Vec<u32> {
+        (0..self.completed.len())
+            .filter(|&index| !self.completed.has(index))
+            .collect()
+    }
+
+    /// Bytes already written
+    pub fn completed_bytes(&self) -> u64 {
+        self.completed
+            .indices()
+            .filter_map(|index| self.manifest.chunk_range(index))
+            .map(|range| range.len() as u64)
+            .sum()
+    }
+
+    /// Read a chunk back from the output file
+    pub fn read_chunk(&mut self, index: u32) -> Result<Vec<u8>> {
+        let range = self
+            .manifest
+            .chunk_range(index)
+            .ok_or_else(|| anyhow!("Chunk {} out of range", index))?;
+
+        let mut data = vec![0u8; range.len()];
+        self.file.seek(SeekFrom::Start(range.start as u64))?;
+        self.file
+            .read_exact(&mut data)
+            .with_context(|| format!("Failed to read {}", self.path.display()))?;
+        Ok(data)
+    }
+
+    /// Write a verified chunk in place and record it in the journal
+    ///
+    /// The journal is saved in batches (see [`flush`](Self::flush)), so a
+    /// crash can lose the last few chunks but never claim one that wasn't
+    /// written.
+    pub fn write_chunk(&mut self, index: u32, data: &[u8]) -> Result<()> {
+        if !self.manifest.verify_chunk(index, data) {
+            return Err(anyhow!("Chunk {} does not match the manifest", index));
+        }
+        if self.completed.has(index) {
+            return Ok(());
+        }
+
+        let range = self
+            .manifest
+            .chunk_range(index)
+            .ok_or_else(|| anyhow!("Chunk {} out of range", index))?;
+        self.file.seek(SeekFrom::Start(range.start as u64))?;
+        self.file
+            .write_all(data)
+            .with_context(|| format!("Failed to write {}", self.path.display()))?;
+
+        self.completed.set(index);
+        self.unsaved += 1;
+        if self.unsaved >= JOURNAL_BATCH_CHUNKS || self.saved_at.elapsed() >= JOURNAL_INTERVAL {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Record every chunk written so far in the journal
+    ///
+    /// The chunks reach the disk before the journal lists them.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.unsaved == 0 {
+            return Ok(());
+        }
+        self.file
+            .sync_data()
+            .with_context(|| format!("Failed to write {}", self.path.display()))?;
+        self.save_journal()?;
+        self.unsaved = 0;
+        self.saved_at = Instant::now();
+        Ok(())
+    }
+
+    /// Check the finished file against the CID and remove the journal
+    ///
+    /// A file that fails the check is left in place, but its journal is
+    /// removed so the next attempt starts over.
+    pub fn finish(mut self) -> Result<u64> {
+        if !self.completed.is_complete() {
+            return Err(anyhow!(
+                "{} is missing {} chunks",
+                self.path.display(),
+                self.completed.len() - self.completed.count()
+            ));
+        }
+        // The journal goes either way, so there is nothing left to save
+        self.unsaved = 0;
+
+        self.file.seek(SeekFrom::Start(0))?;
+        let actual = ContentId::from_reader_with(self.cid.algorithm(), &mut self.file)
+            .with_context(|| format!("Failed to read {}", self.path.display()))?;
+        fs::remove_file(&self.journal_path)
+            .with_context(|| format!("Failed to remove {}", self.journal_path.display()))?;
+
+        if actual.hash() != self.cid.hash() {
+            return Err(anyhow!(
+                "Content verification failed for {}: {} does not match",
+                self.cid,
+                self.path.display()
+            ));
+        }
+
+        debug!(
+            "Finished {} ({} bytes)",
+            self.path.display(),
+            self.manifest.total_size
+        );
+        Ok(self.manifest.total_size)
+    }
+
+    fn save_journal(&self) -> Result<()> {
+        let journal = Journal {
+            cid: self.cid.to_string(),
+            total_size: self.manifest.total_size,
+            chunk_size: self.manifest.chunk_size,
+            completed: hex::encode(self.completed.as_bytes()),
+        };
+        let json = serde_json::to_vec_pretty(&journal)?;
+
+        // `<output>.resume.tmp`, so nothing else next to the output is touched
+        let mut temp = self.journal_path.clone().into_os_string();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let mut file =
+            File::create(&temp).with_context(|| format!("Failed to write {}", temp.display()))?;
+        file.write_all(&json)?;
+        file.sync_all()?;
+        fs::rename(&temp, &self.journal_path)
+            .with_context(|| format!("Failed to write {}", self.journal_path.display()))?;
+        Ok(())
+    }
+}
+
+impl Drop for DownloadFile {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            warn!(
+                "Failed to save journal {}: {}",
+                self.journal_path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// `<output>.resume`, next to the output file
+fn journal_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".");
+    name.push(JOURNAL_EXTENSION);
+    PathBuf::from(name)
+}
+
+fn read_journal(path: &Path) -> Result<Option<Journal>> {
+    match fs::read(path) {
+        Ok(bytes) => match serde_json::from_slice(&bytes) {
+            Ok(journal) => Ok(Some(journal)),
+            Err(e) => {
+                warn!("Ignoring unreadable journal {}: {}", path.display(), e);
+                Ok(None)
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+}
+
+/// The chunks a journal lists, if it describes this download
+fn resume_from(journal: &Journal, cid: &ContentId, manifest: &ContentManifest) -> Option<Bitfield> {
+    let journal_cid: ContentId = journal.cid.parse().ok()?;
+    if journal_cid.routing_key() != cid.routing_key()
+        || journal.total_size != manifest.total_size
+        || journal.chunk_size != manifest.chunk_size
+    {
+        return None;
+    }
+
+    let bits = hex::decode(&journal.completed).ok()?;
+    Bitfield::from_bytes(manifest.chunk_count(), &bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content() -> Vec<u8> {
+        (0..10_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn write(file: &mut DownloadFile, content: &[u8], index: u32) {
+        let range = file.manifest.chunk_range(index).unwrap();
+        file.write_chunk(index, &content[range]).unwrap();
+    }
+
+    #[test]
+    fn test_resume_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.bin");
+        let content = content();
+        let cid = ContentId::new(&content);
+        let manifest = ContentManifest::from_content(&content, 1024);
+
+        let mut file = DownloadFile::open(&path, &cid, &manifest).unwrap();
+        assert_eq!(file.missing().len(), 10);
+        write(&mut file, &content, 0);
+        write(&mut file, &content, 9);
+        assert!(file.journal_path().exists());
+        drop(file);
+
+        let mut file = DownloadFile::open(&path, &cid, &manifest).unwrap();
+        assert_eq!(file.completed().indices().collect::<Vec<_>>(), [0, 9]);
+        assert_eq!(file.completed_bytes(), 1024 + 784);
+        for index in file.missing() {
+            write(&mut file, &content, index);
+        }
+        let journal = file.journal_path().to_path_buf();
+        assert_eq!(file.finish().unwrap(), 10_000);
+
+        assert_eq!(fs::read(&path).unwrap(), content);
+        assert!(!journal.exists());
+    }
+
+    #[test]
+    fn test_changed_chunks_fetched_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.bin");
+        let content = content();
+        let cid = ContentId::new(&content);
+        let manifest = ContentManifest::from_content(&content, 1024);
+
+        let mut file = DownloadFile::open(&path, &cid, &manifest).unwrap();
+        write(&mut file, &content, 1);
+        write(&mut file, &content, 2);
+        drop(file);
+
+        // Someone scribbles over chunk 2
+        let mut raw = OpenOptions::new().write(true).open(&path).unwrap();
+        raw.seek(SeekFrom::Start(2048)).unwrap();
+        raw.write_all(b"oops").unwrap();
+        drop(raw);
+
+        let file = DownloadFile::open(&path, &cid, &manifest).unwrap();
+        assert_eq!(file.completed().indices().collect::<Vec<_>>(), [1]);
+    }
+
+    #[test]
+    fn test_journal_for_other_content_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.bin");
+        let content = content();
+        let cid = ContentId::new(&content);
+
+        let mut file =
+            DownloadFile::open(&path, &cid, &ContentManifest::from_content(&content, 1024))
+                .unwrap();
+        write(&mut file, &content, 0);
+        drop(file);
+
+        // Same content, different chunk layout
+        let manifest = ContentManifest::from_content(&content, 2048);
+        let file = DownloadFile::open(&path, &cid, &manifest).unwrap();
+        assert_eq!(file.completed().count(), 0);
+
+        // Different content
+        let other = b"something else".to_vec();
+        let other_cid = ContentId::new(&other);
+        let file = DownloadFile::open(
+            &path,
+            &other_cid,
+            &ContentManifest::from_content(&other, 1024),
+        )
+        .unwrap();
+        assert_eq!(file.completed().count(), 0);
+        assert_eq!(fs::metadata(&path).unwrap().len(), other.len() as u64);
+    }
+
+    #[test]
+    fn test_journal_leaves_neighbours_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.bin");
+        let content = content();
+        let cid = ContentId::new(&content);
+        let manifest = ContentManifest::from_content(&content, 1024);
+
+        // A file of the user's that an extension swap on the journal would hit
+        let neighbour = dir.path().join("out.bin.tmp");
+        fs::write(&neighbour, b"keep me").unwrap();
+
+        let mut file = DownloadFile::open(&path, &cid, &manifest).unwrap();
+        write(&mut file, &content, 0);
+        let journal = file.journal_path().to_path_buf();
+        drop(file);
+
+        assert_eq!(fs::read(&neighbour).unwrap(), b"keep me");
+        assert!(journal.exists());
+        assert!(!dir.path().join("out.bin.resume.tmp").exists());
+    }
+
+    #[test]
+    fn test_journal_saved_in_batches() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out.bin");
+        let content: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let cid = ContentId::new(&content);
+        let manifest = ContentManifest::from_content(&content, 1000);
+
+        let mut file = DownloadFile::open(&path, &cid, &manifest).unwrap();
+        for index in 0..JOURNAL_BATCH_CHUNKS as u32 + 1 {
+            write(&mut file, &content, index);
+        }
+        let journal = read_journal(file.journal_path()).unwrap().unwrap();
+        let saved = resume_from(&journal, &cid, &manifest).unwrap();
+        assert_eq!(saved.count(), JOURNAL_BATCH_CHUNKS as u32);
+
+        // The rest is saved when the file is dropped
+        drop(file);
+        let file = DownloadFile::open(&path, &cid, &manifest).unwrap();
+        assert_eq!(file.completed().count(), JOURNAL_BATCH_CHUNKS as u32 + 1);
+    }
+
+    #[test]
+    fn test_rejects_bad_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = content();
+        let cid = ContentId::new(&content);
+        let manifest = ContentManifest::from_content(&content, 1024);
+
+        let mut file = DownloadFile::open(dir.path().join("out.bin"), &cid, &manifest).unwrap();
+        assert!(file.write_chunk(0, b"not the chunk").is_err());
+        assert!(file.finish().is_err(), "Nothing written yet");
+    }
+}
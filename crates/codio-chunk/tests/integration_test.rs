@@ -1,5 +1,11 @@
+use codio_chunk::choke::CHOKE_ROUND_INTERVAL;
 use codio_chunk::{Chunk, ChunkConfig, ChunkDistributor, DistributionStrategy, PeerInfo};
-use codio_common::{ContentId, PeerId};
+use codio_common::{ContentId, PeerId, RateLimits};
+use codio_content::{Chunker, MerkleDAG};
+use codio_network::{
+    ContentManifest, ErrorCode, NetworkConfig, NetworkEvent, NetworkManager, RemoteError,
+};
+use libp2p::multiaddr::{Multiaddr, Protocol};
 use std::collections::HashSet;
 
 /// Test parallel downloads from multiple peers
@@ -12,6 +18,9 @@ async fn test_parallel_download() {
         request_timeout: std::time::Duration::from_secs(5),
         strategy: DistributionStrategy::Sequential,
         chunk_size: 256,
+        endgame_threshold: 4,
+        upload_slots: 4,
+        rate_limits: Default::default(),
     };
 
     let mut distributor = ChunkDistributor::new(config).await.unwrap();
@@ -23,7 +32,8 @@ async fn test_parallel_download() {
     // Store content locally (simulating that all peers have it)
     distributor
         .store_content(cid.clone(), content.clone())
-        .await;
+        .await
+        .unwrap();
 
     // Setup 5 peers, each has all chunks
     let mut providers = Vec::new();
@@ -54,6 +64,9 @@ async fn test_rarest_first() {
         request_timeout: std::time::Duration::from_secs(5),
         strategy: DistributionStrategy::RarestFirst,
         chunk_size: 256,
+        endgame_threshold: 4,
+        upload_slots: 4,
+        rate_limits: Default::default(),
     };
 
     let distributor = ChunkDistributor::new(config).await.unwrap();
@@ -130,7 +143,8 @@ async fn test_chunk_verification_in_download() {
     // Store valid content
     distributor
         .store_content(cid.clone(), content.to_vec())
-        .await;
+        .await
+        .unwrap();
 
     // Create provider
     let peer1 = PeerInfo::new(PeerId("peer1".to_string()));
@@ -153,12 +167,13 @@ async fn test_tit_for_tat() {
     // Store content
     distributor
         .store_content(cid.clone(), content.clone())
-        .await;
+        .await
+        .unwrap();
 
     let peer1 = PeerId("peer1".to_string());
 
     // Initially, new peers should be allowed to download (optimistic unchoking)
-    let can_serve_1 = distributor.should_upload_to_peer(&peer1).await;
+    let can_serve_1 = distributor.should_upload_to_peer(&cid, &peer1).await;
     assert!(
         can_serve_1,
         "New peer should be allowed (optimistic unchoke)"
@@ -179,13 +194,162 @@ async fn test_tit_for_tat() {
 
     // Verify that a peer with good ratio (infinity since no downloads) can still upload
     drop(stats);
-    let can_serve_again = distributor.should_upload_to_peer(&peer1).await;
+    let can_serve_again = distributor.should_upload_to_peer(&cid, &peer1).await;
     assert!(
         can_serve_again,
         "Peer with good ratio should be allowed to continue"
     );
 }
 
+/// Test that uploads are limited to the unchoked peers of each choke round
+#[tokio::test(start_paused = true)]
+async fn test_choke_rounds_limit_uploads() {
+    let config = ChunkConfig::default().with_upload_slots(2);
+    let mut distributor = ChunkDistributor::new(config).await.unwrap();
+
+    let content = b"test content for choke rounds".to_vec();
+    let cid = ContentId::new(&content);
+    distributor
+        .store_content(cid.clone(), content.clone())
+        .await
+        .unwrap();
+
+    let peers: Vec<PeerId> = (1..=4).map(|i| PeerId(format!("peer{}", i))).collect();
+    async fn served(distributor: &mut ChunkDistributor, cid: &ContentId, peer: &PeerId) -> bool {
+        distributor
+            .serve_chunk(cid.clone(), 0, peer.clone())
+            .await
+            .is_ok()
+    }
+
+    // The first two peers fill the free slots, the rest wait for a round
+    assert!(served(&mut distributor, &cid, &peers[0]).await);
+    assert!(served(&mut distributor, &cid, &peers[1]).await);
+    assert!(!served(&mut distributor, &cid, &peers[2]).await);
+    assert!(!served(&mut distributor, &cid, &peers[3]).await);
+
+    // Next round, one slot goes to a peer we uploaded to and the optimistic
+    // slot to a peer that hasn't had one yet
+    tokio::time::sleep(CHOKE_ROUND_INTERVAL + std::time::Duration::from_millis(1)).await;
+    let unchoked = distributor.unchoked_peers(&cid).await;
+    assert_eq!(unchoked.len(), 2);
+    assert!(unchoked.contains(&peers[0]));
+    assert!(unchoked.contains(&peers[2]));
+
+    assert!(served(&mut distributor, &cid, &peers[2]).await);
+    assert!(!served(&mut distributor, &cid, &peers[1]).await);
+    assert!(!served(&mut distributor, &cid, &peers[3]).await);
+}
+
+/// Test that content being seeded is ranked by upload while another content
+/// is still downloading
+#[tokio::test(start_paused = true)]
+async fn test_choke_seeding_is_per_content() {
+    let config = ChunkConfig::default()
+        .with_chunk_size(256)
+        .with_upload_slots(2)
+        .with_rate_limits(RateLimits::default().with_download(1024));
+    let mut distributor = ChunkDistributor::new(config).await.unwrap();
+
+    let downloading = b"d".repeat(4096); // 16 chunks, 3s at 1 KB/s
+    let seeded = b"s".repeat(4096);
+    let downloading_cid = ContentId::new(&downloading);
+    let seeded_cid = ContentId::new(&seeded);
+    for (cid, content) in [(&downloading_cid, &downloading), (&seeded_cid, &seeded)] {
+        distributor
+            .store_content(cid.clone(), content.clone())
+            .await
+            .unwrap();
+    }
+
+    let giver = PeerId("peer1".to_string());
+    let taker = PeerId("taker".to_string());
+    let other = PeerId("other".to_string());
+
+    // Download one content from `giver` and stop halfway through
+    let mut downloader = distributor.clone_arc_fields();
+    let cid = downloading_cid.clone();
+    let providers = vec![PeerInfo::with_chunks(giver.clone(), (0..16).collect())];
+    let download = tokio::spawn(async move { downloader.download_content(cid, providers).await });
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+
+    for peer in [&taker, &other, &giver] {
+        distributor
+            .should_upload_to_peer(&downloading_cid, peer)
+            .await;
+    }
+    for peer in [&giver, &taker, &other] {
+        distributor.should_upload_to_peer(&seeded_cid, peer).await;
+    }
+    distributor
+        .serve_chunk(seeded_cid.clone(), 0, taker.clone())
+        .await
+        .unwrap();
+    distributor.rechoke().await;
+
+    // The download ranks by what peers gave us, the seed by what they took
+    assert!(distributor
+        .unchoked_peers(&downloading_cid)
+        .await
+        .contains(&giver));
+    let unchoked = distributor.unchoked_peers(&seeded_cid).await;
+    assert!(unchoked.contains(&taker));
+    assert!(!unchoked.contains(&giver));
+
+    assert_eq!(download.await.unwrap().unwrap(), downloading);
+}
+
+/// Test that bandwidth limits pace downloads and uploads, and can be changed
+/// while running
+#[tokio::test(start_paused = true)]
+async fn test_rate_limits_pace_transfers() {
+    let limits = RateLimits::default()
+        .with_download(1024)
+        .with_peer_upload(512);
+    let config = ChunkConfig::default()
+        .with_chunk_size(256)
+        .with_rate_limits(limits);
+    let mut distributor = ChunkDistributor::new(config).await.unwrap();
+
+    let content = b"r".repeat(4096); // 16 chunks
+    let cid = ContentId::new(&content);
+    distributor
+        .store_content(cid.clone(), content.clone())
+        .await
+        .unwrap();
+    let providers = vec![PeerInfo::with_chunks(
+        PeerId("peer1".to_string()),
+        (0..16).collect(),
+    )];
+
+    // One second's worth goes at once, the other 3072 bytes take 3s
+    let start = tokio::time::Instant::now();
+    let downloaded = distributor
+        .download_content(cid.clone(), providers.clone())
+        .await
+        .unwrap();
+    assert_eq!(downloaded, content);
+    assert_eq!(start.elapsed(), std::time::Duration::from_secs(3));
+
+    // Each peer gets 512 bytes a second, so the last 512 of 1024 take 1s
+    let start = tokio::time::Instant::now();
+    for index in 0..4 {
+        let peer = PeerId("peer2".to_string());
+        distributor
+            .serve_chunk(cid.clone(), index, peer)
+            .await
+            .unwrap();
+    }
+    assert_eq!(start.elapsed(), std::time::Duration::from_secs(1));
+
+    // Lifting the limits takes effect straight away
+    distributor.set_rate_limits(RateLimits::unlimited());
+    assert!(distributor.rate_limits().is_unlimited());
+    let start = tokio::time::Instant::now();
+    distributor.download_content(cid, providers).await.unwrap();
+    assert_eq!(start.elapsed(), std::time::Duration::ZERO);
+}
+
 /// Test download progress tracking
 #[tokio::test]
 async fn test_download_progress() {
@@ -194,14 +358,15 @@ async fn test_download_progress() {
         ..Default::default()
     };
 
-    let mut distributor = ChunkDistributor::new(config).await.unwrap();
+    let distributor = ChunkDistributor::new(config).await.unwrap();
 
     let content = b"a".repeat(1024); // 4 chunks
     let cid = ContentId::new(&content);
 
     distributor
         .store_content(cid.clone(), content.clone())
-        .await;
+        .await
+        .unwrap();
 
     let peer1 = PeerInfo::new(PeerId("peer1".to_string()));
     let providers = vec![peer1];
@@ -313,6 +478,32 @@ async fn test_chunk_reconstruction() {
     );
 }
 
+/// Test reading a byte range from a partial download
+#[tokio::test]
+async fn test_range_read_from_partial_download() {
+    use codio_chunk::Download;
+
+    let content = b"Hello, distributed world!".to_vec();
+    let cid = ContentId::new(&content);
+
+    let mut download = Download::new(
+        cid,
+        content.len() as u64,
+        10,
+        DistributionStrategy::Sequential,
+    );
+
+    // Only the middle chunk has arrived
+    let chunk = Chunk::new(1, content[10..20].to_vec());
+    download.chunks.insert(1, chunk);
+    download.downloaded_chunks.insert(1);
+
+    assert_eq!(download.chunk_range(12, 5).unwrap(), 1..2);
+    assert_eq!(download.read_range(12, 5).unwrap(), &content[12..17]);
+    assert!(download.read_range(8, 5).is_err(), "Chunk 0 is missing");
+    assert!(download.reconstruct().is_err());
+}
+
 /// Test handling of missing chunks
 #[tokio::test]
 async fn test_missing_chunks() {
@@ -362,3 +553,540 @@ async fn test_peer_selection() {
     assert!(selected.is_some());
     assert_eq!(selected.unwrap().peer_id, peer2.peer_id);
 }
+
+/// Start a loopback network node without mDNS
+async fn start_node(config: NetworkConfig) -> (NetworkManager, Multiaddr) {
+    let mut manager = NetworkManager::new(config.without_mdns()).await.unwrap();
+    manager.start().await.unwrap();
+
+    let port = manager.listen_addrs()[0]
+        .iter()
+        .find_map(|component| match component {
+            Protocol::Tcp(port) => Some(port),
+            _ => None,
+        })
+        .unwrap();
+    let addr = format!("/ip4/127.0.0.1/tcp/{}/p2p/{}", port, manager.peer_id())
+        .parse()
+        .unwrap();
+
+    (manager, addr)
+}
+
+/// Test downloading a file end to end from two providers over loopback
+#[tokio::test]
+async fn test_network_download_between_nodes() {
+    let content: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+    let cid = ContentId::new(&content);
+
+    // Two providers serving the same content in 1 KB chunks
+    let mut providers = Vec::new();
+    let mut provider_addrs = Vec::new();
+    for _ in 0..2 {
+        let (mut node, addr) = start_node(NetworkConfig::default().with_chunk_size(1024)).await;
+        node.provide_content(cid.clone(), content.clone()).unwrap();
+        providers.push(PeerInfo::new(PeerId(node.peer_id().to_string())));
+        provider_addrs.push(addr);
+        tokio::spawn(async move { node.run().await });
+    }
+
+    let (mut fetcher, _) = start_node(NetworkConfig::default()).await;
+    for addr in provider_addrs {
+        fetcher.connect_peer(addr).await.unwrap();
+    }
+    let handle = fetcher.handle();
+    tokio::spawn(async move { fetcher.run().await });
+
+    let config = ChunkConfig {
+        strategy: DistributionStrategy::Sequential,
+        request_timeout: std::time::Duration::from_secs(10),
+        ..Default::default()
+    };
+    let mut distributor = ChunkDistributor::new(config)
+        .await
+        .unwrap()
+        .with_network(handle);
+
+    let downloaded = distributor
+        .download_content(cid.clone(), providers.clone())
+        .await
+        .unwrap();
+    assert_eq!(downloaded, content);
+
+    // Both providers contributed chunks
+    let stats = distributor.peer_stats.read().await;
+    for provider in &providers {
+        let downloaded = stats.get(&provider.peer_id).map(|s| s.downloaded_bytes);
+        assert!(
+            downloaded.unwrap_or(0) > 0,
+            "Provider {:?} should have served chunks",
+            provider.peer_id
+        );
+    }
+    drop(stats);
+
+    // Content no provider has fails instead of hanging
+    let missing = ContentId::new(b"nobody has this");
+    let result = distributor.download_content(missing, providers).await;
+    assert!(result.is_err(), "Download of unknown content should fail");
+}
+
+/// Merkle DAG root of `content` in 1 KB chunks
+fn dag_root(content: &[u8]) -> ContentId {
+    MerkleDAG::from_chunks(&Chunker::new(1024).chunk(content))
+        .root_cid()
+        .clone()
+}
+
+/// Start a node serving `content` under `cid` in 1 KB chunks, and a
+/// distributor connected to it
+async fn range_fixture(cid: &ContentId, content: Vec<u8>) -> (ChunkDistributor, PeerInfo) {
+    let (mut node, addr) = start_node(NetworkConfig::default().with_chunk_size(1024)).await;
+    node.provide_content(cid.clone(), content).unwrap();
+    let provider = PeerInfo::new(PeerId(node.peer_id().to_string()));
+    tokio::spawn(async move { node.run().await });
+
+    let (mut fetcher, _) = start_node(NetworkConfig::default()).await;
+    fetcher.connect_peer(addr).await.unwrap();
+    let handle = fetcher.handle();
+    tokio::spawn(async move { fetcher.run().await });
+
+    let config = ChunkConfig {
+        request_timeout: std::time::Duration::from_secs(10),
+        ..Default::default()
+    };
+    let distributor = ChunkDistributor::new(config)
+        .await
+        .unwrap()
+        .with_network(handle);
+
+    (distributor, provider)
+}
+
+/// Test that a range download over the network fetches only the chunks it needs
+#[tokio::test]
+async fn test_network_range_download() {
+    let content: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+    let cid = dag_root(&content);
+    let (mut distributor, provider) = range_fixture(&cid, content.clone()).await;
+
+    // Bytes 5000..7000 lie in chunks 4 to 6
+    let range = distributor
+        .download_range(cid.clone(), vec![provider.clone()], 5000, 2000)
+        .await
+        .unwrap();
+    assert_eq!(range, &content[5000..7000]);
+
+    let stats = distributor.peer_stats.read().await;
+    assert_eq!(stats[&provider.peer_id].downloaded_bytes, 3 * 1024);
+    drop(stats);
+
+    // The end of the file is cut short; past it is an error
+    let tail = distributor
+        .download_range(cid.clone(), vec![provider.clone()], 19_900, 1000)
+        .await
+        .unwrap();
+    assert_eq!(tail, &content[19_900..]);
+    assert!(distributor
+        .download_range(cid, vec![provider], 20_001, 1)
+        .await
+        .is_err());
+}
+
+/// Test that a range read is refused when the provider's manifest doesn't
+/// rebuild to the requested root
+#[tokio::test]
+async fn test_network_range_rejects_forged_manifest() {
+    let content: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+    let cid = dag_root(&content);
+
+    // The provider serves other bytes under the root, with a manifest and
+    // chunks that agree with each other
+    let mut forged = content.clone();
+    forged[5500] ^= 0xff;
+    let (mut distributor, provider) = range_fixture(&cid, forged).await;
+
+    let result = distributor
+        .download_range(cid, vec![provider.clone()], 5000, 2000)
+        .await;
+    assert!(result.is_err(), "Forged manifest should be rejected");
+
+    // Nothing was fetched on the forged manifest's word
+    let stats = distributor.peer_stats.read().await;
+    let downloaded = stats.get(&provider.peer_id).map(|s| s.downloaded_bytes);
+    assert_eq!(downloaded.unwrap_or(0), 0);
+}
+
+/// Test that a range of content addressed by a whole-content hash is refused
+#[tokio::test]
+async fn test_network_range_rejects_unverifiable_cid() {
+    let content: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+    let cid = ContentId::new(&content);
+    let (mut distributor, provider) = range_fixture(&cid, content).await;
+
+    assert!(distributor
+        .download_range(cid, vec![provider], 5000, 2000)
+        .await
+        .is_err());
+}
+
+/// Test that bitfields keep a partial peer from being asked for chunks it lacks
+#[tokio::test]
+async fn test_network_download_with_partial_peer() {
+    let content: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+    let cid = ContentId::new(&content);
+    let manifest = ContentManifest::from_content(&content, 1024);
+
+    let (mut seeder, seeder_addr) =
+        start_node(NetworkConfig::default().with_chunk_size(1024)).await;
+    seeder
+        .provide_content(cid.clone(), content.clone())
+        .unwrap();
+    let seeder_id = seeder.peer_id();
+    let seeder_handle = seeder.handle();
+    tokio::spawn(async move { seeder.run().await });
+
+    // A peer midway through its own download holds only the first 4 chunks
+    let (mut partial, partial_addr) = start_node(NetworkConfig::default()).await;
+    let partial_handle = partial.handle();
+    tokio::spawn(async move { partial.run().await });
+    partial_handle
+        .start_sharing(&cid, manifest.clone())
+        .unwrap();
+    for index in 0..4 {
+        let range = manifest.chunk_range(index).unwrap();
+        partial_handle
+            .announce_have(&cid, index, content[range].to_vec())
+            .unwrap();
+    }
+
+    let (mut fetcher, _) = start_node(NetworkConfig::default()).await;
+    let fetcher_id = fetcher.peer_id();
+    fetcher.connect_peer(seeder_addr).await.unwrap();
+    fetcher.connect_peer(partial_addr).await.unwrap();
+    let handle = fetcher.handle();
+    tokio::spawn(async move { fetcher.run().await });
+
+    let config = ChunkConfig {
+        request_timeout: std::time::Duration::from_secs(10),
+        ..Default::default()
+    };
+    let mut distributor = ChunkDistributor::new(config)
+        .await
+        .unwrap()
+        .with_network(handle);
+
+    let seeder_peer = PeerInfo::new(PeerId(seeder_id.to_string()));
+    let partial_peer = PeerInfo::new(PeerId(partial_handle.peer_id().to_string()));
+    let downloaded = distributor
+        .download_content(cid.clone(), vec![seeder_peer, partial_peer.clone()])
+        .await
+        .unwrap();
+    assert_eq!(downloaded, content);
+
+    // The partial peer helped, but only with chunks it had
+    let stats = distributor.peer_stats.read().await;
+    let from_partial = stats[&partial_peer.peer_id].downloaded_bytes;
+    assert!(from_partial > 0 && from_partial <= 4 * 1024);
+    drop(stats);
+
+    // The seeder heard a HAVE for every chunk the fetcher completed
+    let fetcher_has = tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        loop {
+            let availability = seeder_handle.availability(&cid).await.unwrap();
+            match availability.get(&fetcher_id) {
+                Some(bitfield) if bitfield.is_complete() => return bitfield.clone(),
+                _ => tokio::time::sleep(std::time::Duration::from_millis(50)).await,
+            }
+        }
+    })
+    .await
+    .expect("Seeder should learn the fetcher has every chunk");
+    assert_eq!(fetcher_has.count(), manifest.chunk_count());
+}
+
+/// Drive a provider until it is first asked for a chunk, then hand it back
+/// without sending the chunk
+async fn serve_until_first_chunk(mut node: NetworkManager) -> NetworkManager {
+    loop {
+        if let Some(NetworkEvent::ContentServed { .. }) = node.next_event().await {
+            return node;
+        }
+    }
+}
+
+/// Test that chunks a peer fails to deliver are retried on another provider
+#[tokio::test]
+async fn test_network_download_retries_dropped_peer() {
+    let content: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+    let cid = ContentId::new(&content);
+
+    let (mut steady, steady_addr) =
+        start_node(NetworkConfig::default().with_chunk_size(1024)).await;
+    steady
+        .provide_content(cid.clone(), content.clone())
+        .unwrap();
+    let steady_peer = PeerInfo::new(PeerId(steady.peer_id().to_string()));
+    tokio::spawn(async move { steady.run().await });
+
+    // This provider goes away after its first chunk request
+    let (mut dropped, dropped_addr) =
+        start_node(NetworkConfig::default().with_chunk_size(1024)).await;
+    dropped
+        .provide_content(cid.clone(), content.clone())
+        .unwrap();
+    let dropped_peer = PeerInfo::new(PeerId(dropped.peer_id().to_string()));
+    tokio::spawn(async move { drop(serve_until_first_chunk(dropped).await) });
+
+    let (mut fetcher, _) = start_node(NetworkConfig::default()).await;
+    fetcher.connect_peer(steady_addr).await.unwrap();
+    fetcher.connect_peer(dropped_addr).await.unwrap();
+    let handle = fetcher.handle();
+    tokio::spawn(async move { fetcher.run().await });
+
+    let config = ChunkConfig {
+        strategy: DistributionStrategy::Sequential,
+        request_timeout: std::time::Duration::from_secs(10),
+        ..Default::default()
+    };
+    let mut distributor = ChunkDistributor::new(config)
+        .await
+        .unwrap()
+        .with_network(handle);
+
+    let downloaded = distributor
+        .download_content(cid, vec![dropped_peer, steady_peer.clone()])
+        .await
+        .unwrap();
+    assert_eq!(downloaded, content);
+
+    let stats = distributor.peer_stats.read().await;
+    assert!(stats[&steady_peer.peer_id].downloaded_bytes >= 19 * 1024);
+}
+
+/// Test that endgame mode finishes a download without waiting on a stalled peer
+#[tokio::test]
+async fn test_network_endgame_bypasses_stalled_peer() {
+    let content: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+    let cid = ContentId::new(&content);
+
+    let (mut fast, fast_addr) = start_node(NetworkConfig::default().with_chunk_size(1024)).await;
+    fast.provide_content(cid.clone(), content.clone()).unwrap();
+    let fast_peer = PeerInfo::new(PeerId(fast.peer_id().to_string()));
+    tokio::spawn(async move { fast.run().await });
+
+    // This provider stays connected but never answers a chunk request
+    let (mut stalled, stalled_addr) =
+        start_node(NetworkConfig::default().with_chunk_size(1024)).await;
+    stalled
+        .provide_content(cid.clone(), content.clone())
+        .unwrap();
+    let stalled_peer = PeerInfo::new(PeerId(stalled.peer_id().to_string()));
+    tokio::spawn(async move {
+        let _stalled = serve_until_first_chunk(stalled).await;
+        std::future::pending::<()>().await
+    });
+
+    let (mut fetcher, _) = start_node(NetworkConfig::default()).await;
+    fetcher.connect_peer(fast_addr).await.unwrap();
+    fetcher.connect_peer(stalled_addr).await.unwrap();
+    let handle = fetcher.handle();
+    tokio::spawn(async move { fetcher.run().await });
+
+    // Without endgame the stalled requests would hold the download for a minute
+    let request_timeout = std::time::Duration::from_secs(60);
+    let config = ChunkConfig {
+        strategy: DistributionStrategy::Sequential,
+        request_timeout,
+        ..Default::default()
+    };
+    let mut distributor = ChunkDistributor::new(config)
+        .await
+        .unwrap()
+        .with_network(handle);
+
+    let started = std::time::Instant::now();
+    let downloaded = distributor
+        .download_content(cid, vec![stalled_peer, fast_peer])
+        .await
+        .unwrap();
+    assert_eq!(downloaded, content);
+    assert!(
+        started.elapsed() < request_timeout / 4,
+        "Download took {:?}",
+        started.elapsed()
+    );
+}
+
+/// Test that a download to a file continues from its journal after failing
+#[tokio::test]
+async fn test_network_download_to_file_resumes() {
+    let content: Vec<u8> = (0..10 * 1024u32).map(|i| (i % 251) as u8).collect();
+    let cid = ContentId::new(&content);
+    let manifest = ContentManifest::from_content(&content, 1024);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("download.bin");
+
+    // The first attempt only finds a peer holding chunks 0 to 3
+    let (mut partial, partial_addr) = start_node(NetworkConfig::default()).await;
+    let partial_handle = partial.handle();
+    tokio::spawn(async move { partial.run().await });
+    partial_handle
+        .start_sharing(&cid, manifest.clone())
+        .unwrap();
+    for index in 0..4 {
+        let range = manifest.chunk_range(index).unwrap();
+        partial_handle
+            .announce_have(&cid, index, content[range].to_vec())
+            .unwrap();
+    }
+    let partial_peer = PeerInfo::new(PeerId(partial_handle.peer_id().to_string()));
+
+    let (mut seeder, seeder_addr) =
+        start_node(NetworkConfig::default().with_chunk_size(1024)).await;
+    seeder
+        .provide_content(cid.clone(), content.clone())
+        .unwrap();
+    let seeder_peer = PeerInfo::new(PeerId(seeder.peer_id().to_string()));
+    tokio::spawn(async move { seeder.run().await });
+
+    let config = ChunkConfig {
+        request_timeout: std::time::Duration::from_secs(10),
+        ..Default::default()
+    };
+
+    let (mut fetcher, _) = start_node(NetworkConfig::default()).await;
+    fetcher.connect_peer(partial_addr).await.unwrap();
+    let handle = fetcher.handle();
+    let fetcher_task = tokio::spawn(async move { fetcher.run().await });
+
+    let mut distributor = ChunkDistributor::new(config.clone())
+        .await
+        .unwrap()
+        .with_network(handle);
+    let result = distributor
+        .download_to_file(cid.clone(), vec![partial_peer], &path)
+        .await;
+    assert!(result.is_err(), "Chunks 4 to 9 are nowhere to be found");
+    assert!(std::path::Path::new(&format!("{}.resume", path.display())).exists());
+    drop(distributor);
+    fetcher_task.abort();
+
+    // A fresh process resumes and only fetches the rest
+    let (mut fetcher, fetcher_addr) = start_node(NetworkConfig::default()).await;
+    let fetcher_id = fetcher.peer_id();
+    fetcher.connect_peer(seeder_addr).await.unwrap();
+    let handle = fetcher.handle();
+    tokio::spawn(async move { fetcher.run().await });
+
+    let mut distributor = ChunkDistributor::new(config)
+        .await
+        .unwrap()
+        .with_network(handle);
+    let size = distributor
+        .download_to_file(cid.clone(), vec![seeder_peer.clone()], &path)
+        .await
+        .unwrap();
+    assert_eq!(size, content.len() as u64);
+    assert_eq!(std::fs::read(&path).unwrap(), content);
+    assert!(!std::path::Path::new(&format!("{}.resume", path.display())).exists());
+
+    let stats = distributor.peer_stats.read().await;
+    assert_eq!(stats[&seeder_peer.peer_id].downloaded_bytes, 6 * 1024);
+    drop(stats);
+
+    // Chunks from both attempts are served from the file
+    let (mut late, _) = start_node(NetworkConfig::default()).await;
+    late.connect_peer(fetcher_addr).await.unwrap();
+    let late_handle = late.handle();
+    tokio::spawn(async move { late.run().await });
+    for index in [0, 9] {
+        let chunk = late_handle
+            .fetch_chunk(fetcher_id, &cid, index)
+            .await
+            .unwrap();
+        assert!(manifest.verify_chunk(index, &chunk));
+    }
+}
+
+/// Test that a choked peer's chunk request is refused over the network
+#[tokio::test]
+async fn test_network_refuses_choked_peer() {
+    let content: Vec<u8> = (0..4096u32).map(|i| (i % 251) as u8).collect();
+    let cid = ContentId::new(&content);
+
+    // A provider with a single upload slot per content
+    let (mut node, addr) = start_node(NetworkConfig::default().with_chunk_size(1024)).await;
+    node.provide_content(cid.clone(), content.clone()).unwrap();
+    let provider = node.peer_id();
+    let _distributor = ChunkDistributor::new(ChunkConfig::default().with_upload_slots(1))
+        .await
+        .unwrap()
+        .with_network(node.handle());
+    tokio::spawn(async move { node.run().await });
+
+    let mut handles = Vec::new();
+    for _ in 0..2 {
+        let (mut fetcher, _) = start_node(NetworkConfig::default()).await;
+        fetcher.connect_peer(addr.clone()).await.unwrap();
+        handles.push(fetcher.handle());
+        tokio::spawn(async move { fetcher.run().await });
+    }
+
+    // The first peer takes the only slot, the second is choked
+    let chunk = handles[0].fetch_chunk(provider, &cid, 0).await.unwrap();
+    assert_eq!(chunk, content[..1024]);
+
+    let error = handles[1].fetch_chunk(provider, &cid, 0).await.unwrap_err();
+    let remote = error
+        .downcast_ref::<RemoteError>()
+        .expect("Refusal should come from the provider");
+    assert_eq!(remote.code, ErrorCode::Refused);
+
+    // The unchoked peer keeps its slot
+    assert!(handles[0].fetch_chunk(provider, &cid, 1).await.is_ok());
+}
+
+/// Test that peers choked by the only provider wait for an unchoke and finish
+#[tokio::test]
+async fn test_network_choked_downloads_finish() {
+    let content: Vec<u8> = (0..8192u32).map(|i| (i % 251) as u8).collect();
+    let cid = ContentId::new(&content);
+
+    // One provider uploading to two peers at a time, three peers downloading
+    let (mut node, addr) = start_node(NetworkConfig::default().with_chunk_size(1024)).await;
+    node.provide_content(cid.clone(), content.clone()).unwrap();
+    let provider = PeerInfo::new(PeerId(node.peer_id().to_string()));
+    let _seeder = ChunkDistributor::new(ChunkConfig::default().with_upload_slots(2))
+        .await
+        .unwrap()
+        .with_network(node.handle());
+    tokio::spawn(async move { node.run().await });
+
+    let mut downloads = Vec::new();
+    for _ in 0..3 {
+        let (mut fetcher, _) = start_node(NetworkConfig::default()).await;
+        fetcher.connect_peer(addr.clone()).await.unwrap();
+        let handle = fetcher.handle();
+        tokio::spawn(async move { fetcher.run().await });
+
+        let mut distributor = ChunkDistributor::new(ChunkConfig::default())
+            .await
+            .unwrap()
+            .with_network(handle);
+        let (cid, providers) = (cid.clone(), vec![provider.clone()]);
+        downloads.push(tokio::spawn(async move {
+            distributor.download_content(cid, providers).await
+        }));
+    }
+
+    // The third peer is refused until a later round frees a slot
+    for download in downloads {
+        let downloaded = tokio::time::timeout(4 * CHOKE_ROUND_INTERVAL, download)
+            .await
+            .expect("Choked download should finish after an unchoke")
+            .unwrap()
+            .unwrap();
+        assert_eq!(downloaded, content);
+    }
+}
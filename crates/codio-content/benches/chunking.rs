@@ -0,0 +1,86 @@
+//! Sequential vs parallel chunk hashing.
+//!
+//! Run with `cargo bench -p codio-content`. Each group chunks the same input
+//! with 1 worker and with one worker per CPU (at least 2); the throughput ratio between
+//! the two is the speedup.
+
+use codio_content::{config::ContentConfig, Chunker, Compression, Content, HashAlgorithm};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const INPUT_SIZE: usize = 64 * 1024 * 1024;
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Deterministic, incompressible-ish input
+fn input() -> Vec<u8> {
+    let mut state = 0x9e37_79b9_7f4a_7c15u64;
+    (0..INPUT_SIZE)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+fn workers() -> [usize; 2] {
+    let cpus = std::thread::available_parallelism().map_or(4, |n| n.get());
+    [1, cpus.max(2)]
+}
+
+fn bench_chunker(c: &mut Criterion, name: &str, chunker: Chunker, data: &[u8]) {
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.sample_size(10);
+
+    for workers in workers() {
+        let chunker = chunker.clone().with_parallelism(workers);
+        group.bench_with_input(BenchmarkId::new("workers", workers), data, |b, data| {
+            b.iter(|| black_box(chunker.chunk(data)))
+        });
+    }
+
+    group.finish();
+}
+
+fn chunking(c: &mut Criterion) {
+    let data = input();
+
+    bench_chunker(c, "chunk_sha256", Chunker::new(CHUNK_SIZE), &data);
+    bench_chunker(
+        c,
+        "chunk_blake3",
+        Chunker::new(CHUNK_SIZE).with_hash_algorithm(HashAlgorithm::Blake3),
+        &data,
+    );
+    bench_chunker(
+        c,
+        "chunk_cdc_zstd",
+        Chunker::content_defined(256 * 1024, CHUNK_SIZE, 4 * CHUNK_SIZE)
+            .with_compression(Compression::Zstd),
+        &data,
+    );
+}
+
+fn content(c: &mut Criterion) {
+    let data = input();
+    let mut group = c.benchmark_group("content_new");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.sample_size(10);
+
+    for workers in workers() {
+        let config = if workers == 1 {
+            ContentConfig::new(CHUNK_SIZE)
+        } else {
+            ContentConfig::with_parallel(CHUNK_SIZE, workers)
+        };
+        group.bench_with_input(BenchmarkId::new("workers", workers), &data, |b, data| {
+            b.iter(|| black_box(Content::from_config(data.clone(), &config).unwrap()))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, chunking, content);
+criterion_main!(benches);
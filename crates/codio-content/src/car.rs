@@ -0,0 +1,638 @@
+//! CAR (Content Addressable aRchive) files: the blocks of one or more DAGs
+//! in a single file, for moving content between nodes or into cold storage.
+//!
+//! CARv1 is a header naming the root CIDs, then one section per block:
+//!
+//! ```text
+//! varint(len) dag-cbor {roots: [CID], version: 1}
+//! varint(len) CID | block data                      (repeated)
+//! ```
+//!
+//! CARv2 wraps a CARv1 payload between a fixed header and an optional index,
+//! so readers can seek straight to a block:
+//!
+//! ```text
+//! pragma (11 bytes) | header (40 bytes) | CARv1 payload | index
+//! ```
+//!
+//! The index written here is `IndexSorted`: block digests with the offset of
+//! their section in the payload, bucketed by digest width and sorted, as
+//! go-car reads it.
+
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+use crate::dagpb::put_varint;
+use crate::{Block, ContentId, UnixFsDag};
+
+/// The CARv2 pragma: a CARv1-style header of `{version: 2}`
+const V2_PRAGMA: [u8; 11] = [
+    0x0a, 0xa1, 0x67, b'v', b'e', b'r', b's', b'i', b'o', b'n', 0x02,
+];
+
+/// Size of the CARv2 header after the pragma
+const V2_HEADER_LEN: u64 = 40;
+
+/// Multicodec of the `IndexSorted` CARv2 index
+const INDEX_SORTED: u64 = 0x0400;
+
+/// CBOR tag for CIDs in dag-cbor
+const CID_TAG: u64 = 42;
+
+/// Largest header accepted when reading
+const MAX_HEADER_LEN: u64 = 1024 * 1024;
+
+/// CAR format version to write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CarVersion {
+    /// Header and blocks
+    V1,
+    /// CARv1 payload plus an index of block offsets
+    V2,
+}
+
+/// A content archive: root CIDs and the distinct blocks under them.
+///
+/// Reading verifies every block against its CID, so a [`Car`] only ever
+/// holds blocks that match their CIDs.
+///
+/// # Example
+///
+/// ```rust
+/// use codio_content::{Car, CarVersion, UnixFsBuilder};
+///
+/// let dag = UnixFsBuilder::new().file(b"hello world\n").unwrap();
+/// let car = Car::from_dag(&dag);
+///
+/// let bytes = car.to_vec(CarVersion::V2);
+/// let read = Car::decode(&bytes).unwrap();
+///
+/// assert_eq!(read.roots(), [dag.root_cid().clone()]);
+/// assert_eq!(read.blocks(), dag.blocks());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Car {
+    /// Root CIDs, in header order
+    roots: Vec<ContentId>,
+
+    /// Distinct blocks, in file order
+    blocks: Vec<Block>,
+
+    /// Position of each block in `blocks`
+    index: HashMap<ContentId, usize>,
+}
+
+impl Car {
+    /// Creates an empty archive with the given roots.
+    pub fn new(roots: Vec<ContentId>) -> Self {
+        Car {
+            roots,
+            ..Default::default()
+        }
+    }
+
+    /// Creates an archive of a UnixFS DAG, rooted at its root CID.
+    pub fn from_dag(dag: &UnixFsDag) -> Self {
+        let mut car = Car::new(vec![dag.root_cid().clone()]);
+        for block in dag.blocks() {
+            car.push(block.clone());
+        }
+        car
+    }
+
+    /// Returns the root CIDs.
+    pub fn roots(&self) -> &[ContentId] {
+        &self.roots
+    }
+
+    /// Returns the blocks, in file order.
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// Consumes the archive, returning its blocks.
+    pub fn into_blocks(self) -> Vec<Block> {
+        self.blocks
+    }
+
+    /// Returns the data of the block with CID `cid`, if the archive has it.
+    pub fn get(&self, cid: &ContentId) -> Option<&[u8]> {
+        self.index.get(cid).map(|&i| self.blocks[i].data.as_slice())
+    }
+
+    /// Adds a block, returning `false` if the archive already had it.
+    ///
+    /// The block is trusted to match its CID; [`Car::read_from`] checks
+    /// blocks it reads.
+    pub fn push(&mut self, block: Block) -> bool {
+        if self.index.contains_key(&block.cid) {
+            return false;
+        }
+        self.index.insert(block.cid.clone(), self.blocks.len());
+        self.blocks.push(block);
+        true
+    }
+
+    /// Encodes the archive as a CAR file of the given version.
+    pub fn to_vec(&self, version: CarVersion) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_to(&mut out, version)
+            .expect("writing to a Vec cannot fail");
+        out
+    }
+
+    /// Writes the archive as a CAR file of the given version.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing fails.
+    pub fn write_to<W: Write>(&self, mut writer: W, version: CarVersion) -> Result<()> {
+        let payload = self.v1_payload();
+
+        match version {
+            CarVersion::V1 => writer.write_all(&payload.bytes)?,
+            CarVersion::V2 => {
+                let data_offset = V2_PRAGMA.len() as u64 + V2_HEADER_LEN;
+                let data_size = payload.bytes.len() as u64;
+
+                writer.write_all(&V2_PRAGMA)?;
+                // Characteristics: none claimed
+                writer.write_all(&[0; 16])?;
+                writer.write_all(&data_offset.to_le_bytes())?;
+                writer.write_all(&data_size.to_le_bytes())?;
+                writer.write_all(&(data_offset + data_size).to_le_bytes())?;
+                writer.write_all(&payload.bytes)?;
+                writer.write_all(&sorted_index(&self.blocks, &payload.offsets))?;
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Decodes a CARv1 or CARv2 file held in memory.
+    ///
+    /// # Errors
+    ///
+    /// As for [`Car::read_from`].
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        Self::read_from(bytes)
+    }
+
+    /// Reads a CARv1 or CARv2 file, verifying every block against its CID.
+    ///
+    /// A CARv2 index is not needed to read the whole archive and is skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails, the file is not a valid CAR, or a
+    /// block does not match its CID.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self> {
+        let header = read_header(&mut reader)?;
+
+        match header.version {
+            1 => Self::read_v1_sections(header.roots, reader),
+            2 => {
+                let mut fixed = [0u8; V2_HEADER_LEN as usize];
+                reader.read_exact(&mut fixed)?;
+                let data_offset = u64::from_le_bytes(fixed[16..24].try_into().unwrap());
+                let data_size = u64::from_le_bytes(fixed[24..32].try_into().unwrap());
+
+                // Skip any padding before the payload
+                let consumed = V2_PRAGMA.len() as u64 + V2_HEADER_LEN;
+                let padding = data_offset
+                    .checked_sub(consumed)
+                    .ok_or_else(|| anyhow!("Invalid CARv2 header: data offset {}", data_offset))?;
+                std::io::copy(&mut (&mut reader).take(padding), &mut std::io::sink())?;
+
+                let mut payload = reader.take(data_size);
+                let inner = read_header(&mut payload)?;
+                if inner.version != 1 {
+                    return Err(anyhow!("Invalid CARv2 payload: version {}", inner.version));
+                }
+                Self::read_v1_sections(inner.roots, payload)
+            }
+            version => Err(anyhow!("Unsupported CAR version {}", version)),
+        }
+    }
+
+    fn read_v1_sections<R: Read>(roots: Vec<ContentId>, mut reader: R) -> Result<Self> {
+        let mut car = Car::new(roots);
+
+        while let Some(len) = read_uvarint(&mut reader)? {
+            let mut section = Vec::new();
+            (&mut reader).take(len).read_to_end(&mut section)?;
+            if section.len() as u64 != len {
+                return Err(anyhow!("Truncated CAR section"));
+            }
+
+            let cid_len = cid_len(&section)?;
+            let cid = ContentId::from_bytes(&section[..cid_len])
+                .map_err(|e| anyhow!("Invalid CID in CAR section: {}", e))?;
+            let data = section.split_off(cid_len);
+            if !cid.verify(&data) {
+                return Err(anyhow!("Block {} does not match its CID", cid));
+            }
+
+            car.push(Block { cid, data });
+        }
+
+        Ok(car)
+    }
+
+    /// The CARv1 encoding, with the offset of each block's section
+    fn v1_payload(&self) -> Payload {
+        let mut header = Vec::new();
+        cbor_head(&mut header, 5, 2);
+        cbor_text(&mut header, "roots");
+        cbor_head(&mut header, 4, self.roots.len() as u64);
+        for root in &self.roots {
+            // dag-cbor CIDs are tagged bytes with a leading multibase 0x00
+            let mut cid = vec![0x00];
+            cid.extend_from_slice(&root.to_bytes());
+            cbor_head(&mut header, 6, CID_TAG);
+            cbor_head(&mut header, 2, cid.len() as u64);
+            header.extend_from_slice(&cid);
+        }
+        cbor_text(&mut header, "version");
+        cbor_head(&mut header, 0, 1);
+
+        let mut bytes = Vec::new();
+        put_varint(&mut bytes, header.len() as u64);
+        bytes.extend_from_slice(&header);
+
+        let mut offsets = Vec::with_capacity(self.blocks.len());
+        for block in &self.blocks {
+            offsets.push(bytes.len() as u64);
+            let cid = block.cid.to_bytes();
+            put_varint(&mut bytes, (cid.len() + block.data.len()) as u64);
+            bytes.extend_from_slice(&cid);
+            bytes.extend_from_slice(&block.data);
+        }
+
+        Payload { bytes, offsets }
+    }
+}
+
+struct Payload {
+    bytes: Vec<u8>,
+    /// Offset of each block's section in `bytes`
+    offsets: Vec<u64>,
+}
+
+/// An `IndexSorted` index: per digest width, the sorted `digest | offset`
+/// records
+fn sorted_index(blocks: &[Block], offsets: &[u64]) -> Vec<u8> {
+    let mut buckets: HashMap<usize, Vec<Vec<u8>>> = HashMap::new();
+    for (block, offset) in blocks.iter().zip(offsets) {
+        let mut record = block.cid.hash().to_vec();
+        record.extend_from_slice(&offset.to_le_bytes());
+        buckets.entry(record.len()).or_default().push(record);
+    }
+
+    let mut widths: Vec<_> = buckets.into_iter().collect();
+    widths.sort_by_key(|(width, _)| *width);
+
+    let mut out = Vec::new();
+    put_varint(&mut out, INDEX_SORTED);
+    out.extend_from_slice(&(widths.len() as i32).to_le_bytes());
+    for (width, mut records) in widths {
+        records.sort();
+        out.extend_from_slice(&(width as u32).to_le_bytes());
+        out.extend_from_slice(&((width * records.len()) as u64).to_le_bytes());
+        for record in records {
+            out.extend_from_slice(&record);
+        }
+    }
+    out
+}
+
+/// A decoded CAR header
+struct Header {
+    version: u64,
+    roots: Vec<ContentId>,
+}
+
+fn read_header<R: Read>(reader: &mut R) -> Result<Header> {
+    let len = read_uvarint(reader)?.ok_or_else(|| anyhow!("Empty CAR file"))?;
+    if len > MAX_HEADER_LEN {
+        return Err(anyhow!("CAR header too large: {} bytes", len));
+    }
+    let mut bytes = vec![0u8; len as usize];
+    reader
+        .read_exact(&mut bytes)
+        .map_err(|_| anyhow!("Truncated CAR header"))?;
+
+    decode_header(&bytes)
+}
+
+/// Decodes the dag-cbor header map: `version`, and `roots` for CARv1
+fn decode_header(mut bytes: &[u8]) -> Result<Header> {
+    let invalid = || anyhow!("Invalid CAR header");
+
+    let (major, entries) = read_cbor_head(&mut bytes)?;
+    if major != 5 {
+        return Err(invalid());
+    }
+
+    let mut version = None;
+    let mut roots = None;
+    for _ in 0..entries {
+        match read_cbor_text(&mut bytes)? {
+            "version" => match read_cbor_head(&mut bytes)? {
+                (0, value) => version = Some(value),
+                _ => return Err(invalid()),
+            },
+            "roots" => {
+                let (major, count) = read_cbor_head(&mut bytes)?;
+                if major != 4 {
+                    return Err(invalid());
+                }
+                let mut cids = Vec::new();
+                for _ in 0..count {
+                    if read_cbor_head(&mut bytes)? != (6, CID_TAG) {
+                        return Err(invalid());
+                    }
+                    let cid = match read_cbor_bytes(&mut bytes)? {
+                        [0x00, cid @ ..] => ContentId::from_bytes(cid)
+                            .map_err(|e| anyhow!("Invalid CAR root: {}", e))?,
+                        _ => return Err(invalid()),
+                    };
+                    cids.push(cid);
+                }
+                roots = Some(cids);
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    if !bytes.is_empty() {
+        return Err(invalid());
+    }
+
+    let version = version.ok_or_else(invalid)?;
+    if version == 1 && roots.is_none() {
+        return Err(anyhow!("Invalid CAR header: missing roots"));
+    }
+    Ok(Header {
+        version,
+        roots: roots.unwrap_or_default(),
+    })
+}
+
+/// Length of the binary CID at the start of a section
+fn cid_len(section: &[u8]) -> Result<usize> {
+    let truncated = || anyhow!("Truncated CID in CAR section");
+
+    // A v0 CID is a bare sha2-256 multihash
+    if section.starts_with(&[0x12, 0x20]) {
+        return (section.len() >= 34).then_some(34).ok_or_else(truncated);
+    }
+
+    // version, codec, hash function, digest length, digest
+    let mut rest = section;
+    for _ in 0..3 {
+        crate::dagpb::read_varint(&mut rest)?;
+    }
+    let digest_len = crate::dagpb::read_varint(&mut rest)?;
+    if digest_len > rest.len() as u64 {
+        return Err(truncated());
+    }
+    Ok(section.len() - rest.len() + digest_len as usize)
+}
+
+/// Reads a varint, or `None` at a clean end of input
+fn read_uvarint<R: Read>(reader: &mut R) -> Result<Option<u64>> {
+    let mut value = 0u64;
+
+    for i in 0..10 {
+        let mut byte = [0u8];
+        if reader.read(&mut byte)? == 0 {
+            return match i {
+                0 => Ok(None),
+                _ => Err(anyhow!("Truncated CAR varint")),
+            };
+        }
+        value |= u64::from(byte[0] & 0x7f) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+
+    Err(anyhow!("Invalid CAR varint"))
+}
+
+// Just enough dag-cbor for CAR headers
+
+fn cbor_head(out: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    match value {
+        0..=23 => out.push(major | value as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, value as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+fn cbor_text(out: &mut Vec<u8>, text: &str) {
+    cbor_head(out, 3, text.len() as u64);
+    out.extend_from_slice(text.as_bytes());
+}
+
+/// Reads a CBOR item head: `(major type, argument)`
+fn read_cbor_head(bytes: &mut &[u8]) -> Result<(u8, u64)> {
+    let invalid = || anyhow!("Invalid CAR header");
+
+    let (&first, rest) = bytes.split_first().ok_or_else(invalid)?;
+    let width = match first & 0x1f {
+        info @ 0..=23 => {
+            *bytes = rest;
+            return Ok((first >> 5, u64::from(info)));
+        }
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        _ => return Err(invalid()),
+    };
+    if rest.len() < width {
+        return Err(invalid());
+    }
+
+    let value = rest[..width]
+        .iter()
+        .fold(0u64, |value, &byte| value << 8 | u64::from(byte));
+    *bytes = &rest[width..];
+    Ok((first >> 5, value))
+}
+
+fn read_cbor_bytes<'a>(bytes: &mut &'a [u8]) -> Result<&'a [u8]> {
+    match read_cbor_head(bytes)? {
+        (2, len) if len <= bytes.len() as u64 => {
+            let (value, rest) = bytes.split_at(len as usize);
+            *bytes = rest;
+            Ok(value)
+        }
+        _ => Err(anyhow!("Invalid CAR header")),
+    }
+}
+
+fn read_cbor_text<'a>(bytes: &mut &'a [u8]) -> Result<&'a str> {
+    match read_cbor_head(bytes)? {
+        (3, len) if len <= bytes.len() as u64 => {
+            let (value, rest) = bytes.split_at(len as usize);
+            *bytes = rest;
+            std::str::from_utf8(value).map_err(|_| anyhow!("Invalid CAR header"))
+        }
+        _ => Err(anyhow!("Invalid CAR header")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Codec, UnixFsBuilder};
+
+    fn sample() -> Car {
+        let mut car = Car::new(vec![ContentId::new(b"root")]);
+        car.push(Block {
+            cid: ContentId::new(b"root"),
+            data: b"root".to_vec(),
+        });
+        car.push(Block {
+            cid: ContentId::new_v1(Codec::Raw, b"leaf"),
+            data: b"leaf".to_vec(),
+        });
+        car
+    }
+
+    #[test]
+    fn test_v1_layout() {
+        let root = ContentId::new(b"root");
+        let bytes = sample().to_vec(CarVersion::V1);
+
+        // {"roots": [CID(root)], "version": 1}
+        let mut header = vec![0xa2, 0x65];
+        header.extend_from_slice(b"roots");
+        header.extend_from_slice(&[0x81, 0xd8, 0x2a, 0x58, 35, 0x00]);
+        header.extend_from_slice(&root.to_bytes());
+        header.push(0x67);
+        header.extend_from_slice(b"version");
+        header.push(0x01);
+
+        assert_eq!(bytes[0] as usize, header.len());
+        assert_eq!(&bytes[1..1 + header.len()], header);
+
+        // First section: the v0 CID then its data
+        let section = &bytes[1 + header.len()..];
+        assert_eq!(section[0], 34 + 4);
+        assert_eq!(&section[1..35], root.to_bytes());
+        assert_eq!(&section[35..39], b"root");
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let car = sample();
+
+        for version in [CarVersion::V1, CarVersion::V2] {
+            let read = Car::decode(&car.to_vec(version)).unwrap();
+            assert_eq!(read.roots(), car.roots());
+            assert_eq!(read.blocks(), car.blocks());
+        }
+
+        let v2 = car.to_vec(CarVersion::V2);
+        assert_eq!(v2[..11], V2_PRAGMA);
+        assert_eq!(v2[27..35], 51u64.to_le_bytes());
+    }
+
+    #[test]
+    fn test_v2_index() {
+        let car = sample();
+        let v1 = car.to_vec(CarVersion::V1);
+        let v2 = car.to_vec(CarVersion::V2);
+
+        let data_size = u64::from_le_bytes(v2[35..43].try_into().unwrap()) as usize;
+        let index_offset = u64::from_le_bytes(v2[43..51].try_into().unwrap()) as usize;
+        assert_eq!(data_size, v1.len());
+        assert_eq!(&v2[51..51 + data_size], v1);
+        assert_eq!(index_offset, 51 + data_size);
+
+        // IndexSorted, one bucket of 32-byte digests with offsets
+        let index = &v2[index_offset..];
+        assert_eq!(index[..2], [0x80, 0x08]);
+        assert_eq!(index[2..6], 1i32.to_le_bytes());
+        assert_eq!(index[6..10], 40u32.to_le_bytes());
+        assert_eq!(index[10..18], 80u64.to_le_bytes());
+        assert_eq!(index.len(), 18 + 80);
+
+        // Each record points at its block's section
+        for record in index[18..].chunks(40) {
+            let offset = u64::from_le_bytes(record[32..].try_into().unwrap()) as usize;
+            let block = car
+                .blocks()
+                .iter()
+                .find(|block| block.cid.hash() == &record[..32])
+                .unwrap();
+            let cid = block.cid.to_bytes();
+            assert_eq!(&v1[offset + 1..offset + 1 + cid.len()], cid);
+        }
+    }
+
+    #[test]
+    fn test_rejects_tampered_block() {
+        let mut bytes = sample().to_vec(CarVersion::V1);
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+
+        let err = Car::decode(&bytes).unwrap_err();
+        assert!(err.to_string().contains("does not match"));
+    }
+
+    #[test]
+    fn test_rejects_invalid() {
+        let bytes = sample().to_vec(CarVersion::V1);
+
+        assert!(Car::decode(&[]).is_err());
+        // Truncated section
+        assert!(Car::decode(&bytes[..bytes.len() - 1]).is_err());
+        // Header that isn't a map
+        assert!(Car::decode(&[0x01, 0x01]).is_err());
+        // Unknown version
+        assert!(
+            Car::decode(&[0x0a, 0xa1, 0x67, b'v', b'e', b'r', b's', b'i', b'o', b'n', 0x03])
+                .is_err()
+        );
+        // CARv1 without roots
+        assert!(
+            Car::decode(&[0x0a, 0xa1, 0x67, b'v', b'e', b'r', b's', b'i', b'o', b'n', 0x01])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_dag_roundtrip_dedups() {
+        let builder = UnixFsBuilder::new();
+        let a = builder.file(b"same").unwrap();
+        let dir = builder.directory([("a", &a), ("b", &a)]).unwrap();
+
+        let mut car = Car::from_dag(&dir);
+        for block in a.blocks() {
+            assert!(!car.push(block.clone()));
+        }
+
+        let read = Car::decode(&car.to_vec(CarVersion::V2)).unwrap();
+        assert_eq!(read.roots(), [dir.root_cid().clone()]);
+        assert_eq!(read.blocks().len(), dir.blocks().len());
+        assert_eq!(read.get(a.root_cid()), Some(a.blocks()[0].data.as_slice()));
+    }
+}
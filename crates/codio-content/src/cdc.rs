@@ -0,0 +1,143 @@
+//! FastCDC content-defined chunking.
+//!
+//! Chunk boundaries are picked where a rolling "gear" hash of the preceding
+//! bytes matches a mask, so an insertion or deletion only moves the
+//! boundaries next to it and every other chunk keeps its CID. This follows
+//! Xia et al., "FastCDC: a Fast and Efficient Content-Defined Chunking
+//! Approach for Data Deduplication" (USENIX ATC 2016), with normalized
+//! chunking: a stricter mask before the average size and a looser one after
+//! it pull chunk sizes towards the average.
+
+/// Gear table: one pseudo-random 64-bit value per byte value.
+///
+/// Generated with splitmix64 from a fixed seed. Changing it changes every
+/// content-defined chunk boundary, and with them every chunk CID.
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x636f_6469_6f2d_6364; // "codio-cd"
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Mask of the `bits` most significant bits.
+///
+/// The gear hash shifts left once per byte, so its top bits depend on the
+/// last 64 bytes while its low bits depend only on the last few.
+fn top_bits(bits: u32) -> u64 {
+    match bits {
+        0 => 0,
+        64.. => u64::MAX,
+        _ => u64::MAX << (64 - bits),
+    }
+}
+
+/// Length of the first chunk of `data`.
+///
+/// Never less than `min_size` (unless `data` is shorter) and never more than
+/// `max_size`. The result depends only on `data[..max_size]`, so streaming
+/// callers need to buffer at most `max_size` bytes.
+pub(crate) fn cut_point(data: &[u8], min_size: usize, avg_size: usize, max_size: usize) -> usize {
+    if data.len() <= min_size {
+        return data.len();
+    }
+
+    let end = data.len().min(max_size);
+    let normal = avg_size.min(end);
+    let bits = avg_size.ilog2();
+    let mask_strict = top_bits(bits + 1);
+    let mask_loose = top_bits(bits.saturating_sub(1));
+
+    let mut hash = 0u64;
+    let mut i = min_size;
+
+    while i < normal {
+        hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+        if hash & mask_strict == 0 {
+            return i + 1;
+        }
+        i += 1;
+    }
+
+    while i < end {
+        hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+        if hash & mask_loose == 0 {
+            return i + 1;
+        }
+        i += 1;
+    }
+
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random bytes, so boundaries are reproducible
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cut_point_respects_bounds() {
+        let data = noise(1 << 20, 1);
+        let mut offset = 0;
+        let mut sizes = Vec::new();
+
+        while offset < data.len() {
+            let cut = cut_point(&data[offset..], 2048, 8192, 32768);
+            sizes.push(cut);
+            offset += cut;
+        }
+
+        let last = sizes.pop().unwrap();
+        assert!(last <= 32768);
+        assert!(sizes.iter().all(|&s| (2048..=32768).contains(&s)));
+
+        // Normalized chunking keeps the mean near the target
+        let mean = sizes.iter().sum::<usize>() / sizes.len();
+        assert!((4096..=16384).contains(&mean), "mean chunk size {}", mean);
+    }
+
+    #[test]
+    fn test_cut_point_short_input() {
+        assert_eq!(cut_point(&[], 2048, 8192, 32768), 0);
+        assert_eq!(cut_point(&[0u8; 100], 2048, 8192, 32768), 100);
+    }
+
+    #[test]
+    fn test_cut_point_only_looks_at_max_size() {
+        let data = noise(100_000, 2);
+        let full = cut_point(&data, 2048, 8192, 32768);
+        let window = cut_point(&data[..32768], 2048, 8192, 32768);
+        assert_eq!(full, window);
+    }
+
+    #[test]
+    fn test_gear_table_is_stable() {
+        // Pin the table: changing it silently changes every chunk CID
+        assert_ne!(GEAR[0], GEAR[1]);
+        assert_eq!(
+            GEAR.iter().fold(0u64, |acc, &g| acc.rotate_left(1) ^ g),
+            0x7a9a_ff00_b0e0_3200
+        );
+    }
+}
@@ -0,0 +1,190 @@
+//! Per-chunk compression.
+//!
+//! A compressed chunk stores, transfers and hashes its compressed bytes: the
+//! chunk CID (and so the Merkle DAG root) addresses exactly what is in
+//! [`Chunk::data`](crate::Chunk::data), and the codec is recorded next to it
+//! in [`Chunk::compression`](crate::Chunk::compression). Peers can verify a
+//! chunk before decompressing it. The content CID from
+//! [`ContentId::new`](crate::ContentId::new) is still computed over the
+//! original bytes.
+//!
+//! Compressed representations:
+//!
+//! - [`Compression::None`]: the original bytes
+//! - [`Compression::Zstd`]: one zstd frame (level 3) declaring its content size
+//! - [`Compression::Lz4`]: an LZ4 block prefixed with the uncompressed size as
+//!   a little-endian `u32`
+//!
+//! Chunks that do not shrink are stored with [`Compression::None`].
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+use crate::MAX_CHUNK_SIZE;
+
+/// zstd level used for chunks: fast, and most of the gain on text
+const ZSTD_LEVEL: i32 = 3;
+
+/// How a chunk's data is compressed.
+///
+/// # Example
+///
+/// ```rust
+/// use codio_content::Compression;
+///
+/// let text = "hello hello hello hello hello hello".repeat(100);
+/// let compressed = Compression::Zstd.compress(text.as_bytes());
+/// assert!(compressed.len() < text.len());
+///
+/// let restored = Compression::Zstd.decompress(&compressed).unwrap();
+/// assert_eq!(restored, text.as_bytes());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Compression {
+    /// Stored as-is
+    #[default]
+    None,
+
+    /// Zstandard: better ratio, for text-heavy content
+    Zstd,
+
+    /// LZ4: lower ratio, much faster to decompress
+    Lz4,
+}
+
+impl Compression {
+    /// One-byte code for this codec, for formats that carry it in the data
+    pub(crate) fn tag(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd => 1,
+            Compression::Lz4 => 2,
+        }
+    }
+
+    /// The codec with code `tag`, if any
+    pub(crate) fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Zstd),
+            2 => Some(Compression::Lz4),
+            _ => None,
+        }
+    }
+
+    /// Compresses `data` into this codec's representation.
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::None => data.to_vec(),
+            Compression::Zstd => {
+                // Only fails on allocation failure or an invalid level
+                zstd::bulk::compress(data, ZSTD_LEVEL).expect("zstd compression failed")
+            }
+            Compression::Lz4 => lz4_flex::block::compress_prepend_size(data),
+        }
+    }
+
+    /// Returns the size `data`, produced by [`compress`](Self::compress),
+    /// decompresses to, read from its header without decompressing it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `data` has no valid header for this codec.
+    pub fn decompressed_len(&self, data: &[u8]) -> Result<usize> {
+        match self {
+            Compression::None => Ok(data.len()),
+            Compression::Zstd => match zstd::zstd_safe::get_frame_content_size(data) {
+                Ok(Some(size)) => Ok(size as usize),
+                _ => Err(anyhow!("Invalid zstd data: no content size")),
+            },
+            Compression::Lz4 => lz4_flex::block::uncompressed_size(data)
+                .map(|(size, _)| size)
+                .map_err(|e| anyhow!("Invalid lz4 data: {}", e)),
+        }
+    }
+
+    /// Decompresses `data` produced by [`compress`](Self::compress).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `data` is not valid for this codec or would
+    /// decompress to more than [`MAX_CHUNK_SIZE`] bytes.
+    pub fn decompress<'a>(&self, data: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        match self {
+            Compression::None => Ok(Cow::Borrowed(data)),
+            Compression::Zstd => zstd::bulk::decompress(data, MAX_CHUNK_SIZE)
+                .map(Cow::Owned)
+                .map_err(|e| anyhow!("Invalid zstd data: {}", e)),
+            Compression::Lz4 => {
+                let (size, block) = lz4_flex::block::uncompressed_size(data)
+                    .map_err(|e| anyhow!("Invalid lz4 data: {}", e))?;
+                if size > MAX_CHUNK_SIZE {
+                    return Err(anyhow!(
+                        "lz4 data declares {} bytes, more than {}",
+                        size,
+                        MAX_CHUNK_SIZE
+                    ));
+                }
+
+                let out = lz4_flex::block::decompress(block, size)
+                    .map_err(|e| anyhow!("Invalid lz4 data: {}", e))?;
+                if out.len() != size {
+                    return Err(anyhow!(
+                        "lz4 data declares {} bytes but holds {}",
+                        size,
+                        out.len()
+                    ));
+                }
+
+                Ok(Cow::Owned(out))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let data = b"the quick brown fox jumps over the lazy dog. ".repeat(200);
+
+        for codec in [Compression::None, Compression::Zstd, Compression::Lz4] {
+            let compressed = codec.compress(&data);
+            assert_eq!(codec.decompress(&compressed).unwrap(), &data[..]);
+        }
+
+        assert!(Compression::Zstd.compress(&data).len() < data.len() / 10);
+        assert!(Compression::Lz4.compress(&data).len() < data.len() / 5);
+
+        for codec in [Compression::None, Compression::Zstd, Compression::Lz4] {
+            let compressed = codec.compress(&data);
+            assert_eq!(codec.decompressed_len(&compressed).unwrap(), data.len());
+        }
+    }
+
+    #[test]
+    fn test_representation() {
+        // zstd frame magic number
+        let zstd = Compression::Zstd.compress(b"abc");
+        assert_eq!(&zstd[..4], &[0x28, 0xb5, 0x2f, 0xfd]);
+
+        // Little-endian uncompressed size, then the block
+        let lz4 = Compression::Lz4.compress(b"abc");
+        assert_eq!(&lz4[..4], &3u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_decompress_rejects_garbage() {
+        assert!(Compression::Zstd.decompress(b"not zstd").is_err());
+        assert!(Compression::Lz4.decompress(&[1, 2]).is_err());
+        assert!(Compression::Zstd.decompressed_len(b"not zstd").is_err());
+
+        // Declared size beyond the chunk limit
+        let mut bomb = (MAX_CHUNK_SIZE as u32 + 1).to_le_bytes().to_vec();
+        bomb.extend_from_slice(&[0x1f, 0]);
+        assert!(Compression::Lz4.decompress(&bomb).is_err());
+    }
+}
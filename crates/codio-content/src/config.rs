@@ -2,7 +2,10 @@
 
 use serde::{Deserialize, Serialize};
 
-use crate::{DEFAULT_CHUNK_SIZE, MAX_CHUNK_SIZE, MIN_CHUNK_SIZE};
+use crate::{
+    ChunkingStrategy, Compression, DagLayout, ErasureCoding, DEFAULT_CHUNK_SIZE, DEFAULT_MAX_LINKS,
+    MAX_CHUNK_SIZE, MIN_CHUNK_SIZE,
+};
 
 /// Configuration for content addressing operations.
 ///
@@ -21,34 +24,71 @@ pub struct ContentConfig {
     ///
     /// Default: 1MB (1024 * 1024 bytes)
     /// Range: 1KB to 10MB
+    /// With content-defined chunking this is the average chunk size.
     pub chunk_size: usize,
 
-    /// Whether to enable compression for content.
+    /// How chunk boundaries are chosen.
     ///
-    /// Default: false (Phase 1 - no compression)
-    /// Future: Will support gzip/brotli compression
+    /// Default: fixed-size chunks of `chunk_size` bytes
+    #[serde(default)]
+    pub chunking: ChunkingStrategy,
+
+    /// Whether to compress each chunk.
+    ///
+    /// Default: false
+    /// Chunk CIDs are then computed over the compressed bytes.
     pub enable_compression: bool,
 
-    /// Whether to enable parallel chunking.
+    /// Codec used when enable_compression is true.
+    ///
+    /// Default: zstd
+    #[serde(default = "default_compression_codec")]
+    pub compression_codec: Compression,
+
+    /// Whether to hash and compress chunks on a worker pool.
     ///
-    /// Default: false (single-threaded for Phase 1)
-    /// Future: Will support parallel processing for large files
+    /// Default: false (single-threaded)
+    /// Output is identical either way.
     pub enable_parallel: bool,
 
-    /// Maximum number of chunks to process in parallel.
+    /// Maximum number of chunks to process in parallel (the worker count).
     ///
     /// Only applies when enable_parallel is true.
     /// Default: Number of CPU cores
     pub max_parallel_chunks: usize,
+
+    /// Shape of the Merkle DAG over the chunks.
+    ///
+    /// Default: balanced
+    #[serde(default)]
+    pub dag_layout: DagLayout,
+
+    /// Maximum number of links per Merkle DAG node.
+    ///
+    /// Default: 174 (as in UnixFS)
+    /// Minimum: 2
+    #[serde(default = "default_max_links")]
+    pub max_links: usize,
+
+    /// Reed–Solomon coding of the chunks, if any.
+    ///
+    /// Default: none (chunks are stored as they are)
+    #[serde(default)]
+    pub erasure_coding: Option<ErasureCoding>,
 }
 
 impl Default for ContentConfig {
     fn default() -> Self {
         Self {
             chunk_size: DEFAULT_CHUNK_SIZE,
+            chunking: ChunkingStrategy::FixedSize,
             enable_compression: false,
+            compression_codec: default_compression_codec(),
             enable_parallel: false,
             max_parallel_chunks: num_cpus::get(),
+            dag_layout: DagLayout::Balanced,
+            max_links: DEFAULT_MAX_LINKS,
+            erasure_coding: None,
         }
     }
 }
@@ -86,7 +126,7 @@ impl ContentConfig {
         }
     }
 
-    /// Creates a ContentConfig with compression enabled.
+    /// Creates a ContentConfig with zstd compression enabled.
     ///
     /// # Example
     ///
@@ -104,6 +144,46 @@ impl ContentConfig {
         }
     }
 
+    /// Creates a ContentConfig with compression enabled using `codec`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::config::ContentConfig;
+    /// use codio_content::Compression;
+    ///
+    /// let config = ContentConfig::with_compression_codec(1024 * 1024, Compression::Lz4);
+    /// assert_eq!(config.compression(), Compression::Lz4);
+    /// ```
+    pub fn with_compression_codec(chunk_size: usize, codec: Compression) -> Self {
+        Self {
+            chunk_size,
+            enable_compression: true,
+            compression_codec: codec,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the codec chunks are compressed with, taking
+    /// enable_compression into account.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::config::ContentConfig;
+    /// use codio_content::Compression;
+    ///
+    /// assert_eq!(ContentConfig::default().compression(), Compression::None);
+    /// assert_eq!(ContentConfig::with_compression(1024 * 1024).compression(), Compression::Zstd);
+    /// ```
+    pub fn compression(&self) -> Compression {
+        if self.enable_compression {
+            self.compression_codec
+        } else {
+            Compression::None
+        }
+    }
+
     /// Creates a ContentConfig with parallel processing enabled.
     ///
     /// # Example
@@ -124,13 +204,79 @@ impl ContentConfig {
         }
     }
 
+    /// Creates a ContentConfig with content-defined (FastCDC) chunking.
+    ///
+    /// Chunks average `avg_chunk_size` bytes, with bounds from
+    /// [`ChunkingStrategy::content_defined`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::config::ContentConfig;
+    /// use codio_content::ChunkingStrategy;
+    ///
+    /// let config = ContentConfig::with_content_defined_chunking(256 * 1024);
+    /// assert_eq!(config.chunk_size, 256 * 1024);
+    /// assert!(matches!(config.chunking, ChunkingStrategy::ContentDefined { .. }));
+    /// ```
+    pub fn with_content_defined_chunking(avg_chunk_size: usize) -> Self {
+        Self {
+            chunk_size: avg_chunk_size,
+            chunking: ChunkingStrategy::content_defined(avg_chunk_size),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a ContentConfig with the given Merkle DAG layout and fan-out.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::config::ContentConfig;
+    /// use codio_content::DagLayout;
+    ///
+    /// let config = ContentConfig::with_dag_layout(1024 * 1024, DagLayout::Trickle, 64);
+    /// assert_eq!(config.dag_layout, DagLayout::Trickle);
+    /// assert_eq!(config.max_links, 64);
+    /// ```
+    pub fn with_dag_layout(chunk_size: usize, dag_layout: DagLayout, max_links: usize) -> Self {
+        Self {
+            chunk_size,
+            dag_layout,
+            max_links,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a ContentConfig that stores chunks erasure coded with `coding`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::config::ContentConfig;
+    /// use codio_content::ErasureCoding;
+    ///
+    /// let config = ContentConfig::with_erasure_coding(1024 * 1024, ErasureCoding::new(10, 4));
+    /// assert_eq!(config.erasure_coding.unwrap().parity_shards(), 4);
+    /// ```
+    pub fn with_erasure_coding(chunk_size: usize, coding: ErasureCoding) -> Self {
+        Self {
+            chunk_size,
+            erasure_coding: Some(coding),
+            ..Default::default()
+        }
+    }
+
     /// Validates the configuration.
     ///
     /// # Errors
     ///
     /// Returns an error if:
     /// - chunk_size is outside valid range
+    /// - content-defined chunk sizes are out of order or outside the valid range
     /// - max_parallel_chunks is 0
+    /// - max_links is below 2
+    /// - the erasure coding has no data or parity chunks, or over 256 per stripe
     ///
     /// # Example
     ///
@@ -155,15 +301,28 @@ impl ContentConfig {
             ));
         }
 
+        self.chunking.validate()?;
+
         if self.enable_parallel && self.max_parallel_chunks == 0 {
             return Err("max_parallel_chunks must be greater than 0".to_string());
         }
 
+        if self.max_links < 2 {
+            return Err(format!("max_links {} is below minimum 2", self.max_links));
+        }
+
+        if let Some(coding) = &self.erasure_coding {
+            coding.validate()?;
+        }
+
         Ok(())
     }
 
     /// Returns the estimated number of chunks for content of the given size.
     ///
+    /// Exact for fixed-size chunking; based on the average chunk size for
+    /// content-defined chunking.
+    ///
     /// # Example
     ///
     /// ```rust
@@ -180,6 +339,14 @@ impl ContentConfig {
     }
 }
 
+fn default_max_links() -> usize {
+    DEFAULT_MAX_LINKS
+}
+
+fn default_compression_codec() -> Compression {
+    Compression::Zstd
+}
+
 // Helper function to get number of CPUs (stub for now)
 mod num_cpus {
     pub fn get() -> usize {
@@ -224,6 +391,20 @@ mod tests {
     fn test_with_compression() {
         let config = ContentConfig::with_compression(1024 * 1024);
         assert!(config.enable_compression);
+        assert_eq!(config.compression(), Compression::Zstd);
+    }
+
+    #[test]
+    fn test_with_compression_codec() {
+        let config = ContentConfig::with_compression_codec(1024 * 1024, Compression::Lz4);
+        assert!(config.enable_compression);
+        assert_eq!(config.compression(), Compression::Lz4);
+
+        let config = ContentConfig {
+            enable_compression: false,
+            ..config
+        };
+        assert_eq!(config.compression(), Compression::None);
     }
 
     #[test]
@@ -239,6 +420,64 @@ mod tests {
         assert!(config.validate().is_ok());
     }
 
+    #[test]
+    fn test_content_defined_chunking_config() {
+        let config = ContentConfig::with_content_defined_chunking(64 * 1024);
+        assert_eq!(
+            config.chunking,
+            ChunkingStrategy::ContentDefined {
+                min_size: 16 * 1024,
+                avg_size: 64 * 1024,
+                max_size: 256 * 1024,
+            }
+        );
+        assert!(config.validate().is_ok());
+        assert_eq!(config.estimate_chunks(640 * 1024), 10);
+
+        // Bounds are clamped to the allowed chunk sizes
+        let config = ContentConfig::with_content_defined_chunking(MAX_CHUNK_SIZE);
+        assert!(config.validate().is_ok());
+
+        let config = ContentConfig {
+            chunking: ChunkingStrategy::ContentDefined {
+                min_size: 512,
+                avg_size: 4096,
+                max_size: 16384,
+            },
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = ContentConfig {
+            chunking: ChunkingStrategy::ContentDefined {
+                min_size: 8192,
+                avg_size: 4096,
+                max_size: 16384,
+            },
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_with_dag_layout() {
+        let config = ContentConfig::with_dag_layout(1024 * 1024, DagLayout::Trickle, 32);
+        assert_eq!(config.dag_layout, DagLayout::Trickle);
+        assert!(config.validate().is_ok());
+
+        let config = ContentConfig::with_dag_layout(1024 * 1024, DagLayout::Balanced, 1);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_with_erasure_coding() {
+        let config = ContentConfig::with_erasure_coding(64 * 1024, ErasureCoding::new(6, 3));
+        assert!(config.validate().is_ok());
+
+        let builder = crate::DagBuilder::from_config(&config).unwrap();
+        assert_eq!(builder.erasure_coding(), Some(ErasureCoding::new(6, 3)));
+    }
+
     #[test]
     fn test_estimate_chunks() {
         let config = ContentConfig::new(1024 * 1024);
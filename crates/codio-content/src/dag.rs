@@ -0,0 +1,464 @@
+//! Tree layouts for [`MerkleDAG`](crate::MerkleDAG).
+//!
+//! The layouts are generic over how a node is made from its links, so the
+//! UnixFS importer builds exactly the same shapes. For `MerkleDAG`, every
+//! internal node links to at most `max_links` children (trickle nodes
+//! add subtree links on top, see [`trickle`]). Its CID is a CIDv1 of its
+//! children's binary CIDs concatenated, with the hash function of its first
+//! child and the private-use [`Codec::HashList`] codec, which says exactly
+//! that. Chunk CIDs are CIDv0 or raw CIDv1 and UnixFS nodes are dag-pb, so a
+//! node's own CID can never pass for either. Because each link's version and
+//! codec are hashed along with its digest, neither can a link: swapping a
+//! subtree for a chunk CID with the same digest changes the parent's CID, so
+//! inclusion proofs and [`MerkleDAG::from_nodes`](crate::MerkleDAG::from_nodes)
+//! reject it.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use codio_content_id::Codec;
+
+use crate::{Block, ContentId, DagLayout};
+
+/// Internal nodes by CID, each with its ordered links
+pub(crate) type Nodes = HashMap<ContentId, Vec<ContentId>>;
+
+/// How many subtrees of each depth a trickle node holds (as in UnixFS)
+pub(crate) const TRICKLE_LAYER_REPEAT: usize = 4;
+
+/// CID of an internal node with the given links.
+///
+/// The node is the links' binary CIDs concatenated, version and codec
+/// included, so a link to a node can't be swapped for a chunk CID with the
+/// same digest.
+pub(crate) fn node_cid(links: &[ContentId]) -> ContentId {
+    ContentId::new_v1_with(Codec::HashList, links[0].algorithm(), &node_data(links))
+}
+
+/// Block bytes of an internal node: its links' binary CIDs concatenated.
+pub(crate) fn node_data(links: &[ContentId]) -> Vec<u8> {
+    let mut combined = Vec::with_capacity(links.len() * 36);
+    for link in links {
+        combined.extend_from_slice(&link.to_bytes());
+    }
+    combined
+}
+
+/// Links of an internal node, read back from its block bytes.
+pub(crate) fn decode_node(data: &[u8]) -> Result<Vec<ContentId>> {
+    let mut links = Vec::new();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let (link, tail) = ContentId::from_bytes_prefix(rest)
+            .map_err(|e| anyhow!("Invalid DAG node link: {}", e))?;
+        links.push(link);
+        rest = tail;
+    }
+
+    if links.is_empty() {
+        return Err(anyhow!("DAG node has no links"));
+    }
+    Ok(links)
+}
+
+/// Block for a node with `links`, kept in `closed` if it's recording, and
+/// its CID.
+fn close_node(closed: &mut Option<Vec<Block>>, links: Vec<ContentId>) -> ContentId {
+    let cid = node_cid(&links);
+    if let Some(closed) = closed {
+        closed.push(Block {
+            cid: cid.clone(),
+            data: node_data(&links),
+        });
+    }
+    cid
+}
+
+/// Whether `cid` names an internal node rather than a chunk.
+pub(crate) fn is_node_cid(cid: &ContentId) -> bool {
+    cid.codec() == Codec::HashList
+}
+
+/// Records an internal node with the given links and returns its CID.
+pub(crate) fn add_node(nodes: &mut Nodes, links: Vec<ContentId>) -> ContentId {
+    let cid = node_cid(&links);
+    nodes.insert(cid.clone(), links);
+    cid
+}
+
+/// Balanced layout: leaves are grouped `max_links` at a time, then those
+/// nodes, and so on until one root remains. Every node but the rightmost on
+/// each level is full, and all leaves sit at the same depth.
+///
+/// `node` makes a node from its links. `leaves` must not be empty.
+pub(crate) fn balanced<T, F>(leaves: &[T], max_links: usize, node: &mut F) -> T
+where
+    T: Clone,
+    F: FnMut(Vec<T>) -> T,
+{
+    let mut level: Vec<T> = leaves
+        .chunks(max_links)
+        .map(|links| node(links.to_vec()))
+        .collect();
+
+    while level.len() > 1 {
+        level = level
+            .chunks(max_links)
+            .map(|links| node(links.to_vec()))
+            .collect();
+    }
+
+    level.pop().expect("at least one leaf")
+}
+
+/// Trickle layout: each node holds up to `max_links` leaves, then
+/// [`TRICKLE_LAYER_REPEAT`] subtrees of depth 1, then as many of depth 2, and
+/// so on. The beginning of the content sits near the root, which suits
+/// sequential reads such as streaming media. As in UnixFS, a node's subtree
+/// links come on top of its `max_links` leaves.
+///
+/// `node` makes a node from its links. `leaves` must not be empty.
+pub(crate) fn trickle<T, F>(leaves: &[T], max_links: usize, node: &mut F) -> T
+where
+    T: Clone,
+    F: FnMut(Vec<T>) -> T,
+{
+    let mut next = 0;
+    trickle_node(leaves, &mut next, None, max_links, node)
+}
+
+fn trickle_node<T, F>(
+    leaves: &[T],
+    next: &mut usize,
+    max_depth: Option<usize>,
+    max_links: usize,
+    node: &mut F,
+) -> T
+where
+    T: Clone,
+    F: FnMut(Vec<T>) -> T,
+{
+    let take = max_links.min(leaves.len() - *next);
+    let mut links = leaves[*next..*next + take].to_vec();
+    *next += take;
+
+    let mut depth = 1;
+    while max_depth.is_none_or(|max| depth < max) && *next < leaves.len() {
+        for _ in 0..TRICKLE_LAYER_REPEAT {
+            if *next == leaves.len() {
+                break;
+            }
+            links.push(trickle_node(leaves, next, Some(depth), max_links, node));
+        }
+        depth += 1;
+    }
+
+    node(links)
+}
+
+/// Builds a tree's root one leaf at a time, for content too large to keep
+/// every leaf CID.
+///
+/// Only nodes still open are kept: for [`balanced`], up to `max_links - 1`
+/// links per level; for [`trickle`], the chain of unfinished subtrees from
+/// the root down. Either way memory grows with depth times fan-out, and the
+/// root matches what the batch layouts give for the same leaves.
+#[derive(Debug, Clone)]
+pub(crate) struct TreeStream {
+    max_links: usize,
+    open: OpenNodes,
+    /// Blocks of the nodes closed so far, if they're being kept
+    closed: Option<Vec<Block>>,
+}
+
+#[derive(Debug, Clone)]
+enum OpenNodes {
+    /// Links not yet grouped into a node, per level (leaves first)
+    Balanced(Vec<Vec<ContentId>>),
+    /// Unfinished trickle nodes, root first
+    Trickle(Vec<TrickleFrame>),
+}
+
+/// A trickle node still taking links, as in one call of `trickle_node`
+#[derive(Debug, Clone)]
+struct TrickleFrame {
+    max_depth: Option<usize>,
+    links: Vec<ContentId>,
+    leaves: usize,
+    /// Depth of the subtrees being added
+    depth: usize,
+    /// Subtrees of that depth added so far
+    repeats: usize,
+}
+
+impl TrickleFrame {
+    fn new(max_depth: Option<usize>) -> Self {
+        Self {
+            max_depth,
+            links: Vec::new(),
+            leaves: 0,
+            depth: 1,
+            repeats: 0,
+        }
+    }
+
+    /// Whether another subtree fits under this node
+    fn takes_subtree(&self) -> bool {
+        self.max_depth.is_none_or(|max| self.depth < max)
+    }
+
+    fn add_subtree(&mut self, cid: ContentId) {
+        self.links.push(cid);
+        self.repeats += 1;
+        if self.repeats == TRICKLE_LAYER_REPEAT {
+            self.depth += 1;
+            self.repeats = 0;
+        }
+    }
+}
+
+impl TreeStream {
+    pub(crate) fn new(layout: DagLayout, max_links: usize) -> Self {
+        let open = match layout {
+            DagLayout::Balanced => OpenNodes::Balanced(vec![Vec::new()]),
+            DagLayout::Trickle => OpenNodes::Trickle(vec![TrickleFrame::new(None)]),
+        };
+        Self {
+            max_links,
+            open,
+            closed: None,
+        }
+    }
+
+    /// Keeps the block of every node as it's closed, for [`take_closed`].
+    ///
+    /// [`take_closed`]: TreeStream::take_closed
+    pub(crate) fn keep_closed(&mut self) {
+        self.closed.get_or_insert_with(Vec::new);
+    }
+
+    /// Whether closed nodes are being kept.
+    pub(crate) fn keeps_closed(&self) -> bool {
+        self.closed.is_some()
+    }
+
+    /// Blocks of the nodes closed since the last call, children before
+    /// parents; empty unless [`keep_closed`](TreeStream::keep_closed) was
+    /// called.
+    pub(crate) fn take_closed(&mut self) -> Vec<Block> {
+        self.closed.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Adds the next leaf.
+    pub(crate) fn push(&mut self, leaf: ContentId) {
+        let max_links = self.max_links;
+        match &mut self.open {
+            OpenNodes::Balanced(levels) => {
+                levels[0].push(leaf);
+                let mut level = 0;
+                while levels[level].len() == max_links {
+                    let node = close_node(&mut self.closed, std::mem::take(&mut levels[level]));
+                    if level + 1 == levels.len() {
+                        levels.push(Vec::new());
+                    }
+                    levels[level + 1].push(node);
+                    level += 1;
+                }
+            }
+            OpenNodes::Trickle(frames) => loop {
+                let top = frames.last_mut().expect("the root stays open");
+                if top.leaves < max_links {
+                    top.links.push(leaf);
+                    top.leaves += 1;
+                    return;
+                }
+                if top.takes_subtree() {
+                    let depth = top.depth;
+                    frames.push(TrickleFrame::new(Some(depth)));
+                } else {
+                    // Full: close it, and let its parent take the leaf
+                    let done = frames.pop().expect("a full subtree is not the root");
+                    let parent = frames.last_mut().expect("a subtree has a parent");
+                    parent.add_subtree(close_node(&mut self.closed, done.links));
+                }
+            },
+        }
+    }
+
+    /// Closes every open node and returns the root, or `None` if no leaf was
+    /// added.
+    pub(crate) fn finish(&mut self) -> Option<ContentId> {
+        let closed = &mut self.closed;
+        match std::mem::replace(&mut self.open, OpenNodes::Balanced(vec![Vec::new()])) {
+            OpenNodes::Balanced(mut levels) => {
+                let mut level = 0;
+                loop {
+                    // A lone node with nothing above it is the root; leaves
+                    // always get at least one node over them
+                    if level > 0 && levels[level].len() == 1 && level + 1 == levels.len() {
+                        return levels.pop()?.pop();
+                    }
+                    if !levels[level].is_empty() {
+                        let node = close_node(closed, std::mem::take(&mut levels[level]));
+                        if level + 1 == levels.len() {
+                            levels.push(Vec::new());
+                        }
+                        levels[level + 1].push(node);
+                    } else if level + 1 == levels.len() {
+                        return None;
+                    }
+                    level += 1;
+                }
+            }
+            OpenNodes::Trickle(mut frames) => {
+                while frames.len() > 1 {
+                    let done = frames.pop()?;
+                    let parent = frames.last_mut()?;
+                    parent.add_subtree(close_node(closed, done.links));
+                }
+                let root = frames.pop()?;
+                (!root.links.is_empty()).then(|| close_node(closed, root.links))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Layout = fn(&[ContentId], usize, &mut Nodes) -> ContentId;
+
+    fn build_balanced(leaves: &[ContentId], max_links: usize, nodes: &mut Nodes) -> ContentId {
+        balanced(leaves, max_links, &mut |links| add_node(nodes, links))
+    }
+
+    fn build_trickle(leaves: &[ContentId], max_links: usize, nodes: &mut Nodes) -> ContentId {
+        trickle(leaves, max_links, &mut |links| add_node(nodes, links))
+    }
+
+    fn leaves(n: u32) -> Vec<ContentId> {
+        (0..n).map(|i| ContentId::new(&i.to_be_bytes())).collect()
+    }
+
+    /// Leaves under `cid`, in order
+    fn walk(nodes: &Nodes, cid: &ContentId, out: &mut Vec<ContentId>) {
+        match nodes.get(cid) {
+            Some(links) => links.iter().for_each(|l| walk(nodes, l, out)),
+            None => out.push(cid.clone()),
+        }
+    }
+
+    #[test]
+    fn test_small_trees_are_flat() {
+        let leaves = leaves(5);
+
+        for build in [build_balanced as Layout, build_trickle] {
+            let mut nodes = Nodes::new();
+            let root = build(&leaves, 8, &mut nodes);
+
+            assert_eq!(nodes.len(), 1);
+            assert_eq!(nodes[&root], leaves);
+            assert_eq!(root, node_cid(&leaves));
+        }
+    }
+
+    #[test]
+    fn test_balanced_shape() {
+        let leaves = leaves(10);
+        let mut nodes = Nodes::new();
+        let root = build_balanced(&leaves, 3, &mut nodes);
+
+        // 10 leaves -> 4 nodes -> 2 nodes -> root
+        assert_eq!(nodes.len(), 4 + 2 + 1);
+        assert_eq!(nodes[&root].len(), 2);
+        assert!(nodes.values().all(|links| links.len() <= 3));
+
+        let mut out = Vec::new();
+        walk(&nodes, &root, &mut out);
+        assert_eq!(out, leaves);
+    }
+
+    #[test]
+    fn test_trickle_shape() {
+        let leaves = leaves(20);
+        let mut nodes = Nodes::new();
+        let root = build_trickle(&leaves, 2, &mut nodes);
+
+        // Root: 2 leaves, 4 depth-1 subtrees of 2 leaves, then a depth-2
+        // subtree with the remaining 10
+        let links = &nodes[&root];
+        assert_eq!(links[..2], leaves[..2]);
+        assert_eq!(links.len(), 2 + 4 + 1);
+        for (i, subtree) in links[2..6].iter().enumerate() {
+            assert_eq!(nodes[subtree], leaves[2 + 2 * i..4 + 2 * i]);
+        }
+
+        let mut out = Vec::new();
+        walk(&nodes, &root, &mut out);
+        assert_eq!(out, leaves);
+    }
+
+    #[test]
+    fn test_tree_stream_matches_layouts() {
+        let all = leaves(400);
+
+        for (layout, build) in [
+            (DagLayout::Balanced, build_balanced as Layout),
+            (DagLayout::Trickle, build_trickle),
+        ] {
+            for max_links in [2, 3, 5, 174] {
+                for n in (1..=70).chain([125, 126, 174, 175, 400]) {
+                    let leaves = &all[..n];
+                    let mut stream = TreeStream::new(layout, max_links);
+                    stream.keep_closed();
+                    leaves.iter().for_each(|leaf| stream.push(leaf.clone()));
+
+                    let mut nodes = Nodes::new();
+                    let expected = build(leaves, max_links, &mut nodes);
+                    assert_eq!(
+                        stream.finish(),
+                        Some(expected),
+                        "{:?}, {} links, {} leaves",
+                        layout,
+                        max_links,
+                        n
+                    );
+
+                    // Every node's block comes out once, and decodes to its links
+                    let closed = stream.take_closed();
+                    assert_eq!(closed.len(), nodes.len());
+                    for block in closed {
+                        assert_eq!(decode_node(&block.data).unwrap(), nodes[&block.cid]);
+                    }
+                }
+            }
+
+            assert_eq!(TreeStream::new(layout, 4).finish(), None);
+        }
+    }
+
+    #[test]
+    fn test_tree_stream_memory_is_bounded() {
+        let mut stream = TreeStream::new(DagLayout::Balanced, 4);
+        for leaf in leaves(4096) {
+            stream.push(leaf);
+        }
+        // 4^6 leaves fill six levels exactly: every open level is empty
+        let OpenNodes::Balanced(levels) = &stream.open else {
+            unreachable!()
+        };
+        assert_eq!(levels.len(), 7);
+        assert!(levels[..6].iter().all(Vec::is_empty));
+
+        let mut stream = TreeStream::new(DagLayout::Trickle, 4);
+        for leaf in leaves(4096) {
+            stream.push(leaf);
+        }
+        let OpenNodes::Trickle(frames) = &stream.open else {
+            unreachable!()
+        };
+        assert!(frames.len() <= 8);
+        assert!(frames.iter().all(|frame| frame.links.len() <= 4 + 4 * 8));
+    }
+}
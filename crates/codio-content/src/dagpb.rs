@@ -0,0 +1,286 @@
+//! dag-pb: the protobuf node format of IPFS MerkleDAGs.
+//!
+//! ```text
+//! message PBLink {
+//!   optional bytes Hash = 1;
+//!   optional string Name = 2;
+//!   optional uint64 Tsize = 3;
+//! }
+//!
+//! message PBNode {
+//!   repeated PBLink Links = 2;
+//!   optional bytes Data = 1;
+//! }
+//! ```
+//!
+//! Encoding is canonical, as the dag-pb spec requires: links come before the
+//! data, and link fields are written in field order. Nodes with the same
+//! links and data always encode to the same bytes, so they get the same CID
+//! as in go-ipfs.
+
+use anyhow::{anyhow, Result};
+
+use crate::ContentId;
+
+const WIRE_VARINT: u64 = 0;
+const WIRE_BYTES: u64 = 2;
+
+/// A link from a dag-pb node to another block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PbLink {
+    /// CID of the linked block
+    pub hash: ContentId,
+
+    /// Entry name: the file name in directories, empty in files
+    pub name: Option<String>,
+
+    /// Cumulative size of the linked block and everything below it
+    pub tsize: Option<u64>,
+}
+
+/// A dag-pb node: ordered links plus opaque data (UnixFS, for files and
+/// directories).
+///
+/// # Example
+///
+/// ```rust
+/// use codio_content::PbNode;
+///
+/// // The empty UnixFS directory
+/// let node = PbNode {
+///     links: vec![],
+///     data: Some(vec![0x08, 0x01]),
+/// };
+/// let block = node.encode();
+///
+/// assert_eq!(block, [0x0a, 0x02, 0x08, 0x01]);
+/// assert_eq!(PbNode::decode(&block).unwrap(), node);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PbNode {
+    /// Links, in order
+    pub links: Vec<PbLink>,
+
+    /// Node payload
+    pub data: Option<Vec<u8>>,
+}
+
+impl PbNode {
+    /// Encodes the node in canonical dag-pb form.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        for link in &self.links {
+            let mut body = Vec::new();
+            put_bytes(&mut body, 1, &link.hash.to_bytes());
+            if let Some(name) = &link.name {
+                put_bytes(&mut body, 2, name.as_bytes());
+            }
+            if let Some(tsize) = link.tsize {
+                put_uint(&mut body, 3, tsize);
+            }
+            put_bytes(&mut out, 2, &body);
+        }
+
+        if let Some(data) = &self.data {
+            put_bytes(&mut out, 1, data);
+        }
+
+        out
+    }
+
+    /// Decodes a dag-pb block.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the block is not valid dag-pb: truncated, with
+    /// unknown fields, data before links, or links without a valid CID.
+    pub fn decode(mut bytes: &[u8]) -> Result<Self> {
+        let mut node = PbNode::default();
+
+        while !bytes.is_empty() {
+            let (field, wire) = read_key(&mut bytes)?;
+            match (field, wire) {
+                (1, WIRE_BYTES) if node.data.is_none() => {
+                    node.data = Some(read_bytes(&mut bytes)?.to_vec());
+                }
+                (2, WIRE_BYTES) if node.data.is_none() => {
+                    node.links.push(decode_link(read_bytes(&mut bytes)?)?);
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "Invalid dag-pb node: unexpected field {} (wire type {})",
+                        field,
+                        wire
+                    ))
+                }
+            }
+        }
+
+        Ok(node)
+    }
+}
+
+fn decode_link(mut bytes: &[u8]) -> Result<PbLink> {
+    let mut hash = None;
+    let mut name = None;
+    let mut tsize = None;
+    let mut last = 0;
+
+    while !bytes.is_empty() {
+        let (field, wire) = read_key(&mut bytes)?;
+        if field <= last {
+            return Err(anyhow!("Invalid dag-pb link: fields out of order"));
+        }
+        last = field;
+
+        match (field, wire) {
+            (1, WIRE_BYTES) => {
+                let cid = read_bytes(&mut bytes)?;
+                hash = Some(
+                    ContentId::from_bytes(cid)
+                        .map_err(|e| anyhow!("Invalid dag-pb link: {}", e))?,
+                );
+            }
+            (2, WIRE_BYTES) => {
+                let raw = read_bytes(&mut bytes)?;
+                name = Some(
+                    String::from_utf8(raw.to_vec())
+                        .map_err(|_| anyhow!("Invalid dag-pb link: name is not UTF-8"))?,
+                );
+            }
+            (3, WIRE_VARINT) => tsize = Some(read_varint(&mut bytes)?),
+            _ => {
+                return Err(anyhow!(
+                    "Invalid dag-pb link: unexpected field {} (wire type {})",
+                    field,
+                    wire
+                ))
+            }
+        }
+    }
+
+    Ok(PbLink {
+        hash: hash.ok_or_else(|| anyhow!("Invalid dag-pb link: missing hash"))?,
+        name,
+        tsize,
+    })
+}
+
+// Protobuf wire format, shared with the UnixFS data encoding
+
+pub(crate) fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+pub(crate) fn put_uint(out: &mut Vec<u8>, field: u64, value: u64) {
+    put_varint(out, field << 3 | WIRE_VARINT);
+    put_varint(out, value);
+}
+
+pub(crate) fn put_bytes(out: &mut Vec<u8>, field: u64, value: &[u8]) {
+    put_varint(out, field << 3 | WIRE_BYTES);
+    put_varint(out, value.len() as u64);
+    out.extend_from_slice(value);
+}
+
+pub(crate) fn read_varint(bytes: &mut &[u8]) -> Result<u64> {
+    let mut value = 0u64;
+
+    for (i, &byte) in bytes.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            *bytes = &bytes[i + 1..];
+            return Ok(value);
+        }
+    }
+
+    Err(anyhow!("Invalid protobuf varint"))
+}
+
+/// Reads a field key: `(field number, wire type)`.
+pub(crate) fn read_key(bytes: &mut &[u8]) -> Result<(u64, u64)> {
+    let key = read_varint(bytes)?;
+    Ok((key >> 3, key & 0x7))
+}
+
+pub(crate) fn read_bytes<'a>(bytes: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = read_varint(bytes)?;
+    if len > bytes.len() as u64 {
+        return Err(anyhow!("Truncated protobuf field"));
+    }
+
+    let (value, rest) = bytes.split_at(len as usize);
+    *bytes = rest;
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_link_encoding() {
+        let cid = ContentId::new(b"leaf");
+        let node = PbNode {
+            links: vec![PbLink {
+                hash: cid.clone(),
+                name: Some(String::new()),
+                tsize: Some(300),
+            }],
+            data: Some(vec![0x08, 0x02]),
+        };
+        let block = node.encode();
+
+        // Link (field 2): hash, empty name, tsize; then data (field 1)
+        let mut expected = vec![0x12, 34 + 2 + 2 + 3, 0x0a, 34];
+        expected.extend_from_slice(&cid.to_bytes());
+        expected.extend_from_slice(&[0x12, 0x00, 0x18, 0xac, 0x02]);
+        expected.extend_from_slice(&[0x0a, 0x02, 0x08, 0x02]);
+        assert_eq!(block, expected);
+
+        assert_eq!(PbNode::decode(&block).unwrap(), node);
+    }
+
+    #[test]
+    fn test_roundtrip_cidv1_links() {
+        let node = PbNode {
+            links: vec![
+                PbLink {
+                    hash: ContentId::new_v1(codio_content_id::Codec::Raw, b"a"),
+                    name: Some("a.txt".into()),
+                    tsize: Some(1),
+                },
+                PbLink {
+                    hash: ContentId::new(b"b"),
+                    name: None,
+                    tsize: None,
+                },
+            ],
+            data: None,
+        };
+
+        assert_eq!(PbNode::decode(&node.encode()).unwrap(), node);
+        assert_eq!(PbNode::decode(&[]).unwrap(), PbNode::default());
+    }
+
+    #[test]
+    fn test_decode_rejects_invalid() {
+        // Truncated data
+        assert!(PbNode::decode(&[0x0a, 0x05, 0x01]).is_err());
+        // Unknown field
+        assert!(PbNode::decode(&[0x18, 0x01]).is_err());
+        // Links after data
+        assert!(PbNode::decode(&[0x0a, 0x00, 0x12, 0x00]).is_err());
+        // Link without a hash
+        assert!(PbNode::decode(&[0x12, 0x02, 0x18, 0x01]).is_err());
+        // Link with a bad CID
+        assert!(PbNode::decode(&[0x12, 0x03, 0x0a, 0x01, 0xff]).is_err());
+        // Unterminated varint
+        assert!(PbNode::decode(&[0x0a, 0xff]).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! Differences between two versions of content.
+//!
+//! A diff compares the leaves of two DAGs, or any two sets of blocks, by
+//! hash: a block present in both versions is shared even if the CIDs differ
+//! in version or codec, as a blockstore would dedupe it. When version N+1 of
+//! a bundle is published, the added blocks are all peers holding version N
+//! need to fetch.
+
+use std::collections::HashSet;
+
+use crate::ContentId;
+
+/// Blocks added, removed and shared between an old and a new version.
+///
+/// # Example
+///
+/// ```rust
+/// use codio_content::{Chunker, MerkleDAG};
+///
+/// let old = vec![1u8; 8 * 1024];
+/// let mut new = old.clone();
+/// new[5000] = 2;
+///
+/// let chunker = Chunker::new(1024);
+/// let diff = MerkleDAG::from_chunks(&chunker.chunk(&old))
+///     .diff(&MerkleDAG::from_chunks(&chunker.chunk(&new)));
+///
+/// // Seven identical chunks dedupe to one CID; chunk 4 changed
+/// assert_eq!(diff.added.len(), 1);
+/// assert_eq!(diff.added_bytes, 1024);
+/// assert_eq!(diff.shared.len(), 1);
+/// assert!(diff.removed.is_empty());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DagDiff {
+    /// Blocks only the new version has, in its order
+    pub added: Vec<ContentId>,
+
+    /// Blocks only the old version has, in its order
+    pub removed: Vec<ContentId>,
+
+    /// Blocks both versions have, in the new version's order
+    pub shared: Vec<ContentId>,
+
+    /// Total size of the added blocks
+    pub added_bytes: u64,
+
+    /// Total size of the removed blocks
+    pub removed_bytes: u64,
+
+    /// Total size of the shared blocks
+    pub shared_bytes: u64,
+}
+
+impl DagDiff {
+    /// Compares two versions given as `(cid, size)` blocks.
+    ///
+    /// Blocks listed more than once count once.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{ContentId, DagDiff};
+    ///
+    /// let a = ContentId::new(b"a");
+    /// let b = ContentId::new(b"b");
+    /// let c = ContentId::new(b"c");
+    ///
+    /// let diff = DagDiff::between(
+    ///     [(a.clone(), 10), (b.clone(), 20)],
+    ///     [(b.clone(), 20), (c.clone(), 30)],
+    /// );
+    /// assert_eq!(diff.added, [c]);
+    /// assert_eq!(diff.removed, [a]);
+    /// assert_eq!(diff.shared, [b]);
+    /// assert_eq!((diff.added_bytes, diff.removed_bytes, diff.shared_bytes), (30, 10, 20));
+    /// ```
+    pub fn between(
+        old: impl IntoIterator<Item = (ContentId, u64)>,
+        new: impl IntoIterator<Item = (ContentId, u64)>,
+    ) -> Self {
+        let old = dedupe(old);
+        let new = dedupe(new);
+        let in_old: HashSet<Vec<u8>> = old.iter().map(|(cid, _)| cid.routing_key()).collect();
+        let in_new: HashSet<Vec<u8>> = new.iter().map(|(cid, _)| cid.routing_key()).collect();
+
+        let mut diff = DagDiff::default();
+        for (cid, size) in new {
+            if in_old.contains(&cid.routing_key()) {
+                diff.shared_bytes += size;
+                diff.shared.push(cid);
+            } else {
+                diff.added_bytes += size;
+                diff.added.push(cid);
+            }
+        }
+        for (cid, size) in old {
+            if !in_new.contains(&cid.routing_key()) {
+                diff.removed_bytes += size;
+                diff.removed.push(cid);
+            }
+        }
+
+        diff
+    }
+
+    /// Whether the versions have the same blocks
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// `blocks` without repeats, first occurrence kept
+fn dedupe(blocks: impl IntoIterator<Item = (ContentId, u64)>) -> Vec<(ContentId, u64)> {
+    let mut seen = HashSet::new();
+    blocks
+        .into_iter()
+        .filter(|(cid, _)| seen.insert(cid.routing_key()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chunker, Content, DagBuilder, ErasureCoding, MerkleDAG};
+    use codio_content_id::{Codec, Version};
+
+    fn text(lines: std::ops::Range<u32>) -> Vec<u8> {
+        lines
+            .flat_map(|i| format!("line {} of the bundle\n", i).into_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_appended_content_shares_prefix() {
+        let chunker = Chunker::new(4096);
+        let old = Content::with_chunker(text(0..2000), &chunker);
+        let new = Content::with_chunker(text(0..3000), &chunker);
+
+        let diff = old.dag().diff(new.dag());
+        assert_eq!(diff.removed.len(), 1, "The old partial last chunk");
+        assert_eq!(diff.shared.len() + diff.added.len(), new.num_chunks());
+        assert_eq!(
+            diff.shared_bytes + diff.added_bytes,
+            new.size() as u64,
+            "Bytes are counted per chunk"
+        );
+        assert!(!diff.is_empty());
+
+        assert!(new.dag().diff(new.dag()).is_empty());
+    }
+
+    #[test]
+    fn test_cid_versions_match_by_hash() {
+        let v0 = ContentId::new(b"block");
+        let v1 = ContentId::from_digest_v1(Codec::Raw, v0.algorithm(), v0.hash()).unwrap();
+        assert_eq!(v1.version(), Version::V1);
+
+        let diff = DagDiff::between([(v0, 5)], [(v1.clone(), 5)]);
+        assert!(diff.is_empty());
+        assert_eq!(diff.shared, [v1]);
+    }
+
+    #[test]
+    fn test_coded_dag_sizes_only_data_leaves() {
+        let builder = DagBuilder::new().with_erasure_coding(ErasureCoding::new(2, 1));
+        let content = Content::build(text(0..500), &Chunker::new(1024), &builder);
+        let diff = MerkleDAG::from_chunks(&[]).diff(content.dag());
+
+        assert_eq!(diff.added.len(), content.dag().num_children());
+        assert_eq!(diff.added_bytes, content.size() as u64);
+    }
+}
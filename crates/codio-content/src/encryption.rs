@@ -0,0 +1,731 @@
+//! Encrypted content.
+//!
+//! Content is encrypted with a per-file [`ContentKey`] before it is chunked
+//! and addressed, so CIDs, DAGs and everything peers store and relay cover
+//! ciphertext only. The key is either random, or convergent: derived from
+//! the content, so identical files encrypt identically and still
+//! deduplicate, at the cost of revealing that they are identical to anyone
+//! who has the file. Readers get a [`Capability`]: the CID and the key.
+//!
+//! Each piece of content (a chunk, or a segment of a file encrypted whole
+//! with [`ContentKey::encrypt`]) is sealed with ChaCha20-Poly1305:
+//!
+//! - 12 bytes: nonce
+//! - the ciphertext, as long as the plaintext
+//! - 16 bytes: authentication tag
+//!
+//! The piece's position and whether it is the last one are authenticated
+//! with it, so pieces can't be reordered, dropped or truncated without
+//! decryption failing. The nonce is a keyed BLAKE3 hash of the position and
+//! plaintext: encryption is deterministic, and a key used for different
+//! plaintexts never repeats a nonce.
+
+use anyhow::{anyhow, Result};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+
+use crate::{Chunk, Compression, ContentId};
+
+/// Bytes of plaintext per segment of content encrypted whole (64 KB)
+pub const ENCRYPTED_SEGMENT_SIZE: usize = 64 * 1024;
+
+/// Bytes of ciphertext per segment of content encrypted whole: the
+/// plaintext with its nonce and tag
+pub const SEALED_SEGMENT_SIZE: usize = ENCRYPTED_SEGMENT_SIZE + SEAL_OVERHEAD;
+
+/// Bytes a sealed piece adds to its plaintext: nonce and tag
+const SEAL_OVERHEAD: usize = NONCE_LEN + 16;
+
+const NONCE_LEN: usize = 12;
+
+/// BLAKE3 key derivation context for convergent keys
+const CONVERGENT_CONTEXT: &str = "codio-content convergent key v1";
+
+/// A 256-bit key encrypting one piece of content.
+///
+/// Shown and parsed as base58.
+///
+/// # Example
+///
+/// ```rust
+/// use codio_content::ContentKey;
+///
+/// let data = b"licensed asset".repeat(10_000);
+///
+/// // Convergent keys depend only on the content
+/// let key = ContentKey::convergent(&data);
+/// assert_eq!(key, ContentKey::convergent(&data));
+///
+/// let ciphertext = key.encrypt(&data);
+/// assert_eq!(key.decrypt(&ciphertext).unwrap(), data);
+/// assert!(ContentKey::random().decrypt(&ciphertext).is_err());
+///
+/// let parsed: ContentKey = key.to_string().parse().unwrap();
+/// assert_eq!(parsed, key);
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct ContentKey([u8; 32]);
+
+impl ContentKey {
+    /// Creates a random key: encrypting the same content twice gives
+    /// unrelated ciphertexts and CIDs.
+    pub fn random() -> Self {
+        ContentKey(rand::random())
+    }
+
+    /// Derives the key from the content itself: anyone encrypting the same
+    /// content gets the same ciphertext and CIDs, so it deduplicates, but
+    /// anyone holding the plaintext can tell it is what a CID addresses.
+    pub fn convergent(data: &[u8]) -> Self {
+        ContentKey(blake3::derive_key(CONVERGENT_CONTEXT, data))
+    }
+
+    /// Derives the [`convergent`](Self::convergent) key of the content
+    /// `reader` yields, reading it as a stream.
+    pub async fn convergent_from_async_reader<R: AsyncRead + Unpin>(
+        mut reader: R,
+    ) -> io::Result<Self> {
+        let mut hasher = blake3::Hasher::new_derive_key(CONVERGENT_CONTEXT);
+        let mut buf = vec![0u8; ENCRYPTED_SEGMENT_SIZE];
+        loop {
+            let n = reader.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(ContentKey(*hasher.finalize().as_bytes()));
+            }
+            hasher.update(&buf[..n]);
+        }
+    }
+
+    /// Creates a key from its bytes.
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        ContentKey(bytes)
+    }
+
+    /// Returns the key bytes.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Encrypts `data` as a whole, in sealed segments of
+    /// [`ENCRYPTED_SEGMENT_SIZE`] bytes of plaintext.
+    ///
+    /// The result can be published like any file; [`decrypt`](Self::decrypt)
+    /// reverses it.
+    pub fn encrypt(&self, data: &[u8]) -> Vec<u8> {
+        let segments = data.len().div_ceil(ENCRYPTED_SEGMENT_SIZE).max(1);
+        let mut out = Vec::with_capacity(data.len() + segments * SEAL_OVERHEAD);
+
+        for i in 0..segments {
+            let start = i * ENCRYPTED_SEGMENT_SIZE;
+            let end = (start + ENCRYPTED_SEGMENT_SIZE).min(data.len());
+            out.extend_from_slice(&self.seal(i as u64, i + 1 == segments, &data[start..end]));
+        }
+
+        out
+    }
+
+    /// Encrypts what `reader` yields as [`encrypt`](Self::encrypt) would,
+    /// one segment at a time, so content of any size can be encrypted
+    /// without holding it in memory.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::ContentKey;
+    /// use tokio::io::AsyncReadExt;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> std::io::Result<()> {
+    /// let data = b"licensed asset".repeat(10_000);
+    /// let key = ContentKey::convergent_from_async_reader(&data[..]).await?;
+    /// assert_eq!(key, ContentKey::convergent(&data));
+    ///
+    /// let mut ciphertext = Vec::new();
+    /// key.encrypt_reader(&data[..]).read_to_end(&mut ciphertext).await?;
+    /// assert_eq!(ciphertext, key.encrypt(&data));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn encrypt_reader<R>(&self, reader: R) -> EncryptReader<R> {
+        EncryptReader {
+            reader,
+            key: self.clone(),
+            pending: Vec::with_capacity(ENCRYPTED_SEGMENT_SIZE + 1),
+            sealed: Vec::new(),
+            pos: 0,
+            index: 0,
+            eof: false,
+            done: false,
+        }
+    }
+
+    /// Decrypts content encrypted with [`encrypt`](Self::encrypt).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if this is the wrong key, or the data was modified,
+    /// reordered or truncated.
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+        if data.is_empty() {
+            return Err(anyhow!("Encrypted content is empty"));
+        }
+
+        let segments: Vec<&[u8]> = data
+            .chunks(ENCRYPTED_SEGMENT_SIZE + SEAL_OVERHEAD)
+            .collect();
+        let mut out = Vec::with_capacity(data.len());
+        for (i, segment) in segments.iter().enumerate() {
+            out.extend_from_slice(&self.open(i as u64, i + 1 == segments.len(), segment)?);
+        }
+
+        Ok(out)
+    }
+
+    /// Decrypts what `reader` yields as [`decrypt`](Self::decrypt) would,
+    /// one segment at a time, handing out each segment's plaintext only
+    /// once it is authenticated.
+    ///
+    /// Reads fail with [`io::ErrorKind::InvalidData`] if this is the wrong
+    /// key, or the data was modified, reordered or truncated; what was read
+    /// before then is authentic, but not the whole content.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::ContentKey;
+    /// use tokio::io::AsyncReadExt;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> std::io::Result<()> {
+    /// let data = b"licensed asset".repeat(10_000);
+    /// let key = ContentKey::random();
+    /// let ciphertext = key.encrypt(&data);
+    ///
+    /// let mut plaintext = Vec::new();
+    /// key.decrypt_reader(&ciphertext[..]).read_to_end(&mut plaintext).await?;
+    /// assert_eq!(plaintext, data);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn decrypt_reader<R>(&self, reader: R) -> DecryptReader<R> {
+        DecryptReader {
+            reader,
+            key: self.clone(),
+            pending: Vec::with_capacity(SEALED_SEGMENT_SIZE + 1),
+            opened: Vec::new(),
+            pos: 0,
+            index: 0,
+            last: None,
+            eof: false,
+            done: false,
+        }
+    }
+
+    /// Returns the size of the plaintext of `len` bytes of content
+    /// encrypted with [`encrypt`](Self::encrypt), or `None` if no
+    /// ciphertext is that long.
+    pub fn decrypted_len(len: u64) -> Option<u64> {
+        let segments = len.div_ceil(SEALED_SEGMENT_SIZE as u64).max(1);
+        let last = len.checked_sub((segments - 1) * SEALED_SEGMENT_SIZE as u64)?;
+        if last < SEAL_OVERHEAD as u64 {
+            return None;
+        }
+        Some(len - segments * SEAL_OVERHEAD as u64)
+    }
+
+    /// Encrypts data chunk `chunk`, with its codec, into a chunk addressing
+    /// the ciphertext.
+    pub(crate) fn seal_chunk(&self, chunk: &Chunk, last: bool) -> Chunk {
+        let mut plaintext = Vec::with_capacity(1 + chunk.data.len());
+        plaintext.push(chunk.compression.tag());
+        plaintext.extend_from_slice(&chunk.data);
+
+        let sealed = self.seal(chunk.index as u64, last, &plaintext);
+        Chunk::new_with(chunk.index, sealed, chunk.cid.algorithm())
+    }
+
+    /// Decrypts and decompresses the data of chunk `index`, sealed with
+    /// [`seal_chunk`](Self::seal_chunk).
+    pub(crate) fn open_chunk(&self, index: u32, last: bool, data: &[u8]) -> Result<Vec<u8>> {
+        let plaintext = self
+            .open(index as u64, last, data)
+            .map_err(|e| anyhow!("Chunk {}: {}", index, e))?;
+        let (&tag, data) = plaintext
+            .split_first()
+            .ok_or_else(|| anyhow!("Chunk {} has no codec", index))?;
+        let compression = Compression::from_tag(tag)
+            .ok_or_else(|| anyhow!("Chunk {} has unknown codec {}", index, tag))?;
+
+        Ok(compression.decompress(data)?.into_owned())
+    }
+
+    /// Seals piece `index` of some content
+    fn seal(&self, index: u64, last: bool, plaintext: &[u8]) -> Vec<u8> {
+        let aad = associated_data(index, last);
+        let mut hasher = blake3::Hasher::new_keyed(&self.0);
+        hasher.update(&aad);
+        hasher.update(plaintext);
+        let hash = hasher.finalize();
+        let nonce = &hash.as_bytes()[..NONCE_LEN];
+
+        let ciphertext = self
+            .cipher()
+            .encrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: &aad,
+                },
+            )
+            .expect("ChaCha20-Poly1305 encryption can't fail for chunk-sized data");
+
+        let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(nonce);
+        sealed.extend_from_slice(&ciphertext);
+        sealed
+    }
+
+    /// Opens piece `index` of some content
+    fn open(&self, index: u64, last: bool, sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < SEAL_OVERHEAD {
+            return Err(anyhow!("Encrypted data is truncated"));
+        }
+
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.cipher()
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: &associated_data(index, last),
+                },
+            )
+            .map_err(|_| anyhow!("Decryption failed (wrong key, or modified data)"))
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(&self.0))
+    }
+}
+
+/// Encrypts content from a reader; see [`ContentKey::encrypt_reader`].
+pub struct EncryptReader<R> {
+    reader: R,
+    key: ContentKey,
+    /// Plaintext read but not yet sealed; a byte past a full segment shows
+    /// that segment isn't the last
+    pending: Vec<u8>,
+    /// Sealed segment being handed out
+    sealed: Vec<u8>,
+    /// How much of `sealed` has been handed out
+    pos: usize,
+    /// Index of the next segment to seal
+    index: u64,
+    /// Whether the reader is exhausted
+    eof: bool,
+    /// Whether the last segment has been sealed
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for EncryptReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.pos < this.sealed.len() {
+                let n = buf.remaining().min(this.sealed.len() - this.pos);
+                buf.put_slice(&this.sealed[this.pos..this.pos + n]);
+                this.pos += n;
+                return Poll::Ready(Ok(()));
+            }
+            if this.done {
+                return Poll::Ready(Ok(()));
+            }
+
+            if !this.eof && this.pending.len() <= ENCRYPTED_SEGMENT_SIZE {
+                let mut chunk = [0u8; 8192];
+                let want = (ENCRYPTED_SEGMENT_SIZE + 1 - this.pending.len()).min(chunk.len());
+                let mut read = ReadBuf::new(&mut chunk[..want]);
+                ready!(Pin::new(&mut this.reader).poll_read(cx, &mut read))?;
+                match read.filled() {
+                    [] => this.eof = true,
+                    filled => this.pending.extend_from_slice(filled),
+                }
+                continue;
+            }
+
+            let last = this.pending.len() <= ENCRYPTED_SEGMENT_SIZE;
+            let len = this.pending.len().min(ENCRYPTED_SEGMENT_SIZE);
+            this.sealed = this.key.seal(this.index, last, &this.pending[..len]);
+            this.pending.drain(..len);
+            this.pos = 0;
+            this.index += 1;
+            this.done = last;
+        }
+    }
+}
+
+/// Decrypts content from a reader; see [`ContentKey::decrypt_reader`].
+pub struct DecryptReader<R> {
+    reader: R,
+    key: ContentKey,
+    /// Ciphertext read but not yet opened; without a known length, a byte
+    /// past a full segment shows that segment isn't the last
+    pending: Vec<u8>,
+    /// Opened segment being handed out
+    opened: Vec<u8>,
+    /// How much of `opened` has been handed out
+    pos: usize,
+    /// Index of the next segment to open
+    index: u64,
+    /// Index of the last segment, if the ciphertext's length is known
+    last: Option<u64>,
+    /// Whether the reader is exhausted
+    eof: bool,
+    /// Whether the last segment has been opened
+    done: bool,
+}
+
+impl<R> DecryptReader<R> {
+    /// Starts at segment `index` of a ciphertext `len` bytes long, which
+    /// the reader yields from the start of that segment on, for decrypting
+    /// part of some content without the segments before it.
+    pub fn from_segment(mut self, index: u64, len: u64) -> Self {
+        self.index = index;
+        self.last = Some(len.div_ceil(SEALED_SEGMENT_SIZE as u64).max(1) - 1);
+        self
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for DecryptReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.pos < this.opened.len() {
+                let n = buf.remaining().min(this.opened.len() - this.pos);
+                buf.put_slice(&this.opened[this.pos..this.pos + n]);
+                this.pos += n;
+                return Poll::Ready(Ok(()));
+            }
+            if this.done {
+                return Poll::Ready(Ok(()));
+            }
+
+            let want = SEALED_SEGMENT_SIZE + usize::from(this.last.is_none());
+            if !this.eof && this.pending.len() < want {
+                let mut chunk = [0u8; 8192];
+                let want = (want - this.pending.len()).min(chunk.len());
+                let mut read = ReadBuf::new(&mut chunk[..want]);
+                ready!(Pin::new(&mut this.reader).poll_read(cx, &mut read))?;
+                match read.filled() {
+                    [] => this.eof = true,
+                    filled => this.pending.extend_from_slice(filled),
+                }
+                continue;
+            }
+
+            let last = match this.last {
+                Some(last) => this.index >= last,
+                None => this.pending.len() <= SEALED_SEGMENT_SIZE,
+            };
+            let len = this.pending.len().min(SEALED_SEGMENT_SIZE);
+            this.opened = this
+                .key
+                .open(this.index, last, &this.pending[..len])
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            this.pending.drain(..len);
+            this.pos = 0;
+            this.index += 1;
+            this.done = last;
+        }
+    }
+}
+
+/// What a piece is authenticated with besides its ciphertext
+fn associated_data(index: u64, last: bool) -> [u8; 9] {
+    let mut aad = [0u8; 9];
+    aad[..8].copy_from_slice(&index.to_le_bytes());
+    aad[8] = u8::from(last);
+    aad
+}
+
+impl fmt::Display for ContentKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&bs58::encode(self.0).into_string())
+    }
+}
+
+impl fmt::Debug for ContentKey {
+    // Keys stay out of logs
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ContentKey(..)")
+    }
+}
+
+impl FromStr for ContentKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = bs58::decode(s)
+            .into_vec()
+            .map_err(|e| anyhow!("Invalid key: {}", e))?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|bytes: Vec<u8>| anyhow!("Invalid key: {} bytes, expected 32", bytes.len()))?;
+        Ok(ContentKey(bytes))
+    }
+}
+
+/// Everything needed to read encrypted content: its CID and key.
+///
+/// Written `<cid>:<key>`. Anyone holding a capability can read the
+/// content, so share it like a password; the CID alone lets peers store and
+/// relay the ciphertext without reading it.
+///
+/// # Example
+///
+/// ```rust
+/// use codio_content::{Capability, ContentId, ContentKey};
+///
+/// let key = ContentKey::random();
+/// let ciphertext = key.encrypt(b"secret");
+/// let capability = Capability::new(ContentId::new(&ciphertext), key);
+///
+/// let parsed: Capability = capability.to_string().parse().unwrap();
+/// assert_eq!(parsed, capability);
+/// assert_eq!(parsed.key.decrypt(&ciphertext).unwrap(), b"secret");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capability {
+    /// CID of the ciphertext
+    pub cid: ContentId,
+    /// Key decrypting it
+    pub key: ContentKey,
+}
+
+impl Capability {
+    /// Creates a capability for the ciphertext at `cid`.
+    pub fn new(cid: ContentId, key: ContentKey) -> Self {
+        Capability { cid, key }
+    }
+
+    /// Returns whether `s` has the shape of a capability rather than a bare
+    /// CID.
+    pub fn is_capability(s: &str) -> bool {
+        s.contains(':')
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.cid, self.key)
+    }
+}
+
+impl FromStr for Capability {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (cid, key) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Invalid capability: expected <cid>:<key>"))?;
+        Ok(Capability {
+            cid: ContentId::from_str(cid)?,
+            key: key.parse()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_segments() {
+        let key = ContentKey::random();
+        for len in [
+            0,
+            1,
+            ENCRYPTED_SEGMENT_SIZE,
+            ENCRYPTED_SEGMENT_SIZE + 1,
+            3 * ENCRYPTED_SEGMENT_SIZE,
+        ] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let ciphertext = key.encrypt(&data);
+            let segments = len.div_ceil(ENCRYPTED_SEGMENT_SIZE).max(1);
+            assert_eq!(ciphertext.len(), len + segments * SEAL_OVERHEAD);
+            assert_eq!(key.decrypt(&ciphertext).unwrap(), data);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_encrypt_reader_matches_encrypt() {
+        let key = ContentKey::random();
+        for len in [
+            0,
+            1,
+            ENCRYPTED_SEGMENT_SIZE,
+            ENCRYPTED_SEGMENT_SIZE + 1,
+            3 * ENCRYPTED_SEGMENT_SIZE,
+            3 * ENCRYPTED_SEGMENT_SIZE + 5000,
+        ] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+
+            // A reader handing out a few bytes at a time
+            let (mut writer, reader) = tokio::io::duplex(1000);
+            let input = data.clone();
+            tokio::spawn(async move {
+                use tokio::io::AsyncWriteExt;
+                writer.write_all(&input).await.unwrap();
+            });
+
+            let mut ciphertext = Vec::new();
+            key.encrypt_reader(reader)
+                .read_to_end(&mut ciphertext)
+                .await
+                .unwrap();
+            assert_eq!(ciphertext, key.encrypt(&data), "{} bytes", len);
+            assert_eq!(
+                ContentKey::convergent_from_async_reader(&data[..])
+                    .await
+                    .unwrap(),
+                ContentKey::convergent(&data)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_decrypt_reader() {
+        let key = ContentKey::random();
+        for len in [
+            0,
+            1,
+            ENCRYPTED_SEGMENT_SIZE,
+            ENCRYPTED_SEGMENT_SIZE + 1,
+            3 * ENCRYPTED_SEGMENT_SIZE + 5000,
+        ] {
+            let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let ciphertext = key.encrypt(&data);
+            let size = ciphertext.len() as u64;
+            assert_eq!(ContentKey::decrypted_len(size), Some(len as u64));
+
+            let mut plaintext = Vec::new();
+            key.decrypt_reader(&ciphertext[..])
+                .read_to_end(&mut plaintext)
+                .await
+                .unwrap();
+            assert_eq!(plaintext, data, "{} bytes", len);
+
+            // Later segments decrypt without the ones before them
+            let segments = len.div_ceil(ENCRYPTED_SEGMENT_SIZE).max(1);
+            let index = segments - 1;
+            let mut plaintext = Vec::new();
+            key.decrypt_reader(&ciphertext[index * SEALED_SEGMENT_SIZE..])
+                .from_segment(index as u64, size)
+                .read_to_end(&mut plaintext)
+                .await
+                .unwrap();
+            assert_eq!(plaintext, &data[index * ENCRYPTED_SEGMENT_SIZE..]);
+        }
+
+        let ciphertext = key.encrypt(&vec![7u8; 3 * ENCRYPTED_SEGMENT_SIZE]);
+        let read = |key: ContentKey, ciphertext: Vec<u8>| async move {
+            let mut plaintext = Vec::new();
+            key.decrypt_reader(&ciphertext[..])
+                .read_to_end(&mut plaintext)
+                .await
+                .map(|_| plaintext)
+        };
+        let error = read(ContentKey::random(), ciphertext.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(
+            read(key.clone(), ciphertext[..2 * SEALED_SEGMENT_SIZE].to_vec())
+                .await
+                .is_err()
+        );
+        assert!(read(key, Vec::new()).await.is_err());
+
+        assert_eq!(ContentKey::decrypted_len(SEAL_OVERHEAD as u64 - 1), None);
+        assert_eq!(
+            ContentKey::decrypted_len(SEALED_SEGMENT_SIZE as u64 + 1),
+            None
+        );
+    }
+
+    #[test]
+    fn test_convergent_and_random_keys() {
+        let data = vec![42u8; 100_000];
+        let convergent = ContentKey::convergent(&data);
+        assert_eq!(convergent.encrypt(&data), convergent.encrypt(&data));
+        assert_ne!(convergent, ContentKey::convergent(&data[1..]));
+
+        let (a, b) = (ContentKey::random(), ContentKey::random());
+        assert_ne!(a, b);
+        assert_ne!(a.encrypt(&data), b.encrypt(&data));
+        assert!(b.decrypt(&a.encrypt(&data)).is_err());
+    }
+
+    #[test]
+    fn test_tampering_is_detected() {
+        let key = ContentKey::random();
+        let data = vec![7u8; 3 * ENCRYPTED_SEGMENT_SIZE];
+        let ciphertext = key.encrypt(&data);
+        let segment = ENCRYPTED_SEGMENT_SIZE + SEAL_OVERHEAD;
+
+        let mut flipped = ciphertext.clone();
+        flipped[100] ^= 1;
+        assert!(key.decrypt(&flipped).is_err());
+
+        // Dropping the last segment leaves one not marked last
+        assert!(key.decrypt(&ciphertext[..2 * segment]).is_err());
+
+        // Swapping two segments puts them at the wrong positions
+        let mut swapped = ciphertext[segment..2 * segment].to_vec();
+        swapped.extend_from_slice(&ciphertext[..segment]);
+        swapped.extend_from_slice(&ciphertext[2 * segment..]);
+        assert!(key.decrypt(&swapped).is_err());
+
+        assert!(key.decrypt(&[]).is_err());
+    }
+
+    #[test]
+    fn test_chunks_keep_their_codec() {
+        let key = ContentKey::random();
+        let text = b"compressible ".repeat(1000);
+        let chunk = Chunk::compressed(3, &text, Default::default(), Compression::Zstd);
+
+        let sealed = key.seal_chunk(&chunk, true);
+        assert_eq!(sealed.index, 3);
+        assert_eq!(sealed.compression, Compression::None);
+        assert!(sealed.verify());
+        assert_eq!(key.open_chunk(3, true, &sealed.data).unwrap(), text);
+        assert!(key.open_chunk(3, false, &sealed.data).is_err());
+        assert!(key.open_chunk(4, true, &sealed.data).is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("not base58 0OIl".parse::<ContentKey>().is_err());
+        assert!(bs58::encode([1u8; 16])
+            .into_string()
+            .parse::<ContentKey>()
+            .is_err());
+        assert!("QmNotACapability".parse::<Capability>().is_err());
+        assert_eq!(format!("{:?}", ContentKey::random()), "ContentKey(..)");
+    }
+}
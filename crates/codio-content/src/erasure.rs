@@ -0,0 +1,546 @@
+//! Reed–Solomon erasure coding of chunks.
+//!
+//! Chunks are coded in stripes: every `k` data chunks get `m` parity chunks,
+//! and any `k` of the `k + m` rebuild the stripe. The last stripe may hold
+//! fewer data chunks, but always `m` parity chunks. Coded chunks are the
+//! leaves of the Merkle DAG, stripe by stripe (data chunks, then parity), so
+//! every parity chunk has its own CID under the root and is verified like a
+//! data chunk before it is used.
+//!
+//! The code is systematic (data chunks are stored unchanged) and works over
+//! GF(2^8) with a Cauchy matrix, so any `k` rows of the generator matrix are
+//! invertible. Chunks in a stripe differ in size, so each data chunk is
+//! coded as a shard holding its length and codec, then its bytes, then zero
+//! padding up to the longest shard of the stripe:
+//!
+//! - 4 bytes: data length, little-endian `u32`
+//! - 1 byte: [`Compression`] of the data (0 none, 1 zstd, 2 lz4)
+//! - the data, as stored in the chunk
+//!
+//! Parity chunks hold the parity shards as they are.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{Chunk, Compression, ContentId};
+
+/// Bytes before the data in a shard: length and codec
+const SHARD_HEADER: usize = 5;
+
+/// Field elements available to name shards; `k + m` can't exceed it
+const MAX_SHARDS: usize = 256;
+
+/// Reed–Solomon parameters: `k` data chunks and `m` parity chunks per stripe.
+///
+/// Content survives the loss of any `m` chunks of each stripe, for `m / k`
+/// extra storage.
+///
+/// # Example
+///
+/// ```rust
+/// use codio_content::{Chunker, ErasureCoding};
+///
+/// let data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+/// let chunks = Chunker::new(1024).chunk(&data);
+/// let coding = ErasureCoding::new(4, 2);
+///
+/// // 10 data chunks: stripes of 4 + 2, 4 + 2 and 2 + 2
+/// let coded = coding.encode(&chunks);
+/// assert_eq!(coded.len(), 16);
+///
+/// // Lose two chunks of the first stripe
+/// let leaves: Vec<_> = coded.iter().map(|c| c.cid.clone()).collect();
+/// let survivors: Vec<_> = coded.into_iter().filter(|c| c.index != 0 && c.index != 3).collect();
+///
+/// let recovered = coding.decode(&leaves, &survivors).unwrap();
+/// assert_eq!(Chunker::new(1024).reconstruct(recovered).unwrap(), data);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ErasureCoding {
+    data_shards: usize,
+    parity_shards: usize,
+}
+
+impl ErasureCoding {
+    /// Creates parameters for `data_shards` data chunks and `parity_shards`
+    /// parity chunks per stripe.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either count is 0 or they add up to more than 256.
+    pub fn new(data_shards: usize, parity_shards: usize) -> Self {
+        let coding = ErasureCoding {
+            data_shards,
+            parity_shards,
+        };
+        if let Err(e) = coding.validate() {
+            panic!("{}", e);
+        }
+        coding
+    }
+
+    /// Returns the number of data chunks per stripe (`k`).
+    pub fn data_shards(&self) -> usize {
+        self.data_shards
+    }
+
+    /// Returns the number of parity chunks per stripe (`m`).
+    pub fn parity_shards(&self) -> usize {
+        self.parity_shards
+    }
+
+    /// Checks the parameters, e.g. after deserializing them.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if self.data_shards == 0 || self.parity_shards == 0 {
+            return Err(format!(
+                "Erasure coding needs at least 1 data and 1 parity chunk, got {} + {}",
+                self.data_shards, self.parity_shards
+            ));
+        }
+        if self.data_shards + self.parity_shards > MAX_SHARDS {
+            return Err(format!(
+                "Erasure coding supports at most {} chunks per stripe, got {} + {}",
+                MAX_SHARDS, self.data_shards, self.parity_shards
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the number of coded chunks for `data_chunks` data chunks.
+    pub fn num_coded(&self, data_chunks: usize) -> usize {
+        data_chunks + data_chunks.div_ceil(self.data_shards) * self.parity_shards
+    }
+
+    /// Returns the leaf position of data chunk `index` (in content order).
+    pub(crate) fn leaf_index(&self, index: usize) -> usize {
+        let width = self.data_shards + self.parity_shards;
+        index / self.data_shards * width + index % self.data_shards
+    }
+
+    /// Codes data chunks, in content order, into stripes of data and parity
+    /// chunks, indexed by their position among the DAG leaves.
+    ///
+    /// Parity chunks are uncompressed and hashed like the first chunk.
+    pub fn encode(&self, chunks: &[Chunk]) -> Vec<Chunk> {
+        let mut coded = Vec::with_capacity(self.num_coded(chunks.len()));
+
+        for stripe in chunks.chunks(self.data_shards) {
+            let k = stripe.len();
+            let len = stripe.iter().map(|c| c.data.len()).max().unwrap_or(0) + SHARD_HEADER;
+            let shards: Vec<Vec<u8>> = stripe.iter().map(|c| shard(c, len)).collect();
+
+            let base = coded.len() as u32;
+            for (j, chunk) in stripe.iter().enumerate() {
+                coded.push(Chunk {
+                    index: base + j as u32,
+                    ..chunk.clone()
+                });
+            }
+
+            let algorithm = stripe[0].cid.algorithm();
+            for i in 0..self.parity_shards {
+                let mut parity = vec![0u8; len];
+                for (j, shard) in shards.iter().enumerate() {
+                    gf::mul_add(&mut parity, shard, cauchy(k, i, j));
+                }
+                coded.push(Chunk::new_with(base + (k + i) as u32, parity, algorithm));
+            }
+        }
+
+        coded
+    }
+
+    /// Rebuilds the data chunks, in content order, from any `k` coded chunks
+    /// of each stripe.
+    ///
+    /// `leaves` are the CIDs of all coded chunks (the DAG leaves) and
+    /// `chunks` those at hand, indexed by leaf position. Chunks that don't
+    /// match their leaf are ignored; recovered chunks are checked against
+    /// theirs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `leaves` can't be a coded layout or a stripe has
+    /// fewer than `k` valid chunks.
+    pub fn decode(&self, leaves: &[ContentId], chunks: &[Chunk]) -> Result<Vec<Chunk>> {
+        let stripes = self.stripes(leaves.len())?;
+        let mut present: Vec<Option<&Chunk>> = vec![None; leaves.len()];
+        for chunk in chunks {
+            let index = chunk.index as usize;
+            if index < leaves.len() && chunk.cid == leaves[index] && chunk.verify() {
+                present[index] = Some(chunk);
+            }
+        }
+
+        let mut data = Vec::new();
+        for (start, k) in stripes {
+            let stripe = &present[start..start + k + self.parity_shards];
+            let first = data.len() as u32;
+
+            if stripe[..k].iter().all(Option::is_some) {
+                for (j, chunk) in stripe[..k].iter().enumerate() {
+                    let chunk = chunk.expect("all present");
+                    data.push(Chunk {
+                        index: first + j as u32,
+                        ..chunk.clone()
+                    });
+                }
+                continue;
+            }
+
+            let available: Vec<usize> = (0..stripe.len())
+                .filter(|&p| stripe[p].is_some())
+                .take(k)
+                .collect();
+            if available.len() < k {
+                return Err(anyhow!(
+                    "Stripe at chunk {} has {} of the {} chunks needed to rebuild it",
+                    start,
+                    stripe.iter().flatten().count(),
+                    k
+                ));
+            }
+
+            // Parity chunks are as long as the stripe's shards
+            let len = stripe[k..]
+                .iter()
+                .flatten()
+                .map(|c| c.data.len())
+                .next()
+                .expect("a data chunk is missing, so a parity chunk is present");
+            let mut shards = Vec::with_capacity(k);
+            for &p in &available {
+                let chunk = stripe[p].expect("available");
+                if p < k {
+                    if chunk.data.len() + SHARD_HEADER > len {
+                        return Err(anyhow!("Chunk {} is longer than its stripe", start + p));
+                    }
+                    shards.push(shard(chunk, len));
+                } else if chunk.data.len() == len {
+                    shards.push(chunk.data.clone());
+                } else {
+                    return Err(anyhow!("Parity chunk {} has the wrong size", start + p));
+                }
+            }
+
+            let rows: Vec<Vec<u8>> = available
+                .iter()
+                .map(|&p| {
+                    (0..k)
+                        .map(|j| match p < k {
+                            true => u8::from(p == j),
+                            false => cauchy(k, p - k, j),
+                        })
+                        .collect()
+                })
+                .collect();
+            let inverse = gf::invert(rows).ok_or_else(|| anyhow!("Stripe matrix is singular"))?;
+
+            for j in 0..k {
+                let index = first + j as u32;
+                if let Some(chunk) = stripe[j] {
+                    data.push(Chunk {
+                        index,
+                        ..chunk.clone()
+                    });
+                    continue;
+                }
+
+                let mut recovered = vec![0u8; len];
+                for (r, shard) in shards.iter().enumerate() {
+                    gf::mul_add(&mut recovered, shard, inverse[j][r]);
+                }
+                let chunk = unshard(index, recovered, leaves[start + j].clone())?;
+                if !chunk.verify() {
+                    return Err(anyhow!(
+                        "Recovered chunk {} does not match its CID",
+                        start + j
+                    ));
+                }
+                data.push(chunk);
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Checks that the coded chunks at hand can rebuild every stripe,
+    /// without decoding: each stripe needs `k` chunks matching their leaves.
+    pub(crate) fn recoverable(&self, leaves: &[ContentId], chunks: &[Chunk]) -> bool {
+        let Ok(stripes) = self.stripes(leaves.len()) else {
+            return false;
+        };
+
+        let mut present = vec![false; leaves.len()];
+        for chunk in chunks {
+            let index = chunk.index as usize;
+            if index >= leaves.len() || chunk.cid != leaves[index] || !chunk.verify() {
+                return false;
+            }
+            present[index] = true;
+        }
+
+        stripes.into_iter().all(|(start, k)| {
+            present[start..start + k + self.parity_shards]
+                .iter()
+                .filter(|p| **p)
+                .count()
+                >= k
+        })
+    }
+
+    /// Splits `leaves` coded chunks into stripes, as (first leaf, data
+    /// chunks) pairs.
+    fn stripes(&self, leaves: usize) -> Result<Vec<(usize, usize)>> {
+        let width = self.data_shards + self.parity_shards;
+        let mut stripes = Vec::with_capacity(leaves.div_ceil(width));
+
+        let mut start = 0;
+        while start < leaves {
+            let len = width.min(leaves - start);
+            if len <= self.parity_shards {
+                return Err(anyhow!(
+                    "{} chunks don't form {} + {} stripes",
+                    leaves,
+                    self.data_shards,
+                    self.parity_shards
+                ));
+            }
+            stripes.push((start, len - self.parity_shards));
+            start += len;
+        }
+
+        Ok(stripes)
+    }
+}
+
+/// Coefficient of data shard `j` in parity shard `i`, for a stripe of `k`
+/// data shards: `1 / (x_i + y_j)` with `x_i = k + i` and `y_j = j`, all
+/// distinct, so every square submatrix is invertible.
+fn cauchy(k: usize, i: usize, j: usize) -> u8 {
+    gf::inv(((k + i) ^ j) as u8)
+}
+
+/// The shard coding `chunk`, padded to `len` bytes
+fn shard(chunk: &Chunk, len: usize) -> Vec<u8> {
+    let mut shard = Vec::with_capacity(len);
+    shard.extend_from_slice(&(chunk.data.len() as u32).to_le_bytes());
+    shard.push(chunk.compression.tag());
+    shard.extend_from_slice(&chunk.data);
+    shard.resize(len, 0);
+    shard
+}
+
+/// The chunk coded by a recovered shard
+fn unshard(index: u32, mut shard: Vec<u8>, cid: ContentId) -> Result<Chunk> {
+    let len = u32::from_le_bytes(shard[..4].try_into().expect("4 bytes")) as usize;
+    let compression = Compression::from_tag(shard[4])
+        .ok_or_else(|| anyhow!("Recovered chunk {} has unknown codec {}", index, shard[4]))?;
+    if SHARD_HEADER + len > shard.len() {
+        return Err(anyhow!("Recovered chunk {} has an invalid length", index));
+    }
+
+    shard.truncate(SHARD_HEADER + len);
+    shard.drain(..SHARD_HEADER);
+    Ok(Chunk {
+        index,
+        data: shard,
+        cid,
+        compression,
+    })
+}
+
+/// Arithmetic in GF(2^8) with the polynomial x^8 + x^4 + x^3 + x^2 + 1.
+mod gf {
+    const fn tables() -> ([u8; 512], [u8; 256]) {
+        let mut exp = [0u8; 512];
+        let mut log = [0u8; 256];
+        let mut x: u16 = 1;
+        let mut i = 0;
+        while i < 255 {
+            exp[i] = x as u8;
+            log[x as usize] = i as u8;
+            x <<= 1;
+            if x & 0x100 != 0 {
+                x ^= 0x11d;
+            }
+            i += 1;
+        }
+        while i < 512 {
+            exp[i] = exp[i - 255];
+            i += 1;
+        }
+        (exp, log)
+    }
+
+    const EXP: [u8; 512] = tables().0;
+    const LOG: [u8; 256] = tables().1;
+
+    pub(super) fn mul(a: u8, b: u8) -> u8 {
+        if a == 0 || b == 0 {
+            return 0;
+        }
+        EXP[LOG[a as usize] as usize + LOG[b as usize] as usize]
+    }
+
+    /// Multiplicative inverse of a non-zero element
+    pub(super) fn inv(a: u8) -> u8 {
+        debug_assert!(a != 0, "0 has no inverse");
+        EXP[255 - LOG[a as usize] as usize]
+    }
+
+    /// `dst += c * src`, element-wise
+    pub(super) fn mul_add(dst: &mut [u8], src: &[u8], c: u8) {
+        if c == 0 {
+            return;
+        }
+        let mut table = [0u8; 256];
+        for (b, product) in table.iter_mut().enumerate() {
+            *product = mul(c, b as u8);
+        }
+        for (d, s) in dst.iter_mut().zip(src) {
+            *d ^= table[*s as usize];
+        }
+    }
+
+    /// Inverts a square matrix by Gauss–Jordan elimination
+    pub(super) fn invert(mut rows: Vec<Vec<u8>>) -> Option<Vec<Vec<u8>>> {
+        let n = rows.len();
+        let mut inverse: Vec<Vec<u8>> = (0..n)
+            .map(|i| (0..n).map(|j| u8::from(i == j)).collect())
+            .collect();
+
+        for col in 0..n {
+            let pivot = (col..n).find(|&r| rows[r][col] != 0)?;
+            rows.swap(col, pivot);
+            inverse.swap(col, pivot);
+
+            let scale = inv(rows[col][col]);
+            for j in 0..n {
+                rows[col][j] = mul(rows[col][j], scale);
+                inverse[col][j] = mul(inverse[col][j], scale);
+            }
+
+            for r in 0..n {
+                let factor = rows[r][col];
+                if r == col || factor == 0 {
+                    continue;
+                }
+                for j in 0..n {
+                    rows[r][j] ^= mul(factor, rows[col][j]);
+                    inverse[r][j] ^= mul(factor, inverse[col][j]);
+                }
+            }
+        }
+
+        Some(inverse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chunker, HashAlgorithm};
+
+    fn leaves(coded: &[Chunk]) -> Vec<ContentId> {
+        coded.iter().map(|c| c.cid.clone()).collect()
+    }
+
+    #[test]
+    fn test_field_inverse() {
+        for a in 1..=255u8 {
+            assert_eq!(gf::mul(a, gf::inv(a)), 1);
+        }
+    }
+
+    #[test]
+    fn test_any_k_chunks_rebuild_a_stripe() {
+        let data: Vec<u8> = (0..5000u32).map(|i| (i * 31 % 256) as u8).collect();
+        let chunks = Chunker::new(1024).chunk(&data);
+        let coding = ErasureCoding::new(5, 3);
+        let coded = coding.encode(&chunks);
+        assert_eq!(coded.len(), 8);
+
+        // Every way of losing 3 of the 8 chunks
+        for a in 0..8 {
+            for b in a + 1..8 {
+                for c in b + 1..8 {
+                    let survivors: Vec<_> = coded
+                        .iter()
+                        .filter(|ch| ![a, b, c].contains(&(ch.index as usize)))
+                        .cloned()
+                        .collect();
+                    let recovered = coding.decode(&leaves(&coded), &survivors).unwrap();
+                    assert_eq!(Chunker::new(1024).reconstruct(recovered).unwrap(), data);
+                }
+            }
+        }
+
+        // One more and the stripe is lost
+        let survivors = &coded[4..];
+        assert!(!coding.recoverable(&leaves(&coded), survivors));
+        assert!(coding.decode(&leaves(&coded), survivors).is_err());
+    }
+
+    #[test]
+    fn test_compressed_chunks_and_other_hashes() {
+        let text = b"erasure coded text ".repeat(2000);
+        let chunker = Chunker::new(4096)
+            .with_compression(Compression::Lz4)
+            .with_hash_algorithm(HashAlgorithm::Blake3);
+        let chunks = chunker.chunk(&text);
+        let coding = ErasureCoding::new(2, 2);
+        let coded = coding.encode(&chunks);
+        assert!(coded
+            .iter()
+            .all(|c| c.cid.algorithm() == HashAlgorithm::Blake3));
+
+        let survivors: Vec<_> = coded.iter().filter(|c| c.index % 4 >= 2).cloned().collect();
+        let recovered = coding.decode(&leaves(&coded), &survivors).unwrap();
+        assert!(recovered.iter().all(|c| c.compression == Compression::Lz4));
+        assert_eq!(chunker.reconstruct(recovered).unwrap(), text);
+    }
+
+    #[test]
+    fn test_decode_ignores_tampered_chunks() {
+        let data = vec![7u8; 3000];
+        let chunks = Chunker::new(1024).chunk(&data);
+        let coding = ErasureCoding::new(3, 1);
+        let mut coded = coding.encode(&chunks);
+        let leaves = leaves(&coded);
+
+        coded[3].data[0] ^= 1;
+        assert!(!coding.recoverable(&leaves, &coded));
+        assert_eq!(
+            Chunker::new(1024)
+                .reconstruct(coding.decode(&leaves, &coded).unwrap())
+                .unwrap(),
+            data
+        );
+
+        // A tampered parity chunk can't stand in for a lost data chunk
+        coded.remove(0);
+        assert!(coding.decode(&leaves, &coded).is_err());
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        assert!(ErasureCoding {
+            data_shards: 0,
+            parity_shards: 1
+        }
+        .validate()
+        .is_err());
+        assert!(ErasureCoding {
+            data_shards: 200,
+            parity_shards: 57
+        }
+        .validate()
+        .is_err());
+        assert!(ErasureCoding::new(200, 56).validate().is_ok());
+
+        // Leaves that can't be stripes of 4 + 2
+        let coding = ErasureCoding::new(4, 2);
+        assert!(coding.decode(&vec![ContentId::new(b"x"); 8], &[]).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! go-random, which generates the files in go-ipfs' sharness tests: the
+//! little-endian `Uint32`s of Go's `math/rand` seeded with `seed`
+//!
+//! Shared with the CLI tests, which include this file by path.
+
+const LEN: usize = 607;
+const TAP: usize = 273;
+const MASK: u64 = (1 << 63) - 1;
+
+/// `count` bytes from `seed`
+pub fn bytes(count: usize, seed: i64) -> Vec<u8> {
+    let mut rng = GoRand::new(seed);
+    let mut out = Vec::with_capacity(count + 4);
+    while out.len() < count {
+        let value = ((rng.next() & MASK) >> 31) as u32;
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out.truncate(count);
+    out
+}
+
+/// Go's `rngSource`, an additive lagged Fibonacci generator
+struct GoRand {
+    vec: [u64; LEN],
+    tap: usize,
+    feed: usize,
+}
+
+impl GoRand {
+    fn new(seed: i64) -> Self {
+        let mut vec = seed_values(seed, 40, 20);
+        for (value, cooked) in vec.iter_mut().zip(cooked()) {
+            *value ^= cooked;
+        }
+        GoRand {
+            vec,
+            tap: 0,
+            feed: LEN - TAP,
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.tap = (self.tap + LEN - 1) % LEN;
+        self.feed = (self.feed + LEN - 1) % LEN;
+        let x = self.vec[self.feed].wrapping_add(self.vec[self.tap]);
+        self.vec[self.feed] = x;
+        x
+    }
+}
+
+/// x[n+1] = 48271 * x[n] mod (2**31 - 1)
+fn seedrand(x: i64) -> i64 {
+    (48271 * x) % i32::MAX as i64
+}
+
+fn seed_values(seed: i64, high: u32, mid: u32) -> [u64; LEN] {
+    let mut x = seed.rem_euclid(i32::MAX as i64);
+    if x == 0 {
+        x = 89482311;
+    }
+    let mut vec = [0u64; LEN];
+    for i in -20..LEN as i64 {
+        x = seedrand(x);
+        if i >= 0 {
+            let mut u = (x as u64) << high;
+            x = seedrand(x);
+            u ^= (x as u64) << mid;
+            x = seedrand(x);
+            u ^= x as u64;
+            vec[i as usize] = u;
+        }
+    }
+    vec
+}
+
+/// Go's `rngCooked` table: the state `gen_cooked.go` reaches after
+/// 7.8e12 steps from seed 1
+///
+/// Each step is b[k] = b[k - 273] + b[k - 607], so rather than step
+/// we raise x to 7.8e12 modulo x^607 - x^334 - 1.
+fn cooked() -> [u64; LEN] {
+    const STEPS: u64 = 7_800_000_000_000;
+    let start = seed_values(1, 20, 10);
+    // Step k overwrites slot (333 - k) mod 607
+    let slot = |k: u64| (333 + LEN - (k % LEN as u64) as usize) % LEN;
+
+    let mut b = [0u64; LEN];
+    for (k, value) in b.iter_mut().enumerate() {
+        *value = start[slot(k as u64)];
+    }
+    let mut coeffs = pow_x(STEPS);
+    let mut cooked = [0u64; LEN];
+    for k in STEPS..STEPS + LEN as u64 {
+        cooked[slot(k)] = coeffs
+            .iter()
+            .zip(&b)
+            .fold(0u64, |sum, (c, v)| sum.wrapping_add(c.wrapping_mul(*v)));
+        coeffs = mul_x(&coeffs);
+    }
+    cooked
+}
+
+fn mul_x(a: &[u64]) -> Vec<u64> {
+    let mut r = vec![a[LEN - 1]];
+    r.extend_from_slice(&a[..LEN - 1]);
+    r[LEN - TAP] = r[LEN - TAP].wrapping_add(a[LEN - 1]);
+    r
+}
+
+fn mul_mod(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let mut r = vec![0u64; 2 * LEN - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            r[i + j] = r[i + j].wrapping_add(x.wrapping_mul(*y));
+        }
+    }
+    for d in (LEN..2 * LEN - 1).rev() {
+        let c = r[d];
+        r[d - LEN] = r[d - LEN].wrapping_add(c);
+        r[d - TAP] = r[d - TAP].wrapping_add(c);
+    }
+    r.truncate(LEN);
+    r
+}
+
+fn pow_x(mut n: u64) -> Vec<u64> {
+    let mut result = vec![0u64; LEN];
+    result[0] = 1;
+    let mut base = vec![0u64; LEN];
+    base[1] = 1;
+    while n > 0 {
+        if n & 1 == 1 {
+            result = mul_mod(&result, &base);
+        }
+        base = mul_mod(&base, &base);
+        n >>= 1;
+    }
+    result
+}
+
+#[test]
+fn test_matches_go() {
+    // rand.Seed(1); rand.Int63() in Go
+    let mut rng = GoRand::new(1);
+    assert_eq!(rng.next() & MASK, 5577006791947779410);
+    assert_eq!(rng.next() & MASK, 8674665223082153551);
+}
@@ -9,9 +9,10 @@
 //! ### Content Identifiers (CIDs)
 //!
 //! CIDs are cryptographic hashes that uniquely identify content. This implementation uses:
-//! - **SHA-256** hashing for security
+//! - **SHA-256** hashing by default, with BLAKE3, SHA-512 and SHA3 available
+//!   via [`HashAlgorithm`] (the hash function is recorded in the CID's multihash)
 //! - **Base58** encoding for human-readable strings
-//! - **IPFS CIDv0** format with "Qm" prefix for compatibility
+//! - **IPFS CIDv0** format (base58 multihash, "Qm" prefix) for compatibility
 //!
 //! ### Content Chunking
 //!
@@ -19,6 +20,8 @@
 //! - Each chunk gets its own CID
 //! - Chunks can be verified independently
 //! - Enables parallel downloads and deduplication
+//! - Content can be streamed from a reader with [`Chunker::chunk_reader`],
+//!   so files larger than memory can be chunked
 //!
 //! ### Merkle DAG
 //!
@@ -27,6 +30,26 @@
 //! - Provides a root CID representing the entire content
 //! - Enables efficient verification of content integrity
 //!
+//! ### Encryption
+//!
+//! Private content is encrypted with a per-file [`ContentKey`], random or
+//! convergent (derived from the content), before it is chunked: chunk CIDs
+//! and the DAG cover ciphertext, so relaying peers can't read it. Readers
+//! need a [`Capability`], the CID and the key.
+//!
+//! ### Erasure Coding
+//!
+//! Instead of replicating every chunk, content can be stored with
+//! Reed–Solomon [`ErasureCoding`]: every `k` data chunks get `m` parity
+//! chunks, each with its own CID in the DAG, and [`Content::reconstruct`]
+//! rebuilds the file from any `k` chunks of each group.
+//!
+//! ### Diffs
+//!
+//! [`MerkleDAG::diff`] compares two versions of content chunk by chunk, so
+//! publishing a new version only needs to push the [`DagDiff::added`]
+//! chunks to peers that hold the old one.
+//!
 //! ## Example Usage
 //!
 //! ```rust
@@ -50,16 +73,28 @@
 //! println!("Root CID: {}", dag.root_cid().to_string());
 //! ```
 
+mod car;
+mod cdc;
+mod compression;
 pub mod config;
+mod dag;
+mod dagpb;
+mod diff;
+mod encryption;
+mod erasure;
+#[cfg(test)]
+mod go_random;
+mod parallel;
+mod proof;
+mod unixfs;
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, Result};
+use config::ContentConfig;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::fmt;
-
-/// IPFS CIDv0 prefix bytes
-/// Represents: multibase (base58btc) + multicodec (dag-pb) + multihash (sha2-256)
-const IPFS_CID_PREFIX: &[u8] = &[0x12, 0x20]; // 0x12 = sha2-256, 0x20 = 32 bytes
+use std::borrow::Cow;
+use std::io::Read;
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 /// Default chunk size (1 MB)
 pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
@@ -70,16 +105,18 @@ pub const MAX_CHUNK_SIZE: usize = 10 * 1024 * 1024;
 /// Minimum chunk size (1 KB)
 pub const MIN_CHUNK_SIZE: usize = 1024;
 
+/// Default maximum number of links per Merkle DAG node (as in UnixFS)
+pub const DEFAULT_MAX_LINKS: usize = 174;
+
 // ============================================================================
 // ContentId - IPFS-Compatible Content Identifier
 // ============================================================================
 
 /// A content identifier (CID) that uniquely represents a piece of data.
 ///
-/// This implementation follows the IPFS CIDv0 specification:
-/// - Uses SHA-256 hashing
-/// - Base58-encoded with "Qm" prefix
-/// - Immutable and deterministic
+/// This is the workspace-wide [`codio_content_id::ContentId`], re-exported so
+/// chunks and DAGs built here interoperate with the network, DHT and gateway.
+/// CIDs are IPFS CIDv0 strings (base58btc of the sha2-256 multihash).
 ///
 /// # Example
 ///
@@ -95,200 +132,47 @@ pub const MIN_CHUNK_SIZE: usize = 1024;
 /// // Verify content matches CID
 /// assert!(cid.verify(content));
 /// ```
-#[derive(Debug, Clone, Hash, Serialize, Deserialize)]
-pub struct ContentId {
-    /// Raw SHA-256 hash of the content
-    hash: [u8; 32],
-
-    /// Base58-encoded CID string (cached for performance)
-    #[serde(skip)]
-    multibase: Option<String>,
-}
-
-impl PartialEq for ContentId {
-    fn eq(&self, other: &Self) -> bool {
-        self.hash == other.hash
-    }
-}
-
-impl Eq for ContentId {}
-
-impl ContentId {
-    /// Creates a new ContentId from raw content bytes.
-    ///
-    /// This computes the SHA-256 hash and generates an IPFS CIDv0-compatible identifier.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use codio_content::ContentId;
-    ///
-    /// let cid = ContentId::new(b"Hello, Codio!");
-    /// assert!(cid.to_string().starts_with("Qm"));
-    /// ```
-    pub fn new(content: &[u8]) -> Self {
-        let mut hasher = Sha256::new();
-        hasher.update(content);
-        let hash: [u8; 32] = hasher.finalize().into();
-
-        ContentId {
-            hash,
-            multibase: None,
-        }
-    }
-
-    /// Creates a ContentId from a CID string (e.g., "QmXXX...").
-    ///
-    /// # Errors
-    ///
-    /// Returns an error if:
-    /// - The CID doesn't start with "Qm"
-    /// - The Base58 decoding fails
-    /// - The decoded hash is not 34 bytes (2-byte prefix + 32-byte hash)
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use codio_content::ContentId;
-    ///
-    /// let original = ContentId::new(b"test");
-    /// let cid_string = original.to_string();
-    /// let restored = ContentId::from_string(&cid_string).unwrap();
-    /// assert_eq!(original, restored);
-    /// ```
-    pub fn from_string(cid: &str) -> Result<Self> {
-        if !cid.starts_with("Qm") {
-            return Err(anyhow!("Invalid CID: must start with 'Qm'"));
-        }
-
-        let decoded = bs58::decode(cid)
-            .into_vec()
-            .context("Failed to decode base58 CID")?;
-
-        if decoded.len() != 34 {
-            return Err(anyhow!(
-                "Invalid CID length: expected 34 bytes, got {}",
-                decoded.len()
-            ));
-        }
-
-        // Verify prefix
-        if &decoded[0..2] != IPFS_CID_PREFIX {
-            return Err(anyhow!("Invalid CID prefix"));
-        }
+pub use codio_content_id::ContentId;
 
-        let mut hash = [0u8; 32];
-        hash.copy_from_slice(&decoded[2..34]);
-
-        Ok(ContentId {
-            hash,
-            multibase: Some(cid.to_string()),
-        })
-    }
+/// Hash functions a [`ContentId`] can be built on.
+///
+/// Re-exported from [`codio_content_id`]. Pick one with
+/// [`Chunker::with_hash_algorithm`]; SHA-256 is the default.
+pub use codio_content_id::HashAlgorithm;
 
-    /// Converts the ContentId to a CID string (e.g., "QmXXX...").
-    ///
-    /// This is cached after the first call for performance.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use codio_content::ContentId;
-    ///
-    /// let cid = ContentId::new(b"test");
-    /// println!("CID: {}", cid);
-    /// ```
-    fn encode_cid(&self) -> String {
-        if let Some(ref cached) = self.multibase {
-            return cached.clone();
-        }
+pub use compression::Compression;
 
-        // Build the CID bytes: prefix + hash
-        let mut cid_bytes = Vec::with_capacity(34);
-        cid_bytes.extend_from_slice(IPFS_CID_PREFIX);
-        cid_bytes.extend_from_slice(&self.hash);
+pub use proof::{verify_chunk_proof, ChunkProof, ProofStep};
 
-        // Encode to base58
-        bs58::encode(cid_bytes).into_string()
-    }
+pub use car::{Car, CarVersion};
 
-    /// Verifies that the given content matches this CID.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use codio_content::ContentId;
-    ///
-    /// let content = b"original";
-    /// let cid = ContentId::new(content);
-    ///
-    /// assert!(cid.verify(content));
-    /// assert!(!cid.verify(b"tampered"));
-    /// ```
-    pub fn verify(&self, content: &[u8]) -> bool {
-        let computed = ContentId::new(content);
-        computed.hash == self.hash
-    }
+pub use diff::DagDiff;
 
-    /// Returns the raw SHA-256 hash bytes.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use codio_content::ContentId;
-    ///
-    /// let cid = ContentId::new(b"test");
-    /// let hash = cid.hash();
-    /// assert_eq!(hash.len(), 32);
-    /// ```
-    pub fn hash(&self) -> &[u8; 32] {
-        &self.hash
-    }
+pub use encryption::{
+    Capability, ContentKey, DecryptReader, EncryptReader, ENCRYPTED_SEGMENT_SIZE,
+    SEALED_SEGMENT_SIZE,
+};
+pub use erasure::ErasureCoding;
 
-    /// Returns the hash as a hexadecimal string.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use codio_content::ContentId;
-    ///
-    /// let cid = ContentId::new(b"test");
-    /// let hex = cid.hash_hex();
-    /// assert_eq!(hex.len(), 64); // 32 bytes = 64 hex chars
-    /// ```
-    pub fn hash_hex(&self) -> String {
-        hex::encode(self.hash)
-    }
+pub use dagpb::{PbLink, PbNode};
+pub use unixfs::{
+    is_dag_node, split_unixfs_path, Block, UnixFsBuilder, UnixFsDag, UnixFsData, UnixFsDirectory,
+    UnixFsType,
+};
 
-    /// Creates a ContentId from a raw SHA-256 hash.
-    ///
-    /// # Example
-    ///
-    /// ```rust
-    /// use codio_content::ContentId;
-    ///
-    /// let hash = [0u8; 32];
-    /// let cid = ContentId::from_hash(hash);
-    /// ```
-    pub fn from_hash(hash: [u8; 32]) -> Self {
-        ContentId {
-            hash,
-            multibase: None,
-        }
-    }
-}
+/// CID version and codec, for [`UnixFsBuilder::with_cid_version`] and
+/// linking raw blocks.
+///
+/// Re-exported from [`codio_content_id`].
+pub use codio_content_id::{Codec, Version};
 
-impl fmt::Display for ContentId {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.encode_cid())
-    }
-}
+use codio_content_id::ContentIdHasher;
 
 // ============================================================================
 // Chunk - A piece of content with its CID
 // ============================================================================
 
-/// A chunk represents a fixed-size piece of content with its own CID.
+/// A chunk represents a piece of content with its own CID.
 ///
 /// Chunks enable:
 /// - Efficient parallel downloads
@@ -310,14 +194,19 @@ impl fmt::Display for ContentId {
 /// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Chunk {
-    /// Zero-based index of this chunk in the original content
+    /// Zero-based index of this chunk in the original content (of the DAG
+    /// leaf, for erasure-coded chunks)
     pub index: u32,
 
-    /// The actual data in this chunk
+    /// The data in this chunk, compressed as `compression` says
     pub data: Vec<u8>,
 
-    /// Content identifier for this chunk
+    /// Content identifier for this chunk, over `data` as stored
     pub cid: ContentId,
+
+    /// How `data` is compressed
+    #[serde(default)]
+    pub compression: Compression,
 }
 
 impl Chunk {
@@ -335,8 +224,66 @@ impl Chunk {
     /// assert!(chunk.verify());
     /// ```
     pub fn new(index: u32, data: Vec<u8>) -> Self {
-        let cid = ContentId::new(&data);
-        Chunk { index, data, cid }
+        Self::new_with(index, data, HashAlgorithm::default())
+    }
+
+    /// Creates a new chunk whose CID uses the given hash function.
+    ///
+    /// SHA-256 gives the same chunk as [`Chunk::new`]; other hash functions
+    /// give a raw CIDv1.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{Chunk, HashAlgorithm};
+    ///
+    /// let chunk = Chunk::new_with(0, vec![1, 2, 3], HashAlgorithm::Blake3);
+    /// assert_eq!(chunk.cid.algorithm(), HashAlgorithm::Blake3);
+    /// assert!(chunk.verify());
+    /// ```
+    pub fn new_with(index: u32, data: Vec<u8>, algorithm: HashAlgorithm) -> Self {
+        let cid = ContentId::new_with(algorithm, &data);
+        Chunk {
+            index,
+            data,
+            cid,
+            compression: Compression::None,
+        }
+    }
+
+    /// Creates a new chunk from uncompressed `data`, compressing it with
+    /// `compression`.
+    ///
+    /// The CID is computed over the compressed bytes. Data that does not
+    /// shrink is stored uncompressed.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{Chunk, Compression, HashAlgorithm};
+    ///
+    /// let text = b"compressible ".repeat(100);
+    /// let chunk = Chunk::compressed(0, &text, HashAlgorithm::Sha2_256, Compression::Zstd);
+    ///
+    /// assert_eq!(chunk.compression, Compression::Zstd);
+    /// assert!(chunk.size() < text.len());
+    /// assert!(chunk.verify());
+    /// assert_eq!(chunk.decompress().unwrap(), &text[..]);
+    /// ```
+    pub fn compressed(
+        index: u32,
+        data: &[u8],
+        algorithm: HashAlgorithm,
+        compression: Compression,
+    ) -> Self {
+        let packed = compression.compress(data);
+        if compression == Compression::None || packed.len() >= data.len() {
+            return Self::new_with(index, data.to_vec(), algorithm);
+        }
+
+        let mut chunk = Self::new_with(index, packed, algorithm);
+        chunk.compression = compression;
+        chunk
     }
 
     /// Verifies that the chunk's data matches its CID.
@@ -357,18 +304,47 @@ impl Chunk {
         self.cid.verify(&self.data)
     }
 
-    /// Returns the size of this chunk in bytes.
+    /// Returns the size of this chunk in bytes, as stored.
     pub fn size(&self) -> usize {
         self.data.len()
     }
 
+    /// Returns the uncompressed data.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data is not valid for its codec.
+    pub fn decompress(&self) -> Result<Cow<'_, [u8]>> {
+        self.compression
+            .decompress(&self.data)
+            .map_err(|e| anyhow!("Chunk {}: {}", self.index, e))
+    }
+
+    /// Returns the size of this chunk's uncompressed data, from the
+    /// compressed data's header.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data has no valid header for its codec.
+    pub fn content_size(&self) -> Result<usize> {
+        self.compression
+            .decompressed_len(&self.data)
+            .map_err(|e| anyhow!("Chunk {}: {}", self.index, e))
+    }
+
     /// Creates a chunk from existing data and CID (for deserialization).
     ///
     /// # Safety
     ///
     /// This does not verify that the CID matches the data. Use `verify()` to check.
+    /// The chunk is uncompressed; set `compression` for compressed data.
     pub fn from_parts(index: u32, data: Vec<u8>, cid: ContentId) -> Self {
-        Chunk { index, data, cid }
+        Chunk {
+            index,
+            data,
+            cid,
+            compression: Compression::None,
+        }
     }
 }
 
@@ -376,7 +352,10 @@ impl Chunk {
 // Chunker - Splits content into chunks
 // ============================================================================
 
-/// A chunker splits content into fixed-size chunks.
+/// A chunker splits content into fixed-size or content-defined chunks.
+///
+/// See [`ChunkingStrategy`] for the difference. Either way, chunks feed
+/// [`MerkleDAG`] and [`Content`] the same way.
 ///
 /// # Example
 ///
@@ -394,8 +373,112 @@ impl Chunk {
 /// ```
 #[derive(Debug, Clone)]
 pub struct Chunker {
-    /// Size of each chunk in bytes
+    /// Size of each chunk in bytes (the average for content-defined chunking)
     chunk_size: usize,
+
+    /// Where chunk boundaries go
+    strategy: ChunkingStrategy,
+
+    /// Hash function for chunk CIDs
+    hash_algorithm: HashAlgorithm,
+
+    /// Codec for chunk data
+    compression: Compression,
+
+    /// Worker threads for hashing and compressing chunks (1 = sequential)
+    parallelism: usize,
+}
+
+/// How a [`Chunker`] picks chunk boundaries.
+///
+/// Fixed-size chunking cuts every `chunk_size` bytes, so inserting a single
+/// byte near the start of a file shifts, and changes the CID of, every chunk
+/// after it. Content-defined chunking (FastCDC) cuts where the content itself
+/// matches a pattern, so only the chunks around an edit change and successive
+/// versions of a file deduplicate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ChunkingStrategy {
+    /// Cut every `chunk_size` bytes
+    #[default]
+    FixedSize,
+
+    /// FastCDC with the given bounds, in bytes
+    ContentDefined {
+        /// No chunk is shorter, except the last
+        min_size: usize,
+        /// Target average chunk size
+        avg_size: usize,
+        /// No chunk is longer
+        max_size: usize,
+    },
+}
+
+impl ChunkingStrategy {
+    /// Content-defined chunking around `avg_size`, with the usual FastCDC
+    /// bounds of a quarter and four times the average, clamped to
+    /// [`MIN_CHUNK_SIZE`] and [`MAX_CHUNK_SIZE`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::ChunkingStrategy;
+    ///
+    /// let strategy = ChunkingStrategy::content_defined(64 * 1024);
+    /// assert_eq!(
+    ///     strategy,
+    ///     ChunkingStrategy::ContentDefined {
+    ///         min_size: 16 * 1024,
+    ///         avg_size: 64 * 1024,
+    ///         max_size: 256 * 1024,
+    ///     }
+    /// );
+    /// ```
+    pub fn content_defined(avg_size: usize) -> Self {
+        ChunkingStrategy::ContentDefined {
+            min_size: (avg_size / 4).clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE),
+            avg_size,
+            max_size: avg_size
+                .saturating_mul(4)
+                .clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE),
+        }
+    }
+
+    /// Checks the content-defined bounds.
+    ///
+    /// They must satisfy `MIN_CHUNK_SIZE <= min_size <= avg_size <= max_size
+    /// <= MAX_CHUNK_SIZE` with `min_size < max_size`. Fixed-size chunking is
+    /// always valid here; its size is checked by its owner.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        let ChunkingStrategy::ContentDefined {
+            min_size,
+            avg_size,
+            max_size,
+        } = *self
+        else {
+            return Ok(());
+        };
+
+        if min_size < MIN_CHUNK_SIZE {
+            return Err(format!(
+                "Minimum chunk size {} is below {}",
+                min_size, MIN_CHUNK_SIZE
+            ));
+        }
+        if max_size > MAX_CHUNK_SIZE {
+            return Err(format!(
+                "Maximum chunk size {} exceeds {}",
+                max_size, MAX_CHUNK_SIZE
+            ));
+        }
+        if !(min_size <= avg_size && avg_size <= max_size && min_size < max_size) {
+            return Err(format!(
+                "Chunk sizes must satisfy min <= avg <= max and min < max (got {}/{}/{})",
+                min_size, avg_size, max_size
+            ));
+        }
+
+        Ok(())
+    }
 }
 
 impl Chunker {
@@ -424,9 +507,151 @@ impl Chunker {
             MAX_CHUNK_SIZE
         );
 
-        Chunker { chunk_size }
+        Chunker {
+            chunk_size,
+            strategy: ChunkingStrategy::FixedSize,
+            hash_algorithm: HashAlgorithm::default(),
+            compression: Compression::None,
+            parallelism: 1,
+        }
+    }
+
+    /// Creates a content-defined (FastCDC) chunker.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sizes are out of order or outside
+    /// `MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE` (see [`ChunkingStrategy::validate`]).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::Chunker;
+    ///
+    /// let chunker = Chunker::content_defined(16 * 1024, 64 * 1024, 256 * 1024);
+    /// let data: Vec<u8> = (0..1_000_000u64).map(|i| (i * 7919 % 251) as u8).collect();
+    ///
+    /// let chunks = chunker.chunk(&data);
+    /// assert!(chunks.iter().all(|c| c.size() <= 256 * 1024));
+    /// assert_eq!(chunker.reconstruct(chunks).unwrap(), data);
+    /// ```
+    pub fn content_defined(min_size: usize, avg_size: usize, max_size: usize) -> Self {
+        let strategy = ChunkingStrategy::ContentDefined {
+            min_size,
+            avg_size,
+            max_size,
+        };
+        if let Err(e) = strategy.validate() {
+            panic!("{}", e);
+        }
+
+        Chunker {
+            chunk_size: avg_size,
+            strategy,
+            hash_algorithm: HashAlgorithm::default(),
+            compression: Compression::None,
+            parallelism: 1,
+        }
+    }
+
+    /// Creates a chunker for the chunk size and strategy in `config`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the configured sizes are invalid.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{config::ContentConfig, Chunker, ChunkingStrategy};
+    ///
+    /// let config = ContentConfig::with_content_defined_chunking(64 * 1024);
+    /// let chunker = Chunker::from_config(&config);
+    /// assert!(matches!(chunker.strategy(), ChunkingStrategy::ContentDefined { .. }));
+    /// ```
+    pub fn from_config(config: &ContentConfig) -> Self {
+        let chunker = match config.chunking {
+            ChunkingStrategy::FixedSize => Chunker::new(config.chunk_size),
+            ChunkingStrategy::ContentDefined {
+                min_size,
+                avg_size,
+                max_size,
+            } => Chunker::content_defined(min_size, avg_size, max_size),
+        };
+
+        let chunker = chunker.with_compression(config.compression());
+        if config.enable_parallel {
+            chunker.with_parallelism(config.max_parallel_chunks)
+        } else {
+            chunker
+        }
+    }
+
+    /// Sets the hash function used for chunk CIDs (default SHA-256).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{Chunker, HashAlgorithm};
+    ///
+    /// let chunker = Chunker::new(1024).with_hash_algorithm(HashAlgorithm::Blake3);
+    /// let chunks = chunker.chunk(&[7u8; 2048]);
+    /// assert!(chunks.iter().all(|c| c.cid.algorithm() == HashAlgorithm::Blake3));
+    /// ```
+    pub fn with_hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = algorithm;
+        self
+    }
+
+    /// Sets the codec chunks are compressed with (default none).
+    ///
+    /// Chunk CIDs, and so the DAG root, are computed over the compressed
+    /// bytes; see [`Chunk::compressed`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{Chunker, Compression};
+    ///
+    /// let text = b"lorem ipsum dolor sit amet ".repeat(1000);
+    /// let chunker = Chunker::new(4096).with_compression(Compression::Lz4);
+    ///
+    /// let chunks = chunker.chunk(&text);
+    /// assert!(chunks.iter().all(|c| c.compression == Compression::Lz4));
+    /// assert_eq!(chunker.reconstruct(chunks).unwrap(), text);
+    /// ```
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Hashes (and compresses) chunks on up to `workers` threads.
+    ///
+    /// Output is identical to the sequential path; only boundary detection
+    /// stays on the calling thread. `0` and `1` mean sequential. Applies to
+    /// [`chunk`](Self::chunk) and [`reconstruct`](Self::reconstruct);
+    /// [`ChunkStream`] holds one chunk at a time and stays sequential.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::Chunker;
+    ///
+    /// let data = vec![7u8; 8 * 1024 * 1024];
+    /// let sequential = Chunker::new(1024 * 1024).chunk(&data);
+    /// let parallel = Chunker::new(1024 * 1024).with_parallelism(4).chunk(&data);
+    ///
+    /// assert!(sequential.iter().zip(&parallel).all(|(a, b)| a.cid == b.cid));
+    /// ```
+    pub fn with_parallelism(mut self, workers: usize) -> Self {
+        self.parallelism = workers.max(1);
+        self
     }
 
+    /// Returns the number of worker threads used for chunk hashing.
+    pub fn parallelism(&self) -> usize {
+        self.parallelism
+    }
 
     /// Splits data into chunks.
     ///
@@ -446,20 +671,27 @@ impl Chunker {
             return vec![];
         }
 
-        let mut chunks = Vec::new();
+        // Boundaries depend on what came before, so find them first...
+        let ranges: Vec<_> = self.ranges(data).into_iter().enumerate().collect();
+
+        // ...then hash each chunk independently
+        parallel::map(&ranges, self.parallelism, |(index, range)| {
+            self.make_chunk(*index as u32, &data[range.clone()])
+        })
+    }
+
+    /// Byte ranges of the chunks of `data`, in order.
+    pub(crate) fn ranges(&self, data: &[u8]) -> Vec<std::ops::Range<usize>> {
+        let mut ranges = Vec::new();
         let mut offset = 0;
-        let mut index = 0;
 
         while offset < data.len() {
-            let end = std::cmp::min(offset + self.chunk_size, data.len());
-            let chunk_data = data[offset..end].to_vec();
-            chunks.push(Chunk::new(index, chunk_data));
-
+            let end = offset + self.cut_point(&data[offset..]);
+            ranges.push(offset..end);
             offset = end;
-            index += 1;
         }
 
-        chunks
+        ranges
     }
 
     /// Reconstructs the original data from chunks.
@@ -470,6 +702,7 @@ impl Chunker {
     /// - Chunks are not in sequential order
     /// - Any chunk fails verification
     /// - Chunk indices are not contiguous
+    /// - A compressed chunk fails to decompress
     ///
     /// # Example
     ///
@@ -489,10 +722,9 @@ impl Chunker {
         }
 
         // Verify all chunks first
-        for chunk in &chunks {
-            if !chunk.verify() {
-                return Err(anyhow!("Chunk {} failed verification", chunk.index));
-            }
+        let verified = parallel::map(&chunks, self.parallelism, Chunk::verify);
+        if let Some(chunk) = chunks.iter().zip(verified).find(|(_, ok)| !ok) {
+            return Err(anyhow!("Chunk {} failed verification", chunk.0.index));
         }
 
         // Sort chunks by index
@@ -510,24 +742,68 @@ impl Chunker {
             }
         }
 
-        // Reconstruct data
+        // Reconstruct data, decompressing as each chunk says
         let total_size: usize = sorted_chunks.iter().map(|c| c.data.len()).sum();
         let mut data = Vec::with_capacity(total_size);
 
         for chunk in sorted_chunks {
-            data.extend_from_slice(&chunk.data);
+            data.extend_from_slice(&chunk.decompress()?);
         }
 
         Ok(data)
     }
 
-    /// Returns the chunk size in bytes.
+    /// Returns the chunk size in bytes (the average for content-defined chunking).
     pub fn chunk_size(&self) -> usize {
         self.chunk_size
     }
 
+    /// Returns the chunking strategy.
+    pub fn strategy(&self) -> ChunkingStrategy {
+        self.strategy
+    }
+
+    /// Builds chunk `index` from uncompressed `data`.
+    fn make_chunk(&self, index: u32, data: &[u8]) -> Chunk {
+        Chunk::compressed(index, data, self.hash_algorithm, self.compression)
+    }
+
+    /// Returns the codec chunks are compressed with.
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Length of the first chunk of `data`; depends only on the first
+    /// [`max_chunk_size`](Self::max_chunk_size) bytes.
+    fn cut_point(&self, data: &[u8]) -> usize {
+        match self.strategy {
+            ChunkingStrategy::FixedSize => data.len().min(self.chunk_size),
+            ChunkingStrategy::ContentDefined {
+                min_size,
+                avg_size,
+                max_size,
+            } => cdc::cut_point(data, min_size, avg_size, max_size),
+        }
+    }
+
+    /// Longest chunk this chunker produces.
+    fn max_chunk_size(&self) -> usize {
+        match self.strategy {
+            ChunkingStrategy::FixedSize => self.chunk_size,
+            ChunkingStrategy::ContentDefined { max_size, .. } => max_size,
+        }
+    }
+
+    /// Returns the hash function used for chunk CIDs.
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
     /// Calculates the number of chunks needed for data of the given size.
     ///
+    /// Exact for fixed-size chunking; an estimate from the average size for
+    /// content-defined chunking.
+    ///
     /// # Example
     ///
     /// ```rust
@@ -544,6 +820,45 @@ impl Chunker {
         }
         data_size.div_ceil(self.chunk_size)
     }
+
+    /// Splits content read from `reader` into chunks, one at a time.
+    ///
+    /// At most one maximum-size chunk is buffered at once, plus the Merkle
+    /// DAG nodes not yet complete (a few per tree level, so memory doesn't
+    /// grow with the content). The stream yields the same
+    /// chunks as [`Chunker::chunk`] on the whole content and, once exhausted,
+    /// [`ChunkStream::finish`] gives the content CID and Merkle DAG root.
+    /// Works with both `std::io::Read` and `tokio::io::AsyncRead` readers.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{Chunker, ContentId, MerkleDAG};
+    ///
+    /// let data = vec![0u8; 2500];
+    /// let chunker = Chunker::new(1024);
+    ///
+    /// let mut stream = chunker.chunk_reader(&data[..]);
+    /// let chunks: Vec<_> = stream.by_ref().collect::<Result<_, _>>().unwrap();
+    /// let streamed = stream.finish();
+    ///
+    /// assert_eq!(chunks.len(), 3);
+    /// assert_eq!(streamed.cid, ContentId::new(&data));
+    /// assert_eq!(&streamed.root_cid, MerkleDAG::from_chunks(&chunks).root_cid());
+    /// ```
+    pub fn chunk_reader<R>(&self, reader: R) -> ChunkStream<R> {
+        ChunkStream {
+            reader,
+            chunker: self.clone(),
+            pending: Vec::new(),
+            eof: false,
+            next_index: 0,
+            size: 0,
+            content: ContentIdHasher::with_algorithm(self.hash_algorithm),
+            tree: dag::TreeStream::new(DagLayout::Balanced, DEFAULT_MAX_LINKS),
+            done: false,
+        }
+    }
 }
 
 impl Default for Chunker {
@@ -552,16 +867,437 @@ impl Default for Chunker {
     }
 }
 
+// ============================================================================
+// ChunkStream - Streaming chunker
+// ============================================================================
+
+/// Chunks content from a reader with bounded memory.
+///
+/// Created by [`Chunker::chunk_reader`]. Use it as an iterator (or call
+/// [`ChunkStream::next_chunk`]) for `std::io::Read` sources, and
+/// [`ChunkStream::next_chunk_async`] for `tokio::io::AsyncRead` ones.
+pub struct ChunkStream<R> {
+    reader: R,
+    chunker: Chunker,
+    /// Bytes read but not yet emitted; never more than one maximum-size chunk
+    pending: Vec<u8>,
+    /// Whether the reader is exhausted
+    eof: bool,
+    next_index: u32,
+    size: u64,
+    /// CID of the whole content so far
+    content: ContentIdHasher,
+    /// Merkle DAG nodes still taking links
+    tree: dag::TreeStream,
+    done: bool,
+}
+
+/// Summary of a fully streamed piece of content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamedContent {
+    /// CID of the whole content, as [`ContentId::new`] would compute it
+    pub cid: ContentId,
+
+    /// Merkle DAG root, as [`MerkleDAG::from_chunks`] (or the
+    /// [`DagBuilder`] given to [`ChunkStream::with_dag_builder`]) would
+    /// compute it
+    pub root_cid: ContentId,
+
+    /// Total size in bytes
+    pub size: u64,
+
+    /// Number of chunks emitted
+    pub num_chunks: u32,
+
+    /// Blocks of the DAG nodes not yet taken with
+    /// [`ChunkStream::take_node_blocks`], the root last; empty unless the
+    /// stream was made [`ChunkStream::with_node_blocks`]
+    pub node_blocks: Vec<Block>,
+}
+
+impl<R> ChunkStream<R> {
+    /// Builds the Merkle DAG root with `builder`'s layout and fan-out rather
+    /// than [`MerkleDAG::from_chunks`]'s.
+    ///
+    /// # Panics
+    ///
+    /// Panics if chunks have already been read.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{Chunker, DagBuilder, DagLayout};
+    ///
+    /// let data = vec![7u8; 50 * 1024];
+    /// let chunker = Chunker::new(1024);
+    /// let builder = DagBuilder::new().with_layout(DagLayout::Trickle).with_max_links(3);
+    ///
+    /// let mut stream = chunker.chunk_reader(&data[..]).with_dag_builder(&builder);
+    /// let chunks: Vec<_> = stream.by_ref().collect::<Result<_, _>>().unwrap();
+    /// assert_eq!(&stream.finish().root_cid, builder.build(&chunks).root_cid());
+    /// ```
+    pub fn with_dag_builder(mut self, builder: &DagBuilder) -> Self {
+        assert_eq!(
+            self.next_index, 0,
+            "Set the DAG layout before reading chunks"
+        );
+        let keep_nodes = self.tree.keeps_closed();
+        self.tree = dag::TreeStream::new(builder.layout(), builder.max_links());
+        if keep_nodes {
+            self.tree.keep_closed();
+        }
+        self
+    }
+
+    /// Keeps the block of each Merkle DAG node as it's completed, so the
+    /// whole DAG can be stored without holding every chunk CID.
+    ///
+    /// Take them with [`ChunkStream::take_node_blocks`] as chunks are read;
+    /// [`ChunkStream::finish`] returns the rest, the root among them. A
+    /// node's block is its links' binary CIDs concatenated (see
+    /// [`MerkleDAG::decode_node`]).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{Chunker, DagBuilder, MerkleDAG};
+    ///
+    /// let data = vec![3u8; 50 * 1024];
+    /// let builder = DagBuilder::new().with_max_links(4);
+    /// let mut stream = Chunker::new(1024)
+    ///     .chunk_reader(&data[..])
+    ///     .with_dag_builder(&builder)
+    ///     .with_node_blocks();
+    ///
+    /// let mut nodes = Vec::new();
+    /// while let Some(_chunk) = stream.next_chunk().unwrap() {
+    ///     nodes.extend(stream.take_node_blocks());
+    /// }
+    /// let streamed = stream.finish();
+    /// nodes.extend(streamed.node_blocks);
+    ///
+    /// let root = nodes.last().unwrap();
+    /// assert_eq!(root.cid, streamed.root_cid);
+    /// assert_eq!(MerkleDAG::decode_node(&root.data).unwrap().len(), 4);
+    /// ```
+    pub fn with_node_blocks(mut self) -> Self {
+        self.tree.keep_closed();
+        self
+    }
+
+    /// Blocks of the DAG nodes completed since the last call, children
+    /// before parents.
+    ///
+    /// Empty unless the stream was made [`ChunkStream::with_node_blocks`].
+    pub fn take_node_blocks(&mut self) -> Vec<Block> {
+        self.tree.take_closed()
+    }
+
+    /// Bytes to read before the next cut: enough to fill one maximum-size chunk.
+    fn wanted(&self) -> u64 {
+        if self.eof {
+            0
+        } else {
+            (self.chunker.max_chunk_size() - self.pending.len()) as u64
+        }
+    }
+
+    /// Records `read` bytes appended to the buffer; a short read means end of input.
+    fn filled(&mut self, wanted: u64, read: usize) {
+        if (read as u64) < wanted {
+            self.eof = true;
+        }
+    }
+
+    /// Cuts the next chunk off the buffer; an empty buffer means end of input.
+    fn emit(&mut self) -> Option<Chunk> {
+        if self.pending.is_empty() {
+            self.done = true;
+            return None;
+        }
+
+        let len = self.chunker.cut_point(&self.pending);
+        let data: Vec<u8> = self.pending.drain(..len).collect();
+
+        self.content.update(&data);
+        self.size += data.len() as u64;
+
+        let chunk = self.chunker.make_chunk(self.next_index, &data);
+        self.tree.push(chunk.cid.clone());
+        self.next_index += 1;
+
+        Some(chunk)
+    }
+
+    /// Returns the content CID and Merkle DAG root.
+    ///
+    /// Call once the stream is exhausted; otherwise the summary covers only
+    /// the content read so far.
+    pub fn finish(mut self) -> StreamedContent {
+        // Matches MerkleDAG's root for no chunks
+        let root_cid = self.tree.finish().unwrap_or_else(|| ContentId::new(&[]));
+
+        StreamedContent {
+            cid: self.content.finalize(),
+            root_cid,
+            size: self.size,
+            num_chunks: self.next_index,
+            node_blocks: self.tree.take_closed(),
+        }
+    }
+}
+
+impl<R: Read> ChunkStream<R> {
+    /// Reads the next chunk, or `None` at end of input.
+    pub fn next_chunk(&mut self) -> Result<Option<Chunk>> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let wanted = self.wanted();
+        let read = (&mut self.reader)
+            .take(wanted)
+            .read_to_end(&mut self.pending)?;
+        self.filled(wanted, read);
+
+        Ok(self.emit())
+    }
+}
+
+impl<R: Read> Iterator for ChunkStream<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_chunk().transpose()
+    }
+}
+
+impl<R: AsyncRead + Unpin> ChunkStream<R> {
+    /// Reads the next chunk from an async reader, or `None` at end of input.
+    pub async fn next_chunk_async(&mut self) -> Result<Option<Chunk>> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let wanted = self.wanted();
+        let read = (&mut self.reader)
+            .take(wanted)
+            .read_to_end(&mut self.pending)
+            .await?;
+        self.filled(wanted, read);
+
+        Ok(self.emit())
+    }
+}
+
 // ============================================================================
 // MerkleDAG - Merkle Directed Acyclic Graph
 // ============================================================================
 
+/// How a [`MerkleDAG`] arranges chunks into a tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DagLayout {
+    /// All chunks at the same depth, every node full except the rightmost
+    /// on each level. Good for random access.
+    #[default]
+    Balanced,
+
+    /// UnixFS-style trickle DAG: the start of the content sits near the
+    /// root, which suits sequential reads such as streaming media.
+    Trickle,
+}
+
+/// Builds [`MerkleDAG`]s with a given layout and fan-out.
+///
+/// # Example
+///
+/// ```rust
+/// use codio_content::{Chunker, DagBuilder, DagLayout};
+///
+/// let data = vec![0u8; 100 * 1024];
+/// let chunks = Chunker::new(1024).chunk(&data);
+///
+/// let dag = DagBuilder::new().with_max_links(10).build(&chunks);
+/// assert_eq!(dag.num_children(), 100);
+/// assert_eq!(dag.depth(), 2); // 100 chunks -> 10 nodes -> root
+/// assert!(dag.verify(&chunks));
+///
+/// let trickle = DagBuilder::new()
+///     .with_layout(DagLayout::Trickle)
+///     .with_max_links(10)
+///     .build(&chunks);
+/// assert_ne!(trickle.root_cid(), dag.root_cid());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DagBuilder {
+    layout: DagLayout,
+    max_links: usize,
+    erasure: Option<ErasureCoding>,
+}
+
+impl DagBuilder {
+    /// Creates a builder for balanced DAGs with [`DEFAULT_MAX_LINKS`] links per node.
+    pub fn new() -> Self {
+        DagBuilder {
+            layout: DagLayout::Balanced,
+            max_links: DEFAULT_MAX_LINKS,
+            erasure: None,
+        }
+    }
+
+    /// Creates a builder for the layout, fan-out and erasure coding in
+    /// `config`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `config.max_links` is below 2.
+    pub fn from_config(config: &ContentConfig) -> Result<Self> {
+        if config.max_links < 2 {
+            return Err(anyhow!("max_links {} is below minimum 2", config.max_links));
+        }
+        let builder = DagBuilder::new()
+            .with_layout(config.dag_layout)
+            .with_max_links(config.max_links);
+        Ok(match config.erasure_coding {
+            Some(coding) => builder.with_erasure_coding(coding),
+            None => builder,
+        })
+    }
+
+    /// Sets the tree layout.
+    pub fn with_layout(mut self, layout: DagLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Sets the maximum number of links per node.
+    ///
+    /// For [`DagLayout::Trickle`] this bounds the leaves per node; subtree
+    /// links come on top, as in UnixFS.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_links` is less than 2.
+    pub fn with_max_links(mut self, max_links: usize) -> Self {
+        assert!(max_links >= 2, "A DAG node needs at least 2 links");
+        self.max_links = max_links;
+        self
+    }
+
+    /// Records that the leaves are chunks coded with `coding`, as returned by
+    /// [`ErasureCoding::encode`]; [`Content::build`] codes them itself.
+    ///
+    /// Like the layout, the coding is not covered by the root CID.
+    pub fn with_erasure_coding(mut self, coding: ErasureCoding) -> Self {
+        self.erasure = Some(coding);
+        self
+    }
+
+    /// Returns the tree layout.
+    pub fn layout(&self) -> DagLayout {
+        self.layout
+    }
+
+    /// Returns the erasure coding of the leaves, if any.
+    pub fn erasure_coding(&self) -> Option<ErasureCoding> {
+        self.erasure
+    }
+
+    /// Returns the maximum number of links per node.
+    pub fn max_links(&self) -> usize {
+        self.max_links
+    }
+
+    /// Verifies that `data` is chunk `index` of the content with root `root`,
+    /// for a DAG built with this layout and fan-out.
+    ///
+    /// The layout and fan-out are not covered by the root CID; they must be
+    /// the ones the content was published with.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{Chunker, DagBuilder, DagLayout};
+    ///
+    /// let chunks = Chunker::new(1024).chunk(&vec![5u8; 64 * 1024]);
+    /// let builder = DagBuilder::new().with_layout(DagLayout::Trickle).with_max_links(4);
+    /// let dag = builder.build(&chunks);
+    ///
+    /// let proof = dag.prove(42).unwrap();
+    /// assert!(builder.verify_proof(dag.root_cid(), 42, &chunks[42].data, &proof));
+    /// ```
+    pub fn verify_proof(
+        &self,
+        root: &ContentId,
+        index: u32,
+        data: &[u8],
+        proof: &ChunkProof,
+    ) -> bool {
+        proof::verify(self.layout, self.max_links, root, index, data, proof)
+    }
+
+    /// Builds a DAG over `chunks`, in order.
+    ///
+    /// Without erasure coding, the DAG records the chunks' uncompressed
+    /// sizes for [`MerkleDAG::chunk_range`]; coded leaves include parity
+    /// chunks, so [`Content::build`] records the data chunks' sizes itself.
+    pub fn build(&self, chunks: &[Chunk]) -> MerkleDAG {
+        let dag = self.build_from_cids(chunks.iter().map(|c| c.cid.clone()).collect());
+        match self.erasure {
+            Some(_) => dag,
+            None => dag.with_chunk_sizes(content_sizes(chunks)),
+        }
+    }
+
+    /// Builds a DAG over chunk CIDs, in order.
+    pub fn build_from_cids(&self, children: Vec<ContentId>) -> MerkleDAG {
+        let mut nodes = dag::Nodes::new();
+
+        let root = if children.is_empty() {
+            ContentId::new(&[])
+        } else {
+            let mut node = |links| dag::add_node(&mut nodes, links);
+            match self.layout {
+                DagLayout::Balanced => dag::balanced(&children, self.max_links, &mut node),
+                DagLayout::Trickle => dag::trickle(&children, self.max_links, &mut node),
+            }
+        };
+
+        MerkleDAG {
+            root,
+            children,
+            nodes,
+            erasure: self.erasure,
+            chunk_sizes: Vec::new(),
+        }
+    }
+}
+
+/// Uncompressed sizes of `chunks`, or none if a chunk's header is invalid
+fn content_sizes(chunks: &[Chunk]) -> Vec<u64> {
+    chunks
+        .iter()
+        .map(|chunk| chunk.content_size().map(|size| size as u64))
+        .collect::<Result<_>>()
+        .unwrap_or_default()
+}
+
+impl Default for DagBuilder {
+    fn default() -> Self {
+        DagBuilder::new()
+    }
+}
+
 /// A Merkle DAG represents the relationship between content chunks.
 ///
 /// The DAG structure:
-/// - Root node contains the CID of all child CIDs concatenated
-/// - Child nodes are individual chunk CIDs
-/// - Provides cryptographic proof of content integrity
+/// - Leaves are the chunk CIDs, in content order
+/// - Internal nodes link to at most [`DEFAULT_MAX_LINKS`] children (configurable
+///   through [`DagBuilder`]), so large files get a multi-level tree
+/// - Each internal node's CID is a CIDv1 of its children's binary CIDs
+///   concatenated, with the private-use [`Codec::HashList`] codec
+/// - The root CID proves the integrity of every chunk, and any internal node
+///   proves the integrity of the chunks under it
 ///
 /// # Example
 ///
@@ -580,14 +1316,30 @@ pub struct MerkleDAG {
     /// Root CID representing the entire content
     root: ContentId,
 
-    /// Child CIDs (one per chunk)
+    /// Leaf CIDs (one per chunk), in content order
     children: Vec<ContentId>,
+
+    /// Internal nodes (the root included) and their links
+    nodes: dag::Nodes,
+
+    /// How the leaves are erasure coded, if they are
+    #[serde(default)]
+    erasure: Option<ErasureCoding>,
+
+    /// Uncompressed size of each data chunk, in content order; empty if
+    /// unknown
+    #[serde(default)]
+    chunk_sizes: Vec<u64>,
 }
 
 impl MerkleDAG {
-    /// Creates a Merkle DAG from a set of chunks.
+    /// Creates a balanced Merkle DAG from a set of chunks.
     ///
-    /// The root CID is computed by hashing the concatenated child CIDs.
+    /// Uses [`DEFAULT_MAX_LINKS`] links per node; see [`DagBuilder`] for
+    /// other layouts. With at most that many chunks the root links straight
+    /// to the chunks and its CID is a CIDv1 of the concatenated chunk CIDs,
+    /// with the same hash function the chunks used and the
+    /// [`Codec::HashList`] codec.
     ///
     /// # Example
     ///
@@ -602,31 +1354,83 @@ impl MerkleDAG {
     /// assert_eq!(dag.num_children(), 2);
     /// ```
     pub fn from_chunks(chunks: &[Chunk]) -> Self {
-        let children: Vec<ContentId> = chunks.iter().map(|c| c.cid.clone()).collect();
+        DagBuilder::new().build(chunks)
+    }
 
-        // Compute root by hashing all child CIDs
-        let root = Self::compute_root(&children);
+    /// Rebuilds a DAG from its root and internal nodes, e.g. as received from
+    /// a peer.
+    ///
+    /// Nodes not reachable from the root are dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the root is not among the nodes or any node's CID
+    /// does not match its links.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{Chunker, DagBuilder, MerkleDAG};
+    ///
+    /// let chunks = Chunker::new(1024).chunk(&vec![1u8; 50 * 1024]);
+    /// let dag = DagBuilder::new().with_max_links(4).build(&chunks);
+    ///
+    /// let nodes = dag
+    ///     .node_cids()
+    ///     .map(|cid| (cid.clone(), dag.links(cid).unwrap().to_vec()))
+    ///     .collect();
+    /// let rebuilt = MerkleDAG::from_nodes(dag.root_cid().clone(), nodes).unwrap();
+    /// assert_eq!(rebuilt.children(), dag.children());
+    /// ```
+    pub fn from_nodes(
+        root: ContentId,
+        nodes: std::collections::HashMap<ContentId, Vec<ContentId>>,
+    ) -> Result<Self> {
+        if !nodes.contains_key(&root) {
+            return Err(anyhow!("Root node {} not found", root));
+        }
 
-        MerkleDAG { root, children }
-    }
+        let mut dag = MerkleDAG {
+            root,
+            children: Vec::new(),
+            nodes,
+            erasure: None,
+            chunk_sizes: Vec::new(),
+        };
 
-    /// Computes the root CID from child CIDs.
-    fn compute_root(children: &[ContentId]) -> ContentId {
-        if children.is_empty() {
-            return ContentId::new(&[]);
+        let mut reachable = dag::Nodes::new();
+        let mut children = Vec::new();
+        if !dag.walk(&dag.root, &mut children, &mut reachable) {
+            return Err(anyhow!("DAG node does not match its links"));
         }
 
-        // Concatenate all child hashes
-        let mut combined = Vec::new();
-        for child in children {
-            combined.extend_from_slice(child.hash());
+        dag.children = children;
+        dag.nodes = reachable;
+        Ok(dag)
+    }
+
+    /// Walks the tree under `cid`, checking every internal node's CID against
+    /// its links and collecting leaves in order and the nodes visited.
+    fn walk(&self, cid: &ContentId, leaves: &mut Vec<ContentId>, seen: &mut dag::Nodes) -> bool {
+        let Some(links) = self.nodes.get(cid) else {
+            // A node CID with no node is missing, not a leaf
+            leaves.push(cid.clone());
+            return !dag::is_node_cid(cid);
+        };
+
+        if links.is_empty() || dag::node_cid(links) != *cid {
+            return false;
         }
+        seen.insert(cid.clone(), links.clone());
 
-        ContentId::new(&combined)
+        links.iter().all(|link| self.walk(link, leaves, seen))
     }
 
     /// Verifies that the given chunks match this DAG structure.
     ///
+    /// Walks the whole tree, checking every internal node, then checks each
+    /// chunk against its leaf.
+    ///
     /// # Example
     ///
     /// ```rust
@@ -655,9 +1459,8 @@ impl MerkleDAG {
             }
         }
 
-        // Verify root CID
-        let computed_root = Self::compute_root(&self.children);
-        computed_root == self.root
+        // Verify the tree up to the root
+        self.verify_structure()
     }
 
     /// Returns the root CID.
@@ -678,17 +1481,278 @@ impl MerkleDAG {
         &self.root
     }
 
-    /// Returns the child CIDs.
+    /// Returns the leaf (chunk) CIDs, in content order.
     pub fn children(&self) -> &[ContentId] {
         &self.children
     }
 
-    /// Returns the number of child nodes.
+    /// Returns the number of leaves (chunks).
     pub fn num_children(&self) -> usize {
         self.children.len()
     }
 
-    /// Verifies the DAG structure integrity (root matches children).
+    /// Returns how the leaves are erasure coded, or `None` if they are the
+    /// data chunks themselves.
+    pub fn erasure_coding(&self) -> Option<ErasureCoding> {
+        self.erasure
+    }
+
+    /// Records that the leaves are coded with `coding`, e.g. for a DAG
+    /// rebuilt with [`MerkleDAG::from_nodes`].
+    pub fn with_erasure_coding(mut self, coding: ErasureCoding) -> Self {
+        self.erasure = Some(coding);
+        self
+    }
+
+    /// Returns the uncompressed size of each data chunk, in content order,
+    /// or an empty slice if the DAG doesn't record them.
+    pub fn chunk_sizes(&self) -> &[u64] {
+        &self.chunk_sizes
+    }
+
+    /// Records the uncompressed size of each data chunk, in content order,
+    /// e.g. for a DAG rebuilt with [`MerkleDAG::from_nodes`].
+    ///
+    /// Like the layout, the sizes are not covered by the root CID; a chunk
+    /// read through them is still checked against its leaf.
+    pub fn with_chunk_sizes(mut self, sizes: Vec<u64>) -> Self {
+        self.chunk_sizes = sizes;
+        self
+    }
+
+    /// Returns the indices of the data chunks covering `len` bytes from
+    /// `offset`, in content order.
+    ///
+    /// Ranges running past the end are cut short; an empty range gives no
+    /// chunks.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the DAG doesn't record chunk sizes or `offset` is
+    /// past the end of the content.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{Chunker, MerkleDAG};
+    ///
+    /// let chunks = Chunker::new(1024).chunk(&vec![0u8; 5000]);
+    /// let dag = MerkleDAG::from_chunks(&chunks);
+    ///
+    /// assert_eq!(dag.chunk_range(0, 10).unwrap(), 0..1);
+    /// assert_eq!(dag.chunk_range(1000, 100).unwrap(), 0..2);
+    /// assert_eq!(dag.chunk_range(4096, 10_000).unwrap(), 4..5);
+    /// assert!(dag.chunk_range(5001, 1).is_err());
+    /// ```
+    pub fn chunk_range(&self, offset: u64, len: u64) -> Result<std::ops::Range<u32>> {
+        if self.chunk_sizes.is_empty() && !self.children.is_empty() {
+            return Err(anyhow!("DAG {} does not record chunk sizes", self.root));
+        }
+
+        let starts: Vec<u64> = self
+            .chunk_sizes
+            .iter()
+            .scan(0, |offset, size| {
+                let start = *offset;
+                *offset += size;
+                Some(start)
+            })
+            .collect();
+        let size: u64 = self.chunk_sizes.iter().sum();
+        if offset > size {
+            return Err(anyhow!(
+                "Offset {} is past the end of {} bytes",
+                offset,
+                size
+            ));
+        }
+
+        let end = offset.saturating_add(len).min(size);
+        if offset == end {
+            return Ok(0..0);
+        }
+
+        let first = starts.partition_point(|&start| start <= offset) - 1;
+        let last = starts.partition_point(|&start| start < end);
+        Ok(first as u32..last as u32)
+    }
+
+    /// Compares this DAG's leaves, the old version, with `new`'s; see
+    /// [`DagDiff`].
+    ///
+    /// Byte counts are the uncompressed sizes the DAGs record; leaves with
+    /// no recorded size, such as parity chunks, count as 0 bytes.
+    pub fn diff(&self, new: &MerkleDAG) -> DagDiff {
+        DagDiff::between(self.leaf_sizes(), new.leaf_sizes())
+    }
+
+    /// Leaves with their recorded sizes, 0 where unknown
+    fn leaf_sizes(&self) -> Vec<(ContentId, u64)> {
+        let mut sizes = vec![0; self.children.len()];
+        for (i, &size) in self.chunk_sizes.iter().enumerate() {
+            let leaf = match self.erasure {
+                Some(coding) => coding.leaf_index(i),
+                None => i,
+            };
+            if let Some(slot) = sizes.get_mut(leaf) {
+                *slot = size;
+            }
+        }
+
+        self.children.iter().cloned().zip(sizes).collect()
+    }
+
+    /// Reads an internal node's links back from its block, as made by
+    /// [`ChunkStream::with_node_blocks`].
+    ///
+    /// The block isn't checked against a CID; compare the CID of its links
+    /// with [`MerkleDAG::from_nodes`] or hash the block itself.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the block isn't a non-empty run of binary CIDs.
+    pub fn decode_node(data: &[u8]) -> Result<Vec<ContentId>> {
+        dag::decode_node(data)
+    }
+
+    /// Returns the links of internal node `cid`, or `None` if it is not an
+    /// internal node of this DAG.
+    pub fn links(&self, cid: &ContentId) -> Option<&[ContentId]> {
+        self.nodes.get(cid).map(Vec::as_slice)
+    }
+
+    /// Returns the CIDs of all internal nodes, the root included, in no
+    /// particular order.
+    pub fn node_cids(&self) -> impl Iterator<Item = &ContentId> {
+        self.nodes.keys()
+    }
+
+    /// Returns the number of internal nodes, the root included.
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns the number of levels of internal nodes above the deepest leaf
+    /// (0 for empty content).
+    pub fn depth(&self) -> usize {
+        fn depth(nodes: &dag::Nodes, cid: &ContentId) -> usize {
+            match nodes.get(cid) {
+                Some(links) => 1 + links.iter().map(|l| depth(nodes, l)).max().unwrap_or(0),
+                None => 0,
+            }
+        }
+
+        depth(&self.nodes, &self.root)
+    }
+
+    /// Creates an inclusion proof for chunk `index`: the path of nodes from
+    /// the chunk up to the root.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no chunk `index`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{verify_chunk_proof, Chunker, MerkleDAG};
+    ///
+    /// let chunks = Chunker::new(1024).chunk(&vec![9u8; 10 * 1024]);
+    /// let dag = MerkleDAG::from_chunks(&chunks);
+    ///
+    /// let proof = dag.prove(3).unwrap();
+    /// assert!(verify_chunk_proof(dag.root_cid(), 3, &chunks[3].data, &proof));
+    /// ```
+    pub fn prove(&self, index: u32) -> Result<ChunkProof> {
+        if index as usize >= self.children.len() {
+            return Err(anyhow!("Chunk {} not found", index));
+        }
+
+        let mut counts = std::collections::HashMap::new();
+        let mut rest = index as usize;
+        let mut cid = &self.root;
+        let mut steps = Vec::new();
+
+        while let Some(links) = self.nodes.get(cid) {
+            let mut position = 0;
+            loop {
+                let count = self.leaf_count(&links[position], &mut counts);
+                if rest < count {
+                    break;
+                }
+                rest -= count;
+                position += 1;
+            }
+
+            steps.push(ProofStep {
+                links: links.clone(),
+                position,
+            });
+            cid = &links[position];
+        }
+
+        steps.reverse();
+        Ok(ChunkProof::new(steps))
+    }
+
+    /// Number of leaves under `cid`, memoized in `counts`.
+    fn leaf_count(
+        &self,
+        cid: &ContentId,
+        counts: &mut std::collections::HashMap<ContentId, usize>,
+    ) -> usize {
+        let Some(links) = self.nodes.get(cid) else {
+            return 1;
+        };
+        if let Some(&count) = counts.get(cid) {
+            return count;
+        }
+
+        let count = links.iter().map(|l| self.leaf_count(l, counts)).sum();
+        counts.insert(cid.clone(), count);
+        count
+    }
+
+    /// Returns the DAG rooted at internal node `cid`, covering only the
+    /// chunks under it, or `None` if `cid` is not an internal node.
+    ///
+    /// A subtree verifies on its own, so it can be fetched and checked
+    /// independently of the rest of the content.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{Chunker, DagBuilder};
+    ///
+    /// let chunks = Chunker::new(1024).chunk(&vec![3u8; 40 * 1024]);
+    /// let dag = DagBuilder::new().with_max_links(8).build(&chunks);
+    ///
+    /// let first = &dag.links(dag.root_cid()).unwrap()[0];
+    /// let subtree = dag.subtree(first).unwrap();
+    /// assert_eq!(subtree.num_children(), 8);
+    /// assert!(subtree.verify(&chunks[..8]));
+    /// ```
+    pub fn subtree(&self, cid: &ContentId) -> Option<MerkleDAG> {
+        self.nodes.get(cid)?;
+
+        let mut children = Vec::new();
+        let mut nodes = dag::Nodes::new();
+        self.walk(cid, &mut children, &mut nodes);
+
+        Some(MerkleDAG {
+            root: cid.clone(),
+            children,
+            nodes,
+            erasure: None,
+            chunk_sizes: Vec::new(),
+        })
+    }
+
+    /// Verifies the DAG structure integrity.
+    ///
+    /// Walks the tree from the root, checking that every internal node's CID
+    /// matches its links and that the leaves reached are the chunk CIDs, in
+    /// order.
     ///
     /// # Example
     ///
@@ -703,17 +1767,30 @@ impl MerkleDAG {
     /// assert!(dag.verify_structure());
     /// ```
     pub fn verify_structure(&self) -> bool {
-        let computed_root = Self::compute_root(&self.children);
-        computed_root == self.root
+        if self.children.is_empty() {
+            return self.nodes.is_empty() && self.root == ContentId::new(&[]);
+        }
+
+        if !self.nodes.contains_key(&self.root) {
+            return false;
+        }
+
+        let mut leaves = Vec::with_capacity(self.children.len());
+        let mut seen = dag::Nodes::new();
+        self.walk(&self.root, &mut leaves, &mut seen) && leaves == self.children
     }
 
-    /// Creates a DAG from a root CID and child CIDs (for deserialization).
+    /// Creates a DAG from a root CID and chunk CIDs (for deserialization).
+    ///
+    /// The internal nodes are rebuilt with the balanced layout and
+    /// [`DEFAULT_MAX_LINKS`]; use [`MerkleDAG::from_nodes`] for other DAGs.
     ///
     /// # Safety
     ///
     /// This does not verify the structure. Use `verify_structure()` to check.
     pub fn from_parts(root: ContentId, children: Vec<ContentId>) -> Self {
-        MerkleDAG { root, children }
+        let dag = DagBuilder::new().build_from_cids(children);
+        MerkleDAG { root, ..dag }
     }
 }
 
@@ -763,10 +1840,125 @@ impl Content {
     /// let content = Content::new(data, DEFAULT_CHUNK_SIZE);
     /// ```
     pub fn new(data: Vec<u8>, chunk_size: usize) -> Self {
+        Self::with_chunker(data, &Chunker::new(chunk_size))
+    }
+
+    /// Creates a new Content chunked as `config` describes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `config` fails [`ContentConfig::validate`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{config::ContentConfig, Content};
+    ///
+    /// let data: Vec<u8> = (0..1_000_000u64).map(|i| (i * 7919 % 251) as u8).collect();
+    /// let config = ContentConfig::with_content_defined_chunking(64 * 1024);
+    ///
+    /// let content = Content::from_config(data.clone(), &config).unwrap();
+    /// assert_eq!(content.reconstruct().unwrap(), data);
+    /// ```
+    pub fn from_config(data: Vec<u8>, config: &ContentConfig) -> Result<Self> {
+        config.validate().map_err(|e| anyhow!(e))?;
+        Ok(Self::build(
+            data,
+            &Chunker::from_config(config),
+            &DagBuilder::from_config(config)?,
+        ))
+    }
+
+    /// Creates a new Content using `chunker` and a default [`MerkleDAG`].
+    pub fn with_chunker(data: Vec<u8>, chunker: &Chunker) -> Self {
+        Self::build(data, chunker, &DagBuilder::new())
+    }
+
+    /// Creates a new Content using `chunker` and a DAG from `builder`.
+    ///
+    /// If `builder` has erasure coding, the chunks are coded and the DAG
+    /// covers the data and parity chunks.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{Chunker, Content, DagBuilder, ErasureCoding};
+    ///
+    /// let data: Vec<u8> = (0..40_000u32).map(|i| (i % 199) as u8).collect();
+    /// let builder = DagBuilder::new().with_erasure_coding(ErasureCoding::new(4, 2));
+    /// let content = Content::build(data.clone(), &Chunker::new(4096), &builder);
+    /// assert_eq!(content.num_chunks(), 16); // 10 data + 6 parity
+    ///
+    /// // Any 2 chunks of each stripe of 6 can be lost
+    /// let kept = content.chunks().iter().filter(|c| c.index % 6 < 4).cloned().collect();
+    /// let partial = Content::from_parts(content.dag().clone(), kept).unwrap();
+    /// assert_eq!(partial.reconstruct().unwrap(), data);
+    /// ```
+    pub fn build(data: Vec<u8>, chunker: &Chunker, builder: &DagBuilder) -> Self {
         let total_size = data.len();
-        let chunker = Chunker::new(chunk_size);
-        let chunks = chunker.chunk(&data);
-        let dag = MerkleDAG::from_chunks(&chunks);
+        let mut chunks = chunker.chunk(&data);
+        let sizes = content_sizes(&chunks);
+        if let Some(coding) = builder.erasure_coding() {
+            chunks = coding.encode(&chunks);
+        }
+        let dag = builder.build(&chunks).with_chunk_sizes(sizes);
+
+        Content {
+            dag,
+            chunks,
+            total_size,
+        }
+    }
+
+    /// Creates Content whose chunks are encrypted with `key`.
+    ///
+    /// Chunk CIDs and the DAG cover the ciphertext: [`size`](Self::size) and
+    /// [`reconstruct`](Self::reconstruct) see the encrypted chunks, and
+    /// [`decrypt`](Self::decrypt) returns the original data.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{Content, ContentKey};
+    ///
+    /// let data = b"licensed asset ".repeat(10_000);
+    /// let key = ContentKey::convergent(&data);
+    /// let content = Content::encrypted(data.clone(), 16 * 1024, &key);
+    ///
+    /// assert!(content.verify());
+    /// assert_eq!(content.decrypt(&key).unwrap(), data);
+    /// assert!(content.decrypt(&ContentKey::random()).is_err());
+    ///
+    /// // Same content, same convergent key: same root
+    /// let again = Content::encrypted(data.clone(), 16 * 1024, &key);
+    /// assert_eq!(again.root_cid(), content.root_cid());
+    /// ```
+    pub fn encrypted(data: Vec<u8>, chunk_size: usize, key: &ContentKey) -> Self {
+        Self::build_encrypted(data, &Chunker::new(chunk_size), &DagBuilder::new(), key)
+    }
+
+    /// Creates Content whose chunks, made by `chunker` (and compressed as it
+    /// says), are encrypted with `key`, with a DAG from `builder`.
+    ///
+    /// Erasure coding applies to the encrypted chunks.
+    pub fn build_encrypted(
+        data: Vec<u8>,
+        chunker: &Chunker,
+        builder: &DagBuilder,
+        key: &ContentKey,
+    ) -> Self {
+        let plain = chunker.chunk(&data);
+        let mut chunks: Vec<Chunk> = plain
+            .iter()
+            .map(|chunk| key.seal_chunk(chunk, chunk.index as usize + 1 == plain.len()))
+            .collect();
+        let total_size = chunks.iter().map(|c| c.data.len()).sum();
+        let sizes = content_sizes(&chunks);
+
+        if let Some(coding) = builder.erasure_coding() {
+            chunks = coding.encode(&chunks);
+        }
+        let dag = builder.build(&chunks).with_chunk_sizes(sizes);
 
         Content {
             dag,
@@ -776,14 +1968,37 @@ impl Content {
     }
 
     /// Creates Content from existing chunks and DAG.
+    ///
+    /// For an erasure-coded DAG, `chunks` may be any subset of the coded
+    /// chunks, indexed by leaf, that can rebuild the content.
     pub fn from_parts(dag: MerkleDAG, chunks: Vec<Chunk>) -> Result<Self> {
-        let total_size = chunks.iter().map(|c| c.size()).sum();
+        if let Some(coding) = dag.erasure_coding() {
+            if !dag.verify_structure() || !coding.recoverable(dag.children(), &chunks) {
+                return Err(anyhow!("DAG verification failed"));
+            }
+
+            let mut total_size = 0;
+            for chunk in coding.decode(dag.children(), &chunks)? {
+                total_size += chunk.decompress()?.len();
+            }
+
+            return Ok(Content {
+                dag,
+                chunks,
+                total_size,
+            });
+        }
 
         // Verify structure
         if !dag.verify(&chunks) {
             return Err(anyhow!("DAG verification failed"));
         }
 
+        let mut total_size = 0;
+        for chunk in &chunks {
+            total_size += chunk.decompress()?.len();
+        }
+
         Ok(Content {
             dag,
             chunks,
@@ -811,17 +2026,48 @@ impl Content {
         self.total_size
     }
 
-    /// Returns the number of chunks.
+    /// Returns the total size of the chunks as stored, after compression.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{config::ContentConfig, Content};
+    ///
+    /// let text = b"<p>Hello, world!</p>\n".repeat(10_000);
+    /// let config = ContentConfig::with_compression(64 * 1024);
+    /// let content = Content::from_config(text.clone(), &config).unwrap();
+    ///
+    /// assert_eq!(content.size(), text.len());
+    /// assert!(content.stored_size() < text.len() / 10);
+    /// assert_eq!(content.reconstruct().unwrap(), text);
+    /// ```
+    pub fn stored_size(&self) -> usize {
+        self.chunks.iter().map(|c| c.size()).sum()
+    }
+
+    /// Returns the number of chunks (coded chunks held, for erasure-coded
+    /// content).
     pub fn num_chunks(&self) -> usize {
         self.chunks.len()
     }
 
     /// Verifies the integrity of all chunks and the DAG structure.
+    ///
+    /// Erasure-coded content verifies if the chunks held match their leaves
+    /// and are enough to rebuild it.
     pub fn verify(&self) -> bool {
-        self.dag.verify(&self.chunks)
+        match self.dag.erasure_coding() {
+            Some(coding) => {
+                self.dag.verify_structure() && coding.recoverable(self.dag.children(), &self.chunks)
+            }
+            None => self.dag.verify(&self.chunks),
+        }
     }
 
-    /// Reconstructs the original data from chunks.
+    /// Reconstructs the original data from chunks, decompressing them.
+    ///
+    /// Missing data chunks of erasure-coded content are rebuilt from the
+    /// parity chunks first.
     ///
     /// # Errors
     ///
@@ -831,15 +2077,126 @@ impl Content {
             return Err(anyhow!("Content verification failed"));
         }
 
-        // Determine chunk size from first chunk (or use default)
-        let chunk_size = if !self.chunks.is_empty() {
-            std::cmp::max(self.chunks[0].size(), DEFAULT_CHUNK_SIZE)
-        } else {
-            DEFAULT_CHUNK_SIZE
+        if let Some(coding) = self.dag.erasure_coding() {
+            let chunks = coding.decode(self.dag.children(), &self.chunks)?;
+            return Chunker::default().reconstruct(chunks);
+        }
+
+        // Stored chunks can be larger than any chunk size (sealed chunks
+        // carry a nonce and tag), and joining them doesn't need one
+        Chunker::default().reconstruct(self.chunks.clone())
+    }
+
+    /// Decrypts content created with [`Content::encrypted`] or
+    /// [`Content::build_encrypted`], rebuilding erasure-coded chunks first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if verification fails, `key` is the wrong key, or
+    /// chunks are missing.
+    pub fn decrypt(&self, key: &ContentKey) -> Result<Vec<u8>> {
+        if !self.verify() {
+            return Err(anyhow!("Content verification failed"));
+        }
+
+        // Chunks are in content order once verified or decoded
+        let chunks = match self.dag.erasure_coding() {
+            Some(coding) => Cow::Owned(coding.decode(self.dag.children(), &self.chunks)?),
+            None => Cow::Borrowed(&self.chunks),
+        };
+
+        let mut data = Vec::with_capacity(self.total_size);
+        for (i, chunk) in chunks.iter().enumerate() {
+            data.extend_from_slice(&key.open_chunk(
+                i as u32,
+                i + 1 == chunks.len(),
+                &chunk.data,
+            )?);
+        }
+        Ok(data)
+    }
+
+    /// Returns `len` bytes from `offset`, decompressing only the chunks that
+    /// cover them; see [`MerkleDAG::chunk_range`].
+    ///
+    /// Ranges running past the end are cut short. Encrypted content reads
+    /// ciphertext, as [`reconstruct`](Self::reconstruct) does. Missing data
+    /// chunks of erasure-coded content are rebuilt first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the DAG doesn't record chunk sizes, `offset` is
+    /// past the end, or a chunk covering the range is missing or doesn't
+    /// match its leaf or recorded size.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::Content;
+    ///
+    /// let data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+    /// let content = Content::new(data.clone(), 1024);
+    ///
+    /// assert_eq!(content.read_range(1000, 100).unwrap(), &data[1000..1100]);
+    /// assert_eq!(content.read_range(9990, 100).unwrap(), &data[9990..]);
+    /// ```
+    pub fn read_range(&self, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let range = self.dag.chunk_range(offset, len)?;
+        let sizes = self.dag.chunk_sizes();
+        let skip = offset - sizes[..range.start as usize].iter().sum::<u64>();
+        let len = len.min(sizes.iter().sum::<u64>() - offset);
+
+        let decoded;
+        let chunks: Vec<&Chunk> = match self.dag.erasure_coding() {
+            Some(coding) => {
+                let held: Option<Vec<&Chunk>> = range
+                    .clone()
+                    .map(|i| self.leaf_chunk(coding.leaf_index(i as usize)))
+                    .collect();
+                match held {
+                    Some(chunks) => chunks,
+                    None => {
+                        decoded = coding.decode(self.dag.children(), &self.chunks)?;
+                        decoded[range.start as usize..range.end as usize]
+                            .iter()
+                            .collect()
+                    }
+                }
+            }
+            None => range
+                .clone()
+                .map(|i| {
+                    self.leaf_chunk(i as usize)
+                        .ok_or_else(|| anyhow!("Chunk {} not found", i))
+                })
+                .collect::<Result<_>>()?,
         };
 
-        let chunker = Chunker::new(chunk_size);
-        chunker.reconstruct(self.chunks.clone())
+        let mut data = Vec::with_capacity(len as usize);
+        for (i, chunk) in range.zip(chunks) {
+            let bytes = chunk.decompress()?;
+            if bytes.len() as u64 != sizes[i as usize] {
+                return Err(anyhow!(
+                    "Chunk {} has {} bytes, not the {} recorded",
+                    i,
+                    bytes.len(),
+                    sizes[i as usize]
+                ));
+            }
+            data.extend_from_slice(&bytes);
+        }
+
+        let skip = skip as usize;
+        Ok(data[skip.min(data.len())..][..len as usize].to_vec())
+    }
+
+    /// The chunk held for leaf `index`, if it matches the leaf
+    fn leaf_chunk(&self, index: usize) -> Option<&Chunk> {
+        let leaf = self.dag.children().get(index)?;
+        self.chunks
+            .iter()
+            .find(|c| c.index as usize == index)
+            .filter(|c| &c.cid == leaf && c.verify())
     }
 
     /// Returns a chunk by index.
@@ -854,6 +2211,15 @@ impl Content {
             .ok_or_else(|| anyhow!("Chunk {} not found", index))
     }
 
+    /// Creates an inclusion proof for chunk `index`; see [`MerkleDAG::prove`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no chunk `index`.
+    pub fn prove(&self, index: u32) -> Result<ChunkProof> {
+        self.dag.prove(index)
+    }
+
     /// Returns all chunk CIDs.
     pub fn chunk_cids(&self) -> Vec<&ContentId> {
         self.chunks.iter().map(|c| &c.cid).collect()
@@ -1032,6 +2398,376 @@ mod tests {
         assert!(dag.verify_structure());
     }
 
+    #[test]
+    fn test_merkle_dag_blake3() {
+        let data: Vec<u8> = (0..3000u32).map(|i| (i % 251) as u8).collect();
+        let chunker = Chunker::new(1024).with_hash_algorithm(HashAlgorithm::Blake3);
+        let chunks = chunker.chunk(&data);
+        let dag = MerkleDAG::from_chunks(&chunks);
+
+        assert_eq!(dag.root_cid().algorithm(), HashAlgorithm::Blake3);
+        assert!(dag.verify(&chunks));
+        assert_eq!(chunker.reconstruct(chunks).unwrap(), data);
+    }
+
+    #[test]
+    fn test_chunk_reader_matches_in_memory() {
+        let data: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+
+        for algorithm in [HashAlgorithm::Sha2_256, HashAlgorithm::Blake3] {
+            let chunker = Chunker::new(1024).with_hash_algorithm(algorithm);
+            let expected = chunker.chunk(&data);
+
+            let mut stream = chunker.chunk_reader(&data[..]);
+            let chunks: Vec<Chunk> = stream.by_ref().collect::<Result<_>>().unwrap();
+            let streamed = stream.finish();
+
+            assert_eq!(chunks.len(), expected.len());
+            for (a, b) in chunks.iter().zip(&expected) {
+                assert_eq!(a.index, b.index);
+                assert_eq!(a.cid, b.cid);
+            }
+            assert_eq!(streamed.cid, ContentId::new_with(algorithm, &data));
+            assert_eq!(
+                &streamed.root_cid,
+                MerkleDAG::from_chunks(&expected).root_cid()
+            );
+            assert_eq!(streamed.size, data.len() as u64);
+            assert_eq!(streamed.num_chunks, 5);
+        }
+    }
+
+    #[test]
+    fn test_chunk_reader_empty() {
+        let chunker = Chunker::new(1024);
+        let mut stream = chunker.chunk_reader(std::io::empty());
+        assert!(stream.next().is_none());
+
+        let streamed = stream.finish();
+        assert_eq!(streamed.num_chunks, 0);
+        assert_eq!(streamed.cid, ContentId::new(b""));
+        assert_eq!(&streamed.root_cid, MerkleDAG::from_chunks(&[]).root_cid());
+    }
+
+    #[tokio::test]
+    async fn test_chunk_reader_async() {
+        let data = vec![9u8; 2500];
+        let chunker = Chunker::new(1024);
+
+        let mut stream = chunker.chunk_reader(&data[..]);
+        let mut sizes = Vec::new();
+        while let Some(chunk) = stream.next_chunk_async().await.unwrap() {
+            assert!(chunk.verify());
+            sizes.push(chunk.size());
+        }
+
+        assert_eq!(sizes, vec![1024, 1024, 452]);
+        assert_eq!(stream.finish().cid, ContentId::new(&data));
+    }
+
+    /// Deterministic pseudo-random bytes, so chunk boundaries are reproducible
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_content_defined_chunker() {
+        let data = noise(1024 * 1024, 3);
+        let chunker = Chunker::content_defined(4096, 16384, 65536);
+        let chunks = chunker.chunk(&data);
+
+        let (last, rest) = chunks.split_last().unwrap();
+        assert!(rest.iter().all(|c| (4096..=65536).contains(&c.size())));
+        assert!(last.size() <= 65536);
+
+        // Sizes vary with the content rather than sitting at fixed offsets
+        assert!(rest.iter().any(|c| c.size() != rest[0].size()));
+        assert_eq!(chunker.reconstruct(chunks).unwrap(), data);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_content_defined_chunker_invalid_sizes() {
+        Chunker::content_defined(512, 4096, 16384); // Below MIN_CHUNK_SIZE
+    }
+
+    #[test]
+    fn test_content_defined_chunking_survives_insert() {
+        let original = noise(2 * 1024 * 1024, 4);
+        let mut edited = vec![0x42];
+        edited.extend_from_slice(&original);
+
+        let shared = |chunker: &Chunker| {
+            let before: std::collections::HashSet<_> = chunker
+                .chunk(&original)
+                .into_iter()
+                .map(|c| c.cid)
+                .collect();
+            let after = chunker.chunk(&edited);
+            let kept = after.iter().filter(|c| before.contains(&c.cid)).count();
+            (kept, after.len())
+        };
+
+        // Fixed offsets shift every chunk
+        let (kept, _) = shared(&Chunker::new(16384));
+        assert_eq!(kept, 0);
+
+        // Content-defined boundaries only move around the edit
+        let (kept, total) = shared(&Chunker::content_defined(4096, 16384, 65536));
+        assert!(kept + 2 >= total, "kept {} of {} chunks", kept, total);
+    }
+
+    #[tokio::test]
+    async fn test_content_defined_chunk_reader_matches_in_memory() {
+        let data = noise(600_000, 5);
+        let chunker = Chunker::content_defined(2048, 8192, 32768);
+        let expected = chunker.chunk(&data);
+
+        let mut stream = chunker.chunk_reader(&data[..]);
+        let chunks: Vec<Chunk> = stream.by_ref().collect::<Result<_>>().unwrap();
+        assert_eq!(
+            chunks.iter().map(|c| &c.cid).collect::<Vec<_>>(),
+            expected.iter().map(|c| &c.cid).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            &stream.finish().root_cid,
+            MerkleDAG::from_chunks(&expected).root_cid()
+        );
+
+        let mut stream = chunker.chunk_reader(&data[..]);
+        let mut cids = Vec::new();
+        while let Some(chunk) = stream.next_chunk_async().await.unwrap() {
+            cids.push(chunk.cid);
+        }
+        assert_eq!(cids.len(), expected.len());
+        assert!(cids.iter().zip(&expected).all(|(a, b)| a == &b.cid));
+    }
+
+    #[test]
+    fn test_merkle_dag_multi_level() {
+        let data = noise(2000 * 1024, 11);
+        let chunks = Chunker::new(1024).chunk(&data);
+        let dag = MerkleDAG::from_chunks(&chunks);
+
+        // 2000 chunks -> 12 nodes of up to 174 links -> root
+        assert_eq!(dag.depth(), 2);
+        assert_eq!(dag.num_nodes(), 13);
+        assert_eq!(dag.links(dag.root_cid()).unwrap().len(), 12);
+        assert!(dag
+            .node_cids()
+            .all(|cid| dag.links(cid).unwrap().len() <= DEFAULT_MAX_LINKS));
+        assert!(dag.verify_structure());
+        assert!(dag.verify(&chunks));
+
+        // Leaves stay flat and in order
+        let cids: Vec<_> = chunks.iter().map(|c| c.cid.clone()).collect();
+        assert_eq!(dag.children(), &cids[..]);
+    }
+
+    #[test]
+    fn test_merkle_dag_small_root_is_flat() {
+        // Up to max_links chunks, the root is the hash of the chunk CIDs
+        let chunks = Chunker::new(1024).chunk(&noise(10 * 1024, 12));
+        let dag = MerkleDAG::from_chunks(&chunks);
+
+        let mut combined = Vec::new();
+        for chunk in &chunks {
+            combined.extend_from_slice(&chunk.cid.to_bytes());
+        }
+        assert_eq!(
+            dag.root_cid(),
+            &ContentId::new_v1_with(Codec::HashList, HashAlgorithm::Sha2_256, &combined)
+        );
+        assert_eq!(dag.depth(), 1);
+    }
+
+    #[test]
+    fn test_merkle_dag_root_is_pinned() {
+        // Before multi-level DAGs the root was the CIDv0 of the chunk hashes
+        // alone; node CIDs now cover each link's version and codec too, so
+        // old roots do not carry over
+        let data: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+        let dag = MerkleDAG::from_chunks(&Chunker::new(1024).chunk(&data));
+        let old = ContentId::parse("QmS9n4LXxQLe3QY5xYV4ax76JTJ8RsRTaXKNWHN9p5iu9R").unwrap();
+
+        assert_eq!(dag.num_children(), 3);
+        assert_eq!(
+            dag.root_cid().to_string(),
+            "bagaibqabciqkk7j3r6e4kp2eu4c74emmfomsgnvf7k5jqz2ipa5wfzuy3jd5xgi"
+        );
+        assert_ne!(dag.root_cid().hash(), old.hash());
+        assert!(!dag::is_node_cid(&old));
+        assert!(dag::is_node_cid(dag.root_cid()));
+    }
+
+    #[test]
+    fn test_merkle_dag_layouts() {
+        let chunks = Chunker::new(1024).chunk(&noise(300 * 1024, 13));
+
+        for layout in [DagLayout::Balanced, DagLayout::Trickle] {
+            for max_links in [2, 3, 16, 1000] {
+                let dag = DagBuilder::new()
+                    .with_layout(layout)
+                    .with_max_links(max_links)
+                    .build(&chunks);
+                assert!(dag.verify(&chunks), "{:?} / {}", layout, max_links);
+                assert_eq!(dag.num_children(), chunks.len());
+            }
+        }
+
+        let balanced = DagBuilder::new().with_max_links(4).build(&chunks);
+        let trickle = DagBuilder::new()
+            .with_layout(DagLayout::Trickle)
+            .with_max_links(4)
+            .build(&chunks);
+        assert_ne!(balanced.root_cid(), trickle.root_cid());
+
+        // Trickle keeps the first chunks right under the root
+        assert_eq!(
+            trickle.links(trickle.root_cid()).unwrap()[..4],
+            balanced.children()[..4]
+        );
+    }
+
+    #[test]
+    fn test_merkle_dag_detects_tampered_node() {
+        let chunks = Chunker::new(1024).chunk(&noise(64 * 1024, 14));
+        let dag = DagBuilder::new().with_max_links(4).build(&chunks);
+
+        let mut nodes: std::collections::HashMap<_, _> = dag
+            .node_cids()
+            .map(|cid| (cid.clone(), dag.links(cid).unwrap().to_vec()))
+            .collect();
+        assert!(MerkleDAG::from_nodes(dag.root_cid().clone(), nodes.clone()).is_ok());
+
+        // Swap two links in an intermediate node
+        let inner = dag.links(dag.root_cid()).unwrap()[0].clone();
+        nodes.get_mut(&inner).unwrap().swap(0, 1);
+        assert!(MerkleDAG::from_nodes(dag.root_cid().clone(), nodes).is_err());
+
+        // Leaves out of order
+        let mut children = dag.children().to_vec();
+        children.swap(0, 1);
+        assert!(!MerkleDAG::from_parts(dag.root_cid().clone(), children).verify_structure());
+    }
+
+    #[test]
+    fn test_merkle_dag_rejects_subtree_swapped_for_leaf() {
+        let chunks = Chunker::new(1024).chunk(&noise(64 * 1024, 15));
+        let dag = DagBuilder::new().with_max_links(4).build(&chunks);
+        let mut nodes: std::collections::HashMap<_, _> = dag
+            .node_cids()
+            .map(|cid| (cid.clone(), dag.links(cid).unwrap().to_vec()))
+            .collect();
+
+        // Link to the first subtree as a chunk whose data is the node's body
+        let inner = dag.links(dag.root_cid()).unwrap()[0].clone();
+        let body: Vec<u8> = dag
+            .links(&inner)
+            .unwrap()
+            .iter()
+            .flat_map(|c| c.to_bytes())
+            .collect();
+        let leaf = ContentId::new(&body);
+        assert_eq!(leaf.hash(), inner.hash());
+        nodes.get_mut(dag.root_cid()).unwrap()[0] = leaf;
+
+        assert!(MerkleDAG::from_nodes(dag.root_cid().clone(), nodes).is_err());
+    }
+
+    #[test]
+    fn test_merkle_dag_subtree() {
+        let chunks = Chunker::new(1024).chunk(&noise(100 * 1024, 15));
+        let dag = DagBuilder::new().with_max_links(10).build(&chunks);
+
+        let links = dag.links(dag.root_cid()).unwrap();
+        assert_eq!(links.len(), 10);
+
+        let mut covered = 0;
+        for link in links {
+            let subtree = dag.subtree(link).unwrap();
+            let range = covered..covered + subtree.num_children();
+            assert!(subtree.verify(&chunks[range.clone()]));
+            assert!(!subtree.verify(&chunks[range.start..range.end - 1]));
+            covered = range.end;
+        }
+        assert_eq!(covered, chunks.len());
+
+        assert!(dag.subtree(&chunks[0].cid).is_none());
+    }
+
+    #[test]
+    fn test_chunk_reader_with_dag_builder() {
+        let data = noise(50 * 1024, 16);
+        let chunker = Chunker::new(1024);
+        let builder = DagBuilder::new()
+            .with_layout(DagLayout::Trickle)
+            .with_max_links(3);
+
+        let mut stream = chunker.chunk_reader(&data[..]).with_dag_builder(&builder);
+        let chunks: Vec<Chunk> = stream.by_ref().collect::<Result<_>>().unwrap();
+        let streamed = stream.finish();
+
+        assert_eq!(&streamed.root_cid, builder.build(&chunks).root_cid());
+    }
+
+    #[test]
+    fn test_chunk_reader_multi_level_root() {
+        // More chunks than one node holds, so the root is built level by level
+        let data = noise(600 * 1024, 17);
+        let chunker = Chunker::new(1024);
+
+        let mut stream = chunker.chunk_reader(&data[..]);
+        let chunks: Vec<Chunk> = stream.by_ref().collect::<Result<_>>().unwrap();
+        let dag = MerkleDAG::from_chunks(&chunks);
+
+        assert!(dag.depth() > 1);
+        assert_eq!(&stream.finish().root_cid, dag.root_cid());
+    }
+
+    #[test]
+    fn test_chunk_reader_node_blocks_rebuild_dag() {
+        let data = noise(600 * 1024, 18);
+        let chunker = Chunker::new(1024);
+
+        let mut stream = chunker.chunk_reader(&data[..]).with_node_blocks();
+        let mut chunks = Vec::new();
+        let mut blocks = Vec::new();
+        while let Some(chunk) = stream.next_chunk().unwrap() {
+            chunks.push(chunk);
+            blocks.extend(stream.take_node_blocks());
+        }
+        // Nodes come out as they fill, not all at the end
+        assert!(!blocks.is_empty());
+        let streamed = stream.finish();
+        blocks.extend(streamed.node_blocks);
+
+        let nodes = blocks
+            .iter()
+            .map(|block| {
+                assert!(block.cid.verify(&block.data));
+                (
+                    block.cid.clone(),
+                    MerkleDAG::decode_node(&block.data).unwrap(),
+                )
+            })
+            .collect();
+        let dag = MerkleDAG::from_nodes(streamed.root_cid, nodes).unwrap();
+        assert_eq!(dag.num_nodes(), blocks.len());
+        assert!(dag.verify(&chunks));
+
+        assert!(MerkleDAG::decode_node(&[]).is_err());
+        assert!(MerkleDAG::decode_node(&blocks[0].data[1..]).is_err());
+    }
+
     #[test]
     fn test_content_new() {
         let data = vec![0u8; 5 * 1024 * 1024];
@@ -1048,6 +2784,234 @@ mod tests {
         assert_eq!(original, reconstructed);
     }
 
+    #[test]
+    fn test_encrypted_content_reconstruct_at_max_chunk_size() {
+        // Sealed chunks are larger than MAX_CHUNK_SIZE
+        let data = vec![3u8; MAX_CHUNK_SIZE + 1000];
+        let key = ContentKey::random();
+        let content = Content::encrypted(data.clone(), MAX_CHUNK_SIZE, &key);
+        assert!(content.chunks()[0].size() > MAX_CHUNK_SIZE);
+
+        let ciphertext = content.reconstruct().unwrap();
+        assert_eq!(ciphertext.len(), content.size());
+        assert_eq!(content.decrypt(&key).unwrap(), data);
+    }
+
+    #[test]
+    fn test_content_read_range() {
+        let data = noise(300_000, 11);
+        let config = ContentConfig::with_content_defined_chunking(16 * 1024);
+        let content = Content::from_config(data.clone(), &config).unwrap();
+        assert_eq!(content.dag().chunk_sizes().len(), content.num_chunks());
+
+        for (offset, len) in [(0, 1), (12_345, 50_000), (299_990, 100), (300_000, 5)] {
+            let end = (offset + len).min(data.len());
+            assert_eq!(
+                content.read_range(offset as u64, len as u64).unwrap(),
+                &data[offset..end]
+            );
+        }
+        assert!(content.read_range(300_001, 1).is_err());
+
+        // Only the covering chunks are needed
+        let range = content.dag().chunk_range(100_000, 1000).unwrap();
+        let held = content.chunks()[range.start as usize..range.end as usize].to_vec();
+        let partial = Content {
+            chunks: held,
+            ..content.clone()
+        };
+        assert_eq!(
+            partial.read_range(100_000, 1000).unwrap(),
+            &data[100_000..101_000]
+        );
+        assert!(partial.read_range(0, 10).is_err());
+    }
+
+    #[test]
+    fn test_content_read_range_compressed_and_coded() {
+        let text = b"<li>row</li>\n".repeat(20_000);
+        let mut config = ContentConfig::with_compression(16 * 1024);
+        config.erasure_coding = Some(ErasureCoding::new(4, 2));
+        let content = Content::from_config(text.clone(), &config).unwrap();
+        assert_eq!(
+            content.read_range(50_000, 40_000).unwrap(),
+            &text[50_000..90_000]
+        );
+
+        // Lost data chunks are rebuilt from parity
+        let kept = content
+            .chunks()
+            .iter()
+            .filter(|c| c.index % 6 >= 2)
+            .cloned()
+            .collect();
+        let partial = Content::from_parts(content.dag().clone(), kept).unwrap();
+        assert_eq!(partial.read_range(0, 70_000).unwrap(), &text[..70_000]);
+    }
+
+    #[test]
+    fn test_dag_without_chunk_sizes() {
+        let chunks = Chunker::new(1024).chunk(&[7u8; 4096]);
+        let dag = MerkleDAG::from_parts(
+            MerkleDAG::from_chunks(&chunks).root_cid().clone(),
+            chunks.iter().map(|c| c.cid.clone()).collect(),
+        );
+        assert!(dag.chunk_sizes().is_empty());
+        assert!(dag.chunk_range(0, 1).is_err());
+
+        let dag = dag.with_chunk_sizes(vec![1024; 4]);
+        assert_eq!(dag.chunk_range(1024, 2048).unwrap(), 1..3);
+        assert_eq!(dag.chunk_range(4096, 1).unwrap(), 0..0);
+    }
+
+    #[test]
+    fn test_chunk_compressed_skips_incompressible() {
+        let data = noise(4096, 7);
+        let chunk = Chunk::compressed(0, &data, HashAlgorithm::Sha2_256, Compression::Zstd);
+
+        assert_eq!(chunk.compression, Compression::None);
+        assert_eq!(chunk.data, data);
+        assert_eq!(chunk.cid, ContentId::new(&data));
+    }
+
+    #[test]
+    fn test_chunk_compressed_verifies_stored_bytes() {
+        let data = b"aaaaaaaaaaaaaaaa".repeat(256);
+        let mut chunk = Chunk::compressed(0, &data, HashAlgorithm::Blake3, Compression::Lz4);
+
+        assert_eq!(chunk.compression, Compression::Lz4);
+        assert_eq!(
+            chunk.cid,
+            ContentId::new_with(HashAlgorithm::Blake3, &chunk.data)
+        );
+        assert_eq!(chunk.decompress().unwrap(), &data[..]);
+
+        let last = chunk.data.len() - 1;
+        chunk.data[last] ^= 1;
+        assert!(!chunk.verify());
+    }
+
+    #[test]
+    fn test_chunk_reader_compressed_matches_in_memory() {
+        let data = b"streamed and compressed ".repeat(2000);
+        let chunker = Chunker::new(4096).with_compression(Compression::Zstd);
+        let expected = chunker.chunk(&data);
+
+        let mut stream = chunker.chunk_reader(&data[..]);
+        let chunks: Vec<Chunk> = stream.by_ref().collect::<Result<_>>().unwrap();
+        let streamed = stream.finish();
+
+        assert_eq!(
+            chunks.iter().map(|c| &c.cid).collect::<Vec<_>>(),
+            expected.iter().map(|c| &c.cid).collect::<Vec<_>>()
+        );
+        assert_eq!(streamed.cid, ContentId::new(&data));
+        assert_eq!(streamed.size, data.len() as u64);
+        assert_eq!(
+            &streamed.root_cid,
+            MerkleDAG::from_chunks(&expected).root_cid()
+        );
+        assert_eq!(chunker.reconstruct(chunks).unwrap(), data);
+    }
+
+    #[test]
+    fn test_parallel_chunking_matches_sequential() {
+        let data = noise(3 * 1024 * 1024, 8);
+        let chunkers = [
+            Chunker::new(64 * 1024),
+            Chunker::content_defined(8192, 32768, 131072)
+                .with_hash_algorithm(HashAlgorithm::Blake3),
+            Chunker::new(16 * 1024).with_compression(Compression::Lz4),
+        ];
+
+        for chunker in chunkers {
+            let sequential = chunker.chunk(&data);
+            let parallel = chunker.clone().with_parallelism(4).chunk(&data);
+
+            assert_eq!(sequential.len(), parallel.len());
+            for (a, b) in sequential.iter().zip(&parallel) {
+                assert_eq!(a.index, b.index);
+                assert_eq!(a.cid, b.cid);
+                assert_eq!(a.data, b.data);
+                assert_eq!(a.compression, b.compression);
+            }
+            assert_eq!(
+                MerkleDAG::from_chunks(&sequential).root_cid(),
+                MerkleDAG::from_chunks(&parallel).root_cid()
+            );
+
+            let parallel_chunker = chunker.with_parallelism(4);
+            assert_eq!(parallel_chunker.reconstruct(parallel).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_parallel_reconstruct_reports_tampered_chunk() {
+        let data = noise(100_000, 9);
+        let chunker = Chunker::new(4096).with_parallelism(3);
+        let mut chunks = chunker.chunk(&data);
+        chunks[17].data[0] ^= 1;
+
+        let err = chunker.reconstruct(chunks).unwrap_err();
+        assert_eq!(err.to_string(), "Chunk 17 failed verification");
+    }
+
+    #[test]
+    fn test_content_from_parallel_config() {
+        let data = noise(1024 * 1024, 10);
+        let config = config::ContentConfig::with_parallel(32 * 1024, 4);
+        assert_eq!(Chunker::from_config(&config).parallelism(), 4);
+
+        let content = Content::from_config(data.clone(), &config).unwrap();
+        let sequential = Content::new(data.clone(), 32 * 1024);
+        assert_eq!(content.root_cid(), sequential.root_cid());
+        assert_eq!(content.reconstruct().unwrap(), data);
+    }
+
+    #[test]
+    fn test_content_from_config() {
+        let data = noise(500_000, 6);
+        let config = config::ContentConfig::with_content_defined_chunking(16 * 1024);
+
+        let content = Content::from_config(data.clone(), &config).unwrap();
+        assert!(content.verify());
+        assert_eq!(content.size(), data.len());
+        assert_eq!(content.reconstruct().unwrap(), data);
+
+        let dag = content.dag();
+        assert_eq!(dag.num_children(), content.num_chunks());
+        assert!(dag.verify_structure());
+
+        // The default config keeps fixed-size chunks
+        let content = Content::from_config(data, &config::ContentConfig::new(16 * 1024)).unwrap();
+        assert!(content.chunks()[..content.num_chunks() - 1]
+            .iter()
+            .all(|c| c.size() == 16 * 1024));
+    }
+
+    #[test]
+    fn test_content_from_config_dag_layout() {
+        let data = noise(200 * 1024, 17);
+        let config = config::ContentConfig::with_dag_layout(1024, DagLayout::Trickle, 8);
+
+        let content = Content::from_config(data.clone(), &config).unwrap();
+        assert!(content.dag().depth() > 1);
+        assert!(content.verify());
+        assert_eq!(content.reconstruct().unwrap(), data);
+
+        let rebuilt =
+            Content::from_parts(content.dag().clone(), content.chunks().to_vec()).unwrap();
+        assert_eq!(rebuilt.root_cid(), content.root_cid());
+    }
+
+    #[test]
+    fn test_from_config_rejects_bad_max_links() {
+        let config = config::ContentConfig::with_dag_layout(1024, DagLayout::Balanced, 1);
+
+        assert!(DagBuilder::from_config(&config).is_err());
+        assert!(Content::from_config(vec![0u8; 4096], &config).is_err());
+    }
+
     #[test]
     fn test_hash_sha256() {
         let hash1 = hash_sha256(b"test");
@@ -0,0 +1,98 @@
+//! Bounded worker pool for per-chunk work.
+//!
+//! Workers pull the next item index from a shared counter, so uneven chunks
+//! (content-defined, compressed) still keep every worker busy. Results come
+//! back in input order, which keeps parallel output byte-identical to the
+//! sequential path.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Applies `f` to every item on at most `workers` threads, preserving order.
+///
+/// Runs on the calling thread when `workers <= 1` or there is at most one item.
+pub(crate) fn map<T, R, F>(items: &[T], workers: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let workers = workers.min(items.len());
+    if workers <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<Option<R>> = (0..items.len()).map(|_| None).collect();
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= items.len() {
+                            return done;
+                        }
+                        done.push((i, f(&items[i])));
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            // A panic in `f` is re-raised on the calling thread
+            let done = handle
+                .join()
+                .unwrap_or_else(|e| std::panic::resume_unwind(e));
+            for (i, result) in done {
+                results[i] = Some(result);
+            }
+        }
+    });
+
+    results
+        .into_iter()
+        .map(|r| r.expect("every item is processed"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_preserves_order() {
+        let items: Vec<u64> = (0..1000).collect();
+        let expected: Vec<u64> = items.iter().map(|i| i * i).collect();
+
+        for workers in [0, 1, 3, 8, 2000] {
+            assert_eq!(map(&items, workers, |i| i * i), expected);
+        }
+    }
+
+    #[test]
+    fn test_map_uses_several_threads() {
+        let items: Vec<u32> = (0..64).collect();
+        let ids = map(&items, 4, |_| {
+            thread::sleep(std::time::Duration::from_millis(2));
+            thread::current().id()
+        });
+
+        let distinct: std::collections::HashSet<_> = ids.into_iter().collect();
+        assert!(distinct.len() > 1);
+        assert!(distinct.len() <= 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "boom")]
+    fn test_map_propagates_panics() {
+        map(&[1, 2, 3, 4], 2, |&i| {
+            if i == 3 {
+                panic!("boom");
+            }
+            i
+        });
+    }
+}
@@ -0,0 +1,327 @@
+//! Merkle inclusion proofs for single chunks.
+//!
+//! A proof is the path from a chunk up to the root: for each internal node on
+//! the way, its full list of links and the position of the child the path
+//! goes through. The verifier hashes the chunk, checks it sits at that
+//! position, hashes the node, and so on up to the root.
+//!
+//! The positions a chunk index maps to depend on the DAG layout and fan-out,
+//! which the root CID does not cover, so the verifier takes them as trusted
+//! input (see [`DagBuilder::verify_proof`]). Proof size is one node per
+//! level: about `depth * max_links` CIDs.
+//!
+//! Range downloads (`ChunkDistributor::download_range` in `codio-chunk`)
+//! check every chunk they return this way before handing out any of its bytes.
+
+use serde::{Deserialize, Serialize};
+
+use crate::dag::{self, TRICKLE_LAYER_REPEAT};
+use crate::{ContentId, DagBuilder, DagLayout};
+
+/// One internal node on the path from a chunk to the root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofStep {
+    /// All links of the node, in order
+    pub links: Vec<ContentId>,
+
+    /// Position of the path's child among `links`
+    pub position: usize,
+}
+
+/// Proof that a chunk is at a given index under a root CID.
+///
+/// Created by [`MerkleDAG::prove`](crate::MerkleDAG::prove) and checked with
+/// [`verify_chunk_proof`] or [`DagBuilder::verify_proof`].
+///
+/// # Example
+///
+/// ```rust
+/// use codio_content::{verify_chunk_proof, Chunker, MerkleDAG};
+///
+/// let data: Vec<u8> = (0..500_000u32).map(|i| (i % 251) as u8).collect();
+/// let chunks = Chunker::new(1024).chunk(&data);
+/// let dag = MerkleDAG::from_chunks(&chunks);
+///
+/// // A full peer proves chunk 300...
+/// let proof = dag.prove(300).unwrap();
+///
+/// // ...and a light client checks it against the root alone
+/// assert!(verify_chunk_proof(dag.root_cid(), 300, &chunks[300].data, &proof));
+/// assert!(!verify_chunk_proof(dag.root_cid(), 301, &chunks[300].data, &proof));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkProof {
+    /// Path from the chunk's parent up to the root
+    steps: Vec<ProofStep>,
+}
+
+impl ChunkProof {
+    pub(crate) fn new(steps: Vec<ProofStep>) -> Self {
+        ChunkProof { steps }
+    }
+
+    /// Returns the path, from the chunk's parent up to the root.
+    pub fn steps(&self) -> &[ProofStep] {
+        &self.steps
+    }
+}
+
+/// Verifies that `data` is chunk `index` of the content with root `root`.
+///
+/// For DAGs built with the default balanced layout and
+/// [`DEFAULT_MAX_LINKS`](crate::DEFAULT_MAX_LINKS); use
+/// [`DagBuilder::verify_proof`] for others. `data` is the chunk as stored,
+/// i.e. compressed if the chunk is.
+pub fn verify_chunk_proof(root: &ContentId, index: u32, data: &[u8], proof: &ChunkProof) -> bool {
+    DagBuilder::new().verify_proof(root, index, data, proof)
+}
+
+pub(crate) fn verify(
+    layout: DagLayout,
+    max_links: usize,
+    root: &ContentId,
+    index: u32,
+    data: &[u8],
+    proof: &ChunkProof,
+) -> bool {
+    let Some(positions) = positions(layout, max_links as u64, index as u64, proof.steps.len())
+    else {
+        return false;
+    };
+
+    // Chunks use the root's hash function
+    let mut cid = ContentId::new_with(root.algorithm(), data);
+
+    for (step, position) in proof.steps.iter().zip(positions) {
+        if step.position != position || step.links.get(position) != Some(&cid) {
+            return false;
+        }
+        cid = dag::node_cid(&step.links);
+    }
+
+    cid == *root
+}
+
+/// Positions of chunk `index` on its path, from its parent up to the root,
+/// for a DAG `depth` nodes deep; `None` if the index cannot be at that depth.
+fn positions(layout: DagLayout, max_links: u64, index: u64, depth: usize) -> Option<Vec<usize>> {
+    if depth == 0 || max_links < 2 {
+        return None;
+    }
+
+    match layout {
+        DagLayout::Balanced => {
+            // Every node left of the path is full, so the positions are the
+            // index's digits in base max_links
+            let mut rest = index;
+            let mut positions = Vec::with_capacity(depth);
+            for _ in 0..depth {
+                positions.push((rest % max_links) as usize);
+                rest /= max_links;
+            }
+            (rest == 0).then_some(positions)
+        }
+        DagLayout::Trickle => {
+            let mut positions = Vec::with_capacity(depth);
+            trickle_positions(index, max_links, None, &mut positions);
+            positions.reverse();
+            (positions.len() == depth).then_some(positions)
+        }
+    }
+}
+
+/// Pushes the positions of leaf `index` within a trickle node whose subtrees
+/// are shallower than `max_depth`, from the node down.
+fn trickle_positions(index: u64, max_links: u64, max_depth: Option<usize>, out: &mut Vec<usize>) {
+    // Leaves first...
+    if index < max_links {
+        out.push(index as usize);
+        return;
+    }
+
+    // ...then TRICKLE_LAYER_REPEAT subtrees of each depth
+    let mut index = index - max_links;
+    let mut position = max_links as usize;
+    let mut depth = 1;
+
+    while max_depth.is_none_or(|max| depth < max) {
+        let size = trickle_subtree_size(max_links, depth);
+        for _ in 0..TRICKLE_LAYER_REPEAT {
+            if index < size {
+                out.push(position);
+                trickle_positions(index, max_links, Some(depth), out);
+                return;
+            }
+            index -= size;
+            position += 1;
+        }
+        depth += 1;
+    }
+
+    // Callers only descend into subtrees that hold the index
+}
+
+/// Leaves in a full trickle subtree of the given depth:
+/// `max_links * (TRICKLE_LAYER_REPEAT + 1)^(depth - 1)`.
+fn trickle_subtree_size(max_links: u64, depth: usize) -> u64 {
+    let mut size = max_links;
+    for _ in 1..depth {
+        size = size.saturating_mul(TRICKLE_LAYER_REPEAT as u64 + 1);
+    }
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chunker, MerkleDAG};
+
+    fn chunks(n: usize) -> Vec<crate::Chunk> {
+        // Distinct 1KB chunks
+        let data: Vec<u8> = (0..n as u32 * 256).flat_map(|i| i.to_le_bytes()).collect();
+        Chunker::new(1024).chunk(&data)
+    }
+
+    #[test]
+    fn test_every_chunk_proves() {
+        let chunks = chunks(100);
+
+        for layout in [DagLayout::Balanced, DagLayout::Trickle] {
+            for max_links in [2, 3, 7, 174] {
+                let builder = DagBuilder::new()
+                    .with_layout(layout)
+                    .with_max_links(max_links);
+                let dag = builder.build(&chunks);
+
+                for (i, chunk) in chunks.iter().enumerate() {
+                    let proof = dag.prove(i as u32).unwrap();
+                    if layout == DagLayout::Balanced {
+                        assert_eq!(proof.steps().len(), dag.depth());
+                    }
+                    assert!(
+                        builder.verify_proof(dag.root_cid(), i as u32, &chunk.data, &proof),
+                        "{:?} / {} / chunk {}",
+                        layout,
+                        max_links,
+                        i
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_trickle_positions_match_tree() {
+        // Trickle paths don't depend on the total, so a tree built over the
+        // first n leaves agrees with the positions for every n
+        for n in [1, 2, 5, 9, 23, 60] {
+            let chunks = chunks(n);
+            let dag = DagBuilder::new()
+                .with_layout(DagLayout::Trickle)
+                .with_max_links(2)
+                .build(&chunks);
+
+            for i in 0..chunks.len() {
+                let proof = dag.prove(i as u32).unwrap();
+                let expected: Vec<_> = proof.steps().iter().map(|s| s.position).collect();
+                assert_eq!(
+                    positions(DagLayout::Trickle, 2, i as u64, proof.steps().len()),
+                    Some(expected)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_rejects_wrong_claims() {
+        let chunks = chunks(50);
+        let builder = DagBuilder::new().with_max_links(4);
+        let dag = builder.build(&chunks);
+        let root = dag.root_cid();
+        let proof = dag.prove(20).unwrap();
+
+        assert!(builder.verify_proof(root, 20, &chunks[20].data, &proof));
+
+        // Wrong index, data or root
+        assert!(!builder.verify_proof(root, 21, &chunks[20].data, &proof));
+        assert!(!builder.verify_proof(root, 20, &chunks[21].data, &proof));
+        assert!(!builder.verify_proof(&chunks[0].cid, 20, &chunks[20].data, &proof));
+
+        // Wrong fan-out or layout
+        assert!(!DagBuilder::new().with_max_links(5).verify_proof(
+            root,
+            20,
+            &chunks[20].data,
+            &proof
+        ));
+        assert!(!DagBuilder::new()
+            .with_layout(DagLayout::Trickle)
+            .with_max_links(4)
+            .verify_proof(root, 20, &chunks[20].data, &proof));
+
+        // Tampered sibling
+        let mut tampered = proof.clone();
+        tampered.steps[0].links[0] = chunks[0].cid.clone();
+        assert!(!builder.verify_proof(root, 20, &chunks[20].data, &tampered));
+    }
+
+    #[test]
+    fn test_rejects_node_passed_off_as_chunk() {
+        let chunks = chunks(50);
+        let builder = DagBuilder::new().with_max_links(4);
+        let dag = builder.build(&chunks);
+        let full = dag.prove(1).unwrap();
+
+        // Drop the bottom step and offer the parent node's preimage as the
+        // chunk: the positions of index 0 at the shorter depth line up
+        let parent = &full.steps()[0].links;
+        let preimage: Vec<u8> = parent.iter().flat_map(|c| c.to_bytes()).collect();
+        let truncated = ChunkProof::new(full.steps()[1..].to_vec());
+
+        assert!(!builder.verify_proof(dag.root_cid(), 0, &preimage, &truncated));
+    }
+
+    #[test]
+    fn test_rejects_node_link_swapped_for_chunk_cid() {
+        let chunks = chunks(2000);
+        let builder = DagBuilder::new().with_max_links(4);
+        let dag = builder.build(&chunks);
+        let full = dag.prove(0).unwrap();
+
+        // Offer the parent node's body as the chunk, and in the grandparent
+        // link to it by a chunk CID with the node's digest
+        let body: Vec<u8> = full.steps()[0]
+            .links
+            .iter()
+            .flat_map(|c| c.to_bytes())
+            .collect();
+        let mut forged = ChunkProof::new(full.steps()[1..].to_vec());
+        let swapped = ContentId::new(&body);
+        assert_eq!(swapped.hash(), forged.steps[0].links[0].hash());
+        forged.steps[0].links[0] = swapped;
+
+        assert!(!builder.verify_proof(dag.root_cid(), 0, &body, &forged));
+    }
+
+    #[test]
+    fn test_single_chunk_and_empty() {
+        let chunks = chunks(1);
+        let dag = MerkleDAG::from_chunks(&chunks);
+        let proof = dag.prove(0).unwrap();
+        assert!(verify_chunk_proof(
+            dag.root_cid(),
+            0,
+            &chunks[0].data,
+            &proof
+        ));
+
+        assert!(dag.prove(1).is_err());
+        assert!(MerkleDAG::from_chunks(&[]).prove(0).is_err());
+        assert!(!verify_chunk_proof(
+            dag.root_cid(),
+            0,
+            &chunks[0].data,
+            &ChunkProof::new(vec![])
+        ));
+    }
+}
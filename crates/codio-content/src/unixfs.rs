@@ -0,0 +1,1116 @@
+//! UnixFS: files and directories as dag-pb blocks, laid out the way
+//! `ipfs add` does it.
+//!
+//! A file is split into leaves, and the leaves are linked by dag-pb nodes
+//! with the [`DagBuilder`] layout. Every node carries UnixFS data recording
+//! the file size below it and each child's file size, and every link carries
+//! the child's cumulative block size. With the same chunking, layout and
+//! leaf format, the root CID matches go-ipfs:
+//!
+//! | `ipfs add` flag         | [`UnixFsBuilder`]                              |
+//! |-------------------------|------------------------------------------------|
+//! | `--chunker=size-N`      | `with_chunker(Chunker::new(N))`                |
+//! | `--trickle`             | `with_dag_builder(..with_layout(Trickle))`     |
+//! | `--raw-leaves`          | `with_raw_leaves(true)`                        |
+//! | `--cid-version=1`       | `with_cid_version(Version::V1)`                |
+//! | `--hash=blake3`         | `with_chunker(..with_hash_algorithm(Blake3))`  |
+//!
+//! Content-defined chunking is supported, but go-ipfs has no FastCDC
+//! chunker, so such roots can only be reproduced by this library.
+//!
+//! As in go-ipfs, a balanced file with a single leaf has that leaf as its
+//! root, balanced leaves are UnixFS `File` nodes and trickle leaves are
+//! UnixFS `Raw` nodes. Directories are single basic (unsharded) nodes with
+//! links sorted by name.
+
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use codio_content_id::{Codec, Version};
+
+use crate::dagpb::{self, PbLink, PbNode};
+use crate::{dag, parallel, Chunker, Compression, ContentId, DagBuilder, DagLayout, HashAlgorithm};
+
+/// Chunk size of `ipfs add` (`size-262144`)
+const IPFS_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Kind of a UnixFS node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnixFsType {
+    /// Bytes of a file, as a leaf
+    Raw,
+
+    /// Directory; its links are the entries
+    Directory,
+
+    /// File; holds bytes, links to more of the file, or both
+    File,
+}
+
+impl UnixFsType {
+    /// Protobuf enum value
+    pub fn code(&self) -> u64 {
+        match self {
+            UnixFsType::Raw => 0,
+            UnixFsType::Directory => 1,
+            UnixFsType::File => 2,
+        }
+    }
+
+    /// Looks up a node kind by its protobuf enum value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for kinds this library does not handle (metadata,
+    /// symlinks and HAMT shards).
+    pub fn from_code(code: u64) -> Result<Self> {
+        match code {
+            0 => Ok(UnixFsType::Raw),
+            1 => Ok(UnixFsType::Directory),
+            2 => Ok(UnixFsType::File),
+            other => Err(anyhow!("Unsupported UnixFS node type {}", other)),
+        }
+    }
+}
+
+/// The UnixFS payload of a dag-pb node ([`PbNode::data`]).
+///
+/// ```text
+/// message Data {
+///   required DataType Type = 1;
+///   optional bytes Data = 2;
+///   optional uint64 filesize = 3;
+///   repeated uint64 blocksizes = 4;
+/// }
+/// ```
+///
+/// # Example
+///
+/// ```rust
+/// use codio_content::{UnixFsData, UnixFsType};
+///
+/// let data = UnixFsData {
+///     kind: UnixFsType::File,
+///     data: Some(b"hi".to_vec()),
+///     filesize: Some(2),
+///     blocksizes: vec![],
+/// };
+///
+/// assert_eq!(data.encode(), [0x08, 0x02, 0x12, 0x02, b'h', b'i', 0x18, 0x02]);
+/// assert_eq!(UnixFsData::decode(&data.encode()).unwrap(), data);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnixFsData {
+    /// Node kind
+    pub kind: UnixFsType,
+
+    /// File bytes held by this node itself
+    pub data: Option<Vec<u8>>,
+
+    /// Total file bytes in this node and below it
+    pub filesize: Option<u64>,
+
+    /// File bytes below each link, in link order
+    pub blocksizes: Vec<u64>,
+}
+
+impl UnixFsData {
+    /// Encodes the payload as protobuf, fields in order.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        dagpb::put_uint(&mut out, 1, self.kind.code());
+        if let Some(data) = &self.data {
+            dagpb::put_bytes(&mut out, 2, data);
+        }
+        if let Some(filesize) = self.filesize {
+            dagpb::put_uint(&mut out, 3, filesize);
+        }
+        for &size in &self.blocksizes {
+            dagpb::put_uint(&mut out, 4, size);
+        }
+
+        out
+    }
+
+    /// Decodes a UnixFS payload.
+    ///
+    /// Fields this library does not use (hash type, fanout, mode, mtime) are
+    /// skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the payload is malformed, has no type, or has an
+    /// unsupported type.
+    pub fn decode(mut bytes: &[u8]) -> Result<Self> {
+        let mut kind = None;
+        let mut data = None;
+        let mut filesize = None;
+        let mut blocksizes = Vec::new();
+
+        while !bytes.is_empty() {
+            match dagpb::read_key(&mut bytes)? {
+                (1, 0) => kind = Some(UnixFsType::from_code(dagpb::read_varint(&mut bytes)?)?),
+                (2, 2) => data = Some(dagpb::read_bytes(&mut bytes)?.to_vec()),
+                (3, 0) => filesize = Some(dagpb::read_varint(&mut bytes)?),
+                (4, 0) => blocksizes.push(dagpb::read_varint(&mut bytes)?),
+                (4, 2) => {
+                    // Packed encoding
+                    let mut packed = dagpb::read_bytes(&mut bytes)?;
+                    while !packed.is_empty() {
+                        blocksizes.push(dagpb::read_varint(&mut packed)?);
+                    }
+                }
+                (_, 0) => {
+                    dagpb::read_varint(&mut bytes)?;
+                }
+                (_, 2) => {
+                    dagpb::read_bytes(&mut bytes)?;
+                }
+                (field, wire) => {
+                    return Err(anyhow!(
+                        "Invalid UnixFS data: unexpected field {} (wire type {})",
+                        field,
+                        wire
+                    ))
+                }
+            }
+        }
+
+        Ok(UnixFsData {
+            kind: kind.ok_or_else(|| anyhow!("Invalid UnixFS data: missing type"))?,
+            data,
+            filesize,
+            blocksizes,
+        })
+    }
+}
+
+/// A serialized block and its CID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    /// CID of `data`
+    pub cid: ContentId,
+
+    /// Block bytes, as stored and transferred
+    pub data: Vec<u8>,
+}
+
+/// A UnixFS file or directory: its root CID and every block under it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnixFsDag {
+    /// CID of the root block
+    root: ContentId,
+
+    /// Root block size plus the cumulative sizes of its links
+    cumulative_size: u64,
+
+    /// Distinct blocks, children before parents; the root is last
+    blocks: Vec<Block>,
+}
+
+impl UnixFsDag {
+    /// Returns the root CID, as `ipfs add` prints it.
+    pub fn root_cid(&self) -> &ContentId {
+        &self.root
+    }
+
+    /// Returns the size of all blocks under the root, counted once per link
+    /// (the dag-pb `Tsize` of a link to this root).
+    pub fn cumulative_size(&self) -> u64 {
+        self.cumulative_size
+    }
+
+    /// Returns the distinct blocks, children before parents.
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// Consumes the DAG, returning its blocks.
+    pub fn into_blocks(self) -> Vec<Block> {
+        self.blocks
+    }
+
+    /// A DAG known only by its root, for linking content whose blocks are
+    /// stored elsewhere, such as a whole file addressed as one raw block.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{Codec, ContentId, UnixFsBuilder, UnixFsDag};
+    ///
+    /// let app = b"console.log('hi')";
+    /// let file = UnixFsDag::from_root(ContentId::new_v1(Codec::Raw, app), app.len() as u64);
+    ///
+    /// let dir = UnixFsBuilder::new().directory([("app.js", &file)]).unwrap();
+    /// assert_eq!(dir.blocks().len(), 1);
+    /// ```
+    pub fn from_root(root: ContentId, cumulative_size: u64) -> Self {
+        UnixFsDag {
+            root,
+            cumulative_size,
+            blocks: vec![],
+        }
+    }
+}
+
+/// A decoded UnixFS directory node, for listing entries and resolving paths.
+///
+/// # Example
+///
+/// ```rust
+/// use codio_content::{UnixFsBuilder, UnixFsDirectory};
+///
+/// let builder = UnixFsBuilder::new();
+/// let file = builder.file(b"<h1>hi</h1>").unwrap();
+/// let dir = builder.directory([("index.html", &file)]).unwrap();
+///
+/// let decoded = UnixFsDirectory::decode(&dir.blocks().last().unwrap().data).unwrap();
+/// assert_eq!(decoded.get("index.html").unwrap().hash, *file.root_cid());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnixFsDirectory {
+    /// Entries, sorted by name
+    entries: Vec<PbLink>,
+}
+
+impl UnixFsDirectory {
+    /// Decodes a directory block.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the block is not a dag-pb node with UnixFS
+    /// directory data, or has an entry without a name.
+    pub fn decode(block: &[u8]) -> Result<Self> {
+        let node = PbNode::decode(block)?;
+        let data = node
+            .data
+            .as_deref()
+            .ok_or_else(|| anyhow!("Not a UnixFS directory: node has no data"))?;
+        if UnixFsData::decode(data)?.kind != UnixFsType::Directory {
+            return Err(anyhow!("Not a UnixFS directory"));
+        }
+        if node.links.iter().any(|l| l.name.is_none()) {
+            return Err(anyhow!("Invalid UnixFS directory: unnamed entry"));
+        }
+
+        Ok(UnixFsDirectory {
+            entries: node.links,
+        })
+    }
+
+    /// Returns the entries, in block order (sorted by name).
+    pub fn entries(&self) -> &[PbLink] {
+        &self.entries
+    }
+
+    /// Looks up the entry called `name`.
+    pub fn get(&self, name: &str) -> Option<&PbLink> {
+        self.entries
+            .iter()
+            .find(|l| l.name.as_deref() == Some(name))
+    }
+}
+
+/// Whether `cid` can name a dag-pb node, such as a directory: dag-pb CIDv1.
+///
+/// CIDv0 content in this network is addressed as plain bytes, so it is
+/// never decoded as a node.
+pub fn is_dag_node(cid: &ContentId) -> bool {
+    cid.version() == Version::V1 && cid.codec() == Codec::DagPb
+}
+
+/// Splits a path under a root CID (`assets/app.js`) into entry names.
+///
+/// Empty segments and `.` are skipped.
+///
+/// # Errors
+///
+/// Returns an error if the path contains `..`.
+///
+/// # Example
+///
+/// ```rust
+/// use codio_content::split_unixfs_path;
+///
+/// assert_eq!(split_unixfs_path("/assets//app.js").unwrap(), ["assets", "app.js"]);
+/// assert!(split_unixfs_path("../etc/passwd").is_err());
+/// ```
+pub fn split_unixfs_path(path: &str) -> Result<Vec<&str>> {
+    let mut names = Vec::new();
+
+    for name in path.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => return Err(anyhow!("Paths may not contain '..'")),
+            name => names.push(name),
+        }
+    }
+
+    Ok(names)
+}
+
+/// Builds UnixFS files and directories.
+///
+/// Defaults match `ipfs add`: 256 KiB fixed-size chunks, balanced layout
+/// with 174 links per node, UnixFS leaves and CIDv0.
+///
+/// # Example
+///
+/// ```rust
+/// use codio_content::UnixFsBuilder;
+///
+/// let builder = UnixFsBuilder::new();
+/// let file = builder.file(b"hello world\n").unwrap();
+///
+/// // Same CID as `echo "hello world" | ipfs add`
+/// assert_eq!(
+///     file.root_cid().to_string(),
+///     "QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o"
+/// );
+///
+/// let dir = builder.directory([("hello.txt", &file)]).unwrap();
+/// assert_eq!(dir.blocks().len(), 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct UnixFsBuilder {
+    /// Splits files into leaves
+    chunker: Chunker,
+
+    /// Layout and fan-out of file nodes
+    dag: DagBuilder,
+
+    /// Store leaves as raw blocks instead of UnixFS nodes
+    raw_leaves: bool,
+
+    /// CID version of dag-pb nodes
+    version: Version,
+}
+
+impl UnixFsBuilder {
+    /// Creates a builder with the `ipfs add` defaults.
+    pub fn new() -> Self {
+        UnixFsBuilder {
+            chunker: Chunker::new(IPFS_CHUNK_SIZE),
+            dag: DagBuilder::new(),
+            raw_leaves: false,
+            version: Version::V0,
+        }
+    }
+
+    /// Splits files with `chunker`; its hash algorithm is used for every
+    /// block. The chunker must not compress.
+    pub fn with_chunker(mut self, chunker: Chunker) -> Self {
+        self.chunker = chunker;
+        self
+    }
+
+    /// Links file leaves with the layout and fan-out of `dag`.
+    pub fn with_dag_builder(mut self, dag: DagBuilder) -> Self {
+        self.dag = dag;
+        self
+    }
+
+    /// Stores leaves as raw blocks (raw-codec CIDv1) instead of UnixFS nodes.
+    pub fn with_raw_leaves(mut self, raw_leaves: bool) -> Self {
+        self.raw_leaves = raw_leaves;
+        self
+    }
+
+    /// Sets the CID version of dag-pb nodes. As with `ipfs add`, CIDv1 also
+    /// turns on raw leaves; call [`with_raw_leaves`](Self::with_raw_leaves)
+    /// afterwards to override.
+    ///
+    /// Hash functions other than sha2-256 always give CIDv1.
+    pub fn with_cid_version(mut self, version: Version) -> Self {
+        self.version = version;
+        self.raw_leaves = version == Version::V1;
+        self
+    }
+
+    /// Returns the chunker.
+    pub fn chunker(&self) -> &Chunker {
+        &self.chunker
+    }
+
+    /// Returns the DAG builder.
+    pub fn dag_builder(&self) -> &DagBuilder {
+        &self.dag
+    }
+
+    /// Returns whether leaves are raw blocks.
+    pub fn raw_leaves(&self) -> bool {
+        self.raw_leaves
+    }
+
+    /// Returns the CID version of dag-pb nodes.
+    pub fn cid_version(&self) -> Version {
+        self.version
+    }
+
+    /// Imports `data` as a UnixFS file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chunker compresses: UnixFS blocks hold the
+    /// file's own bytes.
+    pub fn file(&self, data: &[u8]) -> Result<UnixFsDag> {
+        if self.chunker.compression() != Compression::None {
+            return Err(anyhow!("UnixFS files cannot use chunk compression"));
+        }
+
+        let ranges = self.chunker.ranges(data);
+        let leaves: Vec<(Block, Link)> =
+            parallel::map(&ranges, self.chunker.parallelism(), |range| {
+                self.leaf(&data[range.clone()])
+            });
+
+        let mut blocks = Blocks::default();
+        let mut links = Vec::with_capacity(leaves.len());
+        for (block, link) in leaves {
+            blocks.push(block);
+            links.push(link);
+        }
+
+        // An empty balanced file is one empty leaf
+        if links.is_empty() && self.dag.layout() == DagLayout::Balanced {
+            let (block, leaf) = self.leaf(&[]);
+            blocks.push(block);
+            links.push(leaf);
+        }
+
+        Ok(self.link_leaves(links, blocks))
+    }
+
+    /// Imports a file read from `reader`, one chunk at a time, keeping only
+    /// its nodes.
+    ///
+    /// The leaves are returned with their sizes, in file order, instead of
+    /// among the DAG's blocks, so they can be served from where the file
+    /// lives. The root is the one [`file`](Self::file) gives.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails, the chunker compresses, or leaves
+    /// aren't raw (UnixFS leaf nodes would have to hold the file's bytes).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use codio_content::{Chunker, UnixFsBuilder, Version};
+    ///
+    /// let data = vec![7u8; 5000];
+    /// let builder = UnixFsBuilder::new()
+    ///     .with_chunker(Chunker::new(1024))
+    ///     .with_cid_version(Version::V1);
+    ///
+    /// let (file, leaves) = builder.file_reader(&data[..]).unwrap();
+    /// assert_eq!(file.root_cid(), builder.file(&data).unwrap().root_cid());
+    /// assert_eq!(leaves.iter().map(|(_, size)| size).sum::<u64>(), 5000);
+    /// assert_eq!(file.blocks().len(), 1);
+    /// ```
+    pub fn file_reader<R: std::io::Read>(
+        &self,
+        reader: R,
+    ) -> Result<(UnixFsDag, Vec<(ContentId, u64)>)> {
+        if self.chunker.compression() != Compression::None {
+            return Err(anyhow!("UnixFS files cannot use chunk compression"));
+        }
+        if !self.raw_leaves {
+            return Err(anyhow!("Streamed UnixFS files need raw leaves"));
+        }
+
+        let mut links = Vec::new();
+        for chunk in self.chunker.chunk_reader(reader) {
+            let (_, link) = self.leaf(&chunk?.data);
+            links.push(link);
+        }
+
+        // An empty balanced file is one empty leaf
+        if links.is_empty() && self.dag.layout() == DagLayout::Balanced {
+            links.push(self.leaf(&[]).1);
+        }
+
+        let leaves = links.iter().map(|l| (l.cid.clone(), l.filesize)).collect();
+        Ok((self.link_leaves(links, Blocks::default()), leaves))
+    }
+
+    /// Links file leaves into nodes, adding the nodes to `blocks`.
+    fn link_leaves(&self, mut links: Vec<Link>, mut blocks: Blocks) -> UnixFsDag {
+        let mut node = |links: Vec<Link>| self.file_node(links, &mut blocks);
+        let root = match self.dag.layout() {
+            // A single leaf is the whole file
+            DagLayout::Balanced if links.len() == 1 => links.remove(0),
+            DagLayout::Balanced => dag::balanced(&links, self.dag.max_links(), &mut node),
+            DagLayout::Trickle if links.is_empty() => node(vec![]),
+            DagLayout::Trickle => dag::trickle(&links, self.dag.max_links(), &mut node),
+        };
+
+        blocks.finish(root)
+    }
+
+    /// Creates a directory holding `entries` (name and file or directory).
+    ///
+    /// The result includes the blocks of every entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a name is empty, `.` or `..`, contains `/`, or
+    /// appears twice.
+    pub fn directory<'a, I>(&self, entries: I) -> Result<UnixFsDag>
+    where
+        I: IntoIterator<Item = (&'a str, &'a UnixFsDag)>,
+    {
+        let mut entries: Vec<_> = entries.into_iter().collect();
+        entries.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+
+        let mut blocks = Blocks::default();
+        let mut links = Vec::with_capacity(entries.len());
+        let mut tsize = 0;
+
+        for (i, (name, entry)) in entries.iter().enumerate() {
+            if name.is_empty() || *name == "." || *name == ".." || name.contains('/') {
+                return Err(anyhow!("Invalid directory entry name {:?}", name));
+            }
+            if i > 0 && entries[i - 1].0 == *name {
+                return Err(anyhow!("Duplicate directory entry {:?}", name));
+            }
+
+            for block in entry.blocks() {
+                blocks.push(block.clone());
+            }
+            links.push(PbLink {
+                hash: entry.root.clone(),
+                name: Some(name.to_string()),
+                tsize: Some(entry.cumulative_size),
+            });
+            tsize += entry.cumulative_size;
+        }
+
+        let unixfs = UnixFsData {
+            kind: UnixFsType::Directory,
+            data: None,
+            filesize: None,
+            blocksizes: vec![],
+        };
+        let node = PbNode {
+            links,
+            data: Some(unixfs.encode()),
+        };
+
+        let block = self.node_block(&node);
+        let root = Link {
+            cid: block.cid.clone(),
+            tsize: tsize + block.data.len() as u64,
+            filesize: 0,
+        };
+        blocks.push(block);
+
+        Ok(blocks.finish(root))
+    }
+
+    /// Creates nested directories holding files at `/`-separated paths.
+    ///
+    /// The result includes the blocks of every file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a path is empty or contains `..`, the same path
+    /// appears twice, or a path runs through a file.
+    pub fn directory_tree<'a, I>(&self, files: I) -> Result<UnixFsDag>
+    where
+        I: IntoIterator<Item = (&'a str, &'a UnixFsDag)>,
+    {
+        let mut root = Tree::default();
+
+        for (path, file) in files {
+            let names = split_unixfs_path(path)?;
+            let Some((name, parents)) = names.split_last() else {
+                return Err(anyhow!("Empty path"));
+            };
+
+            let mut dir = &mut root;
+            for parent in parents {
+                dir = match dir
+                    .entries
+                    .entry(parent.to_string())
+                    .or_insert_with(|| TreeEntry::Dir(Tree::default()))
+                {
+                    TreeEntry::Dir(dir) => dir,
+                    TreeEntry::File(_) => return Err(anyhow!("{:?} is a file", parent)),
+                };
+            }
+
+            if dir
+                .entries
+                .insert(name.to_string(), TreeEntry::File(file))
+                .is_some()
+            {
+                return Err(anyhow!("Duplicate path {:?}", path));
+            }
+        }
+
+        self.build_tree(&root)
+    }
+
+    fn build_tree(&self, tree: &Tree) -> Result<UnixFsDag> {
+        let mut dirs = Vec::new();
+        for (name, entry) in &tree.entries {
+            if let TreeEntry::Dir(dir) = entry {
+                dirs.push((name, self.build_tree(dir)?));
+            }
+        }
+
+        let files = tree.entries.iter().filter_map(|(name, entry)| match entry {
+            TreeEntry::File(file) => Some((name.as_str(), *file)),
+            TreeEntry::Dir(_) => None,
+        });
+        self.directory(
+            dirs.iter()
+                .map(|(name, dir)| (name.as_str(), dir))
+                .chain(files),
+        )
+    }
+
+    /// Leaf block holding `data`.
+    fn leaf(&self, data: &[u8]) -> (Block, Link) {
+        let block = if self.raw_leaves {
+            Block {
+                cid: ContentId::new_v1_with(Codec::Raw, self.algorithm(), data),
+                data: data.to_vec(),
+            }
+        } else {
+            // go-ipfs writes balanced leaves as files and trickle leaves as raw
+            let kind = match self.dag.layout() {
+                DagLayout::Balanced => UnixFsType::File,
+                DagLayout::Trickle => UnixFsType::Raw,
+            };
+            let unixfs = UnixFsData {
+                kind,
+                data: (!data.is_empty()).then(|| data.to_vec()),
+                filesize: Some(data.len() as u64),
+                blocksizes: vec![],
+            };
+            self.node_block(&PbNode {
+                links: vec![],
+                data: Some(unixfs.encode()),
+            })
+        };
+
+        let link = Link {
+            cid: block.cid.clone(),
+            tsize: block.data.len() as u64,
+            filesize: data.len() as u64,
+        };
+        (block, link)
+    }
+
+    /// File node over `links`.
+    fn file_node(&self, links: Vec<Link>, blocks: &mut Blocks) -> Link {
+        let blocksizes: Vec<u64> = links.iter().map(|l| l.filesize).collect();
+        let filesize = blocksizes.iter().sum();
+        let tsize: u64 = links.iter().map(|l| l.tsize).sum();
+
+        let unixfs = UnixFsData {
+            kind: UnixFsType::File,
+            data: None,
+            filesize: Some(filesize),
+            blocksizes,
+        };
+        let node = PbNode {
+            links: links
+                .into_iter()
+                .map(|l| PbLink {
+                    hash: l.cid,
+                    name: Some(String::new()),
+                    tsize: Some(l.tsize),
+                })
+                .collect(),
+            data: Some(unixfs.encode()),
+        };
+
+        let block = self.node_block(&node);
+        let link = Link {
+            cid: block.cid.clone(),
+            tsize: tsize + block.data.len() as u64,
+            filesize,
+        };
+        blocks.push(block);
+        link
+    }
+
+    /// Encodes a dag-pb node into a block.
+    fn node_block(&self, node: &PbNode) -> Block {
+        let data = node.encode();
+        let cid = match (self.version, self.algorithm()) {
+            (Version::V0, HashAlgorithm::Sha2_256) => ContentId::new(&data),
+            (_, algorithm) => ContentId::new_v1_with(Codec::DagPb, algorithm, &data),
+        };
+        Block { cid, data }
+    }
+
+    fn algorithm(&self) -> HashAlgorithm {
+        self.chunker.hash_algorithm()
+    }
+}
+
+impl Default for UnixFsBuilder {
+    fn default() -> Self {
+        UnixFsBuilder::new()
+    }
+}
+
+/// Directory tree being assembled by [`UnixFsBuilder::directory_tree`].
+#[derive(Default)]
+struct Tree<'a> {
+    entries: std::collections::BTreeMap<String, TreeEntry<'a>>,
+}
+
+enum TreeEntry<'a> {
+    File(&'a UnixFsDag),
+    Dir(Tree<'a>),
+}
+
+/// A block as seen from its parent.
+#[derive(Debug, Clone)]
+struct Link {
+    cid: ContentId,
+    /// Cumulative block size
+    tsize: u64,
+    /// File bytes below the link
+    filesize: u64,
+}
+
+/// Distinct blocks in creation order.
+#[derive(Default)]
+struct Blocks {
+    seen: HashSet<ContentId>,
+    blocks: Vec<Block>,
+}
+
+impl Blocks {
+    fn push(&mut self, block: Block) {
+        if self.seen.insert(block.cid.clone()) {
+            self.blocks.push(block);
+        }
+    }
+
+    fn finish(self, root: Link) -> UnixFsDag {
+        UnixFsDag {
+            root: root.cid,
+            cumulative_size: root.tsize,
+            blocks: self.blocks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(block: &Block) -> (PbNode, UnixFsData) {
+        let node = PbNode::decode(&block.data).unwrap();
+        let unixfs = UnixFsData::decode(node.data.as_deref().unwrap()).unwrap();
+        (node, unixfs)
+    }
+
+    #[test]
+    fn test_golden_single_block() {
+        let builder = UnixFsBuilder::new();
+
+        // `ipfs add` of small files and `ipfs object new unixfs-dir`
+        for (data, expected) in [
+            (&b""[..], "QmbFMke1KXqnYyBBWxB74N4c5SBnJMVAiMNRcGu6x1AwQH"),
+            (
+                b"hello world\n",
+                "QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o",
+            ),
+            (
+                b"Hello World\n",
+                "QmWATWQ7fVPP2EFGu71UkfnqhYXDYH566qy47CnJDgvs8u",
+            ),
+            (b"hello\n", "QmZULkCELmmk5XNfCgTnCyFgAVxBRBXyDHGGMVoLFLiXEN"),
+        ] {
+            let file = builder.file(data).unwrap();
+            assert_eq!(file.root_cid().to_string(), expected);
+            assert_eq!(file.blocks().len(), 1);
+        }
+
+        let empty = builder.directory([]).unwrap();
+        assert_eq!(
+            empty.root_cid().to_string(),
+            "QmUNLLsPACCz1vLxQVkXqqLX5R1X345qqfHbsf67hvA3Nn"
+        );
+        assert_eq!(empty.cumulative_size(), 4);
+    }
+
+    #[test]
+    fn test_golden_cidv1() {
+        // `ipfs add --cid-version=1`: raw leaves, so a small file is its leaf
+        let builder = UnixFsBuilder::new().with_cid_version(Version::V1);
+
+        for (data, expected) in [
+            (
+                &b"hello world"[..],
+                "bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e",
+            ),
+            (
+                b"hello world\n",
+                "bafkreifjjcie6lypi6ny7amxnfftagclbuxndqonfipmb64f2km2devei4",
+            ),
+        ] {
+            assert_eq!(builder.file(data).unwrap().root_cid().to_string(), expected);
+        }
+
+        assert_eq!(
+            builder.directory([]).unwrap().root_cid().to_string(),
+            "bafybeiczsscdsbs7ffqz55asqdf3smv6klcw3gofszvwlyarci47bgf354"
+        );
+    }
+
+    #[test]
+    fn test_golden_multi_block() {
+        // go-ipfs `t0040-add-and-cat.sh`: `random 5242880 41`, 20 leaves
+        let data = crate::go_random::bytes(5 * 1024 * 1024, 41);
+        let file = UnixFsBuilder::new().file(&data).unwrap();
+        assert_eq!(
+            file.root_cid().to_string(),
+            "QmSr7FqYkxYWGoSfy8ZiaMWQ5vosb18DQGCzjwEQnVHkTb"
+        );
+        let (root, unixfs) = decode(file.blocks().last().unwrap());
+        assert_eq!(root.links.len(), 20);
+        assert_eq!(unixfs.blocksizes, [IPFS_CHUNK_SIZE as u64; 20]);
+
+        let raw = UnixFsBuilder::new()
+            .with_raw_leaves(true)
+            .file(&data)
+            .unwrap();
+        assert_eq!(
+            raw.root_cid().to_string(),
+            "QmbdLHCmdi48eM8T7D67oXjA1S2Puo8eMfngdHhdPukFd6"
+        );
+
+        // Streamed, the nodes are the same and the leaves are left out
+        let builder = UnixFsBuilder::new().with_raw_leaves(true);
+        let (streamed, leaves) = builder.file_reader(&data[..]).unwrap();
+        assert_eq!(streamed.root_cid(), raw.root_cid());
+        assert_eq!(streamed.cumulative_size(), raw.cumulative_size());
+        assert_eq!(streamed.blocks(), &raw.blocks()[20..]);
+        assert_eq!(leaves.len(), 20);
+        assert!(leaves
+            .iter()
+            .zip(raw.blocks())
+            .all(|((cid, size), block)| { *cid == block.cid && *size == block.data.len() as u64 }));
+    }
+
+    #[test]
+    fn test_golden_two_levels() {
+        // `random 104857600 42`: 400 leaves, past the 174 links of a node
+        let data = crate::go_random::bytes(100 * 1024 * 1024, 42);
+        let file = UnixFsBuilder::new().file(&data).unwrap();
+        assert_eq!(
+            file.root_cid().to_string(),
+            "QmU9SWAPPmNEKZB8umYMmjYvN7VyHqABNvdA6GUi4MMEz3"
+        );
+        let (root, unixfs) = decode(file.blocks().last().unwrap());
+        assert_eq!(root.links.len(), 3);
+        assert_eq!(
+            unixfs.blocksizes,
+            [
+                174 * IPFS_CHUNK_SIZE as u64,
+                174 * IPFS_CHUNK_SIZE as u64,
+                52 * IPFS_CHUNK_SIZE as u64
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multi_block_file() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let builder = UnixFsBuilder::new()
+            .with_chunker(Chunker::new(1024))
+            .with_dag_builder(DagBuilder::new().with_max_links(4));
+        let file = builder.file(&data).unwrap();
+
+        // 10 leaves -> 3 nodes -> root
+        assert_eq!(file.blocks().len(), 10 + 3 + 1);
+        assert_eq!(file.blocks().last().unwrap().cid, *file.root_cid());
+
+        let (root, unixfs) = decode(file.blocks().last().unwrap());
+        assert_eq!(unixfs.kind, UnixFsType::File);
+        assert_eq!(unixfs.filesize, Some(10_000));
+        assert_eq!(unixfs.blocksizes, [4096, 4096, 1808]);
+        assert!(unixfs.data.is_none());
+        assert!(root.links.iter().all(|l| l.name.as_deref() == Some("")));
+
+        // Cumulative sizes add up to every block once
+        let total: u64 = file.blocks().iter().map(|b| b.data.len() as u64).sum();
+        assert_eq!(file.cumulative_size(), total);
+        let tsizes: u64 = root.links.iter().map(|l| l.tsize.unwrap()).sum();
+        assert_eq!(
+            file.cumulative_size(),
+            tsizes + file.blocks()[13].data.len() as u64
+        );
+
+        // Leaves are files holding their bytes
+        let (_, leaf) = decode(&file.blocks()[0]);
+        assert_eq!(leaf.kind, UnixFsType::File);
+        assert_eq!(leaf.data.as_deref(), Some(&data[..1024]));
+    }
+
+    #[test]
+    fn test_trickle_and_raw_leaves() {
+        let data: Vec<u8> = (0..10_000u32).map(|i| (i % 253) as u8).collect();
+        let trickle = DagBuilder::new()
+            .with_layout(DagLayout::Trickle)
+            .with_max_links(2);
+        let builder = UnixFsBuilder::new()
+            .with_chunker(Chunker::new(1024))
+            .with_dag_builder(trickle);
+
+        let file = builder.file(&data).unwrap();
+        let (_, leaf) = decode(&file.blocks()[0]);
+        assert_eq!(leaf.kind, UnixFsType::Raw);
+
+        let (root, unixfs) = decode(file.blocks().last().unwrap());
+        assert_eq!(unixfs.filesize, Some(10_000));
+        assert_eq!(root.links.len(), unixfs.blocksizes.len());
+
+        // Raw leaves are the chunk bytes themselves
+        let raw = builder.clone().with_raw_leaves(true).file(&data).unwrap();
+        assert_eq!(raw.blocks()[0].data, &data[..1024]);
+        assert_eq!(raw.blocks()[0].cid.codec(), Codec::Raw);
+        assert_eq!(raw.root_cid().version(), Version::V0);
+        assert_ne!(raw.root_cid(), file.root_cid());
+
+        // Even one chunk gets a trickle root, like go-ipfs
+        let small = builder.file(b"tiny").unwrap();
+        assert_eq!(small.blocks().len(), 2);
+    }
+
+    #[test]
+    fn test_hash_algorithm_forces_cidv1() {
+        let builder = UnixFsBuilder::new()
+            .with_chunker(Chunker::new(1024).with_hash_algorithm(HashAlgorithm::Blake3));
+        let file = builder.file(&[7u8; 5000]).unwrap();
+
+        assert_eq!(file.root_cid().version(), Version::V1);
+        assert_eq!(file.root_cid().codec(), Codec::DagPb);
+        assert_eq!(file.root_cid().algorithm(), HashAlgorithm::Blake3);
+
+        // Identical chunks are stored once
+        assert_eq!(file.blocks().len(), 3);
+    }
+
+    #[test]
+    fn test_directory() {
+        let builder = UnixFsBuilder::new();
+        let a = builder.file(b"a").unwrap();
+        let b = builder.file(b"b").unwrap();
+        let sub = builder.directory([("b.txt", &b)]).unwrap();
+        let dir = builder
+            .directory([("sub", &sub), ("a.txt", &a), ("a2.txt", &a)])
+            .unwrap();
+
+        // Sorted by name, blocks shared between entries stored once
+        let (node, unixfs) = decode(dir.blocks().last().unwrap());
+        let names: Vec<_> = node
+            .links
+            .iter()
+            .map(|l| l.name.as_deref().unwrap())
+            .collect();
+        assert_eq!(names, ["a.txt", "a2.txt", "sub"]);
+        assert_eq!(unixfs.kind, UnixFsType::Directory);
+        assert_eq!(dir.blocks().len(), 4);
+        assert_eq!(node.links[2].tsize, Some(sub.cumulative_size()));
+
+        assert!(builder.directory([("", &a)]).is_err());
+        assert!(builder.directory([("x/y", &a)]).is_err());
+        assert!(builder.directory([("..", &a)]).is_err());
+        assert!(builder.directory([("a", &a), ("a", &b)]).is_err());
+    }
+
+    #[test]
+    fn test_directory_tree() {
+        let builder = UnixFsBuilder::new().with_cid_version(Version::V1);
+        let app = builder.file(b"app").unwrap();
+        let css = builder.file(b"css").unwrap();
+        let index = builder.file(b"index").unwrap();
+
+        let tree = builder
+            .directory_tree([
+                ("assets/app.js", &app),
+                ("/assets/css/site.css", &css),
+                ("index.html", &index),
+            ])
+            .unwrap();
+
+        // Same as building each level by hand
+        let css_dir = builder.directory([("site.css", &css)]).unwrap();
+        let assets = builder
+            .directory([("app.js", &app), ("css", &css_dir)])
+            .unwrap();
+        let root = builder
+            .directory([("assets", &assets), ("index.html", &index)])
+            .unwrap();
+        assert_eq!(tree.root_cid(), root.root_cid());
+        assert_eq!(tree.cumulative_size(), root.cumulative_size());
+
+        assert!(builder
+            .directory_tree([("a", &app), ("a/b", &css)])
+            .is_err());
+        assert!(builder
+            .directory_tree([("a/b", &app), ("a", &css)])
+            .is_err());
+        assert!(builder
+            .directory_tree([("a", &app), ("./a", &css)])
+            .is_err());
+        assert!(builder.directory_tree([("/", &app)]).is_err());
+    }
+
+    #[test]
+    fn test_directory_decode() {
+        let builder = UnixFsBuilder::new();
+        let file = builder.file(b"x").unwrap();
+        let external = UnixFsDag::from_root(ContentId::new_v1(Codec::Raw, b"big"), 3);
+        let dir = builder
+            .directory([("x.txt", &file), ("big.bin", &external)])
+            .unwrap();
+
+        let decoded = UnixFsDirectory::decode(&dir.blocks().last().unwrap().data).unwrap();
+        let names: Vec<_> = decoded
+            .entries()
+            .iter()
+            .map(|l| l.name.as_deref().unwrap())
+            .collect();
+        assert_eq!(names, ["big.bin", "x.txt"]);
+        assert_eq!(decoded.get("big.bin").unwrap().hash, *external.root_cid());
+        assert_eq!(decoded.get("big.bin").unwrap().tsize, Some(3));
+        assert!(decoded.get("missing").is_none());
+
+        // Files and other bytes are not directories
+        assert!(UnixFsDirectory::decode(&file.blocks()[0].data).is_err());
+        assert!(UnixFsDirectory::decode(b"plain bytes").is_err());
+    }
+
+    #[test]
+    fn test_rejects_compression() {
+        let builder = UnixFsBuilder::new()
+            .with_chunker(Chunker::new(1024).with_compression(Compression::Zstd));
+        assert!(builder.file(b"data").is_err());
+    }
+
+    #[test]
+    fn test_unixfs_data_decode() {
+        // Packed blocksizes and skipped mode/mtime fields
+        let bytes = [
+            0x08, 0x02, 0x18, 0x05, 0x22, 0x02, 0x02, 0x03, 0x38, 0xa4, 0x03,
+        ];
+        let data = UnixFsData::decode(&bytes).unwrap();
+        assert_eq!(data.filesize, Some(5));
+        assert_eq!(data.blocksizes, [2, 3]);
+
+        assert!(UnixFsData::decode(&[0x18, 0x05]).is_err());
+        assert!(UnixFsData::decode(&[0x08, 0x05]).is_err());
+    }
+}
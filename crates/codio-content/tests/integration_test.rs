@@ -3,8 +3,10 @@
 //! These tests verify the end-to-end functionality of the content addressing system.
 
 use codio_content::{
-    config::ContentConfig, hash_sha256, hash_sha256_hex, verify_hash, Chunk, Chunker, Content,
-    ContentId, MerkleDAG, DEFAULT_CHUNK_SIZE,
+    config::ContentConfig, hash_sha256, hash_sha256_hex, verify_hash, Capability, Car, CarVersion,
+    Chunk, Chunker, Codec, Compression, Content, ContentId, ContentKey, DagBuilder, DagLayout,
+    ErasureCoding, HashAlgorithm, MerkleDAG, PbNode, UnixFsBuilder, UnixFsData, UnixFsType,
+    Version, DEFAULT_CHUNK_SIZE,
 };
 
 // ============================================================================
@@ -23,6 +25,26 @@ fn test_cid_generation() {
     assert!(cid.verify(content));
 }
 
+#[test]
+fn test_cid_verify_dispatches_on_hash_function() {
+    let content = b"Hello, Codio CDN!";
+
+    for algorithm in [
+        HashAlgorithm::Sha2_256,
+        HashAlgorithm::Sha2_512,
+        HashAlgorithm::Sha3_256,
+        HashAlgorithm::Sha3_512,
+        HashAlgorithm::Blake3,
+    ] {
+        let cid = ContentId::new_with(algorithm, content);
+        let parsed: ContentId = cid.to_string().parse().unwrap();
+
+        assert_eq!(parsed.algorithm(), algorithm);
+        assert!(parsed.verify(content));
+        assert!(!parsed.verify(b"Tampered"));
+    }
+}
+
 #[test]
 fn test_cid_deterministic() {
     let content = b"Test content";
@@ -192,7 +214,10 @@ fn test_merkle_dag_root_cid() {
     let dag = MerkleDAG::from_chunks(&chunks);
     let root_cid = dag.root_cid();
 
-    assert!(root_cid.to_string().starts_with("Qm"));
+    // Internal nodes are hash-list CIDv1 (base32), chunks stay CIDv0
+    assert_eq!(root_cid.codec(), Codec::HashList);
+    assert!(root_cid.to_string().starts_with('b'));
+    assert!(chunks[0].cid.to_string().starts_with("Qm"));
 }
 
 #[test]
@@ -224,6 +249,53 @@ fn test_merkle_dag_verify_tampered_chunk() {
     assert!(!dag.verify(&chunks));
 }
 
+#[test]
+fn test_merkle_dag_many_chunks_stays_small() {
+    let data: Vec<u8> = (0..40_000u32).flat_map(|i| i.to_le_bytes()).collect();
+    let chunks = Chunker::new(1024).chunk(&data);
+    assert_eq!(chunks.len(), 157);
+
+    for layout in [DagLayout::Balanced, DagLayout::Trickle] {
+        let dag = DagBuilder::new()
+            .with_layout(layout)
+            .with_max_links(8)
+            .build(&chunks);
+
+        assert!(dag.depth() >= 3);
+        assert!(dag.verify(&chunks));
+
+        // Corrupting one chunk fails the walk from the root
+        let mut corrupted = chunks.clone();
+        corrupted[100].data[0] ^= 0xff;
+        assert!(!dag.verify(&corrupted));
+    }
+}
+
+#[test]
+fn test_chunk_inclusion_proofs() {
+    // Compressed content-defined chunks in a multi-level trickle DAG
+    let data: Vec<u8> = (0..400_000u32)
+        .flat_map(|i| (i / 3).to_le_bytes())
+        .collect();
+    let chunker = Chunker::content_defined(1024, 4096, 16384).with_compression(Compression::Lz4);
+    let builder = DagBuilder::new()
+        .with_layout(DagLayout::Trickle)
+        .with_max_links(8);
+    let content = Content::build(data, &chunker, &builder);
+    let root = content.root_cid();
+
+    for (i, chunk) in content.chunks().iter().enumerate() {
+        let proof = content.prove(i as u32).unwrap();
+        assert!(builder.verify_proof(root, i as u32, &chunk.data, &proof));
+    }
+
+    // A chunk from other content doesn't prove against this root
+    let other = Chunker::new(4096).chunk(b"something else");
+    let proof = content.prove(0).unwrap();
+    assert!(!builder.verify_proof(root, 0, &other[0].data, &proof));
+    assert!(content.prove(content.chunks().len() as u32).is_err());
+}
+
 // ============================================================================
 // Corruption Detection Tests
 // ============================================================================
@@ -374,15 +446,13 @@ fn test_binary_content() {
 #[test]
 fn test_random_content() {
     use std::collections::hash_map::RandomState;
-    use std::hash::{BuildHasher, Hash, Hasher};
+    use std::hash::BuildHasher;
 
     let mut data = Vec::new();
     let hasher_builder = RandomState::new();
 
     for i in 0..10000 {
-        let mut hasher = hasher_builder.build_hasher();
-        i.hash(&mut hasher);
-        data.push((hasher.finish() % 256) as u8);
+        data.push((hasher_builder.hash_one(i) % 256) as u8);
     }
 
     let content = Content::new(data.clone(), 1024);
@@ -390,6 +460,147 @@ fn test_random_content() {
     assert_eq!(data, reconstructed);
 }
 
+// ============================================================================
+// Compression Tests
+// ============================================================================
+
+#[test]
+fn test_compressed_content_roundtrip() {
+    let text = "<div class=\"item\">Lorem ipsum dolor sit amet</div>\n".repeat(20_000);
+    let data = text.into_bytes();
+
+    for codec in [Compression::Zstd, Compression::Lz4] {
+        let config = ContentConfig::with_compression_codec(64 * 1024, codec);
+        let content = Content::from_config(data.clone(), &config).unwrap();
+
+        assert!(content.verify());
+        assert!(content.chunks().iter().all(|c| c.compression == codec));
+        assert!(content.stored_size() < data.len() / 4);
+        assert_eq!(content.size(), data.len());
+        assert_eq!(content.reconstruct().unwrap(), data);
+    }
+}
+
+#[test]
+fn test_compressed_chunks_rebuild_from_parts() {
+    let data = b"compressible payload ".repeat(10_000);
+    let config = ContentConfig::with_compression(16 * 1024);
+    let content = Content::from_config(data.clone(), &config).unwrap();
+
+    // As a receiver would: chunks and DAG arrive separately
+    let rebuilt = Content::from_parts(content.dag().clone(), content.chunks().to_vec()).unwrap();
+    assert_eq!(rebuilt.size(), data.len());
+    assert_eq!(rebuilt.reconstruct().unwrap(), data);
+}
+
+#[test]
+fn test_compression_changes_chunk_cids() {
+    let data = b"same bytes, different representation ".repeat(1000);
+
+    let plain = Content::new(data.clone(), 4096);
+    let packed = Content::from_config(data, &ContentConfig::with_compression(4096)).unwrap();
+
+    // Chunk CIDs address the stored bytes
+    assert_ne!(plain.root_cid(), packed.root_cid());
+    assert_ne!(plain.chunks()[0].cid, packed.chunks()[0].cid);
+}
+
+#[test]
+fn test_compressed_chunk_with_wrong_codec_fails() {
+    let data = b"abcabcabc".repeat(1000);
+    let chunker = Chunker::new(4096).with_compression(Compression::Zstd);
+    let mut chunks = chunker.chunk(&data);
+
+    // The CID still matches, but the data is not lz4
+    chunks[0].compression = Compression::Lz4;
+    assert!(chunks[0].verify());
+    assert!(chunker.reconstruct(chunks).is_err());
+}
+
+// ============================================================================
+// Erasure Coding Tests
+// ============================================================================
+
+#[test]
+fn test_erasure_coded_content_survives_lost_chunks() {
+    let data: Vec<u8> = (0..300_000u32).map(|i| (i * 7 % 253) as u8).collect();
+    let mut config = ContentConfig::with_erasure_coding(16 * 1024, ErasureCoding::new(6, 3));
+    config.max_links = 4;
+    let content = Content::from_config(data.clone(), &config).unwrap();
+
+    // 19 data chunks: stripes of 6 + 3 (three times) and 1 + 3
+    assert_eq!(content.num_chunks(), 31);
+    assert_eq!(content.dag().num_children(), 31);
+    assert_eq!(content.dag().erasure_coding(), config.erasure_coding);
+    assert!(content.verify());
+
+    // Parity chunks are leaves like any other, with proofs to the root
+    let builder = DagBuilder::from_config(&config).unwrap();
+    let parity = content.get_chunk(8).unwrap();
+    let proof = content.prove(8).unwrap();
+    assert!(builder.verify_proof(content.root_cid(), 8, &parity.data, &proof));
+
+    // Lose 3 chunks of every stripe, data and parity alike
+    let kept: Vec<Chunk> = content
+        .chunks()
+        .iter()
+        .filter(|c| ![0, 4, 8, 9, 10, 11, 27, 28, 29].contains(&c.index))
+        .cloned()
+        .collect();
+    let partial = Content::from_parts(content.dag().clone(), kept.clone()).unwrap();
+    assert_eq!(partial.size(), data.len());
+    assert_eq!(partial.reconstruct().unwrap(), data);
+
+    // The DAG survives serialization with its coding
+    let json = serde_json::to_string(content.dag()).unwrap();
+    let dag: MerkleDAG = serde_json::from_str(&json).unwrap();
+    assert_eq!(dag.erasure_coding(), config.erasure_coding);
+
+    // One more lost chunk and the content can't be rebuilt
+    let too_few: Vec<Chunk> = kept.into_iter().filter(|c| c.index != 1).collect();
+    assert!(Content::from_parts(dag, too_few).is_err());
+}
+
+// ============================================================================
+// Encryption Tests
+// ============================================================================
+
+#[test]
+fn test_encrypted_content_hides_plaintext() {
+    let data = b"<p>licensed asset</p>\n".repeat(20_000);
+    let chunker = Chunker::new(64 * 1024).with_compression(Compression::Zstd);
+    let builder = DagBuilder::new().with_erasure_coding(ErasureCoding::new(2, 1));
+
+    let plain = Content::build(data.clone(), &chunker, &builder);
+    let key = ContentKey::random();
+    let content = Content::build_encrypted(data.clone(), &chunker, &builder, &key);
+
+    // Ciphertext chunks: nothing in common with the plaintext DAG, still verifiable
+    assert_ne!(content.root_cid(), plain.root_cid());
+    assert!(content.verify());
+    assert!(content
+        .chunks()
+        .iter()
+        .all(|c| !plain.chunk_cids().contains(&&c.cid)));
+
+    // Lose a chunk, then decrypt through the capability
+    let kept = content.chunks()[1..].to_vec();
+    let partial = Content::from_parts(content.dag().clone(), kept).unwrap();
+    let capability: Capability = Capability::new(content.root_cid().clone(), key)
+        .to_string()
+        .parse()
+        .unwrap();
+    assert_eq!(partial.decrypt(&capability.key).unwrap(), data);
+    assert!(partial.decrypt(&ContentKey::random()).is_err());
+
+    // Convergent keys let identical files deduplicate
+    let key = ContentKey::convergent(&data);
+    let a = Content::build_encrypted(data.clone(), &chunker, &DagBuilder::new(), &key);
+    let b = Content::build_encrypted(data.clone(), &chunker, &DagBuilder::new(), &key);
+    assert_eq!(a.root_cid(), b.root_cid());
+    assert_eq!(a.decrypt(&key).unwrap(), data);
+}
+
 // ============================================================================
 // ContentConfig Tests
 // ============================================================================
@@ -524,6 +735,75 @@ fn test_ipfs_cid_decode() {
     assert_eq!(original.hash(), decoded.hash());
 }
 
+#[test]
+fn test_unixfs_matches_ipfs_add() {
+    let builder = UnixFsBuilder::new();
+    let file = builder.file(b"hello world\n").unwrap();
+    assert_eq!(
+        file.root_cid().to_string(),
+        "QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o"
+    );
+
+    let raw = builder
+        .clone()
+        .with_cid_version(Version::V1)
+        .file(b"hello world")
+        .unwrap();
+    assert_eq!(
+        raw.root_cid().to_string(),
+        "bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e"
+    );
+}
+
+#[test]
+fn test_unixfs_blocks_decode() {
+    // 1MB in 256 KiB leaves: every block is a valid node and the root
+    // records the file size
+    let data: Vec<u8> = (0..1024 * 1024u32).map(|i| (i % 241) as u8).collect();
+    let file = UnixFsBuilder::new().file(&data).unwrap();
+    assert_eq!(file.blocks().len(), 5);
+
+    let mut leaves = Vec::new();
+    for block in file.blocks() {
+        assert!(block.cid.verify(&block.data));
+
+        let node = PbNode::decode(&block.data).unwrap();
+        assert_eq!(node.encode(), block.data);
+
+        let unixfs = UnixFsData::decode(node.data.as_deref().unwrap()).unwrap();
+        assert_eq!(unixfs.kind, UnixFsType::File);
+        if node.links.is_empty() {
+            leaves.extend_from_slice(unixfs.data.as_deref().unwrap());
+        } else {
+            assert_eq!(unixfs.filesize, Some(data.len() as u64));
+            assert_eq!(unixfs.blocksizes, [262144; 4]);
+        }
+    }
+    assert_eq!(leaves, data);
+}
+
+#[test]
+fn test_car_roundtrip_directory() {
+    // A multi-block file in a directory survives a CAR round trip, and
+    // both versions carry the same blocks
+    let builder = UnixFsBuilder::new();
+    let data: Vec<u8> = (0..600 * 1024u32).map(|i| (i % 251) as u8).collect();
+    let file = builder.file(&data).unwrap();
+    let dir = builder.directory([("big.bin", &file)]).unwrap();
+    let car = Car::from_dag(&dir);
+
+    let v1 = car.to_vec(CarVersion::V1);
+    let v2 = car.to_vec(CarVersion::V2);
+    assert!(v2.len() > v1.len());
+
+    for bytes in [v1, v2] {
+        let read = Car::decode(&bytes).unwrap();
+        assert_eq!(read.roots(), [dir.root_cid().clone()]);
+        assert_eq!(read.blocks(), dir.blocks());
+        assert!(read.get(file.root_cid()).is_some());
+    }
+}
+
 // ============================================================================
 // Performance Smoke Tests
 // ============================================================================
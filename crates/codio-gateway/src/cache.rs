@@ -4,7 +4,7 @@ use std::num::NonZeroUsize;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-/// LRU cache for hot content
+/// LRU cache for hot content, keyed by hash like `ContentStorage`
 #[derive(Clone)]
 pub struct ContentCache {
     cache: Arc<RwLock<LruCache<String, Vec<u8>>>>,
@@ -30,7 +30,7 @@ impl ContentCache {
 
     /// Put content in cache
     pub async fn put(&self, cid: &ContentId, content: Vec<u8>) {
-        let cid_str = cid.as_str().to_string();
+        let key = cid.hash_hex();
         let content_size = content.len();
 
         // Evict items if needed
@@ -45,21 +45,30 @@ impl ContentCache {
         }
 
         // Insert new item
-        self.cache.write().await.put(cid_str, content.clone());
+        self.cache.write().await.put(key, content.clone());
         let mut size = self.current_size.write().await;
         *size += content_size;
     }
 
     /// Get content from cache
     pub async fn get(&self, cid: &ContentId) -> Option<Vec<u8>> {
-        let cid_str = cid.as_str();
-        self.cache.write().await.get(cid_str).cloned()
+        let key = cid.hash_hex();
+        self.cache.write().await.get(&key).cloned()
+    }
+
+    /// Remove content from cache
+    pub async fn remove(&self, cid: &ContentId) {
+        let key = cid.hash_hex();
+        if let Some(evicted) = self.cache.write().await.pop(&key) {
+            let mut size = self.current_size.write().await;
+            *size = size.saturating_sub(evicted.len());
+        }
     }
 
     /// Check if content is in cache
     pub async fn contains(&self, cid: &ContentId) -> bool {
-        let cid_str = cid.as_str();
-        self.cache.read().await.contains(cid_str)
+        let key = cid.hash_hex();
+        self.cache.read().await.contains(&key)
     }
 
     /// Get cache size in bytes
@@ -118,4 +127,16 @@ mod tests {
         cache.put(&cid, content).await;
         assert!(cache.contains(&cid).await);
     }
+
+    #[tokio::test]
+    async fn test_cache_remove() {
+        let cache = ContentCache::new(1024 * 1024);
+        let content = b"Test".to_vec();
+        let cid = ContentId::new(&content);
+
+        cache.put(&cid, content).await;
+        cache.remove(&cid.to_v1()).await;
+        assert!(!cache.contains(&cid).await);
+        assert_eq!(cache.size().await, 0);
+    }
 }
@@ -1,3 +1,6 @@
+use codio_common::RateLimits;
+use std::path::PathBuf;
+
 /// Gateway configuration
 #[derive(Debug, Clone)]
 pub struct GatewayConfig {
@@ -9,6 +12,13 @@ pub struct GatewayConfig {
     pub max_upload_size: u64,
     /// DHT listen address
     pub dht_addr: String,
+    /// Directory to keep content in; in memory when unset
+    pub data_dir: Option<PathBuf>,
+    /// Bearer token for the `/admin` endpoints; disabled when unset
+    pub admin_token: Option<String>,
+    /// Bandwidth limits; uploads are content sent to clients, downloads
+    /// files posted by them, and the per-peer limits apply per client address
+    pub rate_limits: RateLimits,
 }
 
 impl Default for GatewayConfig {
@@ -18,6 +28,9 @@ impl Default for GatewayConfig {
             cache_size: 100 * 1024 * 1024,      // 100MB
             max_upload_size: 100 * 1024 * 1024, // 100MB
             dht_addr: "/ip4/0.0.0.0/tcp/0".to_string(),
+            data_dir: None,
+            admin_token: None,
+            rate_limits: RateLimits::unlimited(),
         }
     }
 }
@@ -30,6 +43,27 @@ impl GatewayConfig {
             cache_size,
             max_upload_size,
             dht_addr: "/ip4/0.0.0.0/tcp/0".to_string(),
+            data_dir: None,
+            admin_token: None,
+            rate_limits: RateLimits::unlimited(),
         }
     }
+
+    /// Keep content on disk in `dir`, so it survives restarts
+    pub fn with_data_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.data_dir = Some(dir.into());
+        self
+    }
+
+    /// Limit the bandwidth used serving and receiving content
+    pub fn with_rate_limits(mut self, limits: RateLimits) -> Self {
+        self.rate_limits = limits;
+        self
+    }
+
+    /// Enable the `/admin` endpoints for requests bearing `token`
+    pub fn with_admin_token(mut self, token: impl Into<String>) -> Self {
+        self.admin_token = Some(token.into());
+        self
+    }
 }
@@ -10,5 +10,8 @@ pub mod storage;
 pub use cache::ContentCache;
 pub use config::GatewayConfig;
 pub use metrics::Metrics;
-pub use response::{ErrorResponse, HealthResponse, MetricsResponse, UploadResponse};
+pub use response::{
+    DirectoryEntry, DirectoryListing, ErrorResponse, GcResponse, HealthResponse, MetricsResponse,
+    PinEntry, PinListResponse, UploadResponse,
+};
 pub use storage::ContentStorage;
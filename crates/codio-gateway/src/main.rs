@@ -1,26 +1,45 @@
-mod cache;
-mod config;
-mod metrics;
-mod response;
-mod storage;
-
 use axum::{
-    extract::{Multipart, Path, State},
-    http::{header, StatusCode},
-    response::{IntoResponse, Json, Response},
+    body::Body,
+    extract::{ConnectInfo, Multipart, Path, Query, State},
+    http::{header, HeaderMap, Request, StatusCode},
+    response::{IntoResponse, Json, Redirect, Response},
     routing::{get, post},
     Router,
 };
-use cache::ContentCache;
+use bytes::Bytes;
+use codio_blockstore::{ChunkSlice, ChunkedFile, Pin, PinMode, PinSet};
+use codio_common::{Direction, RateLimiter, RateLimits};
+use codio_content::{
+    is_dag_node, split_unixfs_path, Capability, Codec, ContentKey, UnixFsBuilder, UnixFsDag,
+    UnixFsDirectory, Version, ENCRYPTED_SEGMENT_SIZE, SEALED_SEGMENT_SIZE,
+};
 use codio_content_id::ContentId;
-use config::GatewayConfig;
-use metrics::Metrics;
-use response::{ErrorResponse, HealthResponse, MetricsResponse, UploadResponse};
+use codio_gateway::{
+    ContentCache, ContentStorage, DirectoryEntry, DirectoryListing, ErrorResponse, GatewayConfig,
+    GcResponse, HealthResponse, Metrics, MetricsResponse, PinEntry, PinListResponse,
+    UploadResponse,
+};
+use futures::stream::{self, BoxStream};
+use futures::StreamExt;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
-use storage::ContentStorage;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+use tokio_util::io::{ReaderStream, StreamReader};
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::trace::TraceLayer;
 
+/// Pin file in the data directory
+const PINS_FILE: &str = "pins.json";
+
+/// Bytes a paced response body is sent in
+const PACED_PIECE_SIZE: usize = 16 * 1024;
+
 /// Gateway state shared across handlers
 #[derive(Clone)]
 struct GatewayState {
@@ -30,19 +49,35 @@ struct GatewayState {
     cache: ContentCache,
     /// Metrics tracker
     metrics: Metrics,
+    /// Content kept through garbage collection. Held for writing while
+    /// content is stored and pinned, and for reading while collecting, so
+    /// a collection never removes an upload before it is pinned.
+    pins: Arc<tokio::sync::RwLock<PinSet>>,
+    /// Bandwidth limits, per client address
+    limiter: Arc<RateLimiter<IpAddr>>,
     /// Configuration
     config: GatewayConfig,
 }
 
 impl GatewayState {
     /// Create new gateway state
-    fn new(config: GatewayConfig) -> Self {
-        Self {
-            storage: ContentStorage::new(),
+    fn new(config: GatewayConfig) -> anyhow::Result<Self> {
+        let (storage, pins) = match &config.data_dir {
+            Some(dir) => (
+                ContentStorage::open(dir)?,
+                PinSet::open(dir.join(PINS_FILE))?,
+            ),
+            None => (ContentStorage::new(), PinSet::new()),
+        };
+
+        Ok(Self {
+            storage,
             cache: ContentCache::new(config.cache_size),
             metrics: Metrics::new(),
+            pins: Arc::new(tokio::sync::RwLock::new(pins)),
+            limiter: Arc::new(RateLimiter::new(config.rate_limits)),
             config,
-        }
+        })
     }
 }
 
@@ -57,7 +92,14 @@ async fn main() -> anyhow::Result<()> {
         .init();
 
     // Load configuration
-    let config = GatewayConfig::default();
+    let mut config = GatewayConfig::default();
+    if let Some(dir) = std::env::var_os("CODIO_GATEWAY_DATA_DIR") {
+        config = config.with_data_dir(dir);
+    }
+    if let Ok(token) = std::env::var("CODIO_GATEWAY_ADMIN_TOKEN") {
+        config = config.with_admin_token(token);
+    }
+    config = config.with_rate_limits(rate_limits_from_env()?);
     let port = config.port;
 
     tracing::info!("Starting Codio Gateway on port {}", port);
@@ -66,69 +108,216 @@ async fn main() -> anyhow::Result<()> {
         "Max upload size: {} MB",
         config.max_upload_size / (1024 * 1024)
     );
+    match &config.data_dir {
+        Some(dir) => tracing::info!("Storing content in {}", dir.display()),
+        None => tracing::info!("Storing content in memory"),
+    }
+    if !config.rate_limits.is_unlimited() {
+        tracing::info!("Bandwidth limits: {:?}", config.rate_limits);
+    }
 
     // Create gateway state
-    let state = GatewayState::new(config);
+    let state = GatewayState::new(config)?;
+    let admin_enabled = state.config.admin_token.is_some();
 
     // Build router
-    let app = Router::new()
-        .route("/upload", post(upload_handler))
-        .route("/:cid", get(download_handler))
-        .route("/metrics", get(metrics_handler))
-        .route("/health", get(health_handler))
+    let app = app(state)
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
                 .allow_methods(Any)
                 .allow_headers(Any),
         )
-        .layer(TraceLayer::new_for_http())
-        .with_state(state);
+        .layer(TraceLayer::new_for_http().make_span_with(request_span));
 
     // Start server
     let addr = format!("0.0.0.0:{}", port);
     let listener = tokio::net::TcpListener::bind(&addr).await?;
     tracing::info!("Gateway listening on {}", addr);
     tracing::info!("API endpoints:");
-    tracing::info!("  POST /upload     - Upload file, get CID");
-    tracing::info!("  GET  /<cid>      - Download content by CID");
+    tracing::info!("  POST /upload       - Upload file or directory, get CID");
+    tracing::info!("  GET  /<cid>        - Download content by CID");
+    tracing::info!("  GET  /<cid>/<path> - Download a file inside a directory");
     tracing::info!("  GET  /metrics    - Prometheus metrics");
     tracing::info!("  GET  /health     - Health check");
+    if admin_enabled {
+        tracing::info!("  /admin/pins, /admin/gc - Pinning and garbage collection");
+        tracing::info!("  /admin/rate-limits    - Bandwidth limits");
+    }
 
-    axum::serve(listener, app).await?;
+    // Client addresses key the per-client bandwidth limits
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
 
+/// Tracing span for a request, with any capability key in its path
+/// redacted
+fn request_span(request: &Request<Body>) -> tracing::Span {
+    let uri = request.uri();
+    let path = redact_capability(uri.path());
+    let query = uri.query().map(|query| format!("?{}", query));
+    tracing::debug_span!(
+        "request",
+        method = %request.method(),
+        uri = %format_args!("{}{}", path, query.unwrap_or_default()),
+        version = ?request.version(),
+    )
+}
+
+/// `path` with the key of a capability in its first segment replaced, so
+/// keys stay out of logs. The separator may be percent-encoded, as routing
+/// decodes it.
+fn redact_capability(path: &str) -> Cow<'_, str> {
+    let start = path.len() - path.trim_start_matches('/').len();
+    let end = path[start..].find('/').map_or(path.len(), |i| start + i);
+    let segment = &path[start..end];
+    let separator = segment
+        .find(':')
+        .or_else(|| segment.to_ascii_lowercase().find("%3a"));
+    match separator {
+        Some(i) => Cow::Owned(format!("{}:<key>{}", &path[..start + i], &path[end..])),
+        None => Cow::Borrowed(path),
+    }
+}
+
+/// Gateway routes
+fn app(state: GatewayState) -> Router {
+    Router::new()
+        .route("/upload", post(upload_handler))
+        .route("/:cid", get(download_handler))
+        .route("/:cid/", get(directory_handler))
+        .route("/:cid/*path", get(path_handler))
+        .route("/metrics", get(metrics_handler))
+        .route("/health", get(health_handler))
+        .route("/admin/pins", get(list_pins_handler))
+        .route(
+            "/admin/pins/:cid",
+            post(add_pin_handler).delete(remove_pin_handler),
+        )
+        .route("/admin/gc", post(gc_handler))
+        .route(
+            "/admin/rate-limits",
+            get(get_rate_limits_handler).put(set_rate_limits_handler),
+        )
+        .with_state(state)
+}
+
+/// Read bandwidth limits in bytes per second from the environment
+///
+/// `CODIO_GATEWAY_UPLOAD_LIMIT` and `CODIO_GATEWAY_DOWNLOAD_LIMIT` cap all
+/// clients together, `CODIO_GATEWAY_PEER_UPLOAD_LIMIT` and
+/// `CODIO_GATEWAY_PEER_DOWNLOAD_LIMIT` each client address. Uploads are the
+/// content the gateway sends, downloads the files posted to it.
+fn rate_limits_from_env() -> anyhow::Result<RateLimits> {
+    let var = |name: &str| -> anyhow::Result<u64> {
+        match std::env::var(name) {
+            Ok(value) => value
+                .parse()
+                .map_err(|_| anyhow::anyhow!("{} must be bytes per second, got {:?}", name, value)),
+            Err(_) => Ok(0),
+        }
+    };
+    Ok(RateLimits {
+        upload: var("CODIO_GATEWAY_UPLOAD_LIMIT")?,
+        download: var("CODIO_GATEWAY_DOWNLOAD_LIMIT")?,
+        peer_upload: var("CODIO_GATEWAY_PEER_UPLOAD_LIMIT")?,
+        peer_download: var("CODIO_GATEWAY_PEER_DOWNLOAD_LIMIT")?,
+    })
+}
+
+/// Address of the client, or the unspecified address when it isn't known
+/// (as for requests that don't come through a listener)
+fn client_ip(client: Option<ConnectInfo<SocketAddr>>) -> IpAddr {
+    client
+        .map(|ConnectInfo(addr)| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+}
+
+/// Send `response`'s body no faster than the upload limits allow `client`
+fn paced(state: &GatewayState, client: IpAddr, response: Response) -> Response {
+    if state.limiter.limits().is_unlimited() {
+        return response;
+    }
+
+    let limiter = Arc::clone(&state.limiter);
+    response.map(|body| {
+        let pieces = body.into_data_stream().flat_map(|frame| {
+            let pieces = match frame {
+                Ok(bytes) => (0..bytes.len())
+                    .step_by(PACED_PIECE_SIZE)
+                    .map(|start| Ok(bytes.slice(start..bytes.len().min(start + PACED_PIECE_SIZE))))
+                    .collect(),
+                Err(e) => vec![Err(e)],
+            };
+            stream::iter(pieces)
+        });
+        Body::from_stream(pieces.then(move |piece| {
+            let limiter = Arc::clone(&limiter);
+            async move {
+                if let Ok(bytes) = &piece {
+                    limiter
+                        .acquire(Direction::Upload, &client, bytes.len() as u64)
+                        .await;
+                }
+                piece
+            }
+        }))
+    })
+}
+
 /// Upload content and get CID
 ///
 /// Endpoint: POST /upload
 /// Content-Type: multipart/form-data
-/// Field: file
+/// Field: file (repeat it, with paths as file names, to upload a directory)
+/// Field: encrypt (optional) - "random" or "convergent" to store a single
+/// file encrypted
 ///
-/// Returns: JSON with CID, size, and chunks
+/// Returns: JSON with CID, size, and chunks, and the capability of an
+/// encrypted file
 async fn upload_handler(
     State(state): State<GatewayState>,
+    client: Option<ConnectInfo<SocketAddr>>,
     mut multipart: Multipart,
 ) -> Result<Json<UploadResponse>, AppError> {
     tracing::debug!("Upload request received");
+    let client = client_ip(client);
 
-    // Read file from multipart
-    let mut content: Option<Vec<u8>> = None;
+    // Read files from multipart, with their names
+    let mut files: Vec<(Option<String>, Vec<u8>)> = Vec::new();
+    let mut encrypt = None;
 
-    while let Some(field) = multipart.next_field().await? {
+    while let Some(mut field) = multipart.next_field().await? {
         let name = field.name().unwrap_or("");
         if name == "file" {
-            let data = field.bytes().await?;
-            content = Some(data.to_vec());
-            break;
+            let file_name = field.file_name().map(str::to_string);
+            // Read at the pace the download limits allow
+            let mut data = Vec::new();
+            while let Some(piece) = field.chunk().await? {
+                state
+                    .limiter
+                    .acquire(Direction::Download, &client, piece.len() as u64)
+                    .await;
+                data.extend_from_slice(&piece);
+            }
+            files.push((file_name, data));
+        } else if name == "encrypt" {
+            encrypt = Some(field.text().await?);
         }
     }
 
-    let content = content
-        .ok_or_else(|| AppError::BadRequest("Missing 'file' field in multipart".to_string()))?;
+    if files.is_empty() {
+        return Err(AppError::BadRequest(
+            "Missing 'file' field in multipart".to_string(),
+        ));
+    }
 
-    let size = content.len() as u64;
+    let size: u64 = files.iter().map(|(_, data)| data.len() as u64).sum();
 
     // Check size limit
     if size > state.config.max_upload_size {
@@ -138,78 +327,557 @@ async fn upload_handler(
         )));
     }
 
-    // Store content and generate CID
-    let cid = state
-        .storage
-        .store(content.clone())
-        .await
-        .map_err(|e| AppError::Internal(e.to_string()))?;
+    // Several files, or one with a path, make a directory
+    let is_directory =
+        files.len() > 1 || files[0].0.as_deref().is_some_and(|name| name.contains('/'));
 
-    // Cache the content
-    state.cache.put(&cid, content).await;
+    // Encrypted files are stored as ciphertext; the key is only returned
+    let key = match encrypt.as_deref() {
+        None => None,
+        Some(_) if is_directory => {
+            return Err(AppError::BadRequest(
+                "Only single files can be encrypted".to_string(),
+            ))
+        }
+        Some("random") => Some(ContentKey::random()),
+        Some("convergent") => Some(ContentKey::convergent(&files[0].1)),
+        Some(other) => {
+            return Err(AppError::BadRequest(format!(
+                "Unknown encryption {:?}, expected \"random\" or \"convergent\"",
+                other
+            )))
+        }
+    };
+
+    // Uploads are pinned, so garbage collection keeps them
+    let mut pins = state.pins.write().await;
+    let cid = if is_directory {
+        store_directory(&state, files).await?
+    } else {
+        let (_, mut content) = files.pop().expect("one file");
+        if let Some(key) = &key {
+            content = key.encrypt(&content);
+        }
+
+        // Stored chunk by chunk, so ranges read only the chunks they cover
+        state
+            .storage
+            .store_chunked(&content)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+    };
+    pins.add(cid.clone(), PinMode::Recursive, None)
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    drop(pins);
 
     // Record metrics
     state.metrics.record_upload().await;
 
     tracing::info!("Uploaded content: {} ({} bytes)", cid, size);
 
-    Ok(Json(UploadResponse::new(cid.to_string(), size)))
+    let response = UploadResponse::new(cid.to_string(), size);
+    Ok(Json(match key {
+        Some(key) => response.with_capability(Capability::new(cid, key).to_string()),
+        None => response,
+    }))
+}
+
+/// Store uploaded files and the directory nodes linking them by path.
+///
+/// Each file is stored whole, linked as a raw block; the directory root
+/// CID is returned.
+async fn store_directory(
+    state: &GatewayState,
+    files: Vec<(Option<String>, Vec<u8>)>,
+) -> Result<ContentId, AppError> {
+    let mut entries = Vec::with_capacity(files.len());
+    for (name, data) in &files {
+        let name = name.as_deref().ok_or_else(|| {
+            AppError::BadRequest("Files uploaded together need file names".to_string())
+        })?;
+        let cid = ContentId::new_v1(Codec::Raw, data);
+        entries.push((name, UnixFsDag::from_root(cid, data.len() as u64)));
+    }
+
+    // Check the paths before storing anything
+    let builder = UnixFsBuilder::new().with_cid_version(Version::V1);
+    let dir = builder
+        .directory_tree(entries.iter().map(|(name, dag)| (*name, dag)))
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let blocks = files
+        .into_iter()
+        .map(|(_, data)| data)
+        .chain(dir.blocks().iter().map(|block| block.data.clone()));
+    for data in blocks {
+        state
+            .storage
+            .store(data)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+    }
+
+    Ok(dir.root_cid().clone())
 }
 
 /// Download content by CID
 ///
-/// Endpoint: GET /{cid}
+/// Endpoint: GET /{cid}, or GET /{cid}:{key} to decrypt an encrypted file
 ///
-/// Returns: Content bytes with appropriate Content-Type header
+/// Returns: Content bytes with appropriate Content-Type header, or the
+/// bytes a `Range` header asks for
 async fn download_handler(
     State(state): State<GatewayState>,
+    client: Option<ConnectInfo<SocketAddr>>,
     Path(cid_str): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let response = serve(&state, &cid_str, "", &headers).await?;
+    Ok(paced(&state, client_ip(client), response))
+}
+
+/// Directory by CID
+///
+/// Endpoint: GET /{cid}/
+///
+/// Returns: The directory's index.html, or a JSON listing
+async fn directory_handler(
+    State(state): State<GatewayState>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    Path(cid_str): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let response = serve(&state, &cid_str, "/", &headers).await?;
+    Ok(paced(&state, client_ip(client), response))
+}
+
+/// Download a file inside a directory
+///
+/// Endpoint: GET /{cid}/{path}
+///
+/// Returns: Content bytes, or for directories as for GET /{cid}/
+async fn path_handler(
+    State(state): State<GatewayState>,
+    client: Option<ConnectInfo<SocketAddr>>,
+    Path((cid_str, path)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let response = serve(&state, &cid_str, &path, &headers).await?;
+    Ok(paced(&state, client_ip(client), response))
+}
+
+/// Resolve `path` below `cid_str` and respond with what it names, or the
+/// part of it the request's `Range` header asks for
+async fn serve(
+    state: &GatewayState,
+    cid_str: &str,
+    path: &str,
+    headers: &HeaderMap,
 ) -> Result<Response, AppError> {
-    tracing::debug!("Download request for CID: {}", cid_str);
+    tracing::debug!(
+        "Download request for {}/{}",
+        redact_capability(cid_str),
+        path
+    );
+
+    if Capability::is_capability(cid_str) {
+        return serve_decrypted(state, cid_str, path, headers).await;
+    }
 
-    // Parse CID
-    let cid = ContentId::from_str(&cid_str)
+    // Parse CID (legacy strings resolve to the same content)
+    let root = ContentId::from_str(cid_str)
         .map_err(|e| AppError::BadRequest(format!("Invalid CID: {}", e)))?;
+    let names = split_unixfs_path(path).map_err(|e| AppError::BadRequest(e.to_string()))?;
+    let requested = std::iter::once(cid_str)
+        .chain(names.iter().copied())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    // Follow the path through directory nodes
+    let mut cid = root;
+    for name in &names {
+        let content = load(state, &cid)
+            .await
+            .ok_or_else(|| AppError::NotFound(requested.clone()))?;
+        cid = directory(&cid, &content)
+            .and_then(|dir| dir.get(name).map(|entry| entry.hash.clone()))
+            .ok_or_else(|| AppError::NotFound(requested.clone()))?;
+    }
+
+    if let Some(file) = state.storage.chunked(&cid) {
+        let name = names.last().copied().unwrap_or(cid_str);
+        return serve_chunked(state, &file, name, headers).await;
+    }
+
+    let Some(content) = load(state, &cid).await else {
+        // Content not found
+        tracing::warn!("Content not found: {}", requested);
+        return Err(AppError::NotFound(requested));
+    };
+
+    let Some(dir) = directory(&cid, &content) else {
+        let name = names.last().copied().unwrap_or(cid_str);
+        state.metrics.record_download(content.len() as u64).await;
+        return Ok(build_response(&cid, name, content, headers));
+    };
+
+    // Directories are served with a trailing slash, so relative links in
+    // their pages resolve inside them
+    if !path.ends_with('/') {
+        let mut location = format!("/{}/", cid_str);
+        for name in &names {
+            location.push_str(&encode_path_segment(name));
+            location.push('/');
+        }
+        return Ok(Redirect::permanent(&location).into_response());
+    }
+
+    if let Some(index) = dir.get("index.html") {
+        let content = load(state, &index.hash)
+            .await
+            .ok_or_else(|| AppError::NotFound(format!("{}/index.html", requested)))?;
+        state.metrics.record_download(content.len() as u64).await;
+        return Ok(build_response(&index.hash, "index.html", content, headers));
+    }
 
+    let entries = dir
+        .entries()
+        .iter()
+        .map(|entry| DirectoryEntry {
+            name: entry.name.clone().unwrap_or_default(),
+            cid: entry.hash.to_string(),
+            size: entry.tsize.unwrap_or(0),
+        })
+        .collect();
+    Ok(Json(DirectoryListing {
+        cid: cid.to_string(),
+        entries,
+    })
+    .into_response())
+}
+
+/// Respond with the file a capability names, decrypted
+///
+/// Only the ciphertext is stored and cached; the plaintext is marked
+/// uncacheable so shared HTTP caches don't keep it either.
+async fn serve_decrypted(
+    state: &GatewayState,
+    capability: &str,
+    path: &str,
+    headers: &HeaderMap,
+) -> Result<Response, AppError> {
+    let capability = Capability::from_str(capability)
+        .map_err(|e| AppError::BadRequest(format!("Invalid capability: {}", e)))?;
+    if !path.is_empty() {
+        return Err(AppError::BadRequest(
+            "Capabilities name files; paths below them aren't supported".to_string(),
+        ));
+    }
+
+    let cid = capability.cid;
+    let forbidden = || AppError::Forbidden(format!("The key does not decrypt {}", cid));
+    // Chunked ciphertext is read in part, a single block whole
+    let file = state.storage.chunked(&cid);
+    let whole = match file {
+        Some(_) => Bytes::new(),
+        None => load(state, &cid)
+            .await
+            .map(Bytes::from)
+            .ok_or_else(|| AppError::NotFound(cid.to_string()))?,
+    };
+    let size = file.as_ref().map_or(whole.len() as u64, |file| file.size);
+    let len = ContentKey::decrypted_len(size).ok_or_else(forbidden)?;
+
+    let range = ByteRange::requested(headers, len);
+    let bytes = match &range {
+        ByteRange::Full => 0..len,
+        ByteRange::Partial(range) => range.clone(),
+        ByteRange::Unsatisfiable => {
+            return Ok(uncacheable(ranged_response(
+                &cid,
+                "",
+                len,
+                &range,
+                Body::empty(),
+            )))
+        }
+    };
+
+    // Only the segments holding the response are read and decrypted, one
+    // at a time as the body is sent
+    let segment = ENCRYPTED_SEGMENT_SIZE as u64;
+    let sealed = SEALED_SEGMENT_SIZE as u64;
+    let first = bytes.start / segment;
+    let last = bytes.end.saturating_sub(1).max(bytes.start) / segment;
+    let ciphertext = first * sealed..((last + 1) * sealed).min(size);
+    let pieces = match &file {
+        Some(file) => chunk_stream(state, file, ciphertext).await?,
+        None => {
+            let piece = whole.slice(ciphertext.start as usize..ciphertext.end as usize);
+            stream::iter([Ok(piece)]).boxed()
+        }
+    };
+    let reader = capability
+        .key
+        .decrypt_reader(StreamReader::new(pieces))
+        .from_segment(first, size);
+    let mut reader = tokio::io::BufReader::new(reader);
+
+    // Open the first segment before responding, so a wrong key is an
+    // error response rather than a cut-off body
+    let decrypt_error = |e: io::Error| match e.kind() {
+        io::ErrorKind::InvalidData => forbidden(),
+        _ => AppError::Internal(e.to_string()),
+    };
+    let skip = bytes.start - first * segment;
+    tokio::io::copy(&mut (&mut reader).take(skip), &mut tokio::io::sink())
+        .await
+        .map_err(decrypt_error)?;
+    let head = reader.fill_buf().await.map_err(decrypt_error)?;
+    let mime_type = match bytes.start {
+        0 => detect_mime_type(cid.as_str(), head),
+        _ => detect_mime_type(cid.as_str(), &[]),
+    };
+
+    state.metrics.record_download(bytes.end - bytes.start).await;
+    let body = Body::from_stream(ReaderStream::new(reader.take(bytes.end - bytes.start)));
+    Ok(uncacheable(ranged_response(
+        &cid, &mime_type, len, &range, body,
+    )))
+}
+
+/// Mark `response` as not to be stored by any cache
+fn uncacheable(mut response: Response) -> Response {
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        header::HeaderValue::from_static("private, no-store"),
+    );
+    response
+}
+
+/// Respond with a file stored chunk by chunk, or the part of it the
+/// `Range` header in `headers` asks for
+///
+/// Only the chunks the response covers are read, one at a time as the body
+/// is sent. The MIME type is sniffed from the first chunk when the response
+/// starts with it, and guessed from `name` otherwise.
+async fn serve_chunked(
+    state: &GatewayState,
+    file: &ChunkedFile,
+    name: &str,
+    headers: &HeaderMap,
+) -> Result<Response, AppError> {
+    let range = ByteRange::requested(headers, file.size);
+    let bytes = match &range {
+        ByteRange::Full => 0..file.size,
+        ByteRange::Partial(range) => range.clone(),
+        ByteRange::Unsatisfiable => {
+            return Ok(ranged_response(
+                &file.cid,
+                "",
+                file.size,
+                &range,
+                Body::empty(),
+            ))
+        }
+    };
+
+    let mut pieces = chunk_stream(state, file, bytes.clone()).await?;
+    // The first piece is read up front, so a missing file is an error
+    // response rather than a cut-off body
+    let first = pieces
+        .next()
+        .await
+        .transpose()
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let head = match (bytes.start, &first) {
+        (0, Some(first)) => &first[..],
+        _ => &[],
+    };
+    let mime_type = detect_mime_type(name, head);
+
+    state.metrics.record_download(bytes.end - bytes.start).await;
+    let body = Body::from_stream(stream::iter(first.map(Ok)).chain(pieces));
+    Ok(ranged_response(
+        &file.cid, &mime_type, file.size, &range, body,
+    ))
+}
+
+/// Bytes `range` of chunked file `file`, read one chunk at a time as the
+/// stream is polled
+async fn chunk_stream(
+    state: &GatewayState,
+    file: &ChunkedFile,
+    range: Range<u64>,
+) -> Result<BoxStream<'static, io::Result<Bytes>>, AppError> {
+    let slices = state
+        .storage
+        .chunk_slices(file, range)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let state = state.clone();
+    Ok(stream::iter(slices)
+        .then(move |slice| {
+            let state = state.clone();
+            async move { read_slice(&state, slice).await }
+        })
+        .boxed())
+}
+
+/// Read the bytes of a chunk that `slice` covers
+async fn read_slice(state: &GatewayState, slice: ChunkSlice) -> io::Result<Bytes> {
+    let chunk = load(state, &slice.cid)
+        .await
+        .ok_or_else(|| io::Error::other(format!("Chunk {} is missing", slice.cid)))?;
+    Ok(Bytes::from(chunk).slice(slice.range))
+}
+
+/// Look up content in the cache, then in storage
+async fn load(state: &GatewayState, cid: &ContentId) -> Option<Vec<u8>> {
     // Try cache first
-    if let Some(content) = state.cache.get(&cid).await {
+    if let Some(content) = state.cache.get(cid).await {
         tracing::debug!("Cache HIT for {}", cid);
         state.metrics.record_cache_hit().await;
-        state.metrics.record_download(content.len() as u64).await;
-
-        return Ok(build_response(&cid_str, content));
+        return Some(content);
     }
 
     // Cache miss - try storage
     state.metrics.record_cache_miss().await;
 
-    if let Some(content) = state.storage.retrieve(&cid).await {
-        tracing::debug!("Storage HIT for {}", cid);
+    let content = state.storage.retrieve(cid).await?;
+    tracing::debug!("Storage HIT for {}", cid);
 
-        // Add to cache for future requests
-        state.cache.put(&cid, content.clone()).await;
-        state.metrics.record_download(content.len() as u64).await;
+    // Add to cache for future requests
+    state.cache.put(cid, content.clone()).await;
+    Some(content)
+}
 
-        return Ok(build_response(&cid_str, content));
+/// Decode `content` as a directory if `cid` can name one
+fn directory(cid: &ContentId, content: &[u8]) -> Option<UnixFsDirectory> {
+    if !is_dag_node(cid) {
+        return None;
     }
+    UnixFsDirectory::decode(content).ok()
+}
 
-    // Content not found
-    tracing::warn!("Content not found: {}", cid);
-    Err(AppError::NotFound(cid_str))
+/// Percent-encode a directory entry name for a URL path
+fn encode_path_segment(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                out.push(byte as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
 }
 
-/// Build HTTP response with content
-fn build_response(cid: &str, content: Vec<u8>) -> Response {
+/// Build HTTP response with content, or the part of it the `Range` header
+/// in `headers` asks for; `name` guides MIME detection
+fn build_response(cid: &ContentId, name: &str, content: Vec<u8>, headers: &HeaderMap) -> Response {
     // Detect MIME type
-    let mime_type = detect_mime_type(cid, &content);
-
-    Response::builder()
-        .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, mime_type)
-        .header(header::CONTENT_LENGTH, content.len())
-        .header("X-Content-ID", cid)
-        .body(axum::body::Body::from(content))
-        .unwrap()
+    let mime_type = detect_mime_type(name, &content);
+    let len = content.len() as u64;
+
+    let range = ByteRange::requested(headers, len);
+    let content = Bytes::from(content);
+    let body = match &range {
+        ByteRange::Partial(range) => content.slice(range.start as usize..range.end as usize),
+        _ => content,
+    };
+    ranged_response(cid, &mime_type, len, &range, Body::from(body))
+}
+
+/// Build the HTTP response sending `body`, the bytes `range` picks from
+/// content `cid` of `len` bytes
+fn ranged_response(
+    cid: &ContentId,
+    mime_type: &str,
+    len: u64,
+    range: &ByteRange,
+    body: Body,
+) -> Response {
+    let response = Response::builder()
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header("X-Content-ID", cid.as_str());
+    let response = match range {
+        ByteRange::Full => response
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, mime_type)
+            .header(header::CONTENT_LENGTH, len)
+            .body(body),
+        ByteRange::Partial(range) => response
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(header::CONTENT_TYPE, mime_type)
+            .header(header::CONTENT_LENGTH, range.end - range.start)
+            .header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", range.start, range.end - 1, len),
+            )
+            .body(body),
+        ByteRange::Unsatisfiable => response
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+            .body(axum::body::Body::empty()),
+    };
+    response.unwrap()
+}
+
+/// What a request's `Range` header asks of a response body
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    /// No range, or one the gateway ignores: send the whole body
+    Full,
+    /// Send these bytes
+    Partial(std::ops::Range<u64>),
+    /// The range starts past the end of the body
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    /// Parse the `Range` header in `headers` for a body of `len` bytes
+    ///
+    /// Supports a single `bytes=start-end`, `bytes=start-` or `bytes=-suffix`
+    /// range. Malformed and multi-part ranges are ignored, as HTTP allows.
+    fn requested(headers: &HeaderMap, len: u64) -> Self {
+        let Some(spec) = headers
+            .get(header::RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().strip_prefix("bytes="))
+        else {
+            return ByteRange::Full;
+        };
+        let Some((start, end)) = spec.trim().split_once('-') else {
+            return ByteRange::Full;
+        };
+
+        if start.is_empty() {
+            return match end.parse::<u64>() {
+                Ok(0) => ByteRange::Unsatisfiable,
+                Ok(_) if len == 0 => ByteRange::Unsatisfiable,
+                Ok(suffix) => ByteRange::Partial(len.saturating_sub(suffix)..len),
+                Err(_) => ByteRange::Full,
+            };
+        }
+
+        let Ok(start) = start.parse::<u64>() else {
+            return ByteRange::Full;
+        };
+        let end = match end {
+            "" => u64::MAX,
+            end => match end.parse::<u64>() {
+                Ok(end) if end >= start => end,
+                _ => return ByteRange::Full,
+            },
+        };
+
+        if start >= len {
+            return ByteRange::Unsatisfiable;
+        }
+        ByteRange::Partial(start..end.saturating_add(1).min(len))
+    }
 }
 
 /// Detect MIME type from content and filename
@@ -227,6 +895,222 @@ fn detect_mime_type(cid: &str, content: &[u8]) -> String {
         .unwrap_or_else(|| "application/octet-stream".to_string())
 }
 
+/// Check the request's bearer token against the configured admin token
+fn authorize(state: &GatewayState, headers: &HeaderMap) -> Result<(), AppError> {
+    let Some(token) = &state.config.admin_token else {
+        return Err(AppError::Forbidden("Admin API is disabled".to_string()));
+    };
+
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    // Compare every byte so timing doesn't reveal how much matched
+    let matches = given.is_some_and(|given| {
+        given.len() == token.len()
+            && given
+                .bytes()
+                .zip(token.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    });
+    match matches {
+        true => Ok(()),
+        false => Err(AppError::Unauthorized),
+    }
+}
+
+fn pin_entry(pin: &Pin) -> PinEntry {
+    PinEntry {
+        cid: pin.cid.to_string(),
+        mode: pin.mode.as_str().to_string(),
+        name: pin.name.clone(),
+    }
+}
+
+fn parse_cid(cid_str: &str) -> Result<ContentId, AppError> {
+    ContentId::from_str(cid_str).map_err(|e| AppError::BadRequest(format!("Invalid CID: {}", e)))
+}
+
+/// List pins
+///
+/// Endpoint: GET /admin/pins
+/// Authorization: Bearer {admin token}
+///
+/// Returns: JSON with the pinned CIDs, modes and names
+async fn list_pins_handler(
+    State(state): State<GatewayState>,
+    headers: HeaderMap,
+) -> Result<Json<PinListResponse>, AppError> {
+    authorize(&state, &headers)?;
+
+    let pins = state.pins.read().await;
+    Ok(Json(PinListResponse {
+        pins: pins.iter().map(pin_entry).collect(),
+    }))
+}
+
+/// Query parameters for pinning
+#[derive(Debug, Deserialize)]
+struct PinQuery {
+    /// "recursive" (default) or "direct"
+    mode: Option<PinMode>,
+    /// Label for the pin
+    name: Option<String>,
+}
+
+/// Pin stored content
+///
+/// Endpoint: POST /admin/pins/{cid}?mode=recursive|direct&name={name}
+/// Authorization: Bearer {admin token}
+///
+/// Returns: JSON with the pin; 404 unless everything it keeps is stored
+async fn add_pin_handler(
+    State(state): State<GatewayState>,
+    headers: HeaderMap,
+    Path(cid_str): Path<String>,
+    Query(query): Query<PinQuery>,
+) -> Result<Json<PinEntry>, AppError> {
+    authorize(&state, &headers)?;
+    let cid = parse_cid(&cid_str)?;
+    let mode = query.mode.unwrap_or(PinMode::Recursive);
+
+    let mut pins = state.pins.write().await;
+    let missing = match mode {
+        PinMode::Direct => match state.storage.contains(&cid).await {
+            true => 0,
+            false => 1,
+        },
+        PinMode::Recursive => state
+            .storage
+            .missing_blocks(&cid)
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))?
+            .len(),
+    };
+    if missing > 0 {
+        return Err(AppError::NotFound(format!(
+            "{} ({} blocks missing)",
+            cid_str, missing
+        )));
+    }
+
+    pins.add(cid.clone(), mode, query.name)
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    tracing::info!("Pinned {} ({})", cid, mode.as_str());
+
+    let pin = pins.get(&cid).expect("just pinned");
+    Ok(Json(pin_entry(pin)))
+}
+
+/// Unpin content; it is removed by the next garbage collection
+///
+/// Endpoint: DELETE /admin/pins/{cid}
+/// Authorization: Bearer {admin token}
+///
+/// Returns: JSON with the removed pin, or 404 if the CID isn't pinned
+async fn remove_pin_handler(
+    State(state): State<GatewayState>,
+    headers: HeaderMap,
+    Path(cid_str): Path<String>,
+) -> Result<Json<PinEntry>, AppError> {
+    authorize(&state, &headers)?;
+    let cid = parse_cid(&cid_str)?;
+
+    let pin = state
+        .pins
+        .write()
+        .await
+        .remove(&cid)
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .ok_or_else(|| AppError::NotFound(format!("{} (not pinned)", cid_str)))?;
+    tracing::info!("Unpinned {}", cid);
+
+    Ok(Json(pin_entry(&pin)))
+}
+
+/// Query parameters for garbage collection
+#[derive(Debug, Deserialize)]
+struct GcQuery {
+    /// Report what would be removed without removing it
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Remove stored content no pin reaches
+///
+/// Endpoint: POST /admin/gc?dry_run=true|false
+/// Authorization: Bearer {admin token}
+///
+/// Returns: JSON with the blocks and bytes removed (or removable)
+async fn gc_handler(
+    State(state): State<GatewayState>,
+    headers: HeaderMap,
+    Query(query): Query<GcQuery>,
+) -> Result<Json<GcResponse>, AppError> {
+    authorize(&state, &headers)?;
+
+    let pins = state.pins.read().await;
+    let report = state
+        .storage
+        .collect_garbage(&pins, query.dry_run)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    drop(pins);
+
+    if !report.dry_run {
+        for cid in &report.removed {
+            state.cache.remove(cid).await;
+        }
+    }
+    tracing::info!(
+        "Garbage collection{}: {} blocks, {} bytes",
+        if report.dry_run { " (dry run)" } else { "" },
+        report.removed.len(),
+        report.reclaimed_bytes
+    );
+
+    Ok(Json(GcResponse {
+        removed: report.removed.len(),
+        reclaimed_bytes: report.reclaimed_bytes,
+        kept: report.kept,
+        dry_run: report.dry_run,
+    }))
+}
+
+/// Bandwidth limits in force
+///
+/// Endpoint: GET /admin/rate-limits
+/// Authorization: Bearer {admin token}
+///
+/// Returns: JSON with the limits in bytes per second (0 for none)
+async fn get_rate_limits_handler(
+    State(state): State<GatewayState>,
+    headers: HeaderMap,
+) -> Result<Json<RateLimits>, AppError> {
+    authorize(&state, &headers)?;
+    Ok(Json(state.limiter.limits()))
+}
+
+/// Change the bandwidth limits; transfers already running pick them up
+///
+/// Endpoint: PUT /admin/rate-limits
+/// Authorization: Bearer {admin token}
+/// Body: JSON with `upload`, `download`, `peer_upload` and `peer_download`
+/// in bytes per second; missing or 0 for no limit
+///
+/// Returns: JSON with the new limits
+async fn set_rate_limits_handler(
+    State(state): State<GatewayState>,
+    headers: HeaderMap,
+    Json(limits): Json<RateLimits>,
+) -> Result<Json<RateLimits>, AppError> {
+    authorize(&state, &headers)?;
+    state.limiter.set_limits(limits);
+    tracing::info!("Bandwidth limits set to {:?}", limits);
+    Ok(Json(limits))
+}
+
 /// Get metrics
 ///
 /// Endpoint: GET /metrics
@@ -258,6 +1142,8 @@ async fn health_handler() -> Json<HealthResponse> {
 #[derive(Debug)]
 enum AppError {
     BadRequest(String),
+    Unauthorized,
+    Forbidden(String),
     NotFound(String),
     Internal(String),
 }
@@ -269,6 +1155,17 @@ impl IntoResponse for AppError {
                 StatusCode::BAD_REQUEST,
                 ErrorResponse::new(msg, StatusCode::BAD_REQUEST.as_u16()),
             ),
+            AppError::Unauthorized => (
+                StatusCode::UNAUTHORIZED,
+                ErrorResponse::new(
+                    "Missing or invalid admin token".to_string(),
+                    StatusCode::UNAUTHORIZED.as_u16(),
+                ),
+            ),
+            AppError::Forbidden(msg) => (
+                StatusCode::FORBIDDEN,
+                ErrorResponse::new(msg, StatusCode::FORBIDDEN.as_u16()),
+            ),
             AppError::NotFound(cid) => (StatusCode::NOT_FOUND, ErrorResponse::not_found(&cid)),
             AppError::Internal(msg) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -295,6 +1192,7 @@ impl From<std::io::Error> for AppError {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
 
     #[test]
     fn test_mime_type_detection() {
@@ -315,7 +1213,7 @@ mod tests {
     #[tokio::test]
     async fn test_gateway_state_creation() {
         let config = GatewayConfig::default();
-        let state = GatewayState::new(config);
+        let state = GatewayState::new(config).unwrap();
 
         assert_eq!(state.config.port, 8080);
         assert!(state.storage.is_empty().await);
@@ -325,7 +1223,7 @@ mod tests {
     #[tokio::test]
     async fn test_upload_flow() {
         let config = GatewayConfig::default();
-        let state = GatewayState::new(config);
+        let state = GatewayState::new(config).unwrap();
 
         let content = b"Test content for upload".to_vec();
         let cid = state.storage.store(content.clone()).await.unwrap();
@@ -336,7 +1234,7 @@ mod tests {
     #[tokio::test]
     async fn test_cache_flow() {
         let config = GatewayConfig::default();
-        let state = GatewayState::new(config);
+        let state = GatewayState::new(config).unwrap();
 
         let content = b"Test content for cache".to_vec();
         let cid = ContentId::new(&content);
@@ -346,4 +1244,633 @@ mod tests {
 
         assert_eq!(retrieved, Some(content));
     }
+
+    #[tokio::test]
+    async fn test_data_dir_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = GatewayConfig::default().with_data_dir(dir.path());
+
+        let root = store_site(&GatewayState::new(config.clone()).unwrap()).await;
+
+        // A new gateway on the same directory serves the same site
+        let state = GatewayState::new(config).unwrap();
+        let response = get(&state, &format!("/{}/assets/app.js", root)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body(response).await, b"console.log(1)");
+    }
+
+    /// Stores a directory with an index page, a nested file and a
+    /// subdirectory without an index, returning its root CID
+    async fn store_site(state: &GatewayState) -> ContentId {
+        let files = vec![
+            (Some("index.html".to_string()), b"<h1>Home</h1>".to_vec()),
+            (
+                Some("assets/app.js".to_string()),
+                b"console.log(1)".to_vec(),
+            ),
+            (Some("assets/my file.txt".to_string()), b"spaced".to_vec()),
+        ];
+        store_directory(state, files).await.unwrap()
+    }
+
+    async fn get(state: &GatewayState, uri: &str) -> Response {
+        use tower::ServiceExt;
+
+        let request = axum::http::Request::get(uri)
+            .body(axum::body::Body::empty())
+            .unwrap();
+        app(state.clone()).oneshot(request).await.unwrap()
+    }
+
+    async fn body(response: Response) -> Vec<u8> {
+        use http_body_util::BodyExt;
+
+        let body = response.into_body().collect().await.unwrap();
+        body.to_bytes().to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_directory_paths() {
+        let state = GatewayState::new(GatewayConfig::default()).unwrap();
+        let root = store_site(&state).await;
+        assert!(root.to_string().starts_with("bafy"));
+
+        // Files resolve by path
+        let response = get(&state, &format!("/{}/assets/app.js", root)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/javascript");
+        assert_eq!(body(response).await, b"console.log(1)");
+
+        let response = get(&state, &format!("/{}/assets/my%20file.txt", root)).await;
+        assert_eq!(body(response).await, b"spaced");
+
+        // Directories redirect to their trailing-slash form
+        let response = get(&state, &format!("/{}", root)).await;
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            format!("/{}/", root).as_str()
+        );
+
+        // and serve their index page
+        let response = get(&state, &format!("/{}/", root)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body(response).await, b"<h1>Home</h1>");
+
+        // or a listing
+        let response = get(&state, &format!("/{}/assets", root)).await;
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            format!("/{}/assets/", root).as_str()
+        );
+        let response = get(&state, &format!("/{}/assets/", root)).await;
+        let listing: serde_json::Value = serde_json::from_slice(&body(response).await).unwrap();
+        let names: Vec<_> = listing["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["name"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(names, ["app.js", "my file.txt"]);
+        assert_eq!(listing["entries"][0]["size"], 14);
+    }
+
+    #[tokio::test]
+    async fn test_directory_path_errors() {
+        let state = GatewayState::new(GatewayConfig::default()).unwrap();
+        let root = store_site(&state).await;
+
+        let response = get(&state, &format!("/{}/missing.txt", root)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // Paths cannot run through files
+        let response = get(&state, &format!("/{}/index.html/x", root)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = get(&state, &format!("/{}/assets/%2E%2E/index.html", root)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // Flat content has no paths below it
+        let cid = state.storage.store(b"flat".to_vec()).await.unwrap();
+        let response = get(&state, &format!("/{}/x", cid)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // Conflicting paths are rejected before anything is stored
+        let files = vec![
+            (Some("a".to_string()), b"file".to_vec()),
+            (Some("a/b".to_string()), b"nested".to_vec()),
+        ];
+        assert!(store_directory(&state, files).await.is_err());
+        assert!(!state.storage.contains(&ContentId::new(b"nested")).await);
+    }
+
+    /// Sends an admin request with `token` as the bearer token
+    async fn admin(state: &GatewayState, method: &str, uri: &str, token: Option<&str>) -> Response {
+        use tower::ServiceExt;
+
+        let mut request = axum::http::Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = request.body(axum::body::Body::empty()).unwrap();
+        app(state.clone()).oneshot(request).await.unwrap()
+    }
+
+    async fn json(response: Response) -> serde_json::Value {
+        serde_json::from_slice(&body(response).await).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_admin_requires_token() {
+        let state = GatewayState::new(GatewayConfig::default()).unwrap();
+        let response = admin(&state, "GET", "/admin/pins", Some("anything")).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let config = GatewayConfig::default().with_admin_token("secret");
+        let state = GatewayState::new(config).unwrap();
+        for token in [None, Some("wrong"), Some("secre")] {
+            let response = admin(&state, "POST", "/admin/gc", token).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        let response = admin(&state, "GET", "/admin/pins", Some("secret")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_admin_pins_and_gc() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = GatewayConfig::default()
+            .with_data_dir(dir.path())
+            .with_admin_token("secret");
+        let state = GatewayState::new(config.clone()).unwrap();
+        let token = Some("secret");
+
+        // Content stored without pinning is garbage
+        let site = store_site(&state).await;
+        let loose = state.storage.store(b"loose".to_vec()).await.unwrap();
+        load(&state, &loose).await.unwrap();
+
+        let response = admin(
+            &state,
+            "POST",
+            &format!("/admin/pins/{}?name=site", site),
+            token,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json(response).await["mode"], "recursive");
+
+        // A dry run reports without removing
+        let report = json(admin(&state, "POST", "/admin/gc?dry_run=true", token).await).await;
+        assert_eq!(report["removed"], 1);
+        assert_eq!(report["reclaimed_bytes"], 5);
+        assert_eq!(report["dry_run"], true);
+        assert!(state.storage.contains(&loose).await);
+
+        let report = json(admin(&state, "POST", "/admin/gc", token).await).await;
+        assert_eq!(report["removed"], 1);
+        assert_eq!(report["kept"], 5);
+        assert!(!state.storage.contains(&loose).await);
+        let response = get(&state, &format!("/{}", loose)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = get(&state, &format!("/{}/assets/app.js", site)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Pins survive restarts
+        let state = GatewayState::new(config).unwrap();
+        let pins = json(admin(&state, "GET", "/admin/pins", token).await).await;
+        assert_eq!(pins["pins"][0]["cid"], site.to_string());
+        assert_eq!(pins["pins"][0]["name"], "site");
+
+        // Only stored content can be pinned
+        let response = admin(&state, "POST", &format!("/admin/pins/{}", loose), token).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let uri = format!("/admin/pins/{}", site);
+        let response = admin(&state, "DELETE", &uri, token).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = admin(&state, "DELETE", &uri, token).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let report = json(admin(&state, "POST", "/admin/gc", token).await).await;
+        assert_eq!(report["removed"], 5);
+        assert!(state.storage.is_empty().await);
+    }
+
+    #[tokio::test]
+    async fn test_uploads_are_pinned() {
+        use tower::ServiceExt;
+
+        let config = GatewayConfig::default().with_admin_token("secret");
+        let state = GatewayState::new(config).unwrap();
+
+        let body = "--b\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\r\nuploaded\r\n--b--\r\n";
+        let request = axum::http::Request::post("/upload")
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=b")
+            .body(axum::body::Body::from(body))
+            .unwrap();
+        let response = app(state.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let report = json(admin(&state, "POST", "/admin/gc", Some("secret")).await).await;
+        assert_eq!(report["removed"], 0);
+        assert_eq!(report["kept"], 1);
+    }
+
+    async fn upload_encrypted(state: &GatewayState, encrypt: &str) -> Response {
+        use tower::ServiceExt;
+
+        let body = format!(
+            "--b\r\nContent-Disposition: form-data; name=\"encrypt\"\r\n\r\n{}\r\n\
+             --b\r\nContent-Disposition: form-data; name=\"file\"; filename=\"asset.html\"\r\n\r\n\
+             <h1>Licensed</h1>\r\n--b--\r\n",
+            encrypt
+        );
+        let request = axum::http::Request::post("/upload")
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=b")
+            .body(axum::body::Body::from(body))
+            .unwrap();
+        app(state.clone()).oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_upload_and_capability() {
+        let state = GatewayState::new(GatewayConfig::default()).unwrap();
+
+        let upload = json(upload_encrypted(&state, "random").await).await;
+        let capability = upload["capability"].as_str().unwrap().to_string();
+        let cid = upload["cid"].as_str().unwrap();
+        assert!(capability.starts_with(&format!("{}:", cid)));
+
+        // The CID serves ciphertext, the capability the file
+        let response = get(&state, &format!("/{}", cid)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!String::from_utf8_lossy(&body(response).await).contains("Licensed"));
+
+        let response = get(&state, &format!("/{}", capability)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "private, no-store"
+        );
+        assert_eq!(body(response).await, b"<h1>Licensed</h1>");
+
+        let wrong = format!("{}:{}", cid, ContentKey::random());
+        assert_eq!(
+            get(&state, &format!("/{}", wrong)).await.status(),
+            StatusCode::FORBIDDEN
+        );
+        let response = get(&state, &format!("/{}/index.html", capability)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // Convergent uploads of the same file share a CID
+        let a = json(upload_encrypted(&state, "convergent").await).await;
+        let b = json(upload_encrypted(&state, "convergent").await).await;
+        assert_eq!(a["capability"], b["capability"]);
+        assert_ne!(a["cid"], upload["cid"]);
+
+        let response = upload_encrypted(&state, "rot13").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        // Ranges apply to the plaintext
+        let response = get_range(&state, &format!("/{}", capability), "bytes=4-9").await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body(response).await, b"Licens");
+    }
+
+    #[test]
+    fn test_capability_keys_are_redacted() {
+        let cid = ContentId::new(b"ciphertext");
+        let capability = Capability::new(cid.clone(), ContentKey::random()).to_string();
+        let key = capability.split_once(':').unwrap().1.to_string();
+
+        assert_eq!(redact_capability(&capability), format!("{}:<key>", cid));
+        assert_eq!(
+            redact_capability(&format!("/{}/x", capability)),
+            format!("/{}:<key>/x", cid)
+        );
+        assert_eq!(
+            redact_capability(&format!("/{}%3a{}", cid, key)),
+            format!("/{}:<key>", cid)
+        );
+        for path in [format!("/{}/a:b", cid), "/upload".to_string()] {
+            assert_eq!(redact_capability(&path), path);
+        }
+
+        // Nor does the request span log it
+        #[derive(Clone, Default)]
+        struct Logs(Arc<std::sync::Mutex<Vec<u8>>>);
+        impl std::io::Write for Logs {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            let request = Request::get(format!("/{}", capability))
+                .body(Body::empty())
+                .unwrap();
+            let _span = request_span(&request).entered();
+            tracing::info!("served");
+        });
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains(&format!("{}:<key>", cid)), "{}", logs);
+        assert!(!logs.contains(&key), "{}", logs);
+    }
+
+    #[tokio::test]
+    async fn test_decrypted_ranges_read_only_covering_chunks() {
+        use codio_content::{Chunker, DEFAULT_CHUNK_SIZE};
+
+        let log = Arc::new(ReadLog::default());
+        let state = GatewayState {
+            storage: ContentStorage::with_blockstore(log.clone()),
+            ..GatewayState::new(GatewayConfig::default()).unwrap()
+        };
+        let content: Vec<u8> = (0..4 * DEFAULT_CHUNK_SIZE as u32)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let key = ContentKey::random();
+        let ciphertext = key.encrypt(&content);
+        let chunks = Chunker::new(DEFAULT_CHUNK_SIZE).chunk(&ciphertext);
+        let cid = state.storage.store_chunked(&ciphertext).await.unwrap();
+        let uri = format!("/{}", Capability::new(cid, key));
+        let is_chunk = |cid: &ContentId| chunks.iter().any(|chunk| &chunk.cid == cid);
+
+        // Plaintext from the middle of the third ciphertext chunk
+        let start = 2 * DEFAULT_CHUNK_SIZE + 1000;
+        let range = format!("bytes={}-{}", start, start + 99);
+        let response = get_range(&state, &uri, &range).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "private, no-store"
+        );
+        assert_eq!(body(response).await, &content[start..start + 100]);
+        let reads = std::mem::take(&mut *log.reads.lock().unwrap());
+        let read_chunks: Vec<_> = reads.iter().filter(|cid| is_chunk(cid)).collect();
+        assert_eq!(read_chunks, [&chunks[2].cid]);
+
+        let response = get(&state, &uri).await;
+        assert_eq!(
+            response.headers()[header::CONTENT_LENGTH],
+            content.len().to_string().as_str()
+        );
+        assert_eq!(body(response).await, content);
+    }
+
+    async fn get_range(state: &GatewayState, uri: &str, range: &str) -> Response {
+        use tower::ServiceExt;
+
+        let request = axum::http::Request::get(uri)
+            .header(header::RANGE, range)
+            .body(axum::body::Body::empty())
+            .unwrap();
+        app(state.clone()).oneshot(request).await.unwrap()
+    }
+
+    #[test]
+    fn test_byte_range_parsing() {
+        let range = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::RANGE, value.parse().unwrap());
+            ByteRange::requested(&headers, 100)
+        };
+
+        assert_eq!(
+            ByteRange::requested(&HeaderMap::new(), 100),
+            ByteRange::Full
+        );
+        assert_eq!(range("bytes=0-9"), ByteRange::Partial(0..10));
+        assert_eq!(range("bytes=90-"), ByteRange::Partial(90..100));
+        assert_eq!(range("bytes=90-500"), ByteRange::Partial(90..100));
+        assert_eq!(range("bytes=-30"), ByteRange::Partial(70..100));
+        assert_eq!(range("bytes=-500"), ByteRange::Partial(0..100));
+        assert_eq!(range("bytes=100-"), ByteRange::Unsatisfiable);
+        assert_eq!(range("bytes=-0"), ByteRange::Unsatisfiable);
+
+        // Ignored: malformed, backwards, other units, several ranges
+        assert_eq!(range("bytes=a-b"), ByteRange::Full);
+        assert_eq!(range("bytes=9-0"), ByteRange::Full);
+        assert_eq!(range("items=0-9"), ByteRange::Full);
+        assert_eq!(range("bytes=0-1,5-6"), ByteRange::Full);
+    }
+
+    #[tokio::test]
+    async fn test_range_requests() {
+        let state = GatewayState::new(GatewayConfig::default()).unwrap();
+        let content: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        let cid = state.storage.store(content.clone()).await.unwrap();
+        let uri = format!("/{}", cid);
+
+        let response = get(&state, &uri).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+
+        let response = get_range(&state, &uri, "bytes=100-199").await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            response.headers()[header::CONTENT_RANGE],
+            "bytes 100-199/1000"
+        );
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "100");
+        assert_eq!(body(response).await, &content[100..200]);
+
+        let response = get_range(&state, &uri, "bytes=-10").await;
+        assert_eq!(
+            response.headers()[header::CONTENT_RANGE],
+            "bytes 990-999/1000"
+        );
+        assert_eq!(body(response).await, &content[990..]);
+
+        let response = get_range(&state, &uri, "bytes=1000-").await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */1000");
+    }
+
+    /// Blocks in memory, recording which are read
+    #[derive(Default)]
+    struct ReadLog {
+        store: codio_blockstore::MemoryBlockstore,
+        reads: std::sync::Mutex<Vec<ContentId>>,
+    }
+
+    #[async_trait::async_trait]
+    impl codio_blockstore::Blockstore for ReadLog {
+        async fn get(&self, cid: &ContentId) -> anyhow::Result<Option<Vec<u8>>> {
+            self.reads.lock().unwrap().push(cid.clone());
+            self.store.get(cid).await
+        }
+
+        async fn put(&self, cid: &ContentId, data: Vec<u8>) -> anyhow::Result<()> {
+            self.store.put(cid, data).await
+        }
+
+        async fn delete(&self, cid: &ContentId) -> anyhow::Result<bool> {
+            self.store.delete(cid).await
+        }
+
+        async fn size(&self, cid: &ContentId) -> anyhow::Result<Option<u64>> {
+            self.store.size(cid).await
+        }
+
+        async fn cids(&self) -> anyhow::Result<Vec<ContentId>> {
+            self.store.cids().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_range_reads_only_covering_chunks() {
+        use codio_content::{Chunker, DEFAULT_CHUNK_SIZE};
+
+        let log = Arc::new(ReadLog::default());
+        let state = GatewayState {
+            storage: ContentStorage::with_blockstore(log.clone()),
+            ..GatewayState::new(GatewayConfig::default()).unwrap()
+        };
+        let content: Vec<u8> = (0..4 * DEFAULT_CHUNK_SIZE as u32)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let chunks = Chunker::new(DEFAULT_CHUNK_SIZE).chunk(&content);
+        let cid = state.storage.store_chunked(&content).await.unwrap();
+        let uri = format!("/{}", cid);
+        let is_chunk = |cid: &ContentId| chunks.iter().any(|chunk| &chunk.cid == cid);
+
+        // A range inside the second chunk reads just that chunk
+        let start = DEFAULT_CHUNK_SIZE + 1000;
+        let range = format!("bytes={}-{}", start, start + 99);
+        let response = get_range(&state, &uri, &range).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body(response).await, &content[start..start + 100]);
+        let reads = std::mem::take(&mut *log.reads.lock().unwrap());
+        let read_chunks: Vec<_> = reads.iter().filter(|cid| is_chunk(cid)).collect();
+        assert_eq!(read_chunks, [&chunks[1].cid]);
+
+        // One across a boundary reads the two chunks it spans
+        let start = 3 * DEFAULT_CHUNK_SIZE - 10;
+        let range = format!("bytes={}-{}", start, start + 19);
+        let response = get_range(&state, &uri, &range).await;
+        assert_eq!(body(response).await, &content[start..start + 20]);
+        let reads = std::mem::take(&mut *log.reads.lock().unwrap());
+        let read_chunks: Vec<_> = reads.iter().filter(|cid| is_chunk(cid)).collect();
+        assert_eq!(read_chunks, [&chunks[2].cid, &chunks[3].cid]);
+
+        // The whole file still comes back
+        let response = get(&state, &uri).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_LENGTH],
+            content.len().to_string().as_str()
+        );
+        assert_eq!(body(response).await, content);
+
+        let response = get_range(&state, &uri, &format!("bytes={}-", content.len())).await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    }
+
+    async fn put_rate_limits(state: &GatewayState, limits: &str) -> Response {
+        use tower::ServiceExt;
+
+        let request = axum::http::Request::put("/admin/rate-limits")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(axum::body::Body::from(limits.to_string()))
+            .unwrap();
+        app(state.clone()).oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_admin() {
+        let config = GatewayConfig::default().with_admin_token("secret");
+        let state = GatewayState::new(config).unwrap();
+
+        let response = admin(&state, "GET", "/admin/rate-limits", None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let limits = json(admin(&state, "GET", "/admin/rate-limits", Some("secret")).await).await;
+        assert_eq!(limits["upload"], 0);
+
+        let response =
+            put_rate_limits(&state, r#"{"upload": 1048576, "peer_download": 65536}"#).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let limits = json(response).await;
+        assert_eq!(limits["upload"], 1048576);
+        assert_eq!(limits["download"], 0);
+        assert_eq!(
+            state.limiter.limits(),
+            RateLimits::default()
+                .with_upload(1 << 20)
+                .with_peer_download(1 << 16)
+        );
+    }
+
+    /// Fetches `uri` as a client at `ip`, returning the body and how long it
+    /// took
+    async fn timed_get(state: &GatewayState, uri: &str, ip: [u8; 4]) -> (Vec<u8>, Duration) {
+        use tower::ServiceExt;
+
+        let start = tokio::time::Instant::now();
+        let mut request = axum::http::Request::get(uri)
+            .body(axum::body::Body::empty())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from((ip, 40000))));
+        let response = app(state.clone()).oneshot(request).await.unwrap();
+        (body(response).await, start.elapsed())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limits_pace_transfers() {
+        let limits = RateLimits::default()
+            .with_peer_upload(64 * 1024)
+            .with_download(64 * 1024);
+        let state = GatewayState::new(GatewayConfig::default().with_rate_limits(limits)).unwrap();
+        let content = vec![7u8; 256 * 1024];
+        let cid = state.storage.store(content.clone()).await.unwrap();
+        let uri = format!("/{}", cid);
+
+        // One second's worth goes at once, the other 192 KB take 3s
+        let (data, took) = timed_get(&state, &uri, [10, 0, 0, 1]).await;
+        assert_eq!(data, content);
+        assert_eq!(took, Duration::from_secs(3));
+
+        // Each client has a bucket of its own
+        let (_, took) = timed_get(&state, &uri, [10, 0, 0, 2]).await;
+        assert_eq!(took, Duration::from_secs(3));
+
+        // Files posted to the gateway are read at the download limit
+        let start = tokio::time::Instant::now();
+        let response = upload_file(&state, &"u".repeat(128 * 1024)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        // Lifting the limits takes effect straight away
+        state.limiter.set_limits(RateLimits::unlimited());
+        let (_, took) = timed_get(&state, &uri, [10, 0, 0, 1]).await;
+        assert_eq!(took, Duration::ZERO);
+    }
+
+    async fn upload_file(state: &GatewayState, content: &str) -> Response {
+        use tower::ServiceExt;
+
+        let body = format!(
+            "--b\r\nContent-Disposition: form-data; name=\"file\"; filename=\"file.txt\"\r\n\r\n\
+             {}\r\n--b--\r\n",
+            content
+        );
+        let request = axum::http::Request::post("/upload")
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=b")
+            .body(axum::body::Body::from(body))
+            .unwrap();
+        app(state.clone()).oneshot(request).await.unwrap()
+    }
 }
@@ -8,8 +8,7 @@ pub struct Metrics {
     data: Arc<RwLock<MetricsData>>,
 }
 
-#[derive(Debug, Clone)]
-#[derive(Default)]
+#[derive(Debug, Clone, Default)]
 struct MetricsData {
     uploads: u64,
     downloads: u64,
@@ -18,7 +17,6 @@ struct MetricsData {
     total_bytes_served: u64,
 }
 
-
 impl Metrics {
     /// Create new metrics tracker
     pub fn new() -> Self {
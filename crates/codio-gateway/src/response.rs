@@ -9,6 +9,9 @@ pub struct UploadResponse {
     pub size: u64,
     /// Number of chunks (always 1 for Phase 1)
     pub chunks: u32,
+    /// `<cid>:<key>` to read an encrypted upload with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capability: Option<String>,
 }
 
 impl UploadResponse {
@@ -17,8 +20,67 @@ impl UploadResponse {
             cid,
             size,
             chunks: 1, // Phase 1: no chunking
+            capability: None,
         }
     }
+
+    /// Add the capability of an encrypted upload
+    pub fn with_capability(mut self, capability: String) -> Self {
+        self.capability = Some(capability);
+        self
+    }
+}
+
+/// Listing of a directory without an index.html
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DirectoryListing {
+    /// Directory CID
+    pub cid: String,
+    /// Entries, sorted by name
+    pub entries: Vec<DirectoryEntry>,
+}
+
+/// One entry of a directory listing
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DirectoryEntry {
+    /// Entry name
+    pub name: String,
+    /// Entry CID
+    pub cid: String,
+    /// Cumulative size in bytes
+    pub size: u64,
+}
+
+/// A pinned CID
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PinEntry {
+    /// Pinned CID
+    pub cid: String,
+    /// "recursive" or "direct"
+    pub mode: String,
+    /// Label given when pinning
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+/// Response listing pins
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PinListResponse {
+    /// Pins, sorted by hash
+    pub pins: Vec<PinEntry>,
+}
+
+/// Response for a garbage collection
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GcResponse {
+    /// Blocks removed, or that would be in a dry run
+    pub removed: usize,
+    /// Bytes those blocks take
+    pub reclaimed_bytes: u64,
+    /// Pinned blocks kept
+    pub kept: usize,
+    /// Whether nothing was actually removed
+    pub dry_run: bool,
 }
 
 /// Error response
@@ -58,8 +120,7 @@ impl ErrorResponse {
 }
 
 /// Metrics response
-#[derive(Debug, Serialize, Deserialize, Clone)]
-#[derive(Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct MetricsResponse {
     /// Total uploads
     pub uploads: u64,
@@ -73,7 +134,6 @@ pub struct MetricsResponse {
     pub total_bytes_served: u64,
 }
 
-
 /// Health check response
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HealthResponse {
@@ -1,53 +1,159 @@
+use codio_blockstore::{
+    chunk_slices, collect_garbage_with_files, missing_blocks_with_files, put_chunked,
+    write_chunked, Blockstore, ChunkSlice, ChunkedFile, FileIndex, FsBlockstore, GcReport,
+    MemoryBlockstore, PinSet,
+};
+use codio_content::{Chunker, DEFAULT_CHUNK_SIZE};
 use codio_content_id::ContentId;
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::RwLock;
-
-/// In-memory content storage
-/// Phase 1: Simple storage, Phase 2+ will add distributed storage
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Index of chunked files, next to the block shards
+const FILES_FILE: &str = "files.json";
+
+/// Content storage backed by a [`Blockstore`]
+///
+/// In memory by default; [`ContentStorage::open`] keeps content on disk so it
+/// survives restarts. Entries are keyed by hash, so every version and
+/// encoding of a CID resolves to the same content. Files stored with
+/// [`ContentStorage::store_chunked`] are kept one block per chunk, so parts
+/// of them can be read without the rest.
 #[derive(Clone)]
 pub struct ContentStorage {
-    store: Arc<RwLock<HashMap<String, Vec<u8>>>>,
+    store: Arc<dyn Blockstore>,
+    files: Arc<Mutex<FileIndex>>,
 }
 
 impl ContentStorage {
-    /// Create new content storage
+    /// Create new in-memory content storage
     pub fn new() -> Self {
+        Self::with_blockstore(Arc::new(MemoryBlockstore::new()))
+    }
+
+    /// Create content storage in a directory, keeping what's already there
+    pub fn open(dir: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        Ok(Self {
+            store: Arc::new(FsBlockstore::open(&dir)?),
+            files: Arc::new(Mutex::new(FileIndex::open(dir.join(FILES_FILE))?)),
+        })
+    }
+
+    /// Create content storage on an existing blockstore, with chunked files
+    /// indexed in memory
+    pub fn with_blockstore(store: Arc<dyn Blockstore>) -> Self {
         Self {
-            store: Arc::new(RwLock::new(HashMap::new())),
+            store,
+            files: Arc::new(Mutex::new(FileIndex::new())),
         }
     }
 
+    /// Get the underlying blockstore
+    pub fn blockstore(&self) -> &Arc<dyn Blockstore> {
+        &self.store
+    }
+
     /// Store content and return CID
     pub async fn store(&self, content: Vec<u8>) -> anyhow::Result<ContentId> {
         let cid = ContentId::new(&content);
-        let cid_str = cid.as_str().to_string();
+        self.store.put(&cid, content).await?;
 
-        self.store.write().await.insert(cid_str, content);
+        Ok(cid)
+    }
+
+    /// Store content one block per chunk and return its CID, the same
+    /// [`ContentStorage::store`] gives
+    pub async fn store_chunked(&self, content: &[u8]) -> anyhow::Result<ContentId> {
+        let chunker = Chunker::new(DEFAULT_CHUNK_SIZE);
+        let file = put_chunked(self.store.as_ref(), &chunker, content).await?;
+        let cid = file.cid.clone();
+        self.files.lock().unwrap().add(file)?;
 
         Ok(cid)
     }
 
-    /// Retrieve content by CID
+    /// The chunked file `cid` is stored as, if it is
+    pub fn chunked(&self, cid: &ContentId) -> Option<ChunkedFile> {
+        self.files.lock().unwrap().get(cid).cloned()
+    }
+
+    /// Find the chunks holding bytes `range` of `file`, reading none of them
+    pub async fn chunk_slices(
+        &self,
+        file: &ChunkedFile,
+        range: Range<u64>,
+    ) -> anyhow::Result<Vec<ChunkSlice>> {
+        chunk_slices(self.store.as_ref(), file, range).await
+    }
+
+    /// Retrieve content by CID, reassembling chunked files
+    ///
+    /// Read errors are logged and reported as missing content.
     pub async fn retrieve(&self, cid: &ContentId) -> Option<Vec<u8>> {
-        let cid_str = cid.as_str();
-        self.store.read().await.get(cid_str).cloned()
+        let read = match self.chunked(cid) {
+            Some(file) => {
+                let mut data = Vec::with_capacity(file.size as usize);
+                write_chunked(self.store.as_ref(), &file, &mut data)
+                    .await
+                    .map(|_| Some(data))
+            }
+            None => self.store.get(cid).await,
+        };
+        read.unwrap_or_else(|e| {
+            tracing::warn!("Failed to read {}: {:#}", cid, e);
+            None
+        })
     }
 
     /// Check if content exists
     pub async fn contains(&self, cid: &ContentId) -> bool {
-        let cid_str = cid.as_str();
-        self.store.read().await.contains_key(cid_str)
+        if self.chunked(cid).is_some() {
+            return true;
+        }
+        self.store.has(cid).await.unwrap_or_else(|e| {
+            tracing::warn!("Failed to check {}: {:#}", cid, e);
+            false
+        })
+    }
+
+    /// Blocks reachable from `cid` that aren't stored, following chunked
+    /// files to their chunks
+    pub async fn missing_blocks(&self, cid: &ContentId) -> anyhow::Result<Vec<ContentId>> {
+        let files = self.files.lock().unwrap().clone();
+        missing_blocks_with_files(self.store.as_ref(), &files, cid).await
+    }
+
+    /// Remove every block `pins` doesn't reach, and forget the chunked
+    /// files whose DAG went with them
+    pub async fn collect_garbage(&self, pins: &PinSet, dry_run: bool) -> anyhow::Result<GcReport> {
+        let files = self.files.lock().unwrap().clone();
+        let report = collect_garbage_with_files(self.store.as_ref(), pins, &files, dry_run).await?;
+
+        if !dry_run {
+            let removed: HashSet<_> = report.removed.iter().map(|cid| cid.routing_key()).collect();
+            let mut files = self.files.lock().unwrap();
+            let gone: Vec<_> = files
+                .iter()
+                .filter(|file| removed.contains(&file.root.routing_key()))
+                .map(|file| file.cid.clone())
+                .collect();
+            for cid in gone {
+                files.remove(&cid)?;
+            }
+        }
+        Ok(report)
     }
 
     /// Get total stored items
     pub async fn len(&self) -> usize {
-        self.store.read().await.len()
+        self.store.cids().await.map(|cids| cids.len()).unwrap_or(0)
     }
 
     /// Check if storage is empty
     pub async fn is_empty(&self) -> bool {
-        self.store.read().await.is_empty()
+        self.len().await == 0
     }
 }
 
@@ -89,4 +195,49 @@ mod tests {
 
         assert!(storage.retrieve(&cid).await.is_none());
     }
+
+    #[tokio::test]
+    async fn test_store_chunked() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = ContentStorage::open(dir.path()).unwrap();
+        let content: Vec<u8> = (0..3 * DEFAULT_CHUNK_SIZE as u32 / 2)
+            .map(|i| (i % 251) as u8)
+            .collect();
+
+        let cid = storage.store_chunked(&content).await.unwrap();
+        assert_eq!(cid, ContentId::new(&content));
+        assert!(!storage.blockstore().has(&cid).await.unwrap());
+        assert_eq!(storage.retrieve(&cid).await.as_ref(), Some(&content));
+        assert!(storage.missing_blocks(&cid).await.unwrap().is_empty());
+
+        // The index survives restarts, and goes with the file's blocks
+        let storage = ContentStorage::open(dir.path()).unwrap();
+        assert!(storage.contains(&cid).await);
+        let report = storage
+            .collect_garbage(&PinSet::new(), false)
+            .await
+            .unwrap();
+        assert_eq!(report.removed.len(), 3);
+        assert!(!storage.contains(&cid).await);
+        assert!(ContentStorage::open(dir.path())
+            .unwrap()
+            .chunked(&cid)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_open_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = b"Survives restarts".to_vec();
+
+        let cid = ContentStorage::open(dir.path())
+            .unwrap()
+            .store(content.clone())
+            .await
+            .unwrap();
+
+        let storage = ContentStorage::open(dir.path()).unwrap();
+        assert_eq!(storage.retrieve(&cid).await, Some(content));
+        assert_eq!(storage.len().await, 1);
+    }
 }
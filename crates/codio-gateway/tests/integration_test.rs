@@ -125,3 +125,27 @@ async fn test_same_content_same_cid() {
     // Should produce same CID
     assert_eq!(cid1, cid2);
 }
+
+#[tokio::test]
+async fn test_legacy_cid_resolves_stored_content() {
+    let storage = ContentStorage::new();
+    let content = b"Published before CIDs were unified".to_vec();
+    let cid = storage.store(content.clone()).await.unwrap();
+
+    // A CID handed out in the old format still finds the content
+    let legacy: ContentId = cid.to_legacy_string().unwrap().parse().unwrap();
+    assert_eq!(legacy, cid);
+    assert_eq!(storage.retrieve(&legacy).await, Some(content));
+}
+
+#[tokio::test]
+async fn test_cidv1_resolves_stored_content() {
+    let storage = ContentStorage::new();
+    let content = b"Served on a subdomain".to_vec();
+    let cid = storage.store(content.clone()).await.unwrap();
+
+    // Subdomain URLs carry the base32 CIDv1 form of the same content
+    let v1: ContentId = cid.to_v1().as_str().parse().unwrap();
+    assert!(v1.as_str().starts_with('b'));
+    assert_eq!(storage.retrieve(&v1).await, Some(content));
+}
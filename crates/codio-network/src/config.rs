@@ -2,6 +2,7 @@
 //!
 //! This module provides configuration options for the P2P network layer.
 
+use codio_common::RateLimits;
 use libp2p::Multiaddr;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
@@ -41,6 +42,27 @@ pub struct NetworkConfig {
 
     /// Kademlia replication factor
     pub kademlia_replication_factor: usize,
+
+    /// Timeout for a single content transfer request
+    pub transfer_timeout: Duration,
+
+    /// Chunk size used when serving local content
+    pub chunk_size: u32,
+
+    /// Bandwidth limits for serving chunks; only the upload limits apply,
+    /// downloads are paced by whoever requests them
+    #[serde(default)]
+    pub rate_limits: RateLimits,
+
+    /// Longest a served chunk may wait for upload bandwidth; requests that
+    /// would wait longer are refused as busy. Keep it well below the
+    /// requesters' timeouts.
+    #[serde(default = "default_max_upload_wait")]
+    pub max_upload_wait: Duration,
+}
+
+fn default_max_upload_wait() -> Duration {
+    Duration::from_secs(10)
 }
 
 impl Default for NetworkConfig {
@@ -54,6 +76,10 @@ impl Default for NetworkConfig {
             connection_timeout: Duration::from_secs(10),
             idle_connection_timeout: Duration::from_secs(60),
             kademlia_replication_factor: 20,
+            transfer_timeout: Duration::from_secs(60),
+            chunk_size: 256 * 1024, // 256 KB
+            rate_limits: RateLimits::unlimited(),
+            max_upload_wait: default_max_upload_wait(),
         }
     }
 }
@@ -104,6 +130,30 @@ impl NetworkConfig {
         self
     }
 
+    /// Sets the timeout for content transfer requests.
+    pub fn with_transfer_timeout(mut self, timeout: Duration) -> Self {
+        self.transfer_timeout = timeout;
+        self
+    }
+
+    /// Sets the chunk size used when serving local content.
+    pub fn with_chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Sets the bandwidth limits for serving chunks.
+    pub fn with_rate_limits(mut self, limits: RateLimits) -> Self {
+        self.rate_limits = limits;
+        self
+    }
+
+    /// Sets the longest a served chunk may wait for upload bandwidth.
+    pub fn with_max_upload_wait(mut self, wait: Duration) -> Self {
+        self.max_upload_wait = wait;
+        self
+    }
+
     /// Disables relay for NAT traversal.
     pub fn without_relay(mut self) -> Self {
         self.enable_relay = false;
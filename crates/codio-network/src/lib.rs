@@ -8,6 +8,14 @@
 //! - **Connection Management**: Automatic reconnection, connection limits, NAT traversal
 //! - **Transport Security**: Noise protocol encryption, Yamux multiplexing
 //! - **Protocol Support**: Custom `/codio/1.0.0` protocol, ping, identify
+//! - **Content Transfer**: `/codio/transfer/1.0.0` request/response protocol
+//! - **Chunk Availability**: bitfields and HAVE messages track which chunks
+//!   each peer holds, so partial downloads can be shared while in progress
+//! - **Upload Limits**: served chunks are paced to the configured bandwidth,
+//!   in total and per peer; requests that can't be served in time are
+//!   refused as busy
+//! - **Upload Gate**: an [`UploadGate`], such as a choker, can refuse chunk
+//!   requests from peers it isn't uploading to
 //!
 //! ## Example
 //!
@@ -24,26 +32,42 @@
 //! ```
 
 pub mod config;
+pub mod transfer;
 
 pub use config::NetworkConfig;
+pub use transfer::{
+    Bitfield, ContentManifest, ErrorCode, RemoteError, TransferCodec, TransferRequest,
+    TransferResponse, TRANSFER_PROTOCOL,
+};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use codio_common::{Direction, RateLimiter, RateLimits};
+use codio_content_id::ContentId;
 use futures::StreamExt;
 use libp2p::{
     identify,
     kad::{self, store::MemoryStore},
     mdns, noise, ping,
+    request_response::{self, OutboundRequestId, ProtocolSupport},
     swarm::{NetworkBehaviour, Swarm, SwarmEvent},
     tcp, yamux, Multiaddr, PeerId, SwarmBuilder,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 use tracing::{debug, info, warn};
 
 /// Custom protocol version for Codio CDN
 const CODIO_PROTOCOL_VERSION: &str = "/codio/1.0.0";
 
+/// Chunks queued for any one peer before its requests are refused as busy
+pub const MAX_QUEUED_UPLOADS: usize = 16;
+
 /// Events emitted by the network manager
 #[derive(Debug, Clone)]
 pub enum NetworkEvent {
@@ -63,6 +87,19 @@ pub enum NetworkEvent {
     },
     /// Kademlia bootstrap completed
     BootstrapCompleted,
+    /// A chunk of local content was served to a peer
+    ContentServed {
+        peer_id: PeerId,
+        cid: ContentId,
+        index: u32,
+        bytes: usize,
+    },
+    /// A peer completed a chunk of content we share with it
+    Have {
+        peer_id: PeerId,
+        cid: ContentId,
+        index: u32,
+    },
     /// Network error occurred
     Error { message: String },
 }
@@ -116,6 +153,449 @@ impl PeerInfo {
     }
 }
 
+/// Content held locally and served over the transfer protocol
+struct LocalContent {
+    manifest: ContentManifest,
+    data: ContentSource,
+    /// Chunks we can serve
+    have: Bitfield,
+}
+
+/// Where the bytes of locally served content live
+enum ContentSource {
+    Memory(Arc<Vec<u8>>),
+    /// Read chunk by chunk on demand, so large files never sit in memory;
+    /// the content starts `offset` bytes into the file
+    File {
+        path: PathBuf,
+        offset: u64,
+    },
+    /// Chunks of a download in progress, by index
+    Chunks(HashMap<u32, Arc<Vec<u8>>>),
+}
+
+impl ContentSource {
+    /// Where to read chunk `index` from, without reading it yet
+    fn chunk(&self, index: u32, range: Range<usize>) -> Option<ChunkRead> {
+        match self {
+            ContentSource::Memory(data) => Some(ChunkRead::Memory {
+                data: Arc::clone(data),
+                range,
+            }),
+            ContentSource::Chunks(chunks) => chunks.get(&index).map(|data| ChunkRead::Memory {
+                data: Arc::clone(data),
+                range: 0..data.len(),
+            }),
+            ContentSource::File { path, offset } => Some(ChunkRead::File {
+                path: path.clone(),
+                offset: offset + range.start as u64,
+                len: range.len(),
+            }),
+        }
+    }
+}
+
+/// A chunk to be read for a peer, off the event loop
+enum ChunkRead {
+    Memory {
+        data: Arc<Vec<u8>>,
+        range: Range<usize>,
+    },
+    File {
+        path: PathBuf,
+        offset: u64,
+        len: usize,
+    },
+}
+
+impl ChunkRead {
+    /// Read the chunk, on the blocking pool for files so a slow disk doesn't
+    /// hold up the swarm
+    async fn load(self) -> std::io::Result<Vec<u8>> {
+        match self {
+            ChunkRead::Memory { data, range } => Ok(data[range].to_vec()),
+            ChunkRead::File { path, offset, len } => tokio::task::spawn_blocking(move || {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(offset))?;
+                let mut data = vec![0u8; len];
+                file.read_exact(&mut data)?;
+                Ok(data)
+            })
+            .await
+            .map_err(std::io::Error::other)?,
+        }
+    }
+}
+
+/// The answer to an inbound transfer request
+enum Reply {
+    /// Ready to send
+    Ready(TransferResponse),
+    /// Chunk `index` of `cid`, still to be read
+    Chunk {
+        cid: ContentId,
+        index: u32,
+        read: ChunkRead,
+    },
+}
+
+impl ChunkRead {
+    fn len(&self) -> usize {
+        match self {
+            ChunkRead::Memory { range, .. } => range.len(),
+            ChunkRead::File { len, .. } => *len,
+        }
+    }
+}
+
+/// Decides which peers chunks are uploaded to, e.g. by choking.
+///
+/// Asked before every chunk the manager serves; a peer it refuses gets
+/// [`ErrorCode::Refused`] and can try another provider or ask again later.
+pub trait UploadGate: Send + Sync {
+    /// Whether to serve a chunk of `cid` to `peer` now. `seeding` says
+    /// whether we hold every chunk of `cid`.
+    fn allow(&self, peer: &PeerId, cid: &ContentId, seeding: bool) -> bool;
+
+    /// Records `bytes` of `cid` served to `peer`
+    fn served(&self, peer: &PeerId, cid: &ContentId, bytes: u64);
+}
+
+/// A transfer request waiting for a connection to its peer
+type QueuedRequest = (TransferRequest, oneshot::Sender<Result<TransferResponse>>);
+
+/// Requests sent from a [`NetworkHandle`] to the task driving the swarm
+enum Command {
+    /// Send a transfer request and report the response
+    Request {
+        peer_id: PeerId,
+        request: TransferRequest,
+        reply: oneshot::Sender<Result<TransferResponse>>,
+    },
+    /// Look up providers on the DHT
+    FindProviders {
+        cid: ContentId,
+        reply: oneshot::Sender<HashSet<PeerId>>,
+    },
+    /// Serve the chunks of a download as they complete
+    Share {
+        cid: ContentId,
+        manifest: ContentManifest,
+    },
+    /// Serve the chunks of a download from the file it is written to
+    ShareFile {
+        cid: ContentId,
+        manifest: ContentManifest,
+        path: PathBuf,
+    },
+    /// Store a completed chunk and send HAVEs for it
+    Have {
+        cid: ContentId,
+        index: u32,
+        data: Vec<u8>,
+    },
+    /// Mark a chunk written to a shared file as complete and send HAVEs for it
+    Stored { cid: ContentId, index: u32 },
+    /// Report which chunks each peer holds
+    Availability {
+        cid: ContentId,
+        reply: oneshot::Sender<HashMap<PeerId, Bitfield>>,
+    },
+    /// Send a chunk to `peer` that waited for upload bandwidth
+    Respond {
+        peer: PeerId,
+        channel: request_response::ResponseChannel<TransferResponse>,
+        response: TransferResponse,
+    },
+    /// Ask `gate` before serving chunks
+    SetUploadGate { gate: Arc<dyn UploadGate> },
+}
+
+/// Cloneable handle for talking to a running [`NetworkManager`].
+///
+/// Requests are forwarded to whichever task drives the manager (`run()`,
+/// `next_event()`, ...), so a handle can be used from other tasks while the
+/// manager itself is busy in its event loop.
+#[derive(Debug, Clone)]
+pub struct NetworkHandle {
+    /// Local peer ID of the manager
+    peer_id: PeerId,
+    /// Channel to the manager
+    command_tx: mpsc::UnboundedSender<Command>,
+    /// Transfer request timeout
+    transfer_timeout: Duration,
+    /// Upload limits of the manager
+    rate_limiter: Arc<RateLimiter<PeerId>>,
+}
+
+impl NetworkHandle {
+    /// Returns the local peer ID of the manager behind this handle
+    pub fn peer_id(&self) -> PeerId {
+        self.peer_id
+    }
+
+    /// Sends a transfer request to a peer and waits for its response.
+    ///
+    /// The peer is dialed if necessary, using addresses known to Kademlia.
+    /// Error responses are returned as-is; see [`fetch_manifest`](Self::fetch_manifest)
+    /// and [`fetch_chunk`](Self::fetch_chunk) for the checked variants.
+    pub async fn request(
+        &self,
+        peer_id: PeerId,
+        request: TransferRequest,
+    ) -> Result<TransferResponse> {
+        debug!("Sending {:?} to {}", request, peer_id);
+
+        let (reply, rx) = oneshot::channel();
+        self.command_tx
+            .send(Command::Request {
+                peer_id,
+                request,
+                reply,
+            })
+            .map_err(|_| anyhow!("Network manager has shut down"))?;
+
+        // The behaviour enforces the request timeout itself; the outer deadline
+        // only guards against a manager that stopped being driven.
+        let deadline = self.transfer_timeout + Duration::from_secs(1);
+        match tokio::time::timeout(deadline, rx).await {
+            Ok(Ok(response)) => response,
+            Ok(Err(_)) => Err(anyhow!("Network manager dropped the request")),
+            Err(_) => Err(anyhow!(
+                "Request to {} timed out after {:?}",
+                peer_id,
+                deadline
+            )),
+        }
+    }
+
+    /// Requests the manifest of `cid` from a peer.
+    ///
+    /// Fails with a [`RemoteError`] if the peer can't serve it, or if the
+    /// manifest it sends back is malformed.
+    pub async fn fetch_manifest(
+        &self,
+        peer_id: PeerId,
+        cid: &ContentId,
+    ) -> Result<ContentManifest> {
+        let response = self
+            .request(peer_id, TransferRequest::Manifest { cid: cid.clone() })
+            .await?;
+
+        match response {
+            TransferResponse::Manifest(manifest) => {
+                if !manifest.is_consistent() {
+                    anyhow::bail!("Peer {} sent an inconsistent manifest for {}", peer_id, cid);
+                }
+                Ok(manifest)
+            }
+            TransferResponse::Error { code, message } => Err(RemoteError { code, message })
+                .with_context(|| format!("Peer {} could not serve manifest for {}", peer_id, cid)),
+            other => anyhow::bail!("Unexpected response from {}: {:?}", peer_id, other),
+        }
+    }
+
+    /// Requests chunk `index` of `cid` from a peer.
+    ///
+    /// The returned bytes are exactly what the peer sent; callers must verify
+    /// them against the manifest before trusting them.
+    pub async fn fetch_chunk(
+        &self,
+        peer_id: PeerId,
+        cid: &ContentId,
+        index: u32,
+    ) -> Result<Vec<u8>> {
+        let response = self
+            .request(
+                peer_id,
+                TransferRequest::Chunk {
+                    cid: cid.clone(),
+                    index,
+                },
+            )
+            .await?;
+
+        match response {
+            TransferResponse::Chunk {
+                index: received,
+                data,
+            } => {
+                if received != index {
+                    anyhow::bail!(
+                        "Peer {} sent chunk {} when asked for chunk {}",
+                        peer_id,
+                        received,
+                        index
+                    );
+                }
+                Ok(data)
+            }
+            TransferResponse::Error { code, message } => Err(RemoteError { code, message })
+                .with_context(|| {
+                    format!(
+                        "Peer {} could not serve chunk {} of {}",
+                        peer_id, index, cid
+                    )
+                }),
+            other => anyhow::bail!("Unexpected response from {}: {:?}", peer_id, other),
+        }
+    }
+
+    /// Swaps bitfields with a peer for `cid`.
+    ///
+    /// Sends the chunks we hold and returns the ones the peer holds. From then
+    /// on each side sends the other a HAVE as it completes chunks, which
+    /// [`availability`](Self::availability) reflects.
+    pub async fn exchange_bitfield(
+        &self,
+        peer_id: PeerId,
+        cid: &ContentId,
+        bitfield: Bitfield,
+    ) -> Result<Bitfield> {
+        let response = self
+            .request(
+                peer_id,
+                TransferRequest::Bitfield {
+                    cid: cid.clone(),
+                    bitfield,
+                },
+            )
+            .await?;
+
+        match response {
+            TransferResponse::Bitfield(bitfield) => Ok(bitfield),
+            TransferResponse::Error { code, message } => Err(RemoteError { code, message })
+                .with_context(|| format!("Peer {} could not send a bitfield for {}", peer_id, cid)),
+            other => anyhow::bail!("Unexpected response from {}: {:?}", peer_id, other),
+        }
+    }
+
+    /// Starts serving the chunks of `cid` as they are downloaded.
+    ///
+    /// The content is announced on the DHT like
+    /// [`NetworkManager::provide_content`], but only chunks passed to
+    /// [`announce_have`](Self::announce_have) are served. Content already
+    /// provided in full is left alone.
+    pub fn start_sharing(&self, cid: &ContentId, manifest: ContentManifest) -> Result<()> {
+        self.command_tx
+            .send(Command::Share {
+                cid: cid.clone(),
+                manifest,
+            })
+            .map_err(|_| anyhow!("Network manager has shut down"))
+    }
+
+    /// Starts serving the chunks of `cid` from `path` as they are written.
+    ///
+    /// Like [`start_sharing`](Self::start_sharing), for downloads written
+    /// straight to disk: chunks passed to
+    /// [`announce_stored`](Self::announce_stored) are read back from `path`
+    /// when a peer asks, so none are held in memory. Replaces an earlier
+    /// [`start_sharing`](Self::start_sharing) of `cid`; content already
+    /// provided in full is left alone.
+    pub fn share_file(
+        &self,
+        cid: &ContentId,
+        manifest: ContentManifest,
+        path: impl Into<PathBuf>,
+    ) -> Result<()> {
+        self.command_tx
+            .send(Command::ShareFile {
+                cid: cid.clone(),
+                manifest,
+                path: path.into(),
+            })
+            .map_err(|_| anyhow!("Network manager has shut down"))
+    }
+
+    /// Tells the manager a verified chunk is in the file shared with
+    /// [`share_file`](Self::share_file).
+    ///
+    /// The chunk is served from then on, and every peer we swapped bitfields
+    /// with for `cid` is sent a HAVE for it. The manager doesn't check the
+    /// chunk, so it must be written and verified before this is called.
+    pub fn announce_stored(&self, cid: &ContentId, index: u32) -> Result<()> {
+        self.command_tx
+            .send(Command::Stored {
+                cid: cid.clone(),
+                index,
+            })
+            .map_err(|_| anyhow!("Network manager has shut down"))
+    }
+
+    /// Hands a completed, verified chunk of shared content to the manager.
+    ///
+    /// The chunk is served from then on, and every peer we swapped bitfields
+    /// with for `cid` is sent a HAVE for it.
+    pub fn announce_have(&self, cid: &ContentId, index: u32, data: Vec<u8>) -> Result<()> {
+        self.command_tx
+            .send(Command::Have {
+                cid: cid.clone(),
+                index,
+                data,
+            })
+            .map_err(|_| anyhow!("Network manager has shut down"))
+    }
+
+    /// Returns the chunks of `cid` each connected peer is known to hold.
+    ///
+    /// Covers the peers we swapped bitfields with, updated by their HAVEs.
+    pub async fn availability(&self, cid: &ContentId) -> Result<HashMap<PeerId, Bitfield>> {
+        let (reply, rx) = oneshot::channel();
+        self.command_tx
+            .send(Command::Availability {
+                cid: cid.clone(),
+                reply,
+            })
+            .map_err(|_| anyhow!("Network manager has shut down"))?;
+
+        rx.await
+            .map_err(|_| anyhow!("Network manager dropped the request"))
+    }
+
+    /// Changes the bandwidth limits for chunks served to other peers.
+    ///
+    /// Takes effect for the next response; only the upload limits apply.
+    pub fn set_rate_limits(&self, limits: RateLimits) {
+        self.rate_limiter.set_limits(limits);
+    }
+
+    /// Returns the bandwidth limits in force.
+    pub fn rate_limits(&self) -> RateLimits {
+        self.rate_limiter.limits()
+    }
+
+    /// Has the manager ask `gate` before serving each chunk, as
+    /// [`NetworkManager::set_upload_gate`] does.
+    pub fn set_upload_gate(&self, gate: Arc<dyn UploadGate>) -> Result<()> {
+        self.command_tx
+            .send(Command::SetUploadGate { gate })
+            .map_err(|_| anyhow!("Network manager has shut down"))
+    }
+
+    /// Finds peers providing the given content.
+    ///
+    /// An empty list means nobody could be found before `timeout` elapsed.
+    pub async fn find_providers(&self, cid: &ContentId, timeout: Duration) -> Result<Vec<PeerId>> {
+        let (reply, rx) = oneshot::channel();
+        self.command_tx
+            .send(Command::FindProviders {
+                cid: cid.clone(),
+                reply,
+            })
+            .map_err(|_| anyhow!("Network manager has shut down"))?;
+
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(providers)) => Ok(providers
+                .into_iter()
+                .filter(|peer_id| *peer_id != self.peer_id)
+                .collect()),
+            Ok(Err(_)) => Err(anyhow!("Network manager dropped the request")),
+            Err(_) => Ok(Vec::new()),
+        }
+    }
+}
+
 /// Network behaviour combining multiple libp2p protocols
 #[derive(NetworkBehaviour)]
 pub struct CodioNetworkBehaviour {
@@ -127,6 +607,8 @@ pub struct CodioNetworkBehaviour {
     ping: ping::Behaviour,
     /// Identify protocol for peer metadata exchange
     identify: identify::Behaviour,
+    /// Content transfer request/response protocol
+    transfer: request_response::Behaviour<TransferCodec>,
 }
 
 /// Main network manager for the P2P layer
@@ -149,6 +631,30 @@ pub struct NetworkManager {
     bootstrap_completed: bool,
     /// Pending connections
     pending_connections: HashMap<PeerId, Vec<Multiaddr>>,
+    /// Content served to other peers, keyed by routing key so any CID
+    /// version finds it
+    local_content: HashMap<Vec<u8>, LocalContent>,
+    /// Provider lookups waiting for a result
+    pending_provider_queries: HashMap<kad::QueryId, oneshot::Sender<HashSet<PeerId>>>,
+    /// Outbound transfer requests waiting for a response
+    pending_transfers: HashMap<OutboundRequestId, oneshot::Sender<Result<TransferResponse>>>,
+    /// Chunks each peer holds of local content, keyed by routing key; these
+    /// are the peers sent a HAVE when we complete a chunk
+    availability: HashMap<Vec<u8>, HashMap<PeerId, Bitfield>>,
+    /// Outbound bitfield swaps waiting for a response, with their routing key
+    pending_bitfields: HashMap<OutboundRequestId, Vec<u8>>,
+    /// Transfer requests held back until an in-flight dial completes
+    queued_requests: HashMap<PeerId, Vec<QueuedRequest>>,
+    /// Pacing for the chunks we serve
+    rate_limiter: Arc<RateLimiter<PeerId>>,
+    /// Chunks accepted for each peer and not yet sent
+    upload_queues: HashMap<PeerId, usize>,
+    /// Decides which peers are served chunks; all of them without one
+    upload_gate: Option<Arc<dyn UploadGate>>,
+    /// Command channel used by handles
+    command_tx: mpsc::UnboundedSender<Command>,
+    /// Command receiver
+    command_rx: mpsc::UnboundedReceiver<Command>,
 }
 
 impl NetworkManager {
@@ -183,9 +689,11 @@ impl NetworkManager {
         let kademlia_replication_factor = config.kademlia_replication_factor;
         let bootstrap_peers = config.bootstrap_peers.clone();
         let idle_timeout = config.idle_connection_timeout;
+        let transfer_timeout = config.transfer_timeout;
+        let rate_limits = config.rate_limits;
 
         // Build the swarm using the new builder API
-        let swarm = SwarmBuilder::with_existing_identity(local_key)
+        let mut swarm = SwarmBuilder::with_existing_identity(local_key)
             .with_tokio()
             .with_tcp(
                 tcp::Config::default(),
@@ -247,11 +755,18 @@ impl NetworkManager {
                         .with_timeout(Duration::from_secs(10)),
                 );
 
+                // Create content transfer protocol
+                let transfer = request_response::Behaviour::new(
+                    [(TRANSFER_PROTOCOL, ProtocolSupport::Full)],
+                    request_response::Config::default().with_request_timeout(transfer_timeout),
+                );
+
                 Ok(CodioNetworkBehaviour {
                     mdns,
                     kademlia,
                     ping,
                     identify,
+                    transfer,
                 })
             })?
             .with_swarm_config(|c| {
@@ -259,6 +774,14 @@ impl NetworkManager {
             })
             .build();
 
+        // Kademlia only answers queries in server mode, which it otherwise enters
+        // once an external address is confirmed. Peers on a LAN or loopback never
+        // get one, so force it or provider lookups against us would go unanswered.
+        swarm
+            .behaviour_mut()
+            .kademlia
+            .set_mode(Some(kad::Mode::Server));
+
         // Log bootstrap peers after swarm creation
         for addr in &bootstrap_peers {
             if let Some(peer_id) = extract_peer_id(addr) {
@@ -270,6 +793,7 @@ impl NetworkManager {
 
         // Create event channel
         let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
 
         Ok(Self {
             swarm,
@@ -281,6 +805,17 @@ impl NetworkManager {
             event_rx,
             bootstrap_completed: false,
             pending_connections: HashMap::new(),
+            local_content: HashMap::new(),
+            pending_provider_queries: HashMap::new(),
+            pending_transfers: HashMap::new(),
+            availability: HashMap::new(),
+            pending_bitfields: HashMap::new(),
+            queued_requests: HashMap::new(),
+            rate_limiter: Arc::new(RateLimiter::new(rate_limits)),
+            upload_queues: HashMap::new(),
+            upload_gate: None,
+            command_tx,
+            command_rx,
         })
     }
 
@@ -304,6 +839,42 @@ impl NetworkManager {
         self.connected_peers.len()
     }
 
+    /// Returns a handle for issuing requests while the manager runs.
+    ///
+    /// Requests made through the handle are only processed while the manager
+    /// is being driven, typically by `run()` on a spawned task.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use codio_content_id::ContentId;
+    /// use codio_network::{NetworkManager, NetworkConfig};
+    /// use libp2p::PeerId;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let mut manager = NetworkManager::new(NetworkConfig::default()).await?;
+    ///     manager.start().await?;
+    ///
+    ///     let handle = manager.handle();
+    ///     tokio::spawn(async move { manager.run().await });
+    ///
+    ///     # let provider = PeerId::random();
+    ///     let cid = ContentId::new(b"Hello, Codio!");
+    ///     let manifest = handle.fetch_manifest(provider, &cid).await?;
+    ///     println!("{} chunks", manifest.chunk_count());
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn handle(&self) -> NetworkHandle {
+        NetworkHandle {
+            peer_id: self.peer_id,
+            command_tx: self.command_tx.clone(),
+            transfer_timeout: self.config.transfer_timeout,
+            rate_limiter: Arc::clone(&self.rate_limiter),
+        }
+    }
+
     /// Starts the network manager and begins listening for connections.
     ///
     /// This method starts listening on the configured port and begins
@@ -448,6 +1019,7 @@ impl NetworkManager {
 
         // Remove from connected peers
         self.connected_peers.remove(&peer_id);
+        self.forget_availability(&peer_id);
 
         // Emit disconnect event
         let _ = self
@@ -492,6 +1064,11 @@ impl NetworkManager {
             event = self.event_rx.recv() => {
                 event
             }
+            // Serve requests from handles
+            Some(command) = self.command_rx.recv() => {
+                self.handle_command(command);
+                None
+            }
         }
     }
 
@@ -516,8 +1093,160 @@ impl NetworkManager {
         info!("Starting network event loop");
 
         loop {
-            let event = self.swarm.select_next_some().await;
-            self.handle_swarm_event(event).await;
+            tokio::select! {
+                event = self.swarm.select_next_some() => {
+                    self.handle_swarm_event(event).await;
+                }
+                Some(command) = self.command_rx.recv() => {
+                    self.handle_command(command);
+                }
+            }
+        }
+    }
+
+    /// Handles a command sent through a [`NetworkHandle`]
+    fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::Request {
+                peer_id,
+                request,
+                reply,
+            } => {
+                // The transfer behaviour dials on its own and fails the request if
+                // that dial loses the race against ours, so wait for ours instead.
+                if self.pending_connections.contains_key(&peer_id) {
+                    self.queued_requests
+                        .entry(peer_id)
+                        .or_default()
+                        .push((request, reply));
+                } else {
+                    self.send_transfer_request(peer_id, request, reply);
+                }
+            }
+            Command::FindProviders { cid, reply } => {
+                let query_id = self.get_providers(kad::RecordKey::new(&cid.routing_key()));
+                self.pending_provider_queries.insert(query_id, reply);
+            }
+            Command::Share { cid, manifest } => {
+                if self.local_content.contains_key(&cid.routing_key()) {
+                    return;
+                }
+                let have = Bitfield::new(manifest.chunk_count());
+                let source = ContentSource::Chunks(HashMap::new());
+                if let Err(e) = self.add_local_content(&cid, manifest, source, have) {
+                    warn!("Failed to share {}: {}", cid, e);
+                }
+            }
+            Command::ShareFile {
+                cid,
+                manifest,
+                path,
+            } => {
+                let complete = self
+                    .local_content
+                    .get(&cid.routing_key())
+                    .is_some_and(|content| content.have.is_complete());
+                if complete {
+                    return;
+                }
+                let have = Bitfield::new(manifest.chunk_count());
+                let source = ContentSource::File { path, offset: 0 };
+                if let Err(e) = self.add_local_content(&cid, manifest, source, have) {
+                    warn!("Failed to share {}: {}", cid, e);
+                }
+            }
+            Command::Have { cid, index, data } => self.complete_chunk(cid, index, data),
+            Command::Stored { cid, index } => self.complete_stored_chunk(cid, index),
+            Command::Availability { cid, reply } => {
+                let peers = self
+                    .availability
+                    .get(&cid.routing_key())
+                    .cloned()
+                    .unwrap_or_default();
+                let _ = reply.send(peers);
+            }
+            Command::Respond {
+                peer,
+                channel,
+                response,
+            } => {
+                if let Some(queued) = self.upload_queues.get_mut(&peer) {
+                    *queued -= 1;
+                    if *queued == 0 {
+                        self.upload_queues.remove(&peer);
+                    }
+                }
+                self.send_response(channel, response);
+            }
+            Command::SetUploadGate { gate } => self.set_upload_gate(gate),
+        }
+    }
+
+    /// Stores a chunk of shared content and sends HAVEs for it
+    fn complete_chunk(&mut self, cid: ContentId, index: u32, data: Vec<u8>) {
+        let routing_key = cid.routing_key();
+        let Some(content) = self.local_content.get_mut(&routing_key) else {
+            debug!("Ignoring chunk {} of {}, which is not shared", index, cid);
+            return;
+        };
+        if content.have.has(index) {
+            return;
+        }
+        let ContentSource::Chunks(chunks) = &mut content.data else {
+            return;
+        };
+        if !content.manifest.verify_chunk(index, &data) {
+            warn!(
+                "Refusing to share chunk {} of {}: hash mismatch",
+                index, cid
+            );
+            return;
+        }
+        chunks.insert(index, Arc::new(data));
+        content.have.set(index);
+
+        self.send_haves(&cid, index);
+    }
+
+    /// Marks a chunk already written to a shared file as served and sends
+    /// HAVEs for it
+    fn complete_stored_chunk(&mut self, cid: ContentId, index: u32) {
+        let Some(content) = self.local_content.get_mut(&cid.routing_key()) else {
+            debug!("Ignoring chunk {} of {}, which is not shared", index, cid);
+            return;
+        };
+        if content.have.has(index) || !matches!(content.data, ContentSource::File { .. }) {
+            return;
+        }
+        content.have.set(index);
+
+        self.send_haves(&cid, index);
+    }
+
+    /// Sends a HAVE for chunk `index` of `cid` to every peer we swapped
+    /// bitfields with
+    fn send_haves(&mut self, cid: &ContentId, index: u32) {
+        let routing_key = cid.routing_key();
+        let peers: Vec<PeerId> = self
+            .availability
+            .get(&routing_key)
+            .map(|peers| peers.keys().copied().collect())
+            .unwrap_or_default();
+        for peer_id in peers {
+            // Nobody waits for the ack
+            let (reply, _) = oneshot::channel();
+            let request = TransferRequest::Have {
+                cid: cid.clone(),
+                index,
+            };
+            self.send_transfer_request(peer_id, request, reply);
+        }
+    }
+
+    /// Drops a disconnected peer from every availability map
+    fn forget_availability(&mut self, peer_id: &PeerId) {
+        for peers in self.availability.values_mut() {
+            peers.remove(peer_id);
         }
     }
 
@@ -551,14 +1280,18 @@ impl NetworkManager {
                     e.insert(peer_info);
 
                     // Emit connected event
-                    let _ = self.event_tx.send(NetworkEvent::PeerConnected {
-                        peer_id,
-                        address,
-                    });
+                    let _ = self
+                        .event_tx
+                        .send(NetworkEvent::PeerConnected { peer_id, address });
                 }
 
                 // Remove from pending connections
                 self.pending_connections.remove(&peer_id);
+
+                // Release requests that were waiting for this connection
+                for (request, reply) in self.queued_requests.remove(&peer_id).unwrap_or_default() {
+                    self.send_transfer_request(peer_id, request, reply);
+                }
             }
 
             SwarmEvent::ConnectionClosed {
@@ -575,6 +1308,8 @@ impl NetworkManager {
                 // If this was the last connection, remove peer info
                 if num_established == 0 {
                     self.connected_peers.remove(&peer_id);
+                    self.forget_availability(&peer_id);
+                    self.rate_limiter.forget(&peer_id);
 
                     // Emit disconnect event
                     let _ = self
@@ -595,6 +1330,11 @@ impl NetworkManager {
                 warn!("Outgoing connection error to {:?}: {}", peer_id, error);
                 if let Some(peer_id) = peer_id {
                     self.pending_connections.remove(&peer_id);
+
+                    for (_, reply) in self.queued_requests.remove(&peer_id).unwrap_or_default() {
+                        let _ =
+                            reply.send(Err(anyhow!("Failed to connect to {}: {}", peer_id, error)));
+                    }
                 }
 
                 let _ = self.event_tx.send(NetworkEvent::Error {
@@ -675,7 +1415,7 @@ impl NetworkManager {
                         }
                     }
 
-                    kad::Event::OutboundQueryProgressed { result, .. } => match result {
+                    kad::Event::OutboundQueryProgressed { id, result, .. } => match result {
                         kad::QueryResult::Bootstrap(Ok(kad::BootstrapOk { peer, .. })) => {
                             info!("Kademlia bootstrap succeeded with peer: {}", peer);
                             if !self.bootstrap_completed {
@@ -686,11 +1426,28 @@ impl NetworkManager {
                         kad::QueryResult::Bootstrap(Err(e)) => {
                             warn!("Kademlia bootstrap error: {:?}", e);
                         }
-                        kad::QueryResult::GetProviders(Ok(_ok)) => {
+                        kad::QueryResult::GetProviders(Ok(ok)) => {
                             debug!("Kademlia GetProviders query completed");
+
+                            // Answer on the first batch of providers; later steps of
+                            // the same query find the waiter already gone.
+                            let providers = match ok {
+                                kad::GetProvidersOk::FoundProviders { providers, .. } => providers,
+                                kad::GetProvidersOk::FinishedWithNoAdditionalRecord { .. } => {
+                                    HashSet::new()
+                                }
+                            };
+
+                            if let Some(tx) = self.pending_provider_queries.remove(&id) {
+                                let _ = tx.send(providers);
+                            }
                         }
                         kad::QueryResult::GetProviders(Err(e)) => {
                             debug!("Kademlia GetProviders failed: {:?}", e);
+
+                            if let Some(tx) = self.pending_provider_queries.remove(&id) {
+                                let _ = tx.send(HashSet::new());
+                            }
                         }
                         kad::QueryResult::StartProviding(Ok(_)) => {
                             debug!("Kademlia StartProviding succeeded");
@@ -761,9 +1518,337 @@ impl NetworkManager {
                     }
                 }
             }
+
+            // Content transfer events
+            CodioNetworkBehaviourEvent::Transfer(transfer_event) => {
+                self.handle_transfer_event(transfer_event);
+            }
+        }
+    }
+
+    /// Handles content transfer protocol events
+    fn handle_transfer_event(
+        &mut self,
+        event: request_response::Event<TransferRequest, TransferResponse>,
+    ) {
+        match event {
+            request_response::Event::Message { peer, message } => match message {
+                request_response::Message::Request {
+                    request, channel, ..
+                } => match self.serve_request(peer, request) {
+                    Reply::Ready(response) => self.send_response(channel, response),
+                    Reply::Chunk { cid, index, read } => {
+                        self.send_chunk(peer, channel, cid, index, read)
+                    }
+                },
+                request_response::Message::Response {
+                    request_id,
+                    response,
+                } => {
+                    if let Some(routing_key) = self.pending_bitfields.remove(&request_id) {
+                        if let TransferResponse::Bitfield(bitfield) = &response {
+                            self.record_bitfield(routing_key, peer, bitfield.clone());
+                        }
+                    }
+                    if let Some(tx) = self.pending_transfers.remove(&request_id) {
+                        let _ = tx.send(Ok(response));
+                    }
+                }
+            },
+
+            request_response::Event::OutboundFailure {
+                peer,
+                request_id,
+                error,
+            } => {
+                warn!("Transfer request to {} failed: {}", peer, error);
+                self.pending_bitfields.remove(&request_id);
+
+                if let Some(tx) = self.pending_transfers.remove(&request_id) {
+                    let _ = tx.send(Err(anyhow!("Request to {} failed: {}", peer, error)));
+                }
+            }
+
+            request_response::Event::InboundFailure { peer, error, .. } => {
+                warn!("Inbound transfer from {} failed: {}", peer, error);
+            }
+
+            request_response::Event::ResponseSent { peer, .. } => {
+                debug!("Transfer response sent to {}", peer);
+            }
+        }
+    }
+
+    /// Hands a transfer request to the behaviour and tracks its reply
+    fn send_transfer_request(
+        &mut self,
+        peer_id: PeerId,
+        request: TransferRequest,
+        reply: oneshot::Sender<Result<TransferResponse>>,
+    ) {
+        let routing_key = match &request {
+            TransferRequest::Bitfield { cid, .. } => Some(cid.routing_key()),
+            _ => None,
+        };
+        let request_id = self
+            .swarm
+            .behaviour_mut()
+            .transfer
+            .send_request(&peer_id, request);
+        self.pending_transfers.insert(request_id, reply);
+        if let Some(routing_key) = routing_key {
+            self.pending_bitfields.insert(request_id, routing_key);
+        }
+    }
+
+    /// Remembers a peer's bitfield for content we hold
+    fn record_bitfield(&mut self, routing_key: Vec<u8>, peer: PeerId, bitfield: Bitfield) {
+        let Some(content) = self.local_content.get(&routing_key) else {
+            return;
+        };
+        if bitfield.len() != content.manifest.chunk_count() {
+            warn!(
+                "Peer {} sent a bitfield of {} chunks for content of {}",
+                peer,
+                bitfield.len(),
+                content.manifest.chunk_count()
+            );
+            return;
+        }
+        self.availability
+            .entry(routing_key)
+            .or_default()
+            .insert(peer, bitfield);
+    }
+
+    /// Sends the response to an inbound transfer request
+    fn send_response(
+        &mut self,
+        channel: request_response::ResponseChannel<TransferResponse>,
+        response: TransferResponse,
+    ) {
+        if self
+            .swarm
+            .behaviour_mut()
+            .transfer
+            .send_response(channel, response)
+            .is_err()
+        {
+            warn!("Failed to send transfer response, the peer is gone");
+        }
+    }
+
+    /// Queues a chunk for `peer` and sends it once upload bandwidth allows
+    ///
+    /// Each peer gets at most [`MAX_QUEUED_UPLOADS`] chunks queued, and none
+    /// that would wait longer than the configured `max_upload_wait`; past
+    /// that it is told we're busy, rather than left to time out. The chunk
+    /// is only read once its wait is over, on its own task so the event loop
+    /// carries on meanwhile.
+    fn send_chunk(
+        &mut self,
+        peer: PeerId,
+        channel: request_response::ResponseChannel<TransferResponse>,
+        cid: ContentId,
+        index: u32,
+        read: ChunkRead,
+    ) {
+        let busy = TransferResponse::Error {
+            code: ErrorCode::Refused,
+            message: "Busy".to_string(),
+        };
+        let queued = self.upload_queues.get(&peer).copied().unwrap_or(0);
+        if queued >= MAX_QUEUED_UPLOADS {
+            debug!("Upload queue for {} is full", peer);
+            self.send_response(channel, busy);
+            return;
+        }
+        let bytes = read.len();
+        let Some(wait) = self.rate_limiter.try_reserve(
+            Direction::Upload,
+            &peer,
+            bytes as u64,
+            self.config.max_upload_wait,
+        ) else {
+            debug!("Upload bandwidth to {} is booked up", peer);
+            self.send_response(channel, busy);
+            return;
+        };
+        *self.upload_queues.entry(peer).or_default() += 1;
+
+        debug!(
+            "Serving chunk {} of {} ({} bytes) to {}",
+            index, cid, bytes, peer
+        );
+        if let Some(gate) = &self.upload_gate {
+            gate.served(&peer, &cid, bytes as u64);
+        }
+        let _ = self.event_tx.send(NetworkEvent::ContentServed {
+            peer_id: peer,
+            cid,
+            index,
+            bytes,
+        });
+
+        let command_tx = self.command_tx.clone();
+        tokio::spawn(async move {
+            if !wait.is_zero() {
+                debug!("Holding a chunk for {} back {:?}", peer, wait);
+                tokio::time::sleep(wait).await;
+            }
+            let response = match read.load().await {
+                Ok(data) => TransferResponse::Chunk { index, data },
+                Err(e) => {
+                    warn!("Failed to read chunk {}: {}", index, e);
+                    TransferResponse::Error {
+                        code: ErrorCode::Internal,
+                        message: format!("Failed to read chunk {}", index),
+                    }
+                }
+            };
+            let _ = command_tx.send(Command::Respond {
+                peer,
+                channel,
+                response,
+            });
+        });
+    }
+
+    /// Builds the response to an inbound transfer request
+    fn serve_request(&mut self, peer: PeerId, request: TransferRequest) -> Reply {
+        match request {
+            TransferRequest::Manifest { cid } => {
+                Reply::Ready(match self.local_content.get(&cid.routing_key()) {
+                    Some(content) => {
+                        debug!("Serving manifest of {} to {}", cid, peer);
+                        TransferResponse::Manifest(content.manifest.clone())
+                    }
+                    None => {
+                        debug!("Peer {} requested unknown content {}", peer, cid);
+                        TransferResponse::Error {
+                            code: ErrorCode::NotFound,
+                            message: format!("Content {} not found", cid),
+                        }
+                    }
+                })
+            }
+
+            TransferRequest::Chunk { cid, index } => self.serve_chunk(peer, cid, index),
+
+            TransferRequest::Bitfield { cid, bitfield } => {
+                let routing_key = cid.routing_key();
+                let Some(content) = self.local_content.get(&routing_key) else {
+                    debug!("Peer {} requested unknown content {}", peer, cid);
+                    return Reply::Ready(TransferResponse::Error {
+                        code: ErrorCode::NotFound,
+                        message: format!("Content {} not found", cid),
+                    });
+                };
+                let have = content.have.clone();
+
+                debug!(
+                    "Swapping bitfields for {} with {} ({} of {} chunks)",
+                    cid,
+                    peer,
+                    bitfield.count(),
+                    bitfield.len()
+                );
+                self.record_bitfield(routing_key, peer, bitfield);
+                Reply::Ready(TransferResponse::Bitfield(have))
+            }
+
+            TransferRequest::Have { cid, index } => {
+                let routing_key = cid.routing_key();
+                let Some(content) = self.local_content.get(&routing_key) else {
+                    return Reply::Ready(TransferResponse::Ack);
+                };
+                if index >= content.manifest.chunk_count() {
+                    return Reply::Ready(TransferResponse::Error {
+                        code: ErrorCode::OutOfRange,
+                        message: format!(
+                            "Chunk {} out of range ({} chunks)",
+                            index,
+                            content.manifest.chunk_count()
+                        ),
+                    });
+                }
+
+                let chunk_count = content.manifest.chunk_count();
+                self.availability
+                    .entry(routing_key)
+                    .or_default()
+                    .entry(peer)
+                    .or_insert_with(|| Bitfield::new(chunk_count))
+                    .set(index);
+                let _ = self.event_tx.send(NetworkEvent::Have {
+                    peer_id: peer,
+                    cid,
+                    index,
+                });
+
+                Reply::Ready(TransferResponse::Ack)
+            }
         }
     }
 
+    /// Checks that a chunk can be served to `peer` and where to read it from
+    fn serve_chunk(&mut self, peer: PeerId, cid: ContentId, index: u32) -> Reply {
+        let Some(content) = self.local_content.get(&cid.routing_key()) else {
+            debug!("Peer {} requested unknown content {}", peer, cid);
+            return Reply::Ready(TransferResponse::Error {
+                code: ErrorCode::NotFound,
+                message: format!("Content {} not found", cid),
+            });
+        };
+
+        let Some(range) = content.manifest.chunk_range(index) else {
+            return Reply::Ready(TransferResponse::Error {
+                code: ErrorCode::OutOfRange,
+                message: format!(
+                    "Chunk {} out of range ({} chunks)",
+                    index,
+                    content.manifest.chunk_count()
+                ),
+            });
+        };
+
+        if !content.have.has(index) {
+            return Reply::Ready(TransferResponse::Error {
+                code: ErrorCode::NotFound,
+                message: format!("Chunk {} not downloaded yet", index),
+            });
+        }
+
+        let seeding = content.have.is_complete();
+        if let Some(gate) = &self.upload_gate {
+            if !gate.allow(&peer, &cid, seeding) {
+                debug!("Peer {} is choked for {}", peer, cid);
+                return Reply::Ready(TransferResponse::Error {
+                    code: ErrorCode::Refused,
+                    message: "Choked".to_string(),
+                });
+            }
+        }
+
+        let Some(read) = content.data.chunk(index, range) else {
+            return Reply::Ready(TransferResponse::Error {
+                code: ErrorCode::NotFound,
+                message: format!("Chunk {} not downloaded yet", index),
+            });
+        };
+
+        Reply::Chunk { cid, index, read }
+    }
+
+    /// Has the manager ask `gate` before serving each chunk, replacing any
+    /// gate set before.
+    ///
+    /// Peers the gate refuses get [`ErrorCode::Refused`]. Manifests,
+    /// bitfields and HAVEs are always answered.
+    pub fn set_upload_gate(&mut self, gate: Arc<dyn UploadGate>) {
+        self.upload_gate = Some(gate);
+    }
+
     /// Adds a peer address to the Kademlia routing table.
     ///
     /// This is useful for adding known peers that may be used for future connections.
@@ -792,6 +1877,148 @@ impl NetworkManager {
         self.swarm.behaviour_mut().kademlia.get_providers(key)
     }
 
+    /// Stores content locally and announces it on the DHT.
+    ///
+    /// The content is split into chunks of `config.chunk_size` bytes and served
+    /// to any peer that requests it over the transfer protocol for as long as
+    /// the swarm keeps being driven (e.g. by `run()`).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use codio_content_id::ContentId;
+    /// use codio_network::{NetworkManager, NetworkConfig};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let mut manager = NetworkManager::new(NetworkConfig::default()).await?;
+    ///     manager.start().await?;
+    ///
+    ///     let data = b"Hello, Codio!".to_vec();
+    ///     manager.provide_content(ContentId::new(&data), data)?;
+    ///     manager.run().await
+    /// }
+    /// ```
+    pub fn provide_content(&mut self, cid: ContentId, data: Vec<u8>) -> Result<()> {
+        if self.config.chunk_size == 0 {
+            anyhow::bail!("Chunk size must be greater than zero");
+        }
+
+        let manifest = ContentManifest::from_content(&data, self.config.chunk_size);
+        let have = Bitfield::full(manifest.chunk_count());
+        self.add_local_content(&cid, manifest, ContentSource::Memory(Arc::new(data)), have)
+    }
+
+    /// Announces a file on the DHT and serves it straight from disk.
+    ///
+    /// Like [`provide_content`](Self::provide_content), but only the chunk
+    /// being served is ever read into memory, so files of any size can be
+    /// provided. The file is read once here to build the chunk manifest; if it
+    /// changes afterwards, peers will reject the chunks that no longer match.
+    pub fn provide_file(&mut self, cid: ContentId, path: impl Into<PathBuf>) -> Result<()> {
+        self.provide_file_range(cid, path, 0..u64::MAX)
+    }
+
+    /// Announces part of a file on the DHT and serves it straight from disk.
+    ///
+    /// Like [`provide_file`](Self::provide_file), for content that is the
+    /// bytes `range` of the file, such as one leaf of a file DAG. A range
+    /// running past the end of the file is cut short.
+    pub fn provide_file_range(
+        &mut self,
+        cid: ContentId,
+        path: impl Into<PathBuf>,
+        range: Range<u64>,
+    ) -> Result<()> {
+        if self.config.chunk_size == 0 {
+            anyhow::bail!("Chunk size must be greater than zero");
+        }
+
+        let path = path.into();
+        let mut file =
+            File::open(&path).with_context(|| format!("Failed to open {}", path.display()))?;
+        file.seek(SeekFrom::Start(range.start))?;
+        let reader = std::io::BufReader::new(file).take(range.end.saturating_sub(range.start));
+        let manifest = ContentManifest::from_reader(reader, self.config.chunk_size)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let have = Bitfield::full(manifest.chunk_count());
+        let source = ContentSource::File {
+            path,
+            offset: range.start,
+        };
+        self.add_local_content(&cid, manifest, source, have)
+    }
+
+    fn add_local_content(
+        &mut self,
+        cid: &ContentId,
+        manifest: ContentManifest,
+        data: ContentSource,
+        have: Bitfield,
+    ) -> Result<()> {
+        let routing_key = cid.routing_key();
+        let key = kad::RecordKey::new(&routing_key);
+        self.local_content.insert(
+            routing_key,
+            LocalContent {
+                manifest,
+                data,
+                have,
+            },
+        );
+        self.start_providing(key)?;
+        Ok(())
+    }
+
+    /// Finds peers providing the given content.
+    ///
+    /// Drives the swarm until the DHT lookup yields its first providers or
+    /// `timeout` elapses. An empty list means nobody could be found.
+    pub async fn find_providers(
+        &mut self,
+        cid: &ContentId,
+        timeout: Duration,
+    ) -> Result<Vec<PeerId>> {
+        let query_id = self.get_providers(kad::RecordKey::new(&cid.routing_key()));
+
+        let (tx, rx) = oneshot::channel();
+        self.pending_provider_queries.insert(query_id, tx);
+
+        match self.wait_for(rx, timeout).await {
+            Ok(providers) => Ok(providers
+                .into_iter()
+                .filter(|peer_id| *peer_id != self.peer_id)
+                .collect()),
+            Err(e) => {
+                self.pending_provider_queries.remove(&query_id);
+                Err(e)
+            }
+        }
+    }
+
+    /// Drives the swarm until `rx` resolves or `timeout` elapses.
+    async fn wait_for<T>(&mut self, mut rx: oneshot::Receiver<T>, timeout: Duration) -> Result<T> {
+        let deadline = tokio::time::sleep(timeout);
+        tokio::pin!(deadline);
+
+        loop {
+            tokio::select! {
+                result = &mut rx => {
+                    return result.map_err(|_| anyhow!("Request was dropped"));
+                }
+                event = self.swarm.select_next_some() => {
+                    self.handle_swarm_event(event).await;
+                }
+                Some(command) = self.command_rx.recv() => {
+                    self.handle_command(command);
+                }
+                _ = &mut deadline => {
+                    anyhow::bail!("Timed out after {:?}", timeout);
+                }
+            }
+        }
+    }
+
     /// Returns whether the bootstrap process has completed.
     pub fn is_bootstrap_completed(&self) -> bool {
         self.bootstrap_completed
@@ -812,8 +2039,15 @@ impl NetworkManager {
     /// This is primarily useful for testing and low-level control.
     /// For normal usage, prefer `run()` or `next_event()`.
     pub async fn poll_once(&mut self) {
-        if let Some(event) = self.swarm.next().await {
-            self.handle_swarm_event(event).await;
+        tokio::select! {
+            event = self.swarm.next() => {
+                if let Some(event) = event {
+                    self.handle_swarm_event(event).await;
+                }
+            }
+            Some(command) = self.command_rx.recv() => {
+                self.handle_command(command);
+            }
         }
     }
 }
@@ -0,0 +1,878 @@
+//! Chunk transfer protocol.
+//!
+//! A request/response protocol, negotiated as `/codio/transfer/1.0.0`, used to
+//! fetch content from providers over the [`NetworkManager`](crate::NetworkManager)
+//! swarm. A download first asks a provider for the content's
+//! [`ContentManifest`] (size, chunk size and per-chunk hashes), then requests
+//! individual chunks, possibly from several providers at once.
+//!
+//! Peers that hold only part of the content describe what they have with a
+//! [`Bitfield`]. Two peers swap bitfields when they first meet over a CID, and
+//! each then sends the other a HAVE for every chunk it completes afterwards.
+//!
+//! ## Wire format
+//!
+//! Every message is a single frame:
+//!
+//! ```text
+//! +--------+----------------+-----------------+
+//! | tag u8 | length u32 BE  | payload         |
+//! +--------+----------------+-----------------+
+//! ```
+//!
+//! | Tag    | Message          | Payload                                              |
+//! |--------|------------------|------------------------------------------------------|
+//! | `0x01` | Manifest request | CID string                                           |
+//! | `0x02` | Chunk request    | index u32, CID string                                |
+//! | `0x03` | Bitfield request | bitfield, CID string                                 |
+//! | `0x04` | Have             | index u32, CID string                                |
+//! | `0x81` | Manifest         | total size u64, chunk size u32, count u32, hashes    |
+//! | `0x82` | Chunk            | index u32, chunk bytes                               |
+//! | `0x83` | Error            | [`ErrorCode`] u16, UTF-8 message                     |
+//! | `0x84` | Bitfield         | bitfield                                             |
+//! | `0x85` | Ack              | empty                                                |
+//!
+//! A bitfield is its chunk count u32 followed by one bit per chunk, most
+//! significant bit first, padded with zero bits to a whole byte.
+//!
+//! All integers are big-endian.
+
+use async_trait::async_trait;
+use codio_content_id::ContentId;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::StreamProtocol;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::io;
+
+/// Protocol name negotiated on transfer streams
+pub const TRANSFER_PROTOCOL: StreamProtocol = StreamProtocol::new("/codio/transfer/1.0.0");
+
+/// Largest frame payload accepted by the codec (64 MB)
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Request tags
+const TAG_REQUEST_MANIFEST: u8 = 0x01;
+const TAG_REQUEST_CHUNK: u8 = 0x02;
+const TAG_REQUEST_BITFIELD: u8 = 0x03;
+const TAG_REQUEST_HAVE: u8 = 0x04;
+
+/// Response tags
+const TAG_RESPONSE_MANIFEST: u8 = 0x81;
+const TAG_RESPONSE_CHUNK: u8 = 0x82;
+const TAG_RESPONSE_ERROR: u8 = 0x83;
+const TAG_RESPONSE_BITFIELD: u8 = 0x84;
+const TAG_RESPONSE_ACK: u8 = 0x85;
+
+/// A request sent to a content provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferRequest {
+    /// Ask for the manifest (size and chunk layout) of a CID
+    Manifest { cid: ContentId },
+
+    /// Ask for a single chunk of a CID
+    Chunk { cid: ContentId, index: u32 },
+
+    /// Swap bitfields: ours for the CID, in exchange for the peer's
+    Bitfield { cid: ContentId, bitfield: Bitfield },
+
+    /// Tell a peer we have completed a chunk of a CID
+    Have { cid: ContentId, index: u32 },
+}
+
+/// A provider's answer to a [`TransferRequest`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferResponse {
+    /// Layout of the requested content
+    Manifest(ContentManifest),
+
+    /// The bytes of one chunk
+    Chunk { index: u32, data: Vec<u8> },
+
+    /// The request could not be served
+    Error { code: ErrorCode, message: String },
+
+    /// The chunks the provider holds of the requested content
+    Bitfield(Bitfield),
+
+    /// A HAVE was received
+    Ack,
+}
+
+/// Reasons a provider can refuse a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// The provider doesn't have the content
+    NotFound,
+
+    /// The chunk index is past the end of the content
+    OutOfRange,
+
+    /// The provider is not uploading to us right now (choked)
+    Refused,
+
+    /// The provider failed while serving the request
+    Internal,
+
+    /// A code this version doesn't know about
+    Unknown(u16),
+}
+
+impl ErrorCode {
+    /// Numeric code used on the wire
+    pub fn as_u16(&self) -> u16 {
+        match self {
+            ErrorCode::NotFound => 1,
+            ErrorCode::OutOfRange => 2,
+            ErrorCode::Refused => 3,
+            ErrorCode::Internal => 4,
+            ErrorCode::Unknown(code) => *code,
+        }
+    }
+
+    /// Decode a wire code
+    pub fn from_u16(code: u16) -> Self {
+        match code {
+            1 => ErrorCode::NotFound,
+            2 => ErrorCode::OutOfRange,
+            3 => ErrorCode::Refused,
+            4 => ErrorCode::Internal,
+            other => ErrorCode::Unknown(other),
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorCode::NotFound => write!(f, "not found"),
+            ErrorCode::OutOfRange => write!(f, "out of range"),
+            ErrorCode::Refused => write!(f, "refused"),
+            ErrorCode::Internal => write!(f, "internal error"),
+            ErrorCode::Unknown(code) => write!(f, "unknown error {}", code),
+        }
+    }
+}
+
+/// An error response received from a remote peer
+///
+/// Returned (wrapped in `anyhow::Error`) by [`NetworkHandle`](crate::NetworkHandle)
+/// methods so callers can tell a refusal apart from a transport failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteError {
+    /// Reason reported by the peer
+    pub code: ErrorCode,
+
+    /// Human readable detail
+    pub message: String,
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.message.is_empty() {
+            write!(f, "Peer responded with {}", self.code)
+        } else {
+            write!(f, "Peer responded with {}: {}", self.code, self.message)
+        }
+    }
+}
+
+impl std::error::Error for RemoteError {}
+
+/// Size and chunk layout of a piece of content
+///
+/// Chunk `i` covers bytes `[i * chunk_size, min((i + 1) * chunk_size, total_size))`
+/// and must hash (SHA-256) to `chunk_hashes[i]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentManifest {
+    /// Total content size in bytes
+    pub total_size: u64,
+
+    /// Size of every chunk except possibly the last
+    pub chunk_size: u32,
+
+    /// SHA-256 of each chunk, in order
+    pub chunk_hashes: Vec<[u8; 32]>,
+}
+
+impl ContentManifest {
+    /// Build the manifest for `data` split into `chunk_size` chunks
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use codio_network::transfer::ContentManifest;
+    ///
+    /// let manifest = ContentManifest::from_content(&[0u8; 1000], 256);
+    /// assert_eq!(manifest.chunk_count(), 4);
+    /// assert_eq!(manifest.chunk_range(3), Some(768..1000));
+    /// ```
+    pub fn from_content(data: &[u8], chunk_size: u32) -> Self {
+        let chunk_hashes = data
+            .chunks(chunk_size.max(1) as usize)
+            .map(|chunk| Sha256::digest(chunk).into())
+            .collect();
+
+        Self {
+            total_size: data.len() as u64,
+            chunk_size,
+            chunk_hashes,
+        }
+    }
+
+    /// Build the manifest for everything `reader` yields, one chunk at a time
+    pub fn from_reader<R: io::Read>(mut reader: R, chunk_size: u32) -> io::Result<Self> {
+        let mut total_size = 0u64;
+        let mut chunk_hashes = Vec::new();
+        let mut buf = Vec::with_capacity(chunk_size.max(1) as usize);
+
+        loop {
+            buf.clear();
+            // Fully qualified: the futures AsyncReadExt methods share these names
+            let mut chunk = io::Read::take(&mut reader, chunk_size.max(1) as u64);
+            io::Read::read_to_end(&mut chunk, &mut buf)?;
+            if buf.is_empty() {
+                break;
+            }
+            total_size += buf.len() as u64;
+            chunk_hashes.push(Sha256::digest(&buf).into());
+        }
+
+        Ok(Self {
+            total_size,
+            chunk_size,
+            chunk_hashes,
+        })
+    }
+
+    /// Number of chunks
+    pub fn chunk_count(&self) -> u32 {
+        self.chunk_hashes.len() as u32
+    }
+
+    /// Byte range covered by a chunk, or `None` if the index is out of range
+    pub fn chunk_range(&self, index: u32) -> Option<std::ops::Range<usize>> {
+        if index >= self.chunk_count() {
+            return None;
+        }
+
+        let start = index as usize * self.chunk_size as usize;
+        let end = std::cmp::min(start + self.chunk_size as usize, self.total_size as usize);
+        Some(start..end)
+    }
+
+    /// Check chunk bytes against the manifest
+    pub fn verify_chunk(&self, index: u32, data: &[u8]) -> bool {
+        match self.chunk_hashes.get(index as usize) {
+            Some(expected) => {
+                let actual: [u8; 32] = Sha256::digest(data).into();
+                &actual == expected
+            }
+            None => false,
+        }
+    }
+
+    /// Check that the declared size agrees with the chunk layout
+    pub fn is_consistent(&self) -> bool {
+        if self.chunk_size == 0 {
+            return self.total_size == 0 && self.chunk_hashes.is_empty();
+        }
+        self.total_size.div_ceil(self.chunk_size as u64) == self.chunk_hashes.len() as u64
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(16 + self.chunk_hashes.len() * 32);
+        buf.extend_from_slice(&self.total_size.to_be_bytes());
+        buf.extend_from_slice(&self.chunk_size.to_be_bytes());
+        buf.extend_from_slice(&(self.chunk_hashes.len() as u32).to_be_bytes());
+        for hash in &self.chunk_hashes {
+            buf.extend_from_slice(hash);
+        }
+        buf
+    }
+
+    fn decode(payload: &[u8]) -> io::Result<Self> {
+        if payload.len() < 16 {
+            return Err(invalid_data("Manifest too short".to_string()));
+        }
+
+        let total_size = u64::from_be_bytes(payload[0..8].try_into().unwrap());
+        let chunk_size = u32::from_be_bytes(payload[8..12].try_into().unwrap());
+        let count = u32::from_be_bytes(payload[12..16].try_into().unwrap()) as usize;
+
+        let hashes = &payload[16..];
+        if hashes.len() != count * 32 {
+            return Err(invalid_data(format!(
+                "Manifest declares {} chunks but carries {} hash bytes",
+                count,
+                hashes.len()
+            )));
+        }
+
+        let chunk_hashes = hashes
+            .chunks_exact(32)
+            .map(|h| h.try_into().unwrap())
+            .collect();
+
+        Ok(Self {
+            total_size,
+            chunk_size,
+            chunk_hashes,
+        })
+    }
+}
+
+/// The set of chunks a peer holds of some content, one bit per chunk
+///
+/// # Example
+///
+/// ```
+/// use codio_network::transfer::Bitfield;
+///
+/// let mut bitfield = Bitfield::new(10);
+/// bitfield.set(0);
+/// bitfield.set(9);
+/// assert!(bitfield.has(9));
+/// assert!(!bitfield.has(5));
+/// assert_eq!(bitfield.count(), 2);
+/// assert_eq!(bitfield.indices().collect::<Vec<_>>(), [0, 9]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bitfield {
+    len: u32,
+    bits: Vec<u8>,
+}
+
+impl Bitfield {
+    /// An empty bitfield for `len` chunks
+    pub fn new(len: u32) -> Self {
+        Self {
+            len,
+            bits: vec![0; (len as usize).div_ceil(8)],
+        }
+    }
+
+    /// A bitfield for `len` chunks with every chunk present
+    pub fn full(len: u32) -> Self {
+        let mut bitfield = Self::new(len);
+        for index in 0..len {
+            bitfield.set(index);
+        }
+        bitfield
+    }
+
+    /// Number of chunks the bitfield covers
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Whether the bitfield covers no chunks
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Whether chunk `index` is present
+    pub fn has(&self, index: u32) -> bool {
+        index < self.len && self.bits[index as usize / 8] & (0x80 >> (index % 8)) != 0
+    }
+
+    /// Mark chunk `index` present; indices past the end are ignored
+    pub fn set(&mut self, index: u32) {
+        if index < self.len {
+            self.bits[index as usize / 8] |= 0x80 >> (index % 8);
+        }
+    }
+
+    /// Number of chunks present
+    pub fn count(&self) -> u32 {
+        self.bits.iter().map(|byte| byte.count_ones()).sum()
+    }
+
+    /// Whether every chunk is present
+    pub fn is_complete(&self) -> bool {
+        self.count() == self.len
+    }
+
+    /// Indices of the chunks present, in order
+    pub fn indices(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.len).filter(|&index| self.has(index))
+    }
+
+    /// The bits, most significant bit first, as sent on the wire
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bits
+    }
+
+    /// Rebuild a bitfield for `len` chunks from [`as_bytes`](Self::as_bytes)
+    ///
+    /// Returns `None` if `bits` is the wrong size for `len` chunks.
+    pub fn from_bytes(len: u32, bits: &[u8]) -> Option<Self> {
+        if bits.len() != (len as usize).div_ceil(8) {
+            return None;
+        }
+
+        let mut bitfield = Self {
+            len,
+            bits: bits.to_vec(),
+        };
+        // Padding bits must not count as chunks
+        if !len.is_multiple_of(8) {
+            if let Some(last) = bitfield.bits.last_mut() {
+                *last &= 0xff << (8 - len % 8);
+            }
+        }
+        Some(bitfield)
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.len.to_be_bytes());
+        buf.extend_from_slice(&self.bits);
+    }
+
+    /// Decode a bitfield off the front of a payload, returning the rest
+    fn decode(payload: &[u8]) -> io::Result<(Self, &[u8])> {
+        let (len, rest) = split_u32(payload)?;
+        let size = (len as usize).div_ceil(8);
+        if rest.len() < size {
+            return Err(invalid_data(format!(
+                "Bitfield declares {} chunks but carries {} bytes",
+                len,
+                rest.len()
+            )));
+        }
+
+        let (bits, rest) = rest.split_at(size);
+        let bitfield = Self::from_bytes(len, bits).expect("sized above");
+        Ok((bitfield, rest))
+    }
+}
+
+/// Length-prefixed binary codec for the transfer protocol
+#[derive(Debug, Clone, Default)]
+pub struct TransferCodec;
+
+#[async_trait]
+impl libp2p::request_response::Codec for TransferCodec {
+    type Protocol = StreamProtocol;
+    type Request = TransferRequest;
+    type Response = TransferResponse;
+
+    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let (tag, payload) = read_frame(io).await?;
+
+        match tag {
+            TAG_REQUEST_MANIFEST => Ok(TransferRequest::Manifest {
+                cid: decode_cid(&payload)?,
+            }),
+            TAG_REQUEST_CHUNK => {
+                let (index, rest) = split_u32(&payload)?;
+                Ok(TransferRequest::Chunk {
+                    cid: decode_cid(rest)?,
+                    index,
+                })
+            }
+            TAG_REQUEST_BITFIELD => {
+                let (bitfield, rest) = Bitfield::decode(&payload)?;
+                Ok(TransferRequest::Bitfield {
+                    cid: decode_cid(rest)?,
+                    bitfield,
+                })
+            }
+            TAG_REQUEST_HAVE => {
+                let (index, rest) = split_u32(&payload)?;
+                Ok(TransferRequest::Have {
+                    cid: decode_cid(rest)?,
+                    index,
+                })
+            }
+            other => Err(invalid_data(format!("Unknown request tag: {:#04x}", other))),
+        }
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let (tag, payload) = read_frame(io).await?;
+
+        match tag {
+            TAG_RESPONSE_MANIFEST => Ok(TransferResponse::Manifest(ContentManifest::decode(
+                &payload,
+            )?)),
+            TAG_RESPONSE_CHUNK => {
+                let (index, data) = split_u32(&payload)?;
+                Ok(TransferResponse::Chunk {
+                    index,
+                    data: data.to_vec(),
+                })
+            }
+            TAG_RESPONSE_ERROR => {
+                if payload.len() < 2 {
+                    return Err(invalid_data("Error response too short".to_string()));
+                }
+                let code = ErrorCode::from_u16(u16::from_be_bytes([payload[0], payload[1]]));
+                let message = String::from_utf8_lossy(&payload[2..]).into_owned();
+                Ok(TransferResponse::Error { code, message })
+            }
+            TAG_RESPONSE_BITFIELD => {
+                let (bitfield, rest) = Bitfield::decode(&payload)?;
+                if !rest.is_empty() {
+                    return Err(invalid_data("Trailing bytes after bitfield".to_string()));
+                }
+                Ok(TransferResponse::Bitfield(bitfield))
+            }
+            TAG_RESPONSE_ACK => Ok(TransferResponse::Ack),
+            other => Err(invalid_data(format!(
+                "Unknown response tag: {:#04x}",
+                other
+            ))),
+        }
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        req: Self::Request,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        match req {
+            TransferRequest::Manifest { cid } => {
+                write_frame(io, TAG_REQUEST_MANIFEST, cid.as_str().as_bytes()).await
+            }
+            TransferRequest::Chunk { cid, index } => {
+                let mut payload = index.to_be_bytes().to_vec();
+                payload.extend_from_slice(cid.as_str().as_bytes());
+                write_frame(io, TAG_REQUEST_CHUNK, &payload).await
+            }
+            TransferRequest::Bitfield { cid, bitfield } => {
+                let mut payload = Vec::new();
+                bitfield.encode(&mut payload);
+                payload.extend_from_slice(cid.as_str().as_bytes());
+                write_frame(io, TAG_REQUEST_BITFIELD, &payload).await
+            }
+            TransferRequest::Have { cid, index } => {
+                let mut payload = index.to_be_bytes().to_vec();
+                payload.extend_from_slice(cid.as_str().as_bytes());
+                write_frame(io, TAG_REQUEST_HAVE, &payload).await
+            }
+        }
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        res: Self::Response,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        match res {
+            TransferResponse::Manifest(manifest) => {
+                write_frame(io, TAG_RESPONSE_MANIFEST, &manifest.encode()).await
+            }
+            TransferResponse::Chunk { index, data } => {
+                let mut payload = Vec::with_capacity(4 + data.len());
+                payload.extend_from_slice(&index.to_be_bytes());
+                payload.extend_from_slice(&data);
+                write_frame(io, TAG_RESPONSE_CHUNK, &payload).await
+            }
+            TransferResponse::Error { code, message } => {
+                let mut payload = code.as_u16().to_be_bytes().to_vec();
+                payload.extend_from_slice(message.as_bytes());
+                write_frame(io, TAG_RESPONSE_ERROR, &payload).await
+            }
+            TransferResponse::Bitfield(bitfield) => {
+                let mut payload = Vec::new();
+                bitfield.encode(&mut payload);
+                write_frame(io, TAG_RESPONSE_BITFIELD, &payload).await
+            }
+            TransferResponse::Ack => write_frame(io, TAG_RESPONSE_ACK, &[]).await,
+        }
+    }
+}
+
+/// Read one `tag | len | payload` frame
+async fn read_frame<T>(io: &mut T) -> io::Result<(u8, Vec<u8>)>
+where
+    T: AsyncRead + Unpin + Send,
+{
+    let mut header = [0u8; 5];
+    io.read_exact(&mut header).await?;
+
+    let tag = header[0];
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+
+    if len > MAX_MESSAGE_SIZE {
+        return Err(invalid_data(format!(
+            "Frame of {} bytes exceeds maximum of {}",
+            len, MAX_MESSAGE_SIZE
+        )));
+    }
+
+    let mut payload = vec![0u8; len];
+    io.read_exact(&mut payload).await?;
+
+    Ok((tag, payload))
+}
+
+/// Write one `tag | len | payload` frame and close the stream
+async fn write_frame<T>(io: &mut T, tag: u8, payload: &[u8]) -> io::Result<()>
+where
+    T: AsyncWrite + Unpin + Send,
+{
+    if payload.len() > MAX_MESSAGE_SIZE {
+        return Err(invalid_data(format!(
+            "Frame of {} bytes exceeds maximum of {}",
+            payload.len(),
+            MAX_MESSAGE_SIZE
+        )));
+    }
+
+    io.write_all(&[tag]).await?;
+    io.write_all(&(payload.len() as u32).to_be_bytes()).await?;
+    io.write_all(payload).await?;
+    io.close().await
+}
+
+/// Split a leading big-endian `u32` off a payload
+fn split_u32(payload: &[u8]) -> io::Result<(u32, &[u8])> {
+    if payload.len() < 4 {
+        return Err(invalid_data("Payload too short".to_string()));
+    }
+    let value = u32::from_be_bytes(payload[0..4].try_into().unwrap());
+    Ok((value, &payload[4..]))
+}
+
+fn decode_cid(payload: &[u8]) -> io::Result<ContentId> {
+    let s = std::str::from_utf8(payload).map_err(|e| invalid_data(e.to_string()))?;
+    ContentId::parse(s).map_err(|e| invalid_data(e.to_string()))
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::io::Cursor;
+    use libp2p::request_response::Codec;
+
+    async fn roundtrip_request(request: TransferRequest) -> TransferRequest {
+        let mut codec = TransferCodec;
+        let mut buf = Cursor::new(Vec::new());
+        codec
+            .write_request(&TRANSFER_PROTOCOL, &mut buf, request)
+            .await
+            .unwrap();
+
+        let mut buf = Cursor::new(buf.into_inner());
+        codec
+            .read_request(&TRANSFER_PROTOCOL, &mut buf)
+            .await
+            .unwrap()
+    }
+
+    async fn roundtrip_response(response: TransferResponse) -> TransferResponse {
+        let mut codec = TransferCodec;
+        let mut buf = Cursor::new(Vec::new());
+        codec
+            .write_response(&TRANSFER_PROTOCOL, &mut buf, response)
+            .await
+            .unwrap();
+
+        let mut buf = Cursor::new(buf.into_inner());
+        codec
+            .read_response(&TRANSFER_PROTOCOL, &mut buf)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_request_roundtrip() {
+        let cid = ContentId::new(b"hello");
+
+        for request in [
+            TransferRequest::Manifest { cid: cid.clone() },
+            TransferRequest::Chunk {
+                cid: cid.clone(),
+                index: 42,
+            },
+            TransferRequest::Bitfield {
+                cid: cid.clone(),
+                bitfield: Bitfield::full(13),
+            },
+            TransferRequest::Bitfield {
+                cid: cid.clone(),
+                bitfield: Bitfield::new(0),
+            },
+            TransferRequest::Have { cid, index: 7 },
+        ] {
+            assert_eq!(roundtrip_request(request.clone()).await, request);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_response_roundtrip() {
+        for response in [
+            TransferResponse::Manifest(ContentManifest::from_content(&[7u8; 1000], 256)),
+            TransferResponse::Manifest(ContentManifest::from_content(&[], 256)),
+            TransferResponse::Chunk {
+                index: 3,
+                data: b"some bytes".to_vec(),
+            },
+            TransferResponse::Chunk {
+                index: 0,
+                data: Vec::new(),
+            },
+            TransferResponse::Error {
+                code: ErrorCode::NotFound,
+                message: "no such content".to_string(),
+            },
+            TransferResponse::Error {
+                code: ErrorCode::Unknown(999),
+                message: String::new(),
+            },
+            TransferResponse::Bitfield(Bitfield::new(9)),
+            TransferResponse::Bitfield(Bitfield::full(16)),
+            TransferResponse::Ack,
+        ] {
+            assert_eq!(roundtrip_response(response.clone()).await, response);
+        }
+    }
+
+    #[test]
+    fn test_manifest_layout() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        let manifest = ContentManifest::from_content(&data, 256);
+
+        assert_eq!(manifest.total_size, 1000);
+        assert_eq!(manifest.chunk_count(), 4);
+        assert!(manifest.is_consistent());
+        assert_eq!(manifest.chunk_range(0), Some(0..256));
+        assert_eq!(manifest.chunk_range(3), Some(768..1000));
+        assert_eq!(manifest.chunk_range(4), None);
+
+        assert!(manifest.verify_chunk(1, &data[256..512]));
+        assert!(!manifest.verify_chunk(1, &data[0..256]));
+        assert!(!manifest.verify_chunk(4, &[]));
+    }
+
+    #[test]
+    fn test_manifest_from_reader_matches_content() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        let manifest = ContentManifest::from_reader(&data[..], 256).unwrap();
+        assert_eq!(manifest, ContentManifest::from_content(&data, 256));
+
+        let empty = ContentManifest::from_reader(io::empty(), 256).unwrap();
+        assert_eq!(empty, ContentManifest::from_content(&[], 256));
+    }
+
+    #[test]
+    fn test_inconsistent_manifest_detected() {
+        let mut manifest = ContentManifest::from_content(&[1u8; 1000], 256);
+        manifest.total_size = 10_000;
+        assert!(!manifest.is_consistent());
+    }
+
+    #[test]
+    fn test_bitfield_layout() {
+        let mut bitfield = Bitfield::new(10);
+        assert_eq!(bitfield.count(), 0);
+        bitfield.set(0);
+        bitfield.set(9);
+        bitfield.set(10);
+        assert_eq!(bitfield.indices().collect::<Vec<_>>(), [0, 9]);
+        assert!(!bitfield.has(10), "Past the end");
+
+        let mut buf = Vec::new();
+        bitfield.encode(&mut buf);
+        assert_eq!(buf, [0, 0, 0, 10, 0b1000_0000, 0b0100_0000], "MSB first");
+
+        let full = Bitfield::full(10);
+        assert!(full.is_complete());
+        assert!(!bitfield.is_complete());
+        assert_eq!(full.count(), 10);
+    }
+
+    #[test]
+    fn test_bitfield_padding_ignored() {
+        let (bitfield, rest) = Bitfield::decode(&[0, 0, 0, 3, 0xff, b'x']).unwrap();
+        assert_eq!(bitfield, Bitfield::full(3));
+        assert_eq!(bitfield.count(), 3);
+        assert_eq!(rest, b"x");
+
+        assert!(Bitfield::decode(&[0, 0, 0, 9, 0xff]).is_err());
+
+        assert_eq!(Bitfield::from_bytes(3, &[0xff]), Some(Bitfield::full(3)));
+        assert_eq!(Bitfield::from_bytes(9, &[0xff]), None);
+    }
+
+    #[test]
+    fn test_error_codes_roundtrip() {
+        for code in [
+            ErrorCode::NotFound,
+            ErrorCode::OutOfRange,
+            ErrorCode::Refused,
+            ErrorCode::Internal,
+            ErrorCode::Unknown(77),
+        ] {
+            assert_eq!(ErrorCode::from_u16(code.as_u16()), code);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oversized_frame_rejected() {
+        let mut codec = TransferCodec;
+
+        let mut frame = vec![TAG_RESPONSE_CHUNK];
+        frame.extend_from_slice(&((MAX_MESSAGE_SIZE as u32) + 1).to_be_bytes());
+
+        let result = codec
+            .read_response(&TRANSFER_PROTOCOL, &mut Cursor::new(frame))
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_tag_rejected() {
+        let mut codec = TransferCodec;
+        let frame = vec![0x7f, 0, 0, 0, 0];
+
+        let result = codec
+            .read_request(&TRANSFER_PROTOCOL, &mut Cursor::new(frame))
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_truncated_manifest_rejected() {
+        let mut codec = TransferCodec;
+
+        // Declares one chunk hash but carries none
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&100u64.to_be_bytes());
+        payload.extend_from_slice(&256u32.to_be_bytes());
+        payload.extend_from_slice(&1u32.to_be_bytes());
+
+        let mut frame = vec![TAG_RESPONSE_MANIFEST];
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&payload);
+
+        let result = codec
+            .read_response(&TRANSFER_PROTOCOL, &mut Cursor::new(frame))
+            .await;
+        assert!(result.is_err());
+    }
+}
@@ -2,7 +2,9 @@
 //!
 //! These tests verify peer discovery, connection management, and protocol interactions.
 
-use codio_network::{NetworkConfig, NetworkManager};
+use codio_common::RateLimits;
+use codio_content_id::ContentId;
+use codio_network::{Bitfield, ErrorCode, NetworkConfig, NetworkManager, RemoteError};
 use libp2p::{Multiaddr, PeerId};
 use std::time::Duration;
 use tokio::time::timeout;
@@ -14,7 +16,11 @@ fn init_tracing() {
 
 /// Helper to create a network manager with a random port
 async fn create_test_manager() -> (NetworkManager, u16) {
-    let config = NetworkConfig::default().without_mdns();
+    create_manager_with_config(NetworkConfig::default().without_mdns()).await
+}
+
+/// Helper to start a network manager with the given config
+async fn create_manager_with_config(config: NetworkConfig) -> (NetworkManager, u16) {
     let mut manager = NetworkManager::new(config).await.unwrap();
     manager.start().await.unwrap();
 
@@ -121,9 +127,9 @@ async fn test_peer_discovery_mdns() {
     println!("Peer 3 discovered {} peers", peer3.peer_count());
 
     // In CI/CD environments, mDNS might not work, so we just verify the setup was correct
-    assert!(peer1.listen_addrs().len() > 0);
-    assert!(peer2.listen_addrs().len() > 0);
-    assert!(peer3.listen_addrs().len() > 0);
+    assert!(!peer1.listen_addrs().is_empty());
+    assert!(!peer2.listen_addrs().is_empty());
+    assert!(!peer3.listen_addrs().is_empty());
 }
 
 #[tokio::test]
@@ -375,3 +381,432 @@ async fn test_peer_info_tracking() {
         assert!(!info.addresses.is_empty());
     }
 }
+
+#[tokio::test]
+async fn test_content_transfer_between_peers() {
+    init_tracing();
+
+    let (mut provider, provider_port) = create_test_manager().await;
+    let (mut fetcher, _) = create_test_manager().await;
+
+    let data = b"Content fetched over the transfer protocol".to_vec();
+    let cid = ContentId::new(&data);
+    provider.provide_content(cid.clone(), data.clone()).unwrap();
+
+    let provider_id = provider.peer_id();
+    let provider_addr = create_peer_addr(provider_id, provider_port);
+    tokio::spawn(async move { provider.run().await });
+
+    fetcher.connect_peer(provider_addr).await.unwrap();
+
+    // Provider discovery via the DHT
+    let providers = fetcher
+        .find_providers(&cid, Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert!(
+        providers.contains(&provider_id),
+        "Provider should be found via the DHT, got {:?}",
+        providers
+    );
+
+    // Transfer through a handle while the fetcher runs its event loop
+    let handle = fetcher.handle();
+    tokio::spawn(async move { fetcher.run().await });
+
+    let manifest = handle.fetch_manifest(provider_id, &cid).await.unwrap();
+    assert_eq!(manifest.total_size, data.len() as u64);
+    assert_eq!(manifest.chunk_count(), 1);
+
+    let fetched = handle.fetch_chunk(provider_id, &cid, 0).await.unwrap();
+    assert_eq!(fetched, data);
+    assert!(cid.verify(&fetched));
+
+    // Unknown content is reported as missing rather than hanging
+    let missing = ContentId::new(b"nobody has this");
+    let err = handle
+        .fetch_manifest(provider_id, &missing)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<RemoteError>().map(|e| e.code),
+        Some(ErrorCode::NotFound)
+    );
+}
+
+#[tokio::test]
+async fn test_chunked_transfer_between_peers() {
+    init_tracing();
+
+    let config = NetworkConfig::default()
+        .without_mdns()
+        .with_chunk_size(1024);
+    let (mut provider, provider_port) = create_manager_with_config(config).await;
+    let (mut fetcher, _) = create_test_manager().await;
+
+    let data: Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+    let cid = ContentId::new(&data);
+    provider.provide_content(cid.clone(), data.clone()).unwrap();
+
+    let provider_id = provider.peer_id();
+    let provider_addr = create_peer_addr(provider_id, provider_port);
+    tokio::spawn(async move { provider.run().await });
+
+    fetcher.connect_peer(provider_addr).await.unwrap();
+    let handle = fetcher.handle();
+    tokio::spawn(async move { fetcher.run().await });
+
+    let manifest = handle.fetch_manifest(provider_id, &cid).await.unwrap();
+    assert_eq!(manifest.chunk_size, 1024);
+    assert_eq!(manifest.chunk_count(), 10);
+
+    let mut reassembled = Vec::new();
+    for index in 0..manifest.chunk_count() {
+        let chunk = handle.fetch_chunk(provider_id, &cid, index).await.unwrap();
+        assert!(manifest.verify_chunk(index, &chunk));
+        reassembled.extend_from_slice(&chunk);
+    }
+    assert_eq!(reassembled, data);
+
+    // Past the last chunk
+    let err = handle
+        .fetch_chunk(provider_id, &cid, manifest.chunk_count())
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<RemoteError>().map(|e| e.code),
+        Some(ErrorCode::OutOfRange)
+    );
+}
+
+#[tokio::test]
+async fn test_file_backed_transfer_between_peers() {
+    init_tracing();
+
+    let config = NetworkConfig::default()
+        .without_mdns()
+        .with_chunk_size(1024);
+    let (mut provider, provider_port) = create_manager_with_config(config).await;
+    let (mut fetcher, _) = create_test_manager().await;
+
+    let data: Vec<u8> = (0..5_000u32).map(|i| (i % 251) as u8).collect();
+    let path = std::env::temp_dir().join(format!("codio-provide-{}", provider.peer_id()));
+    std::fs::write(&path, &data).unwrap();
+
+    let cid = ContentId::from_reader(std::fs::File::open(&path).unwrap()).unwrap();
+    provider.provide_file(cid.clone(), &path).unwrap();
+
+    // Part of the file, as its own content
+    let part = ContentId::new(&data[1500..4200]);
+    provider
+        .provide_file_range(part.clone(), &path, 1500..4200)
+        .unwrap();
+
+    let provider_id = provider.peer_id();
+    let provider_addr = create_peer_addr(provider_id, provider_port);
+    tokio::spawn(async move { provider.run().await });
+
+    fetcher.connect_peer(provider_addr).await.unwrap();
+    let handle = fetcher.handle();
+    tokio::spawn(async move { fetcher.run().await });
+
+    let manifest = handle.fetch_manifest(provider_id, &cid).await.unwrap();
+    assert_eq!(manifest.total_size, 5_000);
+    assert_eq!(manifest.chunk_count(), 5);
+
+    let mut reassembled = Vec::new();
+    for index in 0..manifest.chunk_count() {
+        let chunk = handle.fetch_chunk(provider_id, &cid, index).await.unwrap();
+        assert!(manifest.verify_chunk(index, &chunk));
+        reassembled.extend_from_slice(&chunk);
+    }
+    assert!(cid.verify(&reassembled));
+
+    let manifest = handle.fetch_manifest(provider_id, &part).await.unwrap();
+    assert_eq!(manifest.total_size, 2_700);
+    let mut reassembled = Vec::new();
+    for index in 0..manifest.chunk_count() {
+        let chunk = handle.fetch_chunk(provider_id, &part, index).await.unwrap();
+        reassembled.extend_from_slice(&chunk);
+    }
+    assert_eq!(reassembled, &data[1500..4200]);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_partial_content_shared_with_haves() {
+    init_tracing();
+
+    let config = NetworkConfig::default()
+        .without_mdns()
+        .with_chunk_size(1024);
+    let (mut seeder, seeder_port) = create_manager_with_config(config).await;
+    let (mut leecher, leecher_port) = create_test_manager().await;
+    let (mut late, _) = create_test_manager().await;
+
+    let data: Vec<u8> = (0..4_000u32).map(|i| (i % 251) as u8).collect();
+    let cid = ContentId::new(&data);
+    seeder.provide_content(cid.clone(), data.clone()).unwrap();
+
+    let seeder_id = seeder.peer_id();
+    let seeder_addr = create_peer_addr(seeder_id, seeder_port);
+    tokio::spawn(async move { seeder.run().await });
+
+    let leecher_id = leecher.peer_id();
+    let leecher_addr = create_peer_addr(leecher_id, leecher_port);
+    leecher.connect_peer(seeder_addr).await.unwrap();
+    let leecher = {
+        let handle = leecher.handle();
+        tokio::spawn(async move { leecher.run().await });
+        handle
+    };
+
+    // The leecher shares what it downloads and learns the seeder has it all
+    let manifest = leecher.fetch_manifest(seeder_id, &cid).await.unwrap();
+    leecher.start_sharing(&cid, manifest.clone()).unwrap();
+    let seeder_has = leecher
+        .exchange_bitfield(seeder_id, &cid, Bitfield::new(manifest.chunk_count()))
+        .await
+        .unwrap();
+    assert!(seeder_has.is_complete());
+
+    let chunk = leecher.fetch_chunk(seeder_id, &cid, 0).await.unwrap();
+    leecher.announce_have(&cid, 0, chunk).unwrap();
+
+    // A late peer swaps bitfields with the leecher and sees only chunk 0
+    late.connect_peer(leecher_addr).await.unwrap();
+    let late = {
+        let handle = late.handle();
+        tokio::spawn(async move { late.run().await });
+        handle
+    };
+    late.start_sharing(&cid, manifest.clone()).unwrap();
+    let leecher_has = late
+        .exchange_bitfield(leecher_id, &cid, Bitfield::new(manifest.chunk_count()))
+        .await
+        .unwrap();
+    assert_eq!(leecher_has.indices().collect::<Vec<_>>(), [0]);
+
+    let err = late.fetch_chunk(leecher_id, &cid, 1).await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<RemoteError>().map(|e| e.code),
+        Some(ErrorCode::NotFound),
+        "Chunks not downloaded yet can't be served"
+    );
+
+    // Completing chunk 1 sends the late peer a HAVE
+    let chunk = leecher.fetch_chunk(seeder_id, &cid, 1).await.unwrap();
+    leecher.announce_have(&cid, 1, chunk).unwrap();
+
+    let updated = timeout(Duration::from_secs(10), async {
+        loop {
+            let availability = late.availability(&cid).await.unwrap();
+            if availability.get(&leecher_id).is_some_and(|b| b.has(1)) {
+                return availability;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("HAVE should reach the late peer");
+    assert_eq!(updated[&leecher_id].count(), 2);
+
+    let fetched = late.fetch_chunk(leecher_id, &cid, 1).await.unwrap();
+    assert!(manifest.verify_chunk(1, &fetched));
+
+    // Chunks that don't match the manifest are never shared
+    leecher.announce_have(&cid, 2, b"bogus".to_vec()).unwrap();
+    let err = late.fetch_chunk(leecher_id, &cid, 2).await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<RemoteError>().map(|e| e.code),
+        Some(ErrorCode::NotFound)
+    );
+}
+
+#[tokio::test]
+async fn test_partial_file_shared_from_disk() {
+    init_tracing();
+
+    let (mut leecher, leecher_port) = create_test_manager().await;
+    let (mut fetcher, _) = create_test_manager().await;
+
+    let data: Vec<u8> = (0..4_000u32).map(|i| (i % 251) as u8).collect();
+    let cid = ContentId::new(&data);
+    let manifest = codio_network::ContentManifest::from_content(&data, 1024);
+
+    // A download written in place: chunk 0 is there, the rest are holes
+    let path = std::env::temp_dir().join(format!("codio-share-{}", leecher.peer_id()));
+    let mut partial = vec![0u8; data.len()];
+    partial[..1024].copy_from_slice(&data[..1024]);
+    std::fs::write(&path, &partial).unwrap();
+
+    let leecher_id = leecher.peer_id();
+    let leecher_addr = create_peer_addr(leecher_id, leecher_port);
+    let leecher = {
+        let handle = leecher.handle();
+        tokio::spawn(async move { leecher.run().await });
+        handle
+    };
+    leecher.share_file(&cid, manifest.clone(), &path).unwrap();
+    leecher.announce_stored(&cid, 0).unwrap();
+
+    fetcher.connect_peer(leecher_addr).await.unwrap();
+    let fetcher = {
+        let handle = fetcher.handle();
+        tokio::spawn(async move { fetcher.run().await });
+        handle
+    };
+    fetcher.start_sharing(&cid, manifest.clone()).unwrap();
+    let leecher_has = fetcher
+        .exchange_bitfield(leecher_id, &cid, Bitfield::new(manifest.chunk_count()))
+        .await
+        .unwrap();
+    assert_eq!(leecher_has.indices().collect::<Vec<_>>(), [0]);
+
+    let chunk = fetcher.fetch_chunk(leecher_id, &cid, 0).await.unwrap();
+    assert_eq!(chunk, data[..1024]);
+    let err = fetcher.fetch_chunk(leecher_id, &cid, 1).await.unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<RemoteError>().map(|e| e.code),
+        Some(ErrorCode::NotFound)
+    );
+
+    // Once written to the file, chunk 1 is announced and read from disk
+    partial[1024..2048].copy_from_slice(&data[1024..2048]);
+    std::fs::write(&path, &partial).unwrap();
+    leecher.announce_stored(&cid, 1).unwrap();
+
+    timeout(Duration::from_secs(10), async {
+        loop {
+            let availability = fetcher.availability(&cid).await.unwrap();
+            if availability.get(&leecher_id).is_some_and(|b| b.has(1)) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .expect("HAVE should reach the fetcher");
+    let chunk = fetcher.fetch_chunk(leecher_id, &cid, 1).await.unwrap();
+    assert_eq!(chunk, data[1024..2048]);
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn test_served_chunks_follow_upload_limits() {
+    init_tracing();
+
+    // One chunk a second to each peer
+    let config = NetworkConfig::default()
+        .without_mdns()
+        .with_chunk_size(1024)
+        .with_rate_limits(RateLimits::default().with_peer_upload(1024));
+    let (mut provider, provider_port) = create_manager_with_config(config).await;
+    let (mut fetcher, _) = create_test_manager().await;
+
+    let data: Vec<u8> = (0..4096u32).map(|i| (i % 251) as u8).collect();
+    let cid = ContentId::new(&data);
+    provider.provide_content(cid.clone(), data.clone()).unwrap();
+
+    let provider_id = provider.peer_id();
+    let provider_addr = create_peer_addr(provider_id, provider_port);
+    let provider = {
+        let handle = provider.handle();
+        tokio::spawn(async move { provider.run().await });
+        handle
+    };
+
+    fetcher.connect_peer(provider_addr).await.unwrap();
+    let handle = fetcher.handle();
+    tokio::spawn(async move { fetcher.run().await });
+
+    // The first chunk fits the burst, the other three take a second each
+    let start = std::time::Instant::now();
+    for index in 0..4 {
+        handle.fetch_chunk(provider_id, &cid, index).await.unwrap();
+    }
+    assert!(
+        start.elapsed() >= Duration::from_millis(2500),
+        "4 KB at 1 KB/s came through in {:?}",
+        start.elapsed()
+    );
+
+    // Requests aren't held up behind chunks waiting for bandwidth
+    let waiting = {
+        let (handle, cid) = (handle.clone(), cid.clone());
+        tokio::spawn(async move {
+            for index in 0..2 {
+                handle.fetch_chunk(provider_id, &cid, index).await.unwrap();
+            }
+        })
+    };
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let start = std::time::Instant::now();
+    handle.fetch_manifest(provider_id, &cid).await.unwrap();
+    assert!(start.elapsed() < Duration::from_secs(1));
+    waiting.await.unwrap();
+
+    // Lifting the limit takes effect straight away
+    provider.set_rate_limits(RateLimits::unlimited());
+    assert!(provider.rate_limits().is_unlimited());
+    let start = std::time::Instant::now();
+    for index in 0..4 {
+        handle.fetch_chunk(provider_id, &cid, index).await.unwrap();
+    }
+    assert!(start.elapsed() < Duration::from_secs(2));
+}
+
+#[tokio::test]
+async fn test_overloaded_uploads_refused_not_timed_out() {
+    init_tracing();
+
+    // One chunk a second, but the fetcher asks for 32 at once
+    let config = NetworkConfig::default()
+        .without_mdns()
+        .with_chunk_size(1024)
+        .with_rate_limits(RateLimits::default().with_peer_upload(1024))
+        .with_max_upload_wait(Duration::from_secs(2));
+    let (mut provider, provider_port) = create_manager_with_config(config).await;
+    let (mut fetcher, _) = create_manager_with_config(
+        NetworkConfig::default()
+            .without_mdns()
+            .with_transfer_timeout(Duration::from_secs(5)),
+    )
+    .await;
+
+    let data: Vec<u8> = (0..32 * 1024u32).map(|i| (i % 251) as u8).collect();
+    let cid = ContentId::new(&data);
+    provider.provide_content(cid.clone(), data.clone()).unwrap();
+
+    let provider_id = provider.peer_id();
+    let provider_addr = create_peer_addr(provider_id, provider_port);
+    tokio::spawn(async move { provider.run().await });
+
+    fetcher.connect_peer(provider_addr).await.unwrap();
+    let handle = fetcher.handle();
+    tokio::spawn(async move { fetcher.run().await });
+
+    let requests: Vec<_> = (0..32u32)
+        .map(|index| {
+            let (handle, cid) = (handle.clone(), cid.clone());
+            tokio::spawn(async move { handle.fetch_chunk(provider_id, &cid, index).await })
+        })
+        .collect();
+
+    // Whatever can't go within the wait is refused straight away
+    let mut served = 0;
+    for request in requests {
+        match request.await.unwrap() {
+            Ok(_) => served += 1,
+            Err(e) => assert_eq!(
+                e.downcast_ref::<RemoteError>().map(|e| e.code),
+                Some(ErrorCode::Refused),
+                "{}",
+                e
+            ),
+        }
+    }
+    assert!((1..=4).contains(&served), "{} chunks served", served);
+}
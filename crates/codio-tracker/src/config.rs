@@ -89,16 +89,20 @@ mod tests {
 
     #[test]
     fn test_invalid_reputation() {
-        let mut config = TrackerConfig::default();
-        config.min_reputation = 1.5;
+        let config = TrackerConfig {
+            min_reputation: 1.5,
+            ..Default::default()
+        };
         assert!(config.validate().is_err());
     }
 
     #[test]
     fn test_invalid_weights() {
-        let mut config = TrackerConfig::default();
-        config.uptime_weight = 0.5;
-        config.success_weight = 0.6;
+        let config = TrackerConfig {
+            uptime_weight: 0.5,
+            success_weight: 0.6,
+            ..Default::default()
+        };
         assert!(config.validate().is_err());
     }
 }
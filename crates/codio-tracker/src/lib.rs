@@ -785,8 +785,10 @@ mod tests {
 
     #[tokio::test]
     async fn test_cleanup_stale_peers() {
-        let mut config = TrackerConfig::default();
-        config.peer_timeout = Duration::from_millis(100);
+        let config = TrackerConfig {
+            peer_timeout: Duration::from_millis(100),
+            ..Default::default()
+        };
 
         let tracker = AvailabilityTracker::new(config).await.unwrap();
         let peer_id = PeerId("test_peer".to_string());
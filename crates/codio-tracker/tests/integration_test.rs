@@ -148,8 +148,10 @@ async fn test_load_balancing_with_offline_peers() {
 
 #[tokio::test]
 async fn test_peer_timeout() {
-    let mut config = TrackerConfig::default();
-    config.peer_timeout = Duration::from_millis(200); // 200ms timeout
+    let config = TrackerConfig {
+        peer_timeout: Duration::from_millis(200), // 200ms timeout
+        ..Default::default()
+    };
 
     let tracker = AvailabilityTracker::new(config).await.unwrap();
     let peer_id = PeerId("timeout_peer".to_string());
@@ -174,8 +176,10 @@ async fn test_peer_timeout() {
 
 #[tokio::test]
 async fn test_peer_timeout_with_activity() {
-    let mut config = TrackerConfig::default();
-    config.peer_timeout = Duration::from_millis(300);
+    let config = TrackerConfig {
+        peer_timeout: Duration::from_millis(300),
+        ..Default::default()
+    };
 
     let tracker = AvailabilityTracker::new(config).await.unwrap();
     let peer_id = PeerId("active_peer".to_string());
@@ -334,8 +338,10 @@ async fn test_select_best_peers_respects_count() {
 
 #[tokio::test]
 async fn test_min_reputation_filter() {
-    let mut config = TrackerConfig::default();
-    config.min_reputation = 0.5; // Set higher threshold
+    let config = TrackerConfig {
+        min_reputation: 0.5, // Set higher threshold
+        ..Default::default()
+    };
 
     let tracker = AvailabilityTracker::new(config).await.unwrap();
     let cid = ContentId::new(b"test content");
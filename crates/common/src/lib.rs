@@ -1,4 +1,7 @@
+pub mod rate_limit;
+
 pub use codio_content_id::ContentId;
+pub use rate_limit::{Direction, RateLimiter, RateLimits};
 
 /// Peer identifier in the network
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -0,0 +1,458 @@
+//! Token-bucket bandwidth limits, global and per peer.
+//!
+//! A [`RateLimiter`] keeps one bucket per direction for all traffic and one
+//! per direction for each peer. Every transfer takes its size in tokens from
+//! both the global and the peer's bucket before it goes ahead, waiting while
+//! either is in debt. Buckets refill at their rate and hold at most one
+//! second's worth, so a quiet peer can burst briefly but never sustain more
+//! than its limit. Transfers larger than a bucket go ahead and leave it in
+//! debt, so whole chunks don't need to be split to fit.
+//!
+//! Limits can be changed while transfers are running; waiters already
+//! sleeping finish at the old rate, later transfers see the new one.
+
+use crate::PeerId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Peer buckets kept before idle ones are dropped
+const MAX_IDLE_PEERS: usize = 1024;
+
+/// Bandwidth limits in bytes per second; 0 means unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimits {
+    /// All uploads together
+    #[serde(default)]
+    pub upload: u64,
+    /// All downloads together
+    #[serde(default)]
+    pub download: u64,
+    /// Uploads to any one peer
+    #[serde(default)]
+    pub peer_upload: u64,
+    /// Downloads from any one peer
+    #[serde(default)]
+    pub peer_download: u64,
+}
+
+impl RateLimits {
+    /// No limits at all
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// Limit all uploads together to `bytes_per_sec`
+    pub fn with_upload(mut self, bytes_per_sec: u64) -> Self {
+        self.upload = bytes_per_sec;
+        self
+    }
+
+    /// Limit all downloads together to `bytes_per_sec`
+    pub fn with_download(mut self, bytes_per_sec: u64) -> Self {
+        self.download = bytes_per_sec;
+        self
+    }
+
+    /// Limit uploads to each peer to `bytes_per_sec`
+    pub fn with_peer_upload(mut self, bytes_per_sec: u64) -> Self {
+        self.peer_upload = bytes_per_sec;
+        self
+    }
+
+    /// Limit downloads from each peer to `bytes_per_sec`
+    pub fn with_peer_download(mut self, bytes_per_sec: u64) -> Self {
+        self.peer_download = bytes_per_sec;
+        self
+    }
+
+    /// Whether no limit is set
+    pub fn is_unlimited(&self) -> bool {
+        *self == Self::unlimited()
+    }
+}
+
+/// Which way bytes are going
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Sent to a peer
+    Upload,
+    /// Received from a peer
+    Download,
+}
+
+/// Tokens for one rate, in bytes
+#[derive(Debug)]
+struct TokenBucket {
+    /// Bytes per second; 0 for unlimited
+    rate: u64,
+    /// Bytes that may go right away, negative while in debt
+    tokens: f64,
+    /// When `tokens` was last brought up to date
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64, now: Instant) -> Self {
+        Self {
+            rate,
+            tokens: rate as f64,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+        self.updated = now;
+    }
+
+    /// How long taking `bytes` now would mean waiting before sending them
+    fn wait(&mut self, bytes: u64, now: Instant) -> Duration {
+        if self.rate == 0 {
+            return Duration::ZERO;
+        }
+        self.refill(now);
+        let tokens = self.tokens - bytes as f64;
+        if tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-tokens / self.rate as f64)
+        }
+    }
+
+    /// Take `bytes`, returning how long to wait before sending them
+    fn take(&mut self, bytes: u64, now: Instant) -> Duration {
+        let wait = self.wait(bytes, now);
+        if self.rate != 0 {
+            self.tokens -= bytes as f64;
+        }
+        wait
+    }
+
+    fn set_rate(&mut self, rate: u64, now: Instant) {
+        if self.rate == 0 {
+            *self = Self::new(rate, now);
+        } else {
+            self.refill(now);
+            self.rate = rate;
+            self.tokens = self.tokens.min(rate as f64);
+        }
+    }
+
+    /// Whether the bucket is back to full, so dropping it loses nothing
+    fn is_idle(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.tokens >= self.rate as f64
+    }
+}
+
+/// Upload and download buckets
+#[derive(Debug)]
+struct Buckets {
+    upload: TokenBucket,
+    download: TokenBucket,
+}
+
+impl Buckets {
+    fn new(upload: u64, download: u64, now: Instant) -> Self {
+        Self {
+            upload: TokenBucket::new(upload, now),
+            download: TokenBucket::new(download, now),
+        }
+    }
+
+    fn get(&mut self, direction: Direction) -> &mut TokenBucket {
+        match direction {
+            Direction::Upload => &mut self.upload,
+            Direction::Download => &mut self.download,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State<K> {
+    limits: RateLimits,
+    global: Buckets,
+    peers: HashMap<K, Buckets>,
+}
+
+/// Shared bandwidth limiter, keyed by peer
+///
+/// # Example
+///
+/// ```rust
+/// use codio_common::{Direction, PeerId, RateLimiter, RateLimits};
+///
+/// # #[tokio::main]
+/// # async fn main() {
+/// let limiter: RateLimiter = RateLimiter::new(RateLimits::default().with_upload(1024 * 1024));
+/// let peer = PeerId("peer1".to_string());
+///
+/// // Waits until the global and the peer's upload buckets allow 64 KB
+/// limiter.acquire(Direction::Upload, &peer, 64 * 1024).await;
+///
+/// // Lift the limit at runtime
+/// limiter.set_limits(RateLimits::unlimited());
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct RateLimiter<K = PeerId> {
+    state: Mutex<State<K>>,
+}
+
+impl<K: Hash + Eq + Clone> RateLimiter<K> {
+    /// Create a limiter enforcing `limits`
+    pub fn new(limits: RateLimits) -> Self {
+        let now = Instant::now();
+        Self {
+            state: Mutex::new(State {
+                limits,
+                global: Buckets::new(limits.upload, limits.download, now),
+                peers: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Create a limiter that never waits, until limits are set
+    pub fn unlimited() -> Self {
+        Self::new(RateLimits::unlimited())
+    }
+
+    /// The limits in force
+    pub fn limits(&self) -> RateLimits {
+        self.state.lock().expect("rate limiter poisoned").limits
+    }
+
+    /// Change the limits, keeping the tokens each bucket has built up
+    pub fn set_limits(&self, limits: RateLimits) {
+        let now = Instant::now();
+        let mut state = self.state.lock().expect("rate limiter poisoned");
+        state.limits = limits;
+        state.global.upload.set_rate(limits.upload, now);
+        state.global.download.set_rate(limits.download, now);
+        for buckets in state.peers.values_mut() {
+            buckets.upload.set_rate(limits.peer_upload, now);
+            buckets.download.set_rate(limits.peer_download, now);
+        }
+    }
+
+    /// Take `bytes` for a transfer with `peer`, returning how long the caller
+    /// must wait before making it
+    pub fn reserve(&self, direction: Direction, peer: &K, bytes: u64) -> Duration {
+        self.try_reserve(direction, peer, bytes, Duration::MAX)
+            .expect("every wait is within Duration::MAX")
+    }
+
+    /// Like [`reserve`](Self::reserve), but takes nothing and returns `None`
+    /// if the caller would have to wait longer than `max_wait`
+    ///
+    /// Lets a busy sender turn a transfer away instead of queueing it behind
+    /// ever more debt.
+    pub fn try_reserve(
+        &self,
+        direction: Direction,
+        peer: &K,
+        bytes: u64,
+        max_wait: Duration,
+    ) -> Option<Duration> {
+        let now = Instant::now();
+        let mut state = self.state.lock().expect("rate limiter poisoned");
+        if state.limits.is_unlimited() {
+            return Some(Duration::ZERO);
+        }
+
+        let (peer_upload, peer_download) = (state.limits.peer_upload, state.limits.peer_download);
+        if !state.peers.contains_key(peer) && state.peers.len() >= MAX_IDLE_PEERS {
+            state.peers.retain(|_, buckets| {
+                !(buckets.upload.is_idle(now) && buckets.download.is_idle(now))
+            });
+        }
+        state
+            .peers
+            .entry(peer.clone())
+            .or_insert_with(|| Buckets::new(peer_upload, peer_download, now));
+
+        let State { global, peers, .. } = &mut *state;
+        let global = global.get(direction);
+        let peer = peers
+            .get_mut(peer)
+            .expect("peer buckets were just added")
+            .get(direction);
+        let wait = global.wait(bytes, now).max(peer.wait(bytes, now));
+        if wait > max_wait {
+            return None;
+        }
+        global.take(bytes, now);
+        peer.take(bytes, now);
+        Some(wait)
+    }
+
+    /// Wait until a transfer of `bytes` with `peer` fits the limits
+    pub async fn acquire(&self, direction: Direction, peer: &K, bytes: u64) {
+        let wait = self.reserve(direction, peer, bytes);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Drop a peer's buckets, e.g. once it disconnects
+    pub fn forget(&self, peer: &K) {
+        self.state
+            .lock()
+            .expect("rate limiter poisoned")
+            .peers
+            .remove(peer);
+    }
+}
+
+impl<K: Hash + Eq + Clone> Default for RateLimiter<K> {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(name: &str) -> PeerId {
+        PeerId(name.to_string())
+    }
+
+    /// Time taken to move `count` transfers of `bytes` with each of `peers`
+    async fn elapsed(
+        limiter: &RateLimiter,
+        direction: Direction,
+        peers: &[PeerId],
+        count: usize,
+        bytes: u64,
+    ) -> Duration {
+        let start = Instant::now();
+        for _ in 0..count {
+            for peer in peers {
+                limiter.acquire(direction, peer, bytes).await;
+            }
+        }
+        start.elapsed()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unlimited_never_waits() {
+        let limiter = RateLimiter::unlimited();
+        let taken = elapsed(&limiter, Direction::Upload, &[peer("a")], 100, 1 << 20).await;
+        assert_eq!(taken, Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_global_limit() {
+        let limiter = RateLimiter::new(RateLimits::default().with_upload(1000));
+        let peers = [peer("a"), peer("b")];
+
+        // One second's worth goes at once, the other 4000 bytes take 4s
+        let taken = elapsed(&limiter, Direction::Upload, &peers, 5, 500).await;
+        assert_eq!(taken, Duration::from_secs(4));
+
+        // Downloads aren't limited
+        let taken = elapsed(&limiter, Direction::Download, &peers, 5, 500).await;
+        assert_eq!(taken, Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_peer_limit() {
+        let limiter = RateLimiter::new(RateLimits::default().with_peer_download(1000));
+        let (a, b) = (peer("a"), peer("b"));
+
+        let taken = elapsed(
+            &limiter,
+            Direction::Download,
+            std::slice::from_ref(&a),
+            3,
+            1000,
+        )
+        .await;
+        assert_eq!(taken, Duration::from_secs(2));
+
+        // Another peer has its own bucket
+        let taken = elapsed(&limiter, Direction::Download, &[b], 1, 1000).await;
+        assert_eq!(taken, Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_large_transfer_goes_into_debt() {
+        let limiter = RateLimiter::new(RateLimits::default().with_download(1000));
+        let a = peer("a");
+
+        // A transfer bigger than the bucket isn't held back forever...
+        assert_eq!(
+            limiter.reserve(Direction::Download, &a, 3000),
+            Duration::from_secs(2)
+        );
+        // ...but the next one waits for the debt to clear
+        assert_eq!(
+            limiter.reserve(Direction::Download, &a, 1000),
+            Duration::from_secs(3)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_try_reserve_refuses_long_waits() {
+        let limiter = RateLimiter::new(RateLimits::default().with_peer_upload(1000));
+        let a = peer("a");
+        let max_wait = Duration::from_secs(2);
+
+        assert_eq!(
+            limiter.try_reserve(Direction::Upload, &a, 2000, max_wait),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            limiter.try_reserve(Direction::Upload, &a, 1000, max_wait),
+            Some(Duration::from_secs(2))
+        );
+        // Refusing takes nothing, so the debt doesn't grow
+        assert_eq!(
+            limiter.try_reserve(Direction::Upload, &a, 1000, max_wait),
+            None
+        );
+        assert_eq!(
+            limiter.try_reserve(Direction::Upload, &a, 1000, max_wait),
+            None
+        );
+
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert_eq!(
+            limiter.try_reserve(Direction::Upload, &a, 1000, max_wait),
+            Some(Duration::from_secs(2))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_limits_change_at_runtime() {
+        let limiter = RateLimiter::unlimited();
+        let a = peer("a");
+        assert_eq!(limiter.reserve(Direction::Upload, &a, 5000), Duration::ZERO);
+
+        limiter.set_limits(
+            RateLimits::default()
+                .with_upload(1000)
+                .with_peer_upload(500),
+        );
+        assert_eq!(limiter.limits().peer_upload, 500);
+        let taken = elapsed(
+            &limiter,
+            Direction::Upload,
+            std::slice::from_ref(&a),
+            3,
+            500,
+        )
+        .await;
+        assert_eq!(taken, Duration::from_secs(2));
+
+        limiter.set_limits(RateLimits::unlimited());
+        let taken = elapsed(&limiter, Direction::Upload, &[a], 10, 5000).await;
+        assert_eq!(taken, Duration::ZERO);
+    }
+}
@@ -0,0 +1,149 @@
+//! Incremental CID computation, for content too large to hold in memory
+
+use crate::multihash::Hasher;
+use crate::{Codec, ContentId, HashAlgorithm};
+use std::io::{self, Read};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Read buffer used when hashing from a reader
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Builds a [`ContentId`] from content fed in pieces
+///
+/// Produces the same CID as [`ContentId::new_with`] on the concatenated
+/// input, while only ever holding the hash state. Implements [`io::Write`],
+/// so `io::copy` can feed it directly.
+#[derive(Clone)]
+pub struct ContentIdHasher {
+    hasher: Hasher,
+}
+
+impl ContentIdHasher {
+    /// Start a sha2-256 CID, matching [`ContentId::new`]
+    pub fn new() -> Self {
+        Self::with_algorithm(HashAlgorithm::Sha2_256)
+    }
+
+    /// Start a CID using `algorithm`
+    pub fn with_algorithm(algorithm: HashAlgorithm) -> Self {
+        Self {
+            hasher: algorithm.hasher(),
+        }
+    }
+
+    /// Feed the next piece of content
+    pub fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+    }
+
+    /// Finish hashing and build the CID
+    pub fn finalize(self) -> ContentId {
+        let algorithm = self.hasher.algorithm();
+        let digest = self.hasher.finalize();
+
+        match algorithm {
+            HashAlgorithm::Sha2_256 => ContentId::from_hash(digest.try_into().unwrap()),
+            other => ContentId::from_digest_v1(Codec::Raw, other, &digest).unwrap(),
+        }
+    }
+}
+
+impl Default for ContentIdHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl io::Write for ContentIdHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl ContentId {
+    /// Create a sha2-256 CID from everything `reader` yields
+    pub fn from_reader<R: Read>(reader: R) -> io::Result<Self> {
+        Self::from_reader_with(HashAlgorithm::Sha2_256, reader)
+    }
+
+    /// Create a CID from everything `reader` yields, hashed with `algorithm`
+    pub fn from_reader_with<R: Read>(algorithm: HashAlgorithm, mut reader: R) -> io::Result<Self> {
+        let mut hasher = ContentIdHasher::with_algorithm(algorithm);
+        let mut buf = vec![0u8; READ_BUFFER_SIZE];
+
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => return Ok(hasher.finalize()),
+                Ok(n) => hasher.update(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Create a sha2-256 CID from everything an async `reader` yields
+    pub async fn from_async_reader<R: AsyncRead + Unpin>(reader: R) -> io::Result<Self> {
+        Self::from_async_reader_with(HashAlgorithm::Sha2_256, reader).await
+    }
+
+    /// Create a CID from everything an async `reader` yields, hashed with `algorithm`
+    pub async fn from_async_reader_with<R: AsyncRead + Unpin>(
+        algorithm: HashAlgorithm,
+        mut reader: R,
+    ) -> io::Result<Self> {
+        let mut hasher = ContentIdHasher::with_algorithm(algorithm);
+        let mut buf = vec![0u8; READ_BUFFER_SIZE];
+
+        loop {
+            let n = reader.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(hasher.finalize());
+            }
+            hasher.update(&buf[..n]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_incremental_matches_one_shot() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+
+        for algorithm in [HashAlgorithm::Sha2_256, HashAlgorithm::Blake3] {
+            let mut hasher = ContentIdHasher::with_algorithm(algorithm);
+            for piece in data.chunks(777) {
+                hasher.write_all(piece).unwrap();
+            }
+
+            assert_eq!(hasher.finalize(), ContentId::new_with(algorithm, &data));
+        }
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let data = vec![42u8; 3 * READ_BUFFER_SIZE + 5];
+        let cid = ContentId::from_reader(&data[..]).unwrap();
+        assert_eq!(cid, ContentId::new(&data));
+
+        let empty = ContentId::from_reader(io::empty()).unwrap();
+        assert_eq!(empty, ContentId::new(b""));
+    }
+
+    #[tokio::test]
+    async fn test_from_async_reader() {
+        let data = vec![7u8; READ_BUFFER_SIZE + 1];
+        let cid = ContentId::from_async_reader_with(HashAlgorithm::Sha3_256, &data[..])
+            .await
+            .unwrap();
+        assert_eq!(cid, ContentId::new_with(HashAlgorithm::Sha3_256, &data));
+    }
+}
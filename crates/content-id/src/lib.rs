@@ -1,55 +1,399 @@
-use sha2::{Digest, Sha256};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
 use std::str::FromStr;
 
-/// Content Identifier (CID) using sha256 hash
-/// Format: Qm<base58(sha256(content))>
-/// Compatible with IPFS CIDv0
+mod hasher;
+pub mod multibase;
+pub mod multihash;
+mod varint;
+
+pub use hasher::ContentIdHasher;
+pub use multibase::Multibase;
+pub use multihash::HashAlgorithm;
+
+/// Multihash prefix for a 32-byte sha2-256 digest
+/// 0x12 = sha2-256, 0x20 = 32 bytes
+const SHA2_256_MULTIHASH_PREFIX: [u8; 2] = [0x12, 0x20];
+
+/// Content Identifier (CID): a hash of the content, plus how to read it
+///
+/// CIDv0: base58btc(0x12 0x20 || sha256(content)), always starting with "Qm".
+/// This is the default, and what [`ContentId::new`] produces.
+///
+/// CIDv1: multibase(varint(1) || varint(codec) || multihash(content)).
+/// Canonically written in lowercase base32 ("b..."), which is what subdomain
+/// gateways need, but base58btc ("z...") and base36 ("k...") parse too.
+/// The multihash may use any [`HashAlgorithm`]; CIDv0 is sha2-256 only.
+///
+/// CIDs published before the format was unified used
+/// `Qm<base58(sha256(content))>`, which only looks like CIDv0. Those strings
+/// still parse (see [`ContentId::parse`]) and map to the same hash, so they
+/// resolve to the same content and DHT records.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ContentId {
-    /// Raw sha256 hash bytes
-    hash: [u8; 32],
-    /// Base58-encoded string (Qm prefix)
+    /// CID version
+    version: Version,
+    /// Multicodec of the addressed content (always dag-pb for v0)
+    codec: Codec,
+    /// Hash function that produced `hash`
+    algorithm: HashAlgorithm,
+    /// Raw digest bytes
+    hash: Vec<u8>,
+    /// Canonical string: base58btc for v0, base32 for v1
     multibase: String,
 }
 
+/// CID version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Version {
+    V0,
+    V1,
+}
+
+/// Multicodec describing how the addressed bytes are interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Codec {
+    /// Plain bytes
+    Raw,
+    /// MerkleDAG protobuf, the only codec CIDv0 can express
+    DagPb,
+    /// MerkleDAG CBOR
+    DagCbor,
+    /// Concatenated digests of a node's links, as in a codio Merkle DAG node.
+    /// Not a registered codec: it takes the first code of the multicodec
+    /// private-use range, so no other tool will try to decode it
+    HashList,
+}
+
+impl Codec {
+    /// Multicodec table code
+    pub fn code(&self) -> u64 {
+        match self {
+            Codec::Raw => 0x55,
+            Codec::DagPb => 0x70,
+            Codec::DagCbor => 0x71,
+            Codec::HashList => 0x300000,
+        }
+    }
+
+    /// Look up a codec by its multicodec table code
+    pub fn from_code(code: u64) -> Result<Self, CidError> {
+        match code {
+            0x55 => Ok(Codec::Raw),
+            0x70 => Ok(Codec::DagPb),
+            0x71 => Ok(Codec::DagCbor),
+            0x300000 => Ok(Codec::HashList),
+            other => Err(CidError::UnsupportedCodec(other)),
+        }
+    }
+}
+
+/// String formats a CID can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CidFormat {
+    /// IPFS CIDv0: base58btc of the sha2-256 multihash
+    V0,
+    /// IPFS CIDv1 in any supported multibase
+    V1,
+    /// Pre-unification format: "Qm" followed by base58 of the bare hash
+    Legacy,
+}
+
 impl ContentId {
     /// Create CID from content bytes
     pub fn new(content: &[u8]) -> Self {
-        let mut hasher = Sha256::new();
-        hasher.update(content);
-        let hash: [u8; 32] = hasher.finalize().into();
+        Self::from_hash(HashAlgorithm::Sha2_256.digest(content).try_into().unwrap())
+    }
+
+    /// Create CID from a raw sha256 hash
+    pub fn from_hash(hash: [u8; 32]) -> Self {
+        let mut cid = ContentId {
+            version: Version::V0,
+            codec: Codec::DagPb,
+            algorithm: HashAlgorithm::Sha2_256,
+            hash: hash.to_vec(),
+            multibase: String::new(),
+        };
+        cid.multibase = bs58::encode(cid.multihash()).into_string();
+        cid
+    }
+
+    /// Create a CIDv1 from content bytes, hashed with sha2-256
+    pub fn new_v1(codec: Codec, content: &[u8]) -> Self {
+        Self::new_v1_with(codec, HashAlgorithm::Sha2_256, content)
+    }
 
-        // IPFS CIDv0 format: Qm + base58(hash)
-        let multibase = format!("Qm{}", bs58::encode(&hash).into_string());
+    /// Create a CIDv1 from content bytes, hashed with `algorithm`
+    pub fn new_v1_with(codec: Codec, algorithm: HashAlgorithm, content: &[u8]) -> Self {
+        Self::build_v1(codec, algorithm, algorithm.digest(content))
+    }
 
-        ContentId { hash, multibase }
+    /// Create a CID from content bytes, hashed with `algorithm`
+    ///
+    /// sha2-256 gives the same CIDv0 as [`ContentId::new`]. Other hash
+    /// functions can't be expressed in v0, so they give a raw CIDv1.
+    pub fn new_with(algorithm: HashAlgorithm, content: &[u8]) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha2_256 => Self::new(content),
+            other => Self::new_v1_with(Codec::Raw, other, content),
+        }
     }
 
-    /// Verify content matches this CID
+    /// Create a CIDv1 from a raw sha256 hash
+    pub fn from_hash_v1(codec: Codec, hash: [u8; 32]) -> Self {
+        Self::build_v1(codec, HashAlgorithm::Sha2_256, hash.to_vec())
+    }
+
+    /// Create a CIDv1 from a digest produced by `algorithm`
+    pub fn from_digest_v1(
+        codec: Codec,
+        algorithm: HashAlgorithm,
+        digest: &[u8],
+    ) -> Result<Self, CidError> {
+        if digest.len() != algorithm.digest_len() {
+            return Err(CidError::InvalidHashLength);
+        }
+        Ok(Self::build_v1(codec, algorithm, digest.to_vec()))
+    }
+
+    fn build_v1(codec: Codec, algorithm: HashAlgorithm, hash: Vec<u8>) -> Self {
+        let mut cid = ContentId {
+            version: Version::V1,
+            codec,
+            algorithm,
+            hash,
+            multibase: String::new(),
+        };
+        cid.multibase = Multibase::Base32Lower.encode(&cid.to_bytes());
+        cid
+    }
+
+    /// Verify content matches this CID, using the CID's own hash function
     pub fn verify(&self, content: &[u8]) -> bool {
-        let computed = Self::new(content);
-        self.hash == computed.hash
+        self.hash == self.algorithm.digest(content)
     }
 
-    /// Get multibase string representation
+    /// Get canonical string representation
     pub fn as_str(&self) -> &str {
         &self.multibase
     }
 
-    /// Get raw hash bytes
-    pub fn hash(&self) -> &[u8; 32] {
+    /// Get raw digest bytes
+    pub fn hash(&self) -> &[u8] {
         &self.hash
     }
-}
+
+    /// Get the digest as a lowercase hex string
+    pub fn hash_hex(&self) -> String {
+        self.hash.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Get the hash function
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Get the multihash: `varint(code) || varint(len) || digest`
+    pub fn multihash(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.hash.len() + 2);
+        multihash::encode(self.algorithm, &self.hash, &mut bytes);
+        bytes
+    }
+
+    /// Key this content is announced under in the DHT
+    ///
+    /// sha2-256 content keeps using the bare digest, which is what records
+    /// have always been published under. Other hash functions use the full
+    /// multihash, so their keys can't be mistaken for sha2-256 ones.
+    pub fn routing_key(&self) -> Vec<u8> {
+        match self.algorithm {
+            HashAlgorithm::Sha2_256 => self.hash.clone(),
+            _ => self.multihash(),
+        }
+    }
+
+    /// Inverse of [`ContentId::routing_key`]
+    ///
+    /// The key doesn't record version or codec, so sha2-256 keys give a
+    /// CIDv0 and others a raw CIDv1.
+    pub fn from_routing_key(key: &[u8]) -> Result<Self, CidError> {
+        if let Ok(hash) = <[u8; 32]>::try_from(key) {
+            return Ok(Self::from_hash(hash));
+        }
+        let (algorithm, digest) = multihash::decode(key)?;
+        Self::from_digest_v1(Codec::Raw, algorithm, digest)
+    }
+
+    /// Get the CID version
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Get the content codec
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// Convert to CIDv1, keeping the codec and hash; always lossless
+    pub fn to_v1(&self) -> Self {
+        Self::build_v1(self.codec, self.algorithm, self.hash.clone())
+    }
+
+    /// Convert to CIDv0
+    ///
+    /// Only dag-pb CIDs hashed with sha2-256 have a v0 form.
+    pub fn to_v0(&self) -> Result<Self, CidError> {
+        if self.codec != Codec::DagPb || self.algorithm != HashAlgorithm::Sha2_256 {
+            return Err(CidError::NotV0Compatible);
+        }
+        Ok(Self::from_hash(self.sha256_hash()))
+    }
+
+    fn sha256_hash(&self) -> [u8; 32] {
+        debug_assert_eq!(self.algorithm, HashAlgorithm::Sha2_256);
+        self.hash[..].try_into().unwrap()
+    }
+
+    /// Encode in the given multibase
+    ///
+    /// CIDv0 has no multibase prefix and can only be written in base58btc;
+    /// convert with [`ContentId::to_v1`] first for other bases.
+    pub fn to_string_of_base(&self, base: Multibase) -> Result<String, CidError> {
+        match (self.version, base) {
+            (Version::V0, Multibase::Base58Btc) => Ok(self.multibase.clone()),
+            (Version::V0, other) => Err(CidError::UnsupportedMultibase(other.prefix())),
+            (Version::V1, base) => Ok(base.encode(&self.to_bytes())),
+        }
+    }
+
+    /// Binary form: the bare multihash for v0, version and codec prefixed for v1
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self.version {
+            Version::V0 => self.multihash(),
+            Version::V1 => {
+                let mut bytes = Vec::with_capacity(self.hash.len() + 4);
+                varint::encode(1, &mut bytes);
+                varint::encode(self.codec.code(), &mut bytes);
+                multihash::encode(self.algorithm, &self.hash, &mut bytes);
+                bytes
+            }
+        }
+    }
+
+    /// Parse the binary form produced by [`ContentId::to_bytes`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CidError> {
+        // A v0 CID is a bare sha2-256 multihash
+        if bytes.len() == 34 && bytes[..2] == SHA2_256_MULTIHASH_PREFIX {
+            return Self::from_v0_bytes(bytes);
+        }
+
+        let (version, rest) = varint::decode(bytes)?;
+        if version != 1 {
+            return Err(CidError::UnsupportedVersion(version));
+        }
+        let (code, rest) = varint::decode(rest)?;
+        let codec = Codec::from_code(code)?;
+        let (algorithm, digest) = multihash::decode(rest)?;
+
+        Self::from_digest_v1(codec, algorithm, digest)
+    }
+
+    /// Parse a CID from the front of `bytes`, returning it and the rest
+    ///
+    /// Binary CIDs are self-delimiting, so this reads them back one at a
+    /// time from a concatenation of [`ContentId::to_bytes`].
+    pub fn from_bytes_prefix(bytes: &[u8]) -> Result<(Self, &[u8]), CidError> {
+        if bytes.starts_with(&SHA2_256_MULTIHASH_PREFIX) {
+            if bytes.len() < 34 {
+                return Err(CidError::InvalidHashLength);
+            }
+            let (cid, rest) = bytes.split_at(34);
+            return Ok((Self::from_v0_bytes(cid)?, rest));
+        }
+
+        let (version, rest) = varint::decode(bytes)?;
+        if version != 1 {
+            return Err(CidError::UnsupportedVersion(version));
+        }
+        let (code, rest) = varint::decode(rest)?;
+        let codec = Codec::from_code(code)?;
+        let (algorithm, digest, rest) = multihash::decode_prefix(rest)?;
+
+        Ok((Self::from_digest_v1(codec, algorithm, digest)?, rest))
+    }
+
+    fn from_v0_bytes(bytes: &[u8]) -> Result<Self, CidError> {
+        let (algorithm, digest) = multihash::decode(bytes)?;
+        if algorithm != HashAlgorithm::Sha2_256 {
+            return Err(CidError::UnsupportedMultihash(algorithm.code()));
+        }
+        Ok(Self::from_hash(digest.try_into().unwrap()))
+    }
+
+    /// Get the string this CID had in the legacy format
+    ///
+    /// Useful for looking up records that were stored under the old string.
+    /// Legacy strings only ever carried sha2-256 digests, so this is `None`
+    /// for other hash functions.
+    pub fn to_legacy_string(&self) -> Option<String> {
+        (self.algorithm == HashAlgorithm::Sha2_256)
+            .then(|| format!("Qm{}", bs58::encode(&self.hash).into_string()))
+    }
 
     /// Parse CID from string
+    ///
+    /// Accepts canonical CIDv0 strings, CIDv1 strings in any supported
+    /// multibase and, for CIDs published before the format was unified,
+    /// legacy `Qm<base58(sha256)>` strings. v0 and legacy strings parse to
+    /// the same `ContentId`, which always displays in canonical form.
+    ///
+    /// The v0 and legacy formats never collide: read as a whole, a legacy
+    /// string decodes to a value just below the range covered by the
+    /// `0x12 0x20` prefix, so no string is valid in both. Neither collides
+    /// with v1, since "Q" is not a multibase prefix.
     pub fn parse(s: &str) -> Result<Self, CidError> {
+        if s.starts_with("Qm") {
+            return Self::parse_v0(s).or_else(|v0_err| Self::parse_legacy(s).map_err(|_| v0_err));
+        }
+        // Neither "Qm" nor a known multibase prefix: not something we read as a CID
+        Self::parse_v1(s).map_err(|e| match e {
+            CidError::UnsupportedMultibase(_) => CidError::InvalidFormat,
+            e => e,
+        })
+    }
+
+    /// Parse a canonical CIDv0 string only
+    pub fn parse_v0(s: &str) -> Result<Self, CidError> {
         if !s.starts_with("Qm") {
             return Err(CidError::InvalidFormat);
         }
 
-        let hash_str = &s[2..];
+        let bytes = bs58::decode(s)
+            .into_vec()
+            .map_err(|_| CidError::InvalidBase58)?;
+
+        if bytes.len() != 34 {
+            return Err(CidError::InvalidHashLength);
+        }
+
+        Self::from_v0_bytes(&bytes)
+    }
+
+    /// Parse a multibase CIDv1 string only
+    pub fn parse_v1(s: &str) -> Result<Self, CidError> {
+        let (_, bytes) = Multibase::decode(s)?;
+
+        let cid = Self::from_bytes(&bytes)?;
+        if cid.version != Version::V1 {
+            return Err(CidError::UnsupportedVersion(0));
+        }
+        Ok(cid)
+    }
+
+    /// Parse a legacy `Qm<base58(sha256)>` string only
+    pub fn parse_legacy(s: &str) -> Result<Self, CidError> {
+        let hash_str = s.strip_prefix("Qm").ok_or(CidError::InvalidFormat)?;
+
         let hash_bytes = bs58::decode(hash_str)
             .into_vec()
             .map_err(|_| CidError::InvalidBase58)?;
@@ -61,10 +405,25 @@ impl ContentId {
         let mut hash = [0u8; 32];
         hash.copy_from_slice(&hash_bytes);
 
-        Ok(ContentId {
-            hash,
-            multibase: s.to_string(),
-        })
+        Ok(Self::from_hash(hash))
+    }
+
+    /// Parse CID from string; same as [`ContentId::parse`]
+    pub fn from_string(s: &str) -> Result<Self, CidError> {
+        Self::parse(s)
+    }
+
+    /// Detect which format a CID string is written in
+    pub fn detect_format(s: &str) -> Option<CidFormat> {
+        if Self::parse_v0(s).is_ok() {
+            Some(CidFormat::V0)
+        } else if Self::parse_v1(s).is_ok() {
+            Some(CidFormat::V1)
+        } else if Self::parse_legacy(s).is_ok() {
+            Some(CidFormat::Legacy)
+        } else {
+            None
+        }
     }
 }
 
@@ -82,14 +441,43 @@ impl fmt::Display for ContentId {
     }
 }
 
+impl Serialize for ContentId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.multibase)
+    }
+}
+
+impl<'de> Deserialize<'de> for ContentId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::parse(&s).map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum CidError {
-    #[error("Invalid CID format (must start with Qm)")]
+    #[error("Invalid CID format (expected Qm... CIDv0 or a multibase CIDv1)")]
     InvalidFormat,
     #[error("Invalid base58 encoding")]
     InvalidBase58,
-    #[error("Invalid hash length (expected 32 bytes)")]
+    #[error("Invalid base32 encoding")]
+    InvalidBase32,
+    #[error("Invalid base36 encoding")]
+    InvalidBase36,
+    #[error("Unsupported multibase prefix '{0}'")]
+    UnsupportedMultibase(char),
+    #[error("Invalid varint")]
+    InvalidVarint,
+    #[error("Unsupported CID version {0}")]
+    UnsupportedVersion(u64),
+    #[error("Unsupported multicodec 0x{0:x}")]
+    UnsupportedCodec(u64),
+    #[error("CID cannot be expressed as CIDv0 (needs dag-pb and sha2-256)")]
+    NotV0Compatible,
+    #[error("Invalid hash length for the hash function")]
     InvalidHashLength,
+    #[error("Unsupported multihash code 0x{0:x}")]
+    UnsupportedMultihash(u64),
 }
 
 #[cfg(test)]
@@ -102,6 +490,7 @@ mod tests {
         let cid = ContentId::new(content);
 
         assert!(cid.as_str().starts_with("Qm"));
+        assert_eq!(cid.as_str().len(), 46);
         assert_eq!(cid.hash().len(), 32);
     }
 
@@ -130,4 +519,314 @@ mod tests {
 
         assert_eq!(cid1, cid2);
     }
+
+    #[test]
+    fn test_ipfs_cidv0_vectors() {
+        // Multihash-encoded sha2-256 digests as produced by IPFS tooling
+        let cases: [(&[u8], &str); 2] = [
+            (
+                b"hello world",
+                "QmaozNR7DZHQK1ZcU9p7QdrshMvXqWK6gpu5rmrkPdT3L4",
+            ),
+            (b"", "QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n"),
+        ];
+
+        for (content, expected) in cases {
+            let cid = ContentId::new(content);
+            assert_eq!(cid.as_str(), expected);
+            assert_eq!(ContentId::parse_v0(expected).unwrap(), cid);
+        }
+    }
+
+    #[test]
+    fn test_legacy_cid_parses_to_same_hash() {
+        let cid = ContentId::new(b"hello world");
+        let legacy = cid.to_legacy_string().unwrap();
+        assert_eq!(legacy, "QmDULfJyE3WQqNxy3ymuhAChyNR3yufT88pmqvAazKFMG4");
+
+        let parsed = ContentId::parse(&legacy).unwrap();
+        assert_eq!(parsed, cid);
+        assert_eq!(ContentId::detect_format(&legacy), Some(CidFormat::Legacy));
+
+        // Always displayed in canonical form
+        assert_eq!(parsed.to_string(), cid.as_str());
+    }
+
+    #[test]
+    fn test_formats_never_collide() {
+        for i in 0u32..2000 {
+            let cid = ContentId::new(&i.to_be_bytes());
+            let legacy = cid.to_legacy_string().unwrap();
+
+            assert!(ContentId::parse_legacy(cid.as_str()).is_err());
+            assert!(ContentId::parse_v0(&legacy).is_err());
+            assert_eq!(ContentId::parse(&legacy).unwrap(), cid);
+            assert_eq!(ContentId::detect_format(cid.as_str()), Some(CidFormat::V0));
+        }
+    }
+
+    #[test]
+    fn test_invalid_cids_rejected() {
+        assert!(matches!(
+            ContentId::parse("Zz123456789"),
+            Err(CidError::InvalidFormat)
+        ));
+        assert!(matches!(
+            ContentId::parse("Qm0OIl"),
+            Err(CidError::InvalidBase58)
+        ));
+        assert!(ContentId::parse("Qm").is_err());
+
+        // Valid base58, but a 34-byte value with the wrong multihash code
+        let mut bytes = vec![0x13, 0x20];
+        bytes.extend_from_slice(&[0u8; 32]);
+        let s = bs58::encode(bytes).into_string();
+        assert!(ContentId::parse_v0(&s).is_err());
+    }
+
+    #[test]
+    fn test_hash_hex() {
+        let cid = ContentId::new(b"hello world");
+        assert_eq!(
+            cid.hash_hex(),
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+    }
+
+    #[test]
+    fn test_serde_uses_cid_string() {
+        let cid = ContentId::new(b"serde");
+        let value = serde::de::value::StrDeserializer::<serde::de::value::Error>::new(cid.as_str());
+        let parsed = ContentId::deserialize(value).unwrap();
+        assert_eq!(parsed, cid);
+    }
+
+    #[test]
+    fn test_ipfs_cidv1_vectors() {
+        // "hello world" in every codec and base, as printed by `ipfs cid format`
+        let cases = [
+            (
+                Codec::Raw,
+                "bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e",
+                "zb2rhj7crUKTQYRGCRATFaQ6YFLTde2YzdqbbhAASkL9uRDXn",
+                "k2cwued9o1pvrt3q271rrqbo49x30tbxwpoeaq75z14e5ui2rzygpbe1",
+            ),
+            (
+                Codec::DagPb,
+                "bafybeifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e",
+                "zdj7WhuEjrB52m1BisYCtmjH1hSKa7yZ3jEZ9JcXaFRD51wVz",
+                "k2jmtxvzdh31wdobmmkk6r1ort6878dnwbztw7afic4arrehud5eg4q1",
+            ),
+            (
+                Codec::DagCbor,
+                "bafyreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e",
+                "zdpuAxtn9ETwkv4bBn55v9dYSLvTsBABZvB7GvJAsiGoZJGEt",
+                "k2jvsla0bqzjgjrr3dt98n2ngojwe4xq6zhl4zx7v1uyn8md2gazp4h5",
+            ),
+        ];
+
+        for (codec, base32, base58, base36) in cases {
+            let cid = ContentId::new_v1(codec, b"hello world");
+            assert_eq!(cid.as_str(), base32);
+            assert_eq!(cid.to_string_of_base(Multibase::Base58Btc).unwrap(), base58);
+            assert_eq!(
+                cid.to_string_of_base(Multibase::Base36Lower).unwrap(),
+                base36
+            );
+
+            for s in [base32, base58, base36] {
+                let parsed = ContentId::parse(s).unwrap();
+                assert_eq!(parsed, cid);
+                assert_eq!(parsed.codec(), codec);
+                assert_eq!(ContentId::detect_format(s), Some(CidFormat::V1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_v0_v1_conversion() {
+        let v0 = ContentId::new(b"hello world");
+        let v1 = v0.to_v1();
+
+        assert_eq!(v1.version(), Version::V1);
+        assert_eq!(v1.codec(), Codec::DagPb);
+        assert_eq!(v1.hash(), v0.hash());
+        assert_eq!(
+            v1.as_str(),
+            "bafybeifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e"
+        );
+        assert_eq!(v1.to_v0().unwrap(), v0);
+        assert_eq!(v0.to_v0().unwrap(), v0);
+
+        // Only dag-pb has a v0 form
+        let raw = ContentId::new_v1(Codec::Raw, b"hello world");
+        assert!(matches!(raw.to_v0(), Err(CidError::NotV0Compatible)));
+        assert!(raw.verify(b"hello world"));
+    }
+
+    #[test]
+    fn test_binary_roundtrip() {
+        for cid in [
+            ContentId::new(b"bytes"),
+            ContentId::new_v1(Codec::Raw, b"bytes"),
+            ContentId::new_v1(Codec::DagCbor, b"bytes"),
+            ContentId::new_v1(Codec::HashList, b"bytes"),
+        ] {
+            assert_eq!(ContentId::from_bytes(&cid.to_bytes()).unwrap(), cid);
+        }
+
+        let v1 = ContentId::new_v1(Codec::Raw, b"bytes").to_bytes();
+        assert_eq!(&v1[..4], &[0x01, 0x55, 0x12, 0x20]);
+        assert_eq!(v1.len(), 36);
+
+        // Private-use codes take a three-byte varint
+        let list = ContentId::new_v1(Codec::HashList, b"bytes").to_bytes();
+        assert_eq!(&list[..6], &[0x01, 0x80, 0x80, 0xc0, 0x01, 0x12]);
+        let parsed =
+            ContentId::parse(ContentId::new_v1(Codec::HashList, b"bytes").as_str()).unwrap();
+        assert_eq!(parsed.codec(), Codec::HashList);
+    }
+
+    #[test]
+    fn test_binary_prefix_reads_concatenated_cids() {
+        let cids = [
+            ContentId::new_v1(Codec::HashList, b"node"),
+            ContentId::new(b"chunk"),
+            ContentId::new_v1_with(Codec::Raw, HashAlgorithm::Blake3, b"chunk"),
+        ];
+        let bytes: Vec<u8> = cids.iter().flat_map(ContentId::to_bytes).collect();
+
+        let mut rest = &bytes[..];
+        for cid in &cids {
+            let (parsed, tail) = ContentId::from_bytes_prefix(rest).unwrap();
+            assert_eq!(&parsed, cid);
+            assert_eq!(parsed.codec(), cid.codec());
+            rest = tail;
+        }
+        assert!(rest.is_empty());
+
+        // A truncated CID at the end is an error, not a short digest
+        let last = cids[2].to_bytes();
+        assert!(ContentId::from_bytes_prefix(&last[..last.len() - 1]).is_err());
+        assert!(ContentId::from_bytes_prefix(&cids[1].to_bytes()[..33]).is_err());
+    }
+
+    #[test]
+    fn test_v0_only_encodes_as_base58() {
+        let cid = ContentId::new(b"hello world");
+        assert_eq!(
+            cid.to_string_of_base(Multibase::Base58Btc).unwrap(),
+            cid.as_str()
+        );
+        assert!(matches!(
+            cid.to_string_of_base(Multibase::Base32Lower),
+            Err(CidError::UnsupportedMultibase('b'))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_v1_rejected() {
+        let mut bytes = Vec::new();
+        varint::encode(2, &mut bytes);
+        varint::encode(0x55, &mut bytes);
+        bytes.extend_from_slice(&ContentId::from_hash([0u8; 32]).multihash());
+        assert!(matches!(
+            ContentId::from_bytes(&bytes),
+            Err(CidError::UnsupportedVersion(2))
+        ));
+
+        bytes[0] = 0x01;
+        bytes[1] = 0x50;
+        assert!(matches!(
+            ContentId::from_bytes(&bytes),
+            Err(CidError::UnsupportedCodec(0x50))
+        ));
+
+        // Truncated digest
+        let cid = ContentId::new_v1(Codec::Raw, b"short");
+        let bytes = cid.to_bytes();
+        assert!(ContentId::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        // A base32 string with a v0 multihash inside is not a CIDv1
+        let v0_in_base32 = Multibase::Base32Lower.encode(&ContentId::new(b"x").to_bytes());
+        assert!(ContentId::parse_v1(&v0_in_base32).is_err());
+
+        // Subdomains may arrive uppercased
+        let upper = cid.as_str().to_ascii_uppercase();
+        assert_eq!(ContentId::parse(&upper).unwrap(), cid);
+    }
+
+    #[test]
+    fn test_multihash_cidv1_vectors() {
+        let cases = [
+            (
+                HashAlgorithm::Blake3,
+                "bafkr4igxjga67jykbseaxdmmdgc5a5o3zp3htom2l6mrjznk7fvyggu6eq",
+            ),
+            (
+                HashAlgorithm::Sha3_256,
+                "bafkrmidejpgh4vsdomcatgnkzcphmixtzjy7xiozol6zjiy4hp57etrzha",
+            ),
+            (
+                HashAlgorithm::Sha2_512,
+                "bafkrgqbqt3gerhas23vuzrapkdeqf4vu2dwxp3srdj6hvg6nhsug2tgyn6mj3u23yx7utftq3i2ckw2fwdh5qmhid5qf3t35yvkc5e5ottlw6",
+            ),
+        ];
+
+        for (algorithm, expected) in cases {
+            let cid = ContentId::new_with(algorithm, b"hello world");
+            assert_eq!(cid.as_str(), expected);
+            assert_eq!(cid.algorithm(), algorithm);
+            assert_eq!(cid.hash().len(), algorithm.digest_len());
+
+            let parsed = ContentId::parse(expected).unwrap();
+            assert_eq!(parsed, cid);
+            assert!(parsed.verify(b"hello world"));
+            assert!(!parsed.verify(b"hello world!"));
+        }
+    }
+
+    #[test]
+    fn test_sha256_cids_unchanged() {
+        let cid = ContentId::new_with(HashAlgorithm::Sha2_256, b"hello world");
+        assert_eq!(cid, ContentId::new(b"hello world"));
+        assert_eq!(
+            cid.as_str(),
+            "QmaozNR7DZHQK1ZcU9p7QdrshMvXqWK6gpu5rmrkPdT3L4"
+        );
+        assert_eq!(cid.routing_key(), cid.hash());
+    }
+
+    #[test]
+    fn test_non_sha256_has_no_v0_form() {
+        let cid = ContentId::new_v1_with(Codec::DagPb, HashAlgorithm::Blake3, b"blake3");
+        assert!(matches!(cid.to_v0(), Err(CidError::NotV0Compatible)));
+        assert_eq!(cid.to_legacy_string(), None);
+        assert_eq!(cid.to_v1(), cid);
+    }
+
+    #[test]
+    fn test_routing_key_roundtrip() {
+        for algorithm in [
+            HashAlgorithm::Sha2_256,
+            HashAlgorithm::Sha3_512,
+            HashAlgorithm::Blake3,
+        ] {
+            let cid = ContentId::new_with(algorithm, b"routing");
+            let parsed = ContentId::from_routing_key(&cid.routing_key()).unwrap();
+            assert_eq!(parsed, cid);
+        }
+
+        // A blake3 key can't be mistaken for a sha2-256 one
+        let blake3 = ContentId::new_with(HashAlgorithm::Blake3, b"routing");
+        assert_eq!(blake3.routing_key().len(), 34);
+    }
+
+    #[test]
+    fn test_digest_length_checked() {
+        assert!(matches!(
+            ContentId::from_digest_v1(Codec::Raw, HashAlgorithm::Sha2_512, &[0u8; 32]),
+            Err(CidError::InvalidHashLength)
+        ));
+    }
 }
@@ -0,0 +1,238 @@
+//! Multibase encodings supported for CIDv1 strings
+
+use crate::CidError;
+
+/// RFC 4648 base32 alphabet, lowercase
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// Base36 alphabet, lowercase
+const BASE36_ALPHABET: &[u8; 36] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+/// A multibase encoding, identified by its one-character prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Multibase {
+    /// `z` - base58 with the Bitcoin alphabet (the CIDv0 alphabet)
+    Base58Btc,
+    /// `b` - lowercase RFC 4648 base32 without padding, the CIDv1 default
+    Base32Lower,
+    /// `k` - lowercase base36, short enough for a DNS label
+    Base36Lower,
+}
+
+impl Multibase {
+    /// Prefix character identifying this encoding
+    pub fn prefix(&self) -> char {
+        match self {
+            Multibase::Base58Btc => 'z',
+            Multibase::Base32Lower => 'b',
+            Multibase::Base36Lower => 'k',
+        }
+    }
+
+    /// Look up an encoding by prefix
+    ///
+    /// The uppercase base32 and base36 prefixes are accepted as well, since
+    /// hostnames may have been case-folded on the way in.
+    pub fn from_prefix(prefix: char) -> Result<Self, CidError> {
+        match prefix {
+            'z' => Ok(Multibase::Base58Btc),
+            'b' | 'B' => Ok(Multibase::Base32Lower),
+            'k' | 'K' => Ok(Multibase::Base36Lower),
+            other => Err(CidError::UnsupportedMultibase(other)),
+        }
+    }
+
+    /// Encode bytes, including the prefix
+    pub fn encode(&self, bytes: &[u8]) -> String {
+        let body = match self {
+            Multibase::Base58Btc => bs58::encode(bytes).into_string(),
+            Multibase::Base32Lower => encode_base32(bytes),
+            Multibase::Base36Lower => encode_base36(bytes),
+        };
+
+        format!("{}{}", self.prefix(), body)
+    }
+
+    /// Decode a prefixed string
+    pub fn decode(s: &str) -> Result<(Self, Vec<u8>), CidError> {
+        let mut chars = s.chars();
+        let prefix = chars.next().ok_or(CidError::InvalidFormat)?;
+        let base = Self::from_prefix(prefix)?;
+        let body = chars.as_str();
+
+        let bytes = match base {
+            Multibase::Base58Btc => bs58::decode(body)
+                .into_vec()
+                .map_err(|_| CidError::InvalidBase58)?,
+            Multibase::Base32Lower => decode_base32(body)?,
+            Multibase::Base36Lower => decode_base36(body)?,
+        };
+
+        Ok((base, bytes))
+    }
+}
+
+fn encode_base32(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+
+    out
+}
+
+fn decode_base32(s: &str) -> Result<Vec<u8>, CidError> {
+    let mut out = Vec::with_capacity(s.len() * 5 / 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for c in s.bytes() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&a| a == c.to_ascii_lowercase())
+            .ok_or(CidError::InvalidBase32)? as u32;
+
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+
+    // Leftover bits are padding and must be zero
+    if bits >= 5 || buffer & ((1 << bits) - 1) != 0 {
+        return Err(CidError::InvalidBase32);
+    }
+
+    Ok(out)
+}
+
+fn encode_base36(bytes: &[u8]) -> String {
+    let zeros = bytes.iter().take_while(|&&b| b == 0).count();
+
+    // Repeated division of the big-endian number by 36
+    let mut digits: Vec<u8> = Vec::new();
+    for &byte in &bytes[zeros..] {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 36) as u8;
+            carry /= 36;
+        }
+        while carry > 0 {
+            digits.push((carry % 36) as u8);
+            carry /= 36;
+        }
+    }
+
+    let mut out = "0".repeat(zeros);
+    out.extend(
+        digits
+            .iter()
+            .rev()
+            .map(|&d| BASE36_ALPHABET[d as usize] as char),
+    );
+    out
+}
+
+fn decode_base36(s: &str) -> Result<Vec<u8>, CidError> {
+    let zeros = s.bytes().take_while(|&c| c == b'0').count();
+
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in s.bytes().skip(zeros) {
+        let value = BASE36_ALPHABET
+            .iter()
+            .position(|&a| a == c.to_ascii_lowercase())
+            .ok_or(CidError::InvalidBase36)? as u32;
+
+        let mut carry = value;
+        for byte in bytes.iter_mut() {
+            carry += (*byte as u32) * 36;
+            *byte = (carry & 0xff) as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push((carry & 0xff) as u8);
+            carry >>= 8;
+        }
+    }
+
+    let mut out = vec![0u8; zeros];
+    out.extend(bytes.iter().rev());
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base32_rfc4648_vectors() {
+        // RFC 4648 section 10, lowercased and unpadded
+        let cases: [(&[u8], &str); 7] = [
+            (b"", ""),
+            (b"f", "my"),
+            (b"fo", "mzxq"),
+            (b"foo", "mzxw6"),
+            (b"foob", "mzxw6yq"),
+            (b"fooba", "mzxw6ytb"),
+            (b"foobar", "mzxw6ytboi"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(encode_base32(input), expected);
+            assert_eq!(decode_base32(expected).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn test_base36_roundtrip() {
+        for input in [&b""[..], &[0], &[0, 0, 1], b"hello world", &[0xff; 40]] {
+            let encoded = encode_base36(input);
+            assert_eq!(decode_base36(&encoded).unwrap(), input);
+        }
+
+        assert_eq!(encode_base36(&[35]), "z");
+        assert_eq!(encode_base36(&[36]), "10");
+    }
+
+    #[test]
+    fn test_multibase_prefixes() {
+        for base in [
+            Multibase::Base58Btc,
+            Multibase::Base32Lower,
+            Multibase::Base36Lower,
+        ] {
+            let encoded = base.encode(b"multibase");
+            assert!(encoded.starts_with(base.prefix()));
+            assert_eq!(
+                Multibase::decode(&encoded).unwrap(),
+                (base, b"multibase".to_vec())
+            );
+        }
+
+        assert!(matches!(
+            Multibase::decode("fdeadbeef"),
+            Err(CidError::UnsupportedMultibase('f'))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_characters_rejected() {
+        assert!(decode_base32("mzx1").is_err());
+        assert!(decode_base36("abc!").is_err());
+    }
+}
@@ -0,0 +1,224 @@
+//! Hash functions a CID can be built on, identified by multihash code
+
+use crate::{varint, CidError};
+use sha2::{Digest, Sha256, Sha512};
+use sha3::{Sha3_256, Sha3_512};
+
+/// A multihash function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum HashAlgorithm {
+    /// `0x12` - the default, and the only hash CIDv0 can carry
+    #[default]
+    Sha2_256,
+    /// `0x13`
+    Sha2_512,
+    /// `0x16`
+    Sha3_256,
+    /// `0x14`
+    Sha3_512,
+    /// `0x1e` - 32-byte output; several times faster than SHA-256 on large inputs
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Multihash table code
+    pub fn code(&self) -> u64 {
+        match self {
+            HashAlgorithm::Sha2_256 => 0x12,
+            HashAlgorithm::Sha2_512 => 0x13,
+            HashAlgorithm::Sha3_256 => 0x16,
+            HashAlgorithm::Sha3_512 => 0x14,
+            HashAlgorithm::Blake3 => 0x1e,
+        }
+    }
+
+    /// Look up a hash function by its multihash table code
+    pub fn from_code(code: u64) -> Result<Self, CidError> {
+        match code {
+            0x12 => Ok(HashAlgorithm::Sha2_256),
+            0x13 => Ok(HashAlgorithm::Sha2_512),
+            0x16 => Ok(HashAlgorithm::Sha3_256),
+            0x14 => Ok(HashAlgorithm::Sha3_512),
+            0x1e => Ok(HashAlgorithm::Blake3),
+            other => Err(CidError::UnsupportedMultihash(other)),
+        }
+    }
+
+    /// Digest length in bytes
+    pub fn digest_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha2_256 | HashAlgorithm::Sha3_256 | HashAlgorithm::Blake3 => 32,
+            HashAlgorithm::Sha2_512 | HashAlgorithm::Sha3_512 => 64,
+        }
+    }
+
+    /// Hash `data`
+    pub fn digest(&self, data: &[u8]) -> Vec<u8> {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    /// Start an incremental hash
+    pub(crate) fn hasher(&self) -> Hasher {
+        match self {
+            HashAlgorithm::Sha2_256 => Hasher::Sha2_256(Sha256::new()),
+            HashAlgorithm::Sha2_512 => Hasher::Sha2_512(Sha512::new()),
+            HashAlgorithm::Sha3_256 => Hasher::Sha3_256(Sha3_256::new()),
+            HashAlgorithm::Sha3_512 => Hasher::Sha3_512(Sha3_512::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::default()),
+        }
+    }
+}
+
+/// In-progress hash for any [`HashAlgorithm`]
+#[derive(Clone)]
+pub(crate) enum Hasher {
+    Sha2_256(Sha256),
+    Sha2_512(Sha512),
+    Sha3_256(Sha3_256),
+    Sha3_512(Sha3_512),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub(crate) fn algorithm(&self) -> HashAlgorithm {
+        match self {
+            Hasher::Sha2_256(_) => HashAlgorithm::Sha2_256,
+            Hasher::Sha2_512(_) => HashAlgorithm::Sha2_512,
+            Hasher::Sha3_256(_) => HashAlgorithm::Sha3_256,
+            Hasher::Sha3_512(_) => HashAlgorithm::Sha3_512,
+            Hasher::Blake3(_) => HashAlgorithm::Blake3,
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha2_256(h) => h.update(data),
+            Hasher::Sha2_512(h) => h.update(data),
+            Hasher::Sha3_256(h) => h.update(data),
+            Hasher::Sha3_512(h) => h.update(data),
+            Hasher::Blake3(h) => {
+                h.update(data);
+            }
+        }
+    }
+
+    pub(crate) fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Sha2_256(h) => h.finalize().to_vec(),
+            Hasher::Sha2_512(h) => h.finalize().to_vec(),
+            Hasher::Sha3_256(h) => h.finalize().to_vec(),
+            Hasher::Sha3_512(h) => h.finalize().to_vec(),
+            Hasher::Blake3(h) => h.finalize().as_bytes().to_vec(),
+        }
+    }
+}
+
+/// Append the multihash `varint(code) || varint(len) || digest` to `buf`
+pub(crate) fn encode(algorithm: HashAlgorithm, digest: &[u8], buf: &mut Vec<u8>) {
+    varint::encode(algorithm.code(), buf);
+    varint::encode(digest.len() as u64, buf);
+    buf.extend_from_slice(digest);
+}
+
+/// Read a multihash that makes up the whole of `bytes`
+pub(crate) fn decode(bytes: &[u8]) -> Result<(HashAlgorithm, &[u8]), CidError> {
+    match decode_prefix(bytes)? {
+        (algorithm, digest, []) => Ok((algorithm, digest)),
+        _ => Err(CidError::InvalidHashLength),
+    }
+}
+
+/// Read a multihash from the front of `bytes`, returning it and the rest
+pub(crate) fn decode_prefix(bytes: &[u8]) -> Result<(HashAlgorithm, &[u8], &[u8]), CidError> {
+    let (code, rest) = varint::decode(bytes)?;
+    let algorithm = HashAlgorithm::from_code(code)?;
+    let (len, rest) = varint::decode(rest)?;
+
+    if len != algorithm.digest_len() as u64 || rest.len() < algorithm.digest_len() {
+        return Err(CidError::InvalidHashLength);
+    }
+
+    let (digest, rest) = rest.split_at(algorithm.digest_len());
+    Ok((algorithm, digest, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_known_digests() {
+        let cases = [
+            (
+                HashAlgorithm::Sha2_256,
+                "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+            ),
+            (
+                HashAlgorithm::Sha3_256,
+                "644bcc7e564373040999aac89e7622f3ca71fba1d972fd94a31c3bfbf24e3938",
+            ),
+            (
+                HashAlgorithm::Sha2_512,
+                "309ecc489c12d6eb4cc40f50c902f2b4d0ed77ee511a7c7a9bcd3ca86d4cd86f\
+                 989dd35bc5ff499670da34255b45b0cfd830e81f605dcf7dc5542e93ae9cd76f",
+            ),
+            (
+                HashAlgorithm::Blake3,
+                "d74981efa70a0c880b8d8c1985d075dbcbf679b99a5f9914e5aaf96b831a9e24",
+            ),
+        ];
+
+        for (algorithm, expected) in cases {
+            let digest = algorithm.digest(b"hello world");
+            assert_eq!(hex(&digest), expected);
+            assert_eq!(digest.len(), algorithm.digest_len());
+        }
+    }
+
+    #[test]
+    fn test_multihash_roundtrip() {
+        for algorithm in [
+            HashAlgorithm::Sha2_256,
+            HashAlgorithm::Sha2_512,
+            HashAlgorithm::Sha3_256,
+            HashAlgorithm::Sha3_512,
+            HashAlgorithm::Blake3,
+        ] {
+            assert_eq!(
+                HashAlgorithm::from_code(algorithm.code()).unwrap(),
+                algorithm
+            );
+
+            let digest = algorithm.digest(b"multihash");
+            let mut buf = Vec::new();
+            encode(algorithm, &digest, &mut buf);
+            assert_eq!(decode(&buf).unwrap(), (algorithm, &digest[..]));
+        }
+    }
+
+    #[test]
+    fn test_multihash_rejects_bad_input() {
+        // Unknown code
+        assert!(matches!(
+            decode(&[0x11, 0x14, 0, 0]),
+            Err(CidError::UnsupportedMultihash(0x11))
+        ));
+
+        // Declared length disagrees with the function
+        let mut buf = vec![0x12, 0x10];
+        buf.extend_from_slice(&[0u8; 16]);
+        assert!(matches!(decode(&buf), Err(CidError::InvalidHashLength)));
+
+        // Truncated digest
+        let mut buf = Vec::new();
+        encode(HashAlgorithm::Blake3, &[0u8; 32], &mut buf);
+        buf.pop();
+        assert!(matches!(decode(&buf), Err(CidError::InvalidHashLength)));
+    }
+}
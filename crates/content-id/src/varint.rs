@@ -0,0 +1,73 @@
+//! Unsigned LEB128 varints, as used by multiformats
+
+use crate::CidError;
+
+/// Longest varint multiformats allow (9 bytes, 63 bits)
+const MAX_VARINT_LEN: usize = 9;
+
+/// Append `value` to `buf` as a varint
+pub fn encode(mut value: u64, buf: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+/// Read a varint from the front of `bytes`, returning it and the rest
+pub fn decode(bytes: &[u8]) -> Result<(u64, &[u8]), CidError> {
+    let mut value = 0u64;
+
+    for (i, &byte) in bytes.iter().enumerate().take(MAX_VARINT_LEN) {
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+
+        if byte & 0x80 == 0 {
+            // Multiformats require the minimal encoding
+            if byte == 0 && i > 0 {
+                return Err(CidError::InvalidVarint);
+            }
+            return Ok((value, &bytes[i + 1..]));
+        }
+    }
+
+    Err(CidError::InvalidVarint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint_roundtrip() {
+        for value in [0, 1, 0x55, 0x7f, 0x80, 0x300, 0xb220, u32::MAX as u64] {
+            let mut buf = Vec::new();
+            encode(value, &mut buf);
+            let (decoded, rest) = decode(&buf).unwrap();
+            assert_eq!(decoded, value);
+            assert!(rest.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_varint_known_encodings() {
+        let mut buf = Vec::new();
+        encode(0x70, &mut buf);
+        assert_eq!(buf, [0x70]);
+
+        buf.clear();
+        encode(0x300, &mut buf);
+        assert_eq!(buf, [0x80, 0x06]);
+    }
+
+    #[test]
+    fn test_varint_rejects_bad_input() {
+        assert!(decode(&[]).is_err());
+        assert!(decode(&[0x80]).is_err());
+        assert!(decode(&[0x80, 0x00]).is_err());
+        assert!(decode(&[0xff; 10]).is_err());
+    }
+}
@@ -172,8 +172,10 @@ mod tests {
 
     #[test]
     fn test_invalid_config() {
-        let mut config = DHTConfig::default();
-        config.replication_factor = 0;
+        let config = DHTConfig {
+            replication_factor: 0,
+            ..Default::default()
+        };
         assert!(config.validate().is_err());
 
         let mut config = DHTConfig::default();
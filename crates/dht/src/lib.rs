@@ -1,12 +1,20 @@
+use anyhow::{anyhow, Context, Result};
 use codio_content_id::ContentId;
+use futures::StreamExt;
+use libp2p::identity::Keypair;
 use libp2p::kad::Behaviour as Kademlia;
 use libp2p::{
-    kad::{store::MemoryStore, Config as KademliaConfig, Event as KademliaEvent, QueryResult},
+    kad::{
+        store::MemoryStore, BootstrapOk, Config as KademliaConfig, Event as KademliaEvent,
+        GetClosestPeersOk, GetProvidersOk, Mode, QueryResult, RecordKey,
+    },
     swarm::{Swarm, SwarmEvent},
     Multiaddr, PeerId, SwarmBuilder,
 };
-use std::time::Duration;
-use tokio::sync::mpsc;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::{mpsc, oneshot};
 
 pub mod config;
 pub use config::{ConfigError, DHTConfig};
@@ -48,6 +56,9 @@ pub struct DHTManager {
 
     /// Last republish time
     last_republish: Arc<Mutex<SystemTime>>,
+
+    /// Peers in our routing table, with the addresses Kademlia knows for them
+    known_peers: Arc<Mutex<HashMap<PeerId, PeerInfo>>>,
 }
 
 /// Information about a peer in the network
@@ -236,15 +247,91 @@ pub enum DHTEvent {
 
     /// Content announcement successful
     ProvideSuccess { cid: ContentId },
+
+    /// Content announcement failed
+    ProvideFailed { cid: ContentId, error: String },
+
     /// Bootstrap completed
-    BootstrapComplete,
+    BootstrapComplete { num_peers: usize },
+
+    /// Bootstrap failed
+    BootstrapFailed { error: String },
+
+    /// New peer discovered
+    PeerDiscovered { peer: PeerInfo },
+
+    /// Addresses found for a peer
+    PeerAddressesFound {
+        peer_id: PeerId,
+        addresses: Vec<Multiaddr>,
+    },
+
+    /// Query completed
+    QueryCompleted { query_type: String, success: bool },
+
+    /// Provider record expired
+    ProviderExpired { cid: ContentId, provider: PeerId },
+
+    /// Routing table updated
+    RoutingTableUpdated { num_peers: usize },
+}
+
+/// Kind of query being tracked
+#[derive(Debug, Clone)]
+enum QueryType {
+    Bootstrap,
+    StartProviding { cid: ContentId },
+    GetProviders { cid: ContentId },
+    FindPeer { peer_id: PeerId },
+}
+
+impl QueryType {
+    /// Human-readable description used in query completion events
+    fn describe(&self) -> String {
+        match self {
+            QueryType::Bootstrap => "bootstrap".to_string(),
+            QueryType::StartProviding { cid } => format!("provide {}", cid),
+            QueryType::GetProviders { cid } => format!("get_providers {}", cid),
+            QueryType::FindPeer { peer_id } => format!("find_peer {}", peer_id),
+        }
+    }
+}
+
+/// Response delivered to a caller waiting on a query
+#[derive(Debug)]
+enum QueryResponse {
+    Providers(Vec<PeerInfo>),
+    ProvideComplete,
+    ClosestPeers(Vec<PeerId>),
+    PeerFound(PeerInfo),
+    Error(String),
 }
 
-impl DhtNode {
-    /// Create new DHT node
-    pub async fn new(
-        config: DhtConfig,
-    ) -> anyhow::Result<(Self, mpsc::UnboundedReceiver<DhtEvent>)> {
+/// Bookkeeping for an in-flight query
+struct QueryInfo {
+    query_type: QueryType,
+    started_at: SystemTime,
+    response_tx: Option<oneshot::Sender<QueryResponse>>,
+}
+
+impl DHTManager {
+    /// Create a new DHT manager
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # use codio_dht::{DHTConfig, DHTManager};
+    /// # async fn example() -> anyhow::Result<()> {
+    /// let dht = DHTManager::new(DHTConfig::default()).await?;
+    /// println!("Local peer: {}", dht.peer_id());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn new(config: DHTConfig) -> Result<Self> {
+        config
+            .validate()
+            .map_err(|e| anyhow!("Invalid DHT configuration: {}", e))?;
+
         // Generate keypair
         let local_key = Keypair::generate_ed25519();
         let local_peer_id = PeerId::from(local_key.public());
@@ -267,9 +354,18 @@ impl DhtNode {
             std::num::NonZeroUsize::new(config.parallelism)
                 .context("Parallelism must be non-zero")?,
         );
+        if config.auto_republish {
+            kad_config.set_provider_publication_interval(Some(config.republish_interval));
+        } else {
+            kad_config.set_provider_publication_interval(None);
+        }
 
         // Create Kademlia behaviour
-        let kademlia = Kademlia::with_config(local_peer_id, store, kad_config);
+        let mut kademlia = Kademlia::with_config(local_peer_id, store, kad_config);
+
+        // Without a confirmed external address Kademlia stays in client mode and
+        // refuses inbound queries, which makes nodes on private networks invisible.
+        kademlia.set_mode(Some(Mode::Server));
 
         // Create swarm
         let swarm = SwarmBuilder::with_existing_identity(local_key)
@@ -282,12 +378,25 @@ impl DhtNode {
             .context("Failed to configure TCP transport")?
             .with_behaviour(|_| kademlia)
             .context("Failed to create Kademlia behaviour")?
+            .with_swarm_config(|c| c.with_idle_connection_timeout(config.maintenance_interval))
             .build();
 
         // Event channel
         let (event_tx, event_rx) = mpsc::unbounded_channel();
 
-        Ok((DhtNode { swarm, event_tx }, event_rx))
+        Ok(Self {
+            swarm,
+            config,
+            peer_id: local_peer_id,
+            event_tx,
+            event_rx: Some(event_rx),
+            provider_records: Arc::new(Mutex::new(HashMap::new())),
+            local_providers: Arc::new(Mutex::new(HashSet::new())),
+            active_queries: Arc::new(Mutex::new(HashMap::new())),
+            stats: Arc::new(Mutex::new(DHTStats::default())),
+            last_republish: Arc::new(Mutex::new(SystemTime::now())),
+            known_peers: Arc::new(Mutex::new(HashMap::new())),
+        })
     }
 
     /// Get the local peer ID
@@ -307,6 +416,34 @@ impl DhtNode {
         self.event_rx.take()
     }
 
+    /// Get a snapshot of the DHT statistics
+    pub fn stats(&self) -> DHTStats {
+        let mut stats = self.stats.lock().unwrap().clone();
+        stats.pending_queries = self.active_queries.lock().unwrap().len();
+        stats
+    }
+
+    /// Drive the DHT until the task is cancelled
+    ///
+    /// Processes swarm events and runs periodic maintenance (expiring stale
+    /// provider records and republishing local content). Applications that
+    /// only issue one-off queries don't need this, since `find_providers` and
+    /// `find_peer` drive the swarm themselves while they wait.
+    pub async fn run_event_loop(&mut self) {
+        let mut maintenance = tokio::time::interval(self.config.maintenance_interval);
+
+        loop {
+            tokio::select! {
+                event = self.swarm.select_next_some() => {
+                    self.handle_swarm_event(event).await;
+                }
+                _ = maintenance.tick() => {
+                    self.run_maintenance().await;
+                }
+            }
+        }
+    }
+
     /// Start listening on an address
     ///
     /// # Arguments
@@ -516,8 +653,8 @@ impl DhtNode {
         tracing::debug!("Get providers query started: {:?}", query_id);
 
         // Wait for response (with timeout)
-        match tokio::time::timeout(self.config.query_timeout, rx).await {
-            Ok(Ok(QueryResponse::Providers(providers))) => {
+        match self.wait_for_response(rx).await {
+            Ok(QueryResponse::Providers(providers)) => {
                 tracing::info!("Found {} providers for {}", providers.len(), cid);
 
                 let mut stats = self.stats.lock().unwrap();
@@ -525,7 +662,7 @@ impl DhtNode {
 
                 Ok(providers)
             }
-            Ok(Ok(QueryResponse::Error(e))) => {
+            Ok(QueryResponse::Error(e)) => {
                 tracing::error!("Provider query failed: {}", e);
 
                 let mut stats = self.stats.lock().unwrap();
@@ -533,23 +670,17 @@ impl DhtNode {
 
                 Err(anyhow!("Provider query failed: {}", e))
             }
-            Ok(Ok(_)) => {
+            Ok(_) => {
                 let mut stats = self.stats.lock().unwrap();
                 stats.failed_queries += 1;
 
                 Err(anyhow!("Unexpected query response type"))
             }
-            Ok(Err(_)) => {
-                let mut stats = self.stats.lock().unwrap();
-                stats.failed_queries += 1;
-
-                Err(anyhow!("Query response channel closed"))
-            }
-            Err(_) => {
+            Err(e) => {
                 let mut stats = self.stats.lock().unwrap();
                 stats.failed_queries += 1;
 
-                Err(anyhow!("Query timeout"))
+                Err(e)
             }
         }
     }
@@ -616,13 +747,23 @@ impl DhtNode {
         }
 
         // Wait for response
-        match tokio::time::timeout(self.config.query_timeout, rx).await {
-            Ok(Ok(QueryResponse::PeerFound(peer_info))) => {
+        match self.wait_for_response(rx).await {
+            Ok(QueryResponse::PeerFound(peer_info)) => {
                 let mut stats = self.stats.lock().unwrap();
                 stats.successful_queries += 1;
 
                 Ok(peer_info.addresses)
             }
+            Ok(QueryResponse::ClosestPeers(peers)) => {
+                let mut stats = self.stats.lock().unwrap();
+                stats.failed_queries += 1;
+
+                Err(anyhow!(
+                    "Peer {} not among {} closest peers",
+                    peer_id,
+                    peers.len()
+                ))
+            }
             _ => {
                 let mut stats = self.stats.lock().unwrap();
                 stats.failed_queries += 1;
@@ -647,39 +788,110 @@ impl DhtNode {
         Vec::new()
     }
 
-                        let _ = self
-                            .event_tx
-                            .send(DhtEvent::ProvidersFound { cid, providers });
-                    }
-                    QueryResult::StartProviding(Ok(_)) => {
-                        // Provider record published
-                        tracing::debug!("Content announced to DHT");
-                    }
-                    QueryResult::Bootstrap(Ok(_)) => {
-                        let _ = self.event_tx.send(DhtEvent::BootstrapComplete);
-                    }
-                    _ => {}
+    /// Drive the swarm until a query response arrives or the query times out
+    async fn wait_for_response(
+        &mut self,
+        mut rx: oneshot::Receiver<QueryResponse>,
+    ) -> Result<QueryResponse> {
+        let deadline = tokio::time::sleep(self.config.query_timeout);
+        tokio::pin!(deadline);
+
+        loop {
+            tokio::select! {
+                response = &mut rx => {
+                    return response.map_err(|_| anyhow!("Query response channel closed"));
+                }
+                event = self.swarm.select_next_some() => {
+                    self.handle_swarm_event(event).await;
                 }
+                _ = &mut deadline => {
+                    return Err(anyhow!("Query timeout"));
+                }
+            }
+        }
+    }
+
+    /// Handle a single swarm event
+    async fn handle_swarm_event(&mut self, event: SwarmEvent<KademliaEvent>) {
+        match event {
+            SwarmEvent::Behaviour(event) => {
+                self.handle_kademlia_event(event).await;
+            }
+            SwarmEvent::NewListenAddr { address, .. } => {
+                tracing::info!("DHT listening on {}", address);
+            }
+            SwarmEvent::ConnectionEstablished {
+                peer_id, endpoint, ..
+            } => {
+                tracing::debug!("Connected to {}", peer_id);
+
+                let peer = {
+                    let mut known = self.known_peers.lock().unwrap();
+                    let info = known
+                        .entry(peer_id)
+                        .or_insert_with(|| PeerInfo::new(peer_id));
+                    info.add_address(endpoint.get_remote_address().clone());
+                    info.last_seen = SystemTime::now();
+                    info.clone()
+                };
+
+                let _ = self.event_tx.send(DHTEvent::PeerDiscovered { peer });
+            }
+            SwarmEvent::ConnectionClosed { peer_id, .. } => {
+                tracing::debug!("Disconnected from {}", peer_id);
             }
             _ => {}
         }
     }
 
+    /// Periodic maintenance: expire stale records and republish local content
+    async fn run_maintenance(&mut self) {
+        self.cleanup_expired_records();
+
+        if !self.config.auto_republish {
+            return;
+        }
+
+        let due = {
+            let last = self.last_republish.lock().unwrap();
+            last.elapsed().unwrap_or_default() >= self.config.republish_interval
+        };
+
+        if due {
+            self.republish_content().await;
+        }
+    }
+
     /// Handle Kademlia-specific events
     async fn handle_kademlia_event(&mut self, event: KademliaEvent) {
         match event {
             KademliaEvent::OutboundQueryProgressed { id, result, .. } => {
                 self.handle_query_result(id, result).await;
             }
-            KademliaEvent::RoutingUpdated { peer, .. } => {
+            KademliaEvent::RoutingUpdated {
+                peer, addresses, ..
+            } => {
                 tracing::debug!("Routing table updated with peer: {}", peer);
 
                 // Count peers in routing table
-                let num_peers = self.stats.lock().unwrap().num_peers;
+                let num_peers = {
+                    let mut known = self.known_peers.lock().unwrap();
+                    let info = known.entry(peer).or_insert_with(|| PeerInfo::new(peer));
+                    for addr in addresses.iter() {
+                        info.add_address(addr.clone());
+                    }
+                    known.len()
+                };
 
-                let _ = self.event_tx.send(DHTEvent::RoutingTableUpdated {
-                    num_peers,
-                });
+                {
+                    let mut stats = self.stats.lock().unwrap();
+                    stats.num_peers = num_peers;
+                    stats.routing_table_size = num_peers;
+                }
+
+                let _ = self
+                    .event_tx
+                    .send(DHTEvent::RoutingTableUpdated { num_peers });
             }
             KademliaEvent::InboundRequest { request } => {
                 tracing::trace!("Inbound DHT request: {:?}", request);
@@ -696,11 +908,30 @@ impl DhtNode {
             queries.remove(&query_id)
         };
 
+        // Multi-step queries report several results; only the first one is
+        // delivered, later steps arrive here after the query info is gone.
         let Some(query_info) = query_info else {
-            tracing::warn!("Received result for unknown query: {:?}", query_id);
+            tracing::debug!("Received result for finished query: {:?}", query_id);
             return;
         };
 
+        let success = !matches!(
+            result,
+            QueryResult::GetProviders(Err(_))
+                | QueryResult::StartProviding(Err(_))
+                | QueryResult::Bootstrap(Err(_))
+                | QueryResult::GetClosestPeers(Err(_))
+        );
+        tracing::debug!(
+            "Query {} finished in {:?}",
+            query_info.query_type.describe(),
+            query_info.started_at.elapsed().unwrap_or_default()
+        );
+        let _ = self.event_tx.send(DHTEvent::QueryCompleted {
+            query_type: query_info.query_type.describe(),
+            success,
+        });
+
         match result {
             QueryResult::GetProviders(Ok(GetProvidersOk::FoundProviders {
                 key,
@@ -710,6 +941,15 @@ impl DhtNode {
                 self.handle_providers_found(query_info, key, providers)
                     .await;
             }
+            QueryResult::GetProviders(Ok(GetProvidersOk::FinishedWithNoAdditionalRecord {
+                ..
+            })) => {
+                tracing::debug!("Get providers finished without providers");
+
+                if let Some(tx) = query_info.response_tx {
+                    let _ = tx.send(QueryResponse::Providers(Vec::new()));
+                }
+            }
             QueryResult::GetProviders(Err(e)) => {
                 tracing::error!("Get providers failed: {:?}", e);
 
@@ -752,10 +992,6 @@ impl DhtNode {
                 let _ = self
                     .event_tx
                     .send(DHTEvent::BootstrapComplete { num_peers });
-
-                if let Some(tx) = query_info.response_tx {
-                    let _ = tx.send(QueryResponse::BootstrapComplete { num_peers });
-                }
             }
             QueryResult::Bootstrap(Err(e)) => {
                 tracing::error!("Bootstrap failed: {:?}", e);
@@ -767,8 +1003,28 @@ impl DhtNode {
             QueryResult::GetClosestPeers(Ok(GetClosestPeersOk { key: _, peers })) => {
                 tracing::debug!("Found {} closest peers", peers.len());
 
+                let response = match query_info.query_type {
+                    QueryType::FindPeer { peer_id } if peers.contains(&peer_id) => {
+                        let peer = self
+                            .known_peers
+                            .lock()
+                            .unwrap()
+                            .get(&peer_id)
+                            .cloned()
+                            .unwrap_or_else(|| PeerInfo::new(peer_id));
+
+                        let _ = self.event_tx.send(DHTEvent::PeerAddressesFound {
+                            peer_id,
+                            addresses: peer.addresses.clone(),
+                        });
+
+                        QueryResponse::PeerFound(peer)
+                    }
+                    _ => QueryResponse::ClosestPeers(peers),
+                };
+
                 if let Some(tx) = query_info.response_tx {
-                    let _ = tx.send(QueryResponse::ClosestPeers(peers));
+                    let _ = tx.send(response);
                 }
             }
             QueryResult::GetClosestPeers(Err(e)) => {
@@ -806,10 +1062,14 @@ impl DhtNode {
         let peer_infos: Vec<PeerInfo> = providers
             .into_iter()
             .map(|peer_id| {
-                // Note: libp2p 0.53 doesn't expose addresses_of_peer in the public API
-                // In a production environment, we'd maintain our own address book
-                // For now, we return peers without addresses
-                PeerInfo::new(peer_id)
+                // libp2p 0.53 doesn't expose addresses_of_peer in the public API,
+                // so fill in whatever our own address book knows about the peer
+                self.known_peers
+                    .lock()
+                    .unwrap()
+                    .get(&peer_id)
+                    .cloned()
+                    .unwrap_or_else(|| PeerInfo::new(peer_id))
             })
             .collect();
 
@@ -914,29 +1174,19 @@ impl DhtNode {
 
 /// Convert ContentId to Kademlia RecordKey
 fn cid_to_kad_key(cid: &ContentId) -> RecordKey {
-    RecordKey::new(cid.hash())
+    RecordKey::new(&cid.routing_key())
 }
 
 /// Convert Kademlia RecordKey to ContentId
 fn kad_key_to_cid(key: &RecordKey) -> Result<ContentId> {
-    // The key is the raw hash bytes
-    let key_bytes = key.as_ref();
-
-    if key_bytes.len() != 32 {
-        return Err(anyhow!(
-            "Invalid key length: expected 32 bytes, got {}",
-            key_bytes.len()
-        ));
-    }
-
-    // Reconstruct CID from hash
-    let multibase = format!("Qm{}", bs58::encode(key_bytes).into_string());
-    multibase.parse().map_err(|e| anyhow!("Failed to parse CID: {}", e))
+    ContentId::from_routing_key(key.as_ref())
+        .map_err(|e| anyhow!("Invalid content key: {}", e))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use codio_content_id::HashAlgorithm;
 
     #[tokio::test]
     async fn test_dht_creation() {
@@ -953,6 +1203,16 @@ mod tests {
         assert_eq!(cid, cid2);
     }
 
+    #[tokio::test]
+    async fn test_kad_key_keeps_hash_function() {
+        let cid = ContentId::new_with(HashAlgorithm::Blake3, b"Test");
+        let key = cid_to_kad_key(&cid);
+
+        // Distinct from the sha2-256 key for the same digest length
+        assert_ne!(key, cid_to_kad_key(&ContentId::new(b"Test")));
+        assert_eq!(kad_key_to_cid(&key).unwrap(), cid);
+    }
+
     #[tokio::test]
     async fn test_peer_info_creation() {
         let peer_id = PeerId::random();
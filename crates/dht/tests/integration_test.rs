@@ -166,16 +166,20 @@ async fn test_config_validation() {
     assert!(config.validate().is_ok(), "Default config should be valid");
 
     // Invalid config: replication factor = 0
-    let mut config = DHTConfig::default();
-    config.replication_factor = 0;
+    let config = DHTConfig {
+        replication_factor: 0,
+        ..Default::default()
+    };
     assert!(
         config.validate().is_err(),
         "Zero replication factor should be invalid"
     );
 
     // Invalid config: k_value = 0
-    let mut config = DHTConfig::default();
-    config.k_value = 0;
+    let config = DHTConfig {
+        k_value: 0,
+        ..Default::default()
+    };
     assert!(config.validate().is_err(), "Zero k-value should be invalid");
 }
 
@@ -238,7 +242,7 @@ async fn test_distance_leading_zeros() {
     assert_eq!(dist.leading_zeros(), 256);
 
     // One bit set in first byte
-    let mut bytes1 = [0u8; 32];
+    let bytes1 = [0u8; 32];
     let mut bytes2 = [0u8; 32];
     bytes2[0] = 0b10000000;
     let dist = Distance::between(&bytes1, &bytes2);
@@ -1,8 +1,15 @@
+// The root package doesn't own the `codio-cdn` binary, so `cargo_bin_cmd!` can't
+// resolve it at compile time; the runtime lookup is what we want here.
+#![allow(deprecated)]
+
 use assert_cmd::Command;
 use predicates::prelude::*;
 use std::io::Write;
 use tempfile::NamedTempFile;
 
+#[path = "../crates/codio-content/src/go_random.rs"]
+mod go_random;
+
 #[test]
 fn test_cli_hash_command() {
     let mut file = NamedTempFile::new().unwrap();
@@ -17,6 +24,81 @@ fn test_cli_hash_command() {
         .stdout(predicate::str::contains("Qm"));
 }
 
+#[test]
+fn test_cli_hash_matches_ipfs_cidv0() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "hello world").unwrap();
+
+    let mut cmd = Command::cargo_bin("codio-cdn").unwrap();
+    cmd.arg("hash")
+        .arg(file.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "QmaozNR7DZHQK1ZcU9p7QdrshMvXqWK6gpu5rmrkPdT3L4",
+        ));
+}
+
+#[test]
+fn test_cli_hash_cidv1() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "hello world").unwrap();
+
+    let mut cmd = Command::cargo_bin("codio-cdn").unwrap();
+    cmd.arg("hash")
+        .arg(file.path())
+        .args(["--cid-version", "1"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e",
+        ));
+
+    let mut cmd = Command::cargo_bin("codio-cdn").unwrap();
+    cmd.arg("hash")
+        .arg(file.path())
+        .args(["--cid-version", "1", "--base", "base36"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "k2cwued9o1pvrt3q271rrqbo49x30tbxwpoeaq75z14e5ui2rzygpbe1",
+        ));
+}
+
+#[test]
+fn test_cli_hash_blake3() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "hello world").unwrap();
+
+    let mut cmd = Command::cargo_bin("codio-cdn").unwrap();
+    cmd.arg("hash")
+        .arg(file.path())
+        .args(["--hash", "blake3"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "bafkr4igxjga67jykbseaxdmmdgc5a5o3zp3htom2l6mrjznk7fvyggu6eq",
+        ));
+}
+
+#[test]
+fn test_cli_hash_large_file_streams() {
+    // Spans many read buffers; the streamed CID must match the one-shot CID
+    let data: Vec<u8> = (0..3_000_000u32).map(|i| (i % 251) as u8).collect();
+    let mut file = NamedTempFile::new().unwrap();
+    file.write_all(&data).unwrap();
+
+    let expected = codio_content_id::ContentId::new(&data);
+
+    let mut cmd = Command::cargo_bin("codio-cdn").unwrap();
+    cmd.arg("hash")
+        .arg(file.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(expected.as_str()))
+        .stdout(predicate::str::contains("Size: 3000000 bytes"));
+}
+
 #[test]
 fn test_cli_help() {
     let mut cmd = Command::cargo_bin("codio-cdn").unwrap();
@@ -121,3 +203,855 @@ fn test_cli_hash_help() {
         .success()
         .stdout(predicate::str::contains("Show CID for content"));
 }
+
+/// A small site: index.html and assets/app.js
+fn site_dir() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("assets")).unwrap();
+    std::fs::write(dir.path().join("index.html"), "<script src=assets/app.js>").unwrap();
+    std::fs::write(dir.path().join("assets/app.js"), "console.log('hi')").unwrap();
+    dir
+}
+
+fn hash_cid(path: &std::path::Path) -> String {
+    let output = Command::cargo_bin("codio-cdn")
+        .unwrap()
+        .env("NO_COLOR", "1")
+        .arg("hash")
+        .arg(path)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .find_map(|line| line.strip_prefix("CID: ").map(str::to_string))
+        .unwrap()
+}
+
+#[test]
+fn test_cli_hash_directory() {
+    let dir = site_dir();
+
+    // Directories are dag-pb CIDv1 nodes, stable across runs
+    let cid = hash_cid(dir.path());
+    assert!(cid.starts_with("bafy"), "{}", cid);
+    assert_eq!(hash_cid(dir.path()), cid);
+
+    // Any change below the root changes it
+    std::fs::write(dir.path().join("assets/app.js"), "console.log('bye')").unwrap();
+    assert_ne!(hash_cid(dir.path()), cid);
+}
+
+#[test]
+fn test_cli_hash_directory_matches_ipfs_multi_chunk() {
+    // go-ipfs `t0040-add-and-cat.sh`: `random 5242880 41` is 20 leaves, and
+    // `ipfs add --cid-version=1` (raw leaves) gives it this root
+    let golden = "bafybeigfnx3tka2rf5ovv2slb7ymrt4zbwa3ryeqibe6fipyt5vgsrli3u";
+    let data = go_random::bytes(5 * 1024 * 1024, 41);
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("bigfile"), &data).unwrap();
+    let cid = hash_cid(dir.path());
+
+    let work = tempfile::tempdir().unwrap();
+    let repo = work.path().join("repo");
+    let archive = work.path().join("dir.car");
+    car(&[
+        "export".as_ref(),
+        dir.path().as_os_str(),
+        "-o".as_ref(),
+        archive.as_os_str(),
+    ])
+    .success()
+    .stdout(predicate::str::contains("Wrote 22 blocks"));
+    car(&[
+        "import".as_ref(),
+        archive.as_os_str(),
+        "--repo".as_ref(),
+        repo.as_os_str(),
+    ])
+    .success();
+
+    // The directory links the file by its ipfs root
+    Command::cargo_bin("codio-cdn")
+        .unwrap()
+        .args(["get", &cid, "--no-mdns", "--timeout", "5"])
+        .env("CODIO_REPO", &repo)
+        .assert()
+        .success()
+        .stdout(predicate::str::starts_with(format!("{}\t", golden)))
+        .stdout(predicate::str::ends_with("\tbigfile\n"));
+
+    // ...and the file comes back whole from its leaves
+    let out = work.path().join("bigfile");
+    Command::cargo_bin("codio-cdn")
+        .unwrap()
+        .args(["get", &format!("{}/bigfile", cid), "--no-mdns"])
+        .args(["--timeout", "5"])
+        .arg("-o")
+        .arg(&out)
+        .env("CODIO_REPO", &repo)
+        .assert()
+        .success();
+    assert!(std::fs::read(&out).unwrap() == data);
+}
+
+/// Spawn `publish --serve` and return the child plus its CID and loopback address
+fn spawn_publisher(path: &std::path::Path) -> (std::process::Child, String, String) {
+    spawn_server(&["publish".as_ref(), path.as_os_str()])
+}
+
+/// Spawn a serving command (`--serve` is added) and return the child plus
+/// the CID or root it prints and its loopback address
+fn spawn_server(args: &[&std::ffi::OsStr]) -> (std::process::Child, String, String) {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("codio-cdn"))
+        .env("NO_COLOR", "1")
+        .args(args)
+        .arg("--serve")
+        .arg("--no-mdns")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut cid = None;
+    while let Some(Ok(line)) = lines.next() {
+        if let Some(rest) = line
+            .strip_prefix("CID: ")
+            .or_else(|| line.strip_prefix("Root: "))
+        {
+            cid = Some(rest.trim().to_string());
+        }
+        if line.contains("/ip4/127.0.0.1/") && line.contains("/p2p/") {
+            // Keep draining so the publisher never writes to a closed pipe
+            std::thread::spawn(move || lines.for_each(drop));
+            return (child, cid.unwrap(), line.trim().to_string());
+        }
+    }
+
+    child.kill().unwrap();
+    let _ = child.wait();
+    panic!("Publisher exited without printing a listen address");
+}
+
+#[test]
+fn test_cli_publish_and_get() {
+    let mut file = NamedTempFile::new().unwrap();
+    writeln!(file, "Content shared between two CLI processes").unwrap();
+
+    let (mut publisher, cid, addr) = spawn_publisher(file.path());
+
+    let out = NamedTempFile::new().unwrap();
+    let result = Command::cargo_bin("codio-cdn")
+        .unwrap()
+        .args(["get", &cid, "--peer", &addr, "--no-mdns", "--timeout", "20"])
+        .arg("-o")
+        .arg(out.path())
+        .assert();
+
+    // Also stream to stdout
+    let stdout_result = Command::cargo_bin("codio-cdn")
+        .unwrap()
+        .args(["get", &cid, "--peer", &addr, "--no-mdns", "--timeout", "20"])
+        .assert();
+
+    publisher.kill().unwrap();
+    let _ = publisher.wait();
+
+    result.success();
+    assert_eq!(
+        std::fs::read(out.path()).unwrap(),
+        std::fs::read(file.path()).unwrap()
+    );
+    // The resume journal is removed once the download completes
+    let mut journal = out.path().as_os_str().to_owned();
+    journal.push(".resume");
+    assert!(!std::path::Path::new(&journal).exists());
+    stdout_result
+        .success()
+        .stdout("Content shared between two CLI processes\n");
+}
+
+/// Largest block file under `repo`, in bytes
+fn largest_block(repo: &std::path::Path) -> u64 {
+    let mut largest = 0;
+    let mut pending = vec![repo.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let entry = entry.unwrap();
+            let meta = entry.metadata().unwrap();
+            if meta.is_dir() {
+                pending.push(entry.path());
+            } else if entry.path().extension().is_none_or(|ext| ext != "json") {
+                largest = largest.max(meta.len());
+            }
+        }
+    }
+    largest
+}
+
+#[test]
+fn test_cli_get_to_file_stores_chunks_in_repo() {
+    let work = tempfile::tempdir().unwrap();
+    let repo = work.path().join("repo");
+    let source = work.path().join("large.bin");
+    let data: Vec<u8> = (0..700 * 1024u32).map(|i| (i * 7 % 251) as u8).collect();
+    std::fs::write(&source, &data).unwrap();
+
+    let (mut publisher, cid, addr) = spawn_publisher(&source);
+    let codio = || {
+        let mut cmd = Command::cargo_bin("codio-cdn").unwrap();
+        cmd.env("NO_COLOR", "1").env("CODIO_REPO", &repo);
+        cmd
+    };
+
+    let out = work.path().join("out.bin");
+    let result = codio()
+        .args(["get", &cid, "--peer", &addr, "--no-mdns", "--timeout", "20"])
+        .arg("-o")
+        .arg(&out)
+        .assert();
+    publisher.kill().unwrap();
+    let _ = publisher.wait();
+    result.success();
+    assert_eq!(std::fs::read(&out).unwrap(), data);
+
+    // The repo holds distributor-sized chunks, not one block for the file
+    assert_eq!(largest_block(&repo), 256 * 1024);
+
+    // Pinning and collecting garbage keep the chunks, and the file comes
+    // back from them with no peers
+    codio()
+        .args([
+            "pin",
+            "add",
+            &cid,
+            "--direct",
+            "--no-mdns",
+            "--timeout",
+            "5",
+        ])
+        .assert()
+        .success();
+    codio()
+        .args(["gc"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed 0 blocks"));
+    let again = work.path().join("again.bin");
+    codio()
+        .args(["get", &cid, "--no-mdns", "--timeout", "5"])
+        .arg("-o")
+        .arg(&again)
+        .assert()
+        .success();
+    assert_eq!(std::fs::read(&again).unwrap(), data);
+    codio()
+        .args(["get", &cid, "--no-mdns", "--timeout", "5"])
+        .assert()
+        .success()
+        .stdout(data);
+}
+
+#[test]
+fn test_cli_publish_stores_chunks_in_repo() {
+    let site = site_dir();
+    let data: Vec<u8> = (0..600 * 1024u32).map(|i| (i * 13 % 251) as u8).collect();
+    std::fs::write(site.path().join("assets/video.bin"), &data).unwrap();
+    let cid = hash_cid(site.path());
+    let work = tempfile::tempdir().unwrap();
+    let repo = work.path().join("repo");
+
+    let codio = || {
+        let mut cmd = Command::cargo_bin("codio-cdn").unwrap();
+        cmd.env("NO_COLOR", "1").env("CODIO_REPO", &repo);
+        cmd
+    };
+    codio()
+        .arg("publish")
+        .arg(site.path())
+        .arg("--no-mdns")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Added to repo and pinned"));
+    assert_eq!(largest_block(&repo), 256 * 1024);
+
+    // The pinned directory keeps the large file's chunks
+    codio()
+        .args(["gc"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed 0 blocks"));
+    codio()
+        .args(["get", &format!("{}/assets/video.bin", cid), "--no-mdns"])
+        .args(["--timeout", "5"])
+        .assert()
+        .success()
+        .stdout(data);
+}
+
+#[test]
+fn test_cli_publish_directory_and_get_paths() {
+    let site = site_dir();
+    // Three leaves, served from ranges of the file on disk
+    let video: Vec<u8> = (0..600_000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(site.path().join("video.bin"), &video).unwrap();
+    let (mut publisher, cid, addr) = spawn_publisher(site.path());
+    let get = |target: &str| {
+        Command::cargo_bin("codio-cdn")
+            .unwrap()
+            .args([
+                "get",
+                target,
+                "--peer",
+                &addr,
+                "--no-mdns",
+                "--timeout",
+                "20",
+            ])
+            .assert()
+    };
+
+    // A file by path, a listing, a missing path and the whole tree
+    let file = get(&format!("{}/assets/app.js", cid));
+    let listing = get(&cid);
+    let missing = get(&format!("/ipfs/{}/assets/nope.js", cid));
+
+    let out = tempfile::tempdir().unwrap();
+    let tree = Command::cargo_bin("codio-cdn")
+        .unwrap()
+        .args(["get", &cid, "--peer", &addr, "--no-mdns", "--timeout", "20"])
+        .arg("-o")
+        .arg(out.path().join("site"))
+        .assert();
+
+    publisher.kill().unwrap();
+    let _ = publisher.wait();
+
+    assert_eq!(hash_cid(site.path()), cid);
+    file.success().stdout("console.log('hi')");
+    listing
+        .success()
+        .stdout(predicate::str::contains("\tassets\n"))
+        .stdout(predicate::str::contains("\tindex.html\n"));
+    missing
+        .failure()
+        .stderr(predicate::str::contains("No such file"));
+    tree.success();
+    assert_eq!(
+        std::fs::read_to_string(out.path().join("site/assets/app.js")).unwrap(),
+        "console.log('hi')"
+    );
+    assert_eq!(
+        std::fs::read_to_string(out.path().join("site/index.html")).unwrap(),
+        "<script src=assets/app.js>"
+    );
+    assert!(std::fs::read(out.path().join("site/video.bin")).unwrap() == video);
+}
+
+#[test]
+fn test_cli_get_times_out_without_providers() {
+    let cid = "QmARmDBTPRRv2xP3jL3YFrbVHEyhZ7Zh6DmZUi2voVxm7t";
+
+    let mut cmd = Command::cargo_bin("codio-cdn").unwrap();
+    cmd.args(["get", cid, "--no-mdns", "--timeout", "2"])
+        .assert()
+        .failure();
+}
+
+fn car(args: &[&std::ffi::OsStr]) -> assert_cmd::assert::Assert {
+    Command::cargo_bin("codio-cdn")
+        .unwrap()
+        .env("NO_COLOR", "1")
+        .arg("car")
+        .args(args)
+        .assert()
+}
+
+#[test]
+fn test_cli_car_export_import_directory() {
+    let site = site_dir();
+    let cid = hash_cid(site.path());
+    let work = tempfile::tempdir().unwrap();
+
+    for version in ["1", "2"] {
+        let archive = work.path().join(format!("site-v{}.car", version));
+        car(&[
+            "export".as_ref(),
+            site.path().as_os_str(),
+            "-o".as_ref(),
+            archive.as_os_str(),
+            "--car-version".as_ref(),
+            version.as_ref(),
+        ])
+        .success()
+        .stdout(predicate::str::contains(format!("Root: {}", cid)));
+
+        // Importing verifies every block and unpacks the same tree
+        let out = work.path().join(format!("site-v{}", version));
+        car(&[
+            "import".as_ref(),
+            archive.as_os_str(),
+            "-o".as_ref(),
+            out.as_os_str(),
+        ])
+        .success()
+        .stdout(predicate::str::contains("Verified 4 blocks"))
+        .stdout(predicate::str::contains("Wrote 2 files"));
+        assert_eq!(hash_cid(&out), cid);
+    }
+}
+
+#[test]
+fn test_cli_car_import_rejects_tampered_block() {
+    let mut file = NamedTempFile::new().unwrap();
+    write!(file, "release artifact").unwrap();
+    let archive = NamedTempFile::new().unwrap();
+
+    car(&[
+        "export".as_ref(),
+        file.path().as_os_str(),
+        "-o".as_ref(),
+        archive.path().as_os_str(),
+        "--car-version".as_ref(),
+        "1".as_ref(),
+    ])
+    .success();
+
+    // The block is the last thing in a CARv1
+    let mut bytes = std::fs::read(archive.path()).unwrap();
+    *bytes.last_mut().unwrap() ^= 1;
+    std::fs::write(archive.path(), bytes).unwrap();
+
+    car(&["import".as_ref(), archive.path().as_os_str()])
+        .failure()
+        .stderr(predicate::str::contains("does not match its CID"));
+}
+
+#[test]
+fn test_cli_car_import_serves_and_exports_from_network() {
+    let site = site_dir();
+    let cid = hash_cid(site.path());
+    let work = tempfile::tempdir().unwrap();
+    let archive = work.path().join("site.car");
+    car(&[
+        "export".as_ref(),
+        site.path().as_os_str(),
+        "-o".as_ref(),
+        archive.as_os_str(),
+    ])
+    .success();
+
+    // An edge node imports the archive and serves it
+    let (mut edge, root, addr) =
+        spawn_server(&["car".as_ref(), "import".as_ref(), archive.as_os_str()]);
+
+    let file = Command::cargo_bin("codio-cdn")
+        .unwrap()
+        .args(["get", &format!("{}/assets/app.js", cid)])
+        .args(["--peer", &addr, "--no-mdns", "--timeout", "20"])
+        .assert();
+
+    // and peers can export the DAG from it again
+    let fetched = work.path().join("fetched.car");
+    let export = car(&[
+        "export".as_ref(),
+        cid.as_ref(),
+        "-o".as_ref(),
+        fetched.as_os_str(),
+        "--peer".as_ref(),
+        addr.as_ref(),
+        "--no-mdns".as_ref(),
+        "--timeout".as_ref(),
+        "20".as_ref(),
+    ]);
+
+    edge.kill().unwrap();
+    let _ = edge.wait();
+
+    assert_eq!(root, cid);
+    file.success().stdout("console.log('hi')");
+    export.success();
+
+    let out = work.path().join("site");
+    car(&[
+        "import".as_ref(),
+        fetched.as_os_str(),
+        "-o".as_ref(),
+        out.as_os_str(),
+    ])
+    .success();
+    assert_eq!(hash_cid(&out), cid);
+}
+
+#[test]
+fn test_cli_repo_serves_content_offline() {
+    let site = site_dir();
+    let cid = hash_cid(site.path());
+    let work = tempfile::tempdir().unwrap();
+    let repo = work.path().join("repo");
+    let archive = work.path().join("site.car");
+
+    car(&[
+        "export".as_ref(),
+        site.path().as_os_str(),
+        "-o".as_ref(),
+        archive.as_os_str(),
+    ])
+    .success();
+
+    // Importing into a repo keeps the blocks on disk...
+    car(&[
+        "import".as_ref(),
+        archive.as_os_str(),
+        "--repo".as_ref(),
+        repo.as_os_str(),
+    ])
+    .success()
+    .stdout(predicate::str::contains("Added to repo"));
+
+    // ...so later commands need no peers
+    Command::cargo_bin("codio-cdn")
+        .unwrap()
+        .args(["get", &format!("{}/assets/app.js", cid), "--no-mdns"])
+        .args(["--timeout", "5"])
+        .env("CODIO_REPO", &repo)
+        .assert()
+        .success()
+        .stdout("console.log('hi')");
+
+    let again = work.path().join("again.car");
+    car(&[
+        "export".as_ref(),
+        cid.as_ref(),
+        "-o".as_ref(),
+        again.as_os_str(),
+        "--repo".as_ref(),
+        repo.as_os_str(),
+        "--no-mdns".as_ref(),
+        "--timeout".as_ref(),
+        "5".as_ref(),
+    ])
+    .success()
+    .stdout(predicate::str::contains("Wrote 4 blocks"));
+}
+
+#[test]
+fn test_cli_publish_encrypted_and_get_capability() {
+    let work = tempfile::tempdir().unwrap();
+    let repo = work.path().join("repo");
+    let secret = work.path().join("asset.txt");
+    std::fs::write(&secret, "licensed asset ".repeat(40_000)).unwrap();
+
+    let codio = || {
+        let mut cmd = Command::cargo_bin("codio-cdn").unwrap();
+        cmd.env("NO_COLOR", "1").env("CODIO_REPO", &repo);
+        cmd
+    };
+    let publish = |flag: &str| {
+        let output = codio()
+            .arg("publish")
+            .arg(&secret)
+            .args([flag, "--no-mdns"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        stdout
+            .lines()
+            .find_map(|line| line.strip_prefix("Capability: "))
+            .expect("capability printed")
+            .to_string()
+    };
+
+    // Random keys: a new CID every time
+    let capability = publish("--encrypt");
+    assert_ne!(publish("--encrypt"), capability);
+    let (cid, _) = capability.split_once(':').unwrap();
+    // The ciphertext is kept chunk by chunk, like any published file
+    assert_eq!(largest_block(&repo), 256 * 1024);
+
+    codio()
+        .args(["get", &capability, "--timeout", "5", "--no-mdns"])
+        .assert()
+        .success()
+        .stdout("licensed asset ".repeat(40_000));
+
+    // Written to a file, the downloaded ciphertext doesn't stay next to it
+    let out = work.path().join("out.txt");
+    codio()
+        .args(["get", &capability, "--timeout", "5", "--no-mdns", "-o"])
+        .arg(&out)
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Decrypted 600000 bytes"));
+    assert_eq!(
+        std::fs::read_to_string(&out).unwrap(),
+        "licensed asset ".repeat(40_000)
+    );
+    assert!(!work.path().join("out.txt.ciphertext").exists());
+
+    // The CID alone only gets the ciphertext
+    let ciphertext = codio()
+        .args(["get", cid, "--timeout", "5", "--no-mdns"])
+        .output()
+        .unwrap()
+        .stdout;
+    assert!(!String::from_utf8_lossy(&ciphertext).contains("licensed asset"));
+
+    let wrong = format!(
+        "{}:{}",
+        cid,
+        publish("--convergent").split_once(':').unwrap().1
+    );
+    codio()
+        .args(["get", &wrong, "--timeout", "5", "--no-mdns"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to decrypt"));
+    let failed = work.path().join("failed.txt");
+    codio()
+        .args(["get", &wrong, "--timeout", "5", "--no-mdns", "-o"])
+        .arg(&failed)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Failed to decrypt"));
+    assert!(!failed.exists());
+
+    // Convergent keys: the same file gets the same capability
+    assert_eq!(publish("--convergent"), publish("--convergent"));
+}
+
+#[test]
+fn test_cli_pin_and_gc() {
+    let site = site_dir();
+    let cid = hash_cid(site.path());
+    let work = tempfile::tempdir().unwrap();
+    let repo = work.path().join("repo");
+    let note = work.path().join("note.txt");
+    std::fs::write(&note, "scratch").unwrap();
+    let note_cid = hash_cid(&note);
+
+    let codio = || {
+        let mut cmd = Command::cargo_bin("codio-cdn").unwrap();
+        cmd.env("NO_COLOR", "1").env("CODIO_REPO", &repo);
+        cmd
+    };
+
+    // Imports pin their roots
+    for path in [site.path(), note.as_path()] {
+        let archive = work.path().join("import.car");
+        car(&[
+            "export".as_ref(),
+            path.as_os_str(),
+            "-o".as_ref(),
+            archive.as_os_str(),
+        ])
+        .success();
+        codio()
+            .arg("car")
+            .arg("import")
+            .arg(&archive)
+            .assert()
+            .success();
+    }
+    codio()
+        .args(["pin", "ls"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("{}\trecursive", cid)))
+        .stdout(predicate::str::contains(&note_cid));
+
+    // Nothing is garbage until the note is unpinned
+    codio()
+        .args(["gc"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Removed 0 blocks"));
+    codio().args(["pin", "rm", &note_cid]).assert().success();
+    codio()
+        .args(["pin", "rm", &note_cid])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is not pinned"));
+
+    codio()
+        .args(["gc", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Would remove 1 blocks (7 bytes)"));
+    codio()
+        .args(["gc"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("removed {}", note_cid)))
+        .stdout(predicate::str::contains("kept 4 pinned blocks"));
+
+    // The site is still served from the repo; re-pinning it directly needs
+    // no network either
+    codio()
+        .args(["get", &format!("{}/index.html", cid), "--no-mdns"])
+        .args(["--timeout", "5"])
+        .assert()
+        .success()
+        .stdout("<script src=assets/app.js>");
+    codio()
+        .args([
+            "pin",
+            "add",
+            &cid,
+            "--direct",
+            "--name",
+            "site",
+            "--no-mdns",
+        ])
+        .assert()
+        .success();
+    codio()
+        .args(["pin", "ls"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("{}\tdirect\tsite", cid)));
+
+    // Without a repo there is nothing to pin into
+    Command::cargo_bin("codio-cdn")
+        .unwrap()
+        .env_remove("CODIO_REPO")
+        .args(["gc"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("needs --repo"));
+}
+
+#[test]
+fn test_cli_diff_versions() {
+    let v1 = site_dir();
+    let v2 = site_dir();
+    std::fs::write(v2.path().join("assets/app.js"), "console.log('v2')").unwrap();
+    std::fs::write(v2.path().join("assets/new.css"), "body {}").unwrap();
+    let work = tempfile::tempdir().unwrap();
+    let repo = work.path().join("repo");
+
+    let codio = || {
+        let mut cmd = Command::cargo_bin("codio-cdn").unwrap();
+        cmd.env("NO_COLOR", "1").env("CODIO_REPO", &repo);
+        cmd
+    };
+
+    // The new files and both directories changed; index.html is shared
+    let diff = |old: &std::ffi::OsStr| {
+        codio()
+            .arg("diff")
+            .arg(old)
+            .arg(v2.path())
+            .args(["--no-mdns", "--timeout", "5"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Added:   4 blocks"))
+            .stdout(predicate::str::contains("Removed: 3 blocks"))
+            .stdout(predicate::str::contains("Shared:  1 blocks (26 bytes)"))
+            // The new stylesheet is a raw block of 7 bytes
+            .stdout(predicate::str::is_match(r"\n\+ bafkrei\w+ 7\n").unwrap());
+    };
+    diff(v1.path().as_os_str());
+
+    // The old version can come from the repo by CID
+    let archive = work.path().join("v1.car");
+    car(&[
+        "export".as_ref(),
+        v1.path().as_os_str(),
+        "-o".as_ref(),
+        archive.as_os_str(),
+    ])
+    .success();
+    codio()
+        .arg("car")
+        .arg("import")
+        .arg(&archive)
+        .assert()
+        .success();
+    diff(hash_cid(v1.path()).as_ref());
+
+    codio()
+        .arg("diff")
+        .arg(v1.path())
+        .arg(v1.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No changes"));
+}
+
+#[test]
+fn test_cli_diff_multi_chunk_file() {
+    // Four 256 KB chunks and a short fifth; the second chunk changes
+    let work = tempfile::tempdir().unwrap();
+    let v1: Vec<u8> = (0..1_100_000u32).map(|i| (i % 251) as u8).collect();
+    let mut v2 = v1.clone();
+    v2[300_000] ^= 0xff;
+    let (old, new) = (work.path().join("v1.bin"), work.path().join("v2.bin"));
+    std::fs::write(&old, &v1).unwrap();
+    std::fs::write(&new, &v2).unwrap();
+    let repo = work.path().join("repo");
+
+    let diff = |old: &std::ffi::OsStr| {
+        let output = Command::cargo_bin("codio-cdn")
+            .unwrap()
+            .env("NO_COLOR", "1")
+            .env("CODIO_REPO", &repo)
+            .arg("diff")
+            .arg(old)
+            .arg(&new)
+            .args(["--no-mdns", "--timeout", "5"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+
+        assert!(
+            stdout.contains("Added:   1 blocks (262144 bytes)"),
+            "{}",
+            stdout
+        );
+        assert!(
+            stdout.contains("Removed: 1 blocks (262144 bytes)"),
+            "{}",
+            stdout
+        );
+        assert!(
+            stdout.contains("Shared:  4 blocks (837856 bytes)"),
+            "{}",
+            stdout
+        );
+
+        // The one added block is the changed chunk
+        let changed = format!(
+            "+ {} 262144\n",
+            codio_content_id::ContentId::new(&v2[262_144..524_288])
+        );
+        assert!(stdout.contains(&changed), "{}", stdout);
+    };
+    diff(old.as_os_str());
+
+    // The old version fetched by CID is chunked the same way
+    let archive = work.path().join("v1.car");
+    car(&[
+        "export".as_ref(),
+        old.as_os_str(),
+        "-o".as_ref(),
+        archive.as_os_str(),
+    ])
+    .success();
+    car(&[
+        "import".as_ref(),
+        archive.as_os_str(),
+        "--repo".as_ref(),
+        repo.as_os_str(),
+    ])
+    .success();
+    diff(codio_content_id::ContentId::new(&v1).to_string().as_ref());
+}
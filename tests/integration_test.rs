@@ -1,18 +1,19 @@
 use codio_content_id::ContentId;
-use codio_dht::{DhtConfig, DhtNode};
+use codio_dht::{DHTConfig, DHTManager};
+use std::str::FromStr;
 use std::time::Duration;
 
 #[tokio::test]
 async fn test_publish_retrieve_flow() {
     // Setup two DHT nodes
-    let config1 = DhtConfig {
+    let config1 = DHTConfig {
         query_timeout: Duration::from_secs(5),
         ..Default::default()
     };
     let config2 = config1.clone();
 
-    let (mut node1, _rx1) = DhtNode::new(config1).await.unwrap();
-    let (mut node2, mut rx2) = DhtNode::new(config2).await.unwrap();
+    let mut node1 = DHTManager::new(config1).await.unwrap();
+    let mut node2 = DHTManager::new(config2).await.unwrap();
 
     // Listen on different ports
     node1
@@ -35,11 +36,9 @@ async fn test_publish_retrieve_flow() {
     tokio::time::sleep(Duration::from_secs(1)).await;
 
     // Node 2 finds providers
+    // (The nodes aren't connected, so the lookup completes without results;
+    // the CLI tests cover an end-to-end transfer between two processes)
     node2.find_providers(cid.clone()).await.unwrap();
-
-    // Should find node 1 as provider
-    // (This test needs real P2P connection - simplified here)
-    assert!(true);
 }
 
 #[test]
@@ -118,15 +117,15 @@ fn test_cid_invalid_format() {
 
 #[tokio::test]
 async fn test_dht_node_creation() {
-    let config = DhtConfig::default();
-    let result = DhtNode::new(config).await;
+    let config = DHTConfig::default();
+    let result = DHTManager::new(config).await;
     assert!(result.is_ok());
 }
 
 #[tokio::test]
 async fn test_dht_listen() {
-    let config = DhtConfig::default();
-    let (mut node, _rx) = DhtNode::new(config).await.unwrap();
+    let config = DHTConfig::default();
+    let mut node = DHTManager::new(config).await.unwrap();
 
     let addr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();
     let result = node.listen(addr).await;
@@ -135,8 +134,8 @@ async fn test_dht_listen() {
 
 #[tokio::test]
 async fn test_dht_provide_content() {
-    let config = DhtConfig::default();
-    let (mut node, _rx) = DhtNode::new(config).await.unwrap();
+    let config = DHTConfig::default();
+    let mut node = DHTManager::new(config).await.unwrap();
 
     // Listen first
     let addr = "/ip4/127.0.0.1/tcp/0".parse().unwrap();